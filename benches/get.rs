@@ -0,0 +1,42 @@
+//! Benchmarks of the hot path of `git credential get`, see `warden::bench`
+//! for the budgets they are held to.
+
+use std::fs;
+use std::hint::black_box;
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use warden::bench;
+
+fn parse_request(c: &mut Criterion) {
+    c.bench_function("parse credential request", |b| {
+        b.iter(|| bench::parse_request(black_box(bench::REQUEST)).unwrap());
+    });
+}
+
+fn load_config(c: &mut Criterion) {
+    c.bench_function("parse oauth.toml", |b| {
+        b.iter(|| bench::parse_config(black_box(bench::CONFIG)).unwrap());
+    });
+
+    let dir = std::env::temp_dir().join(format!("warden-bench-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("oauth.toml"), bench::CONFIG).unwrap();
+    // SAFETY: set before anything else reads the environment
+    unsafe {
+        std::env::set_var("WARDEN_CONFIG_DIR", &dir);
+    }
+    c.bench_function("load oauth configuration", |b| {
+        b.iter(|| bench::load_config().unwrap());
+    });
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+fn match_patterns(c: &mut Criterion) {
+    let patterns = bench::patterns(bench::PATTERN_COUNT);
+    c.bench_function("match 1k patterns", |b| {
+        b.iter(|| bench::matches(&patterns, black_box(bench::URL)));
+    });
+}
+
+criterion_group!(benches, parse_request, load_config, match_patterns);
+criterion_main!(benches);
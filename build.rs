@@ -0,0 +1,37 @@
+//! Captures build metadata for `warden --version --json`.
+
+use std::env;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn main() {
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs/heads");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+
+    // packaged sources have no repository, leave the commit empty there
+    let commit = Command::new("git")
+        .args(["rev-parse", "--short=12", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        .unwrap_or_default();
+    println!("cargo:rustc-env=WARDEN_GIT_COMMIT={commit}");
+
+    // reproducible builds pin the date through SOURCE_DATE_EPOCH
+    let date = env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.parse::<u64>().ok())
+        .or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .ok()
+                .map(|since| since.as_secs())
+        })
+        .unwrap_or_default();
+    println!("cargo:rustc-env=WARDEN_BUILD_EPOCH={date}");
+
+    let target = env::var("TARGET").unwrap_or_default();
+    println!("cargo:rustc-env=WARDEN_TARGET={target}");
+}
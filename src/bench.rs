@@ -0,0 +1,153 @@
+//! The hot path of `get`, for the benchmarks in `benches/` and the
+//! performance budget.
+//!
+//! Git runs the helper for every fetch and push, so parsing its request,
+//! loading the configuration and matching the repository against the
+//! patterns must stay well below what a user notices. The budgets below hold
+//! for release builds and are checked by `just budget` and in CI, which run the
+//! ignored test of this module in release mode. `just bench` measures them and
+//! `just bench-e2e` the whole `get` with hyperfine.
+//! Nothing in here is a stable API.
+
+use std::iter;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use regex::Regex;
+
+use crate::config::provider::validate_providers;
+use crate::config::{LoadableConfig as _, OAuthConfig};
+use crate::profile::url::{Pattern, Patterns};
+use crate::utils::{CredentialRequest, parse_credential_input};
+
+/// Parsing a credential request with a `WWW-Authenticate` challenge
+pub const PARSE_BUDGET: Duration = Duration::from_micros(50);
+/// Parsing and validating an `oauth.toml` with a few providers
+pub const CONFIG_BUDGET: Duration = Duration::from_millis(2);
+/// Matching a URL against [`PATTERN_COUNT`] patterns, only the last of which
+/// matches
+pub const MATCH_BUDGET: Duration = Duration::from_millis(1);
+/// Patterns of the matching workload
+pub const PATTERN_COUNT: usize = 1000;
+
+/// What Git sends for a push that was refused with the token it was given
+pub const REQUEST: &str = concat!(
+    "protocol=https\n",
+    "host=github.com\n",
+    "path=org/repo.git\n",
+    "username=oauth\n",
+    "capability[]=authtype\n",
+    "wwwauth[]=Bearer realm=\"GitHub\", error=\"invalid_token\"\n",
+    "\n",
+);
+
+/// An `oauth.toml` with the providers of a typical setup
+pub const CONFIG: &str = r#"
+[providers."github.com"]
+type = "github"
+client_id = "Iv1.0123456789abcdef"
+
+[providers."gitlab.com"]
+type = "gitlab"
+client_id = "0123456789abcdef0123456789abcdef"
+scopes = ["read_repository", "write_repository"]
+
+[providers."gitlab.example.com"]
+type = "gitlab"
+client_id = "fedcba9876543210"
+auth_url = "/oauth/authorize"
+token_url = "/oauth/token"
+"#;
+
+/// The URL of the matching workload, matched by the last of [`patterns`]
+pub const URL: &str = "git.example999.com:org/repo";
+
+pub fn parse_request(input: &str) -> Result<CredentialRequest> {
+    parse_credential_input(input.as_bytes())
+}
+
+/// Parse and validate `text` as `oauth.toml`
+pub fn parse_config(text: &str) -> Result<OAuthConfig> {
+    let cfg = toml::from_str(text)?;
+    validate_providers(cfg)
+}
+
+/// Load the OAuth configuration like `get` does, from `$WARDEN_CONFIG_DIR`
+/// and the git config
+pub fn load_config() -> Result<OAuthConfig> {
+    OAuthConfig::load()
+}
+
+/// `count` patterns for hosts `git.example0.com` onwards
+///
+/// # Panics
+///
+/// If a pattern of the workload is no valid regular expression
+pub fn patterns(count: usize) -> Patterns {
+    (0..count).fold(Patterns::new(), |patterns, i| {
+        let regex = Regex::new(&format!(
+            r"^(?P<host>git\.example{i}\.com):(?P<owner>[^/]+)/(?P<repo>[^/]+)$"
+        ))
+        .expect("Pattern of the workload is valid");
+        patterns.with(Pattern::from(regex))
+    })
+}
+
+pub fn matches(patterns: &Patterns, url: &str) -> bool {
+    patterns.matches(url).is_some()
+}
+
+/// The fastest of `runs` runs of `f`, the least disturbed by the machine
+pub fn fastest<T, F: FnMut() -> T>(runs: usize, mut f: F) -> Duration {
+    iter::repeat_with(|| {
+        let start = Instant::now();
+        std::hint::black_box(f());
+        start.elapsed()
+    })
+    .take(runs)
+    .min()
+    .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Debug builds are unoptimized, the budgets are for release builds
+    fn budget(release: Duration) -> Duration {
+        if cfg!(debug_assertions) {
+            release * 50
+        } else {
+            release
+        }
+    }
+
+    #[test]
+    fn workloads_are_what_they_document() {
+        let req = parse_request(REQUEST).unwrap();
+        assert_eq!(req.username.as_deref(), Some("oauth"));
+        assert_eq!(req.wwwauth.len(), 1, "one challenge");
+        assert_eq!(req.bearer_error().as_deref(), Some("invalid_token"));
+        assert_eq!(parse_config(CONFIG).unwrap().providers.len(), 3);
+        assert!(
+            matches(&patterns(PATTERN_COUNT), URL),
+            "last pattern matches"
+        );
+    }
+
+    /// Timings are unreliable on loaded machines, `just budget` and CI run
+    /// this
+    #[test]
+    #[ignore = "timing sensitive, run with `just budget`"]
+    fn hot_path_stays_within_budget() {
+        let took = fastest(20, || parse_request(REQUEST).unwrap());
+        assert!(took < budget(PARSE_BUDGET), "parsing took {took:?}");
+
+        let took = fastest(20, || parse_config(CONFIG).unwrap());
+        assert!(took < budget(CONFIG_BUDGET), "config took {took:?}");
+
+        let patterns = patterns(PATTERN_COUNT);
+        let took = fastest(20, || matches(&patterns, URL));
+        assert!(took < budget(MATCH_BUDGET), "matching took {took:?}");
+    }
+}
@@ -1,28 +1,104 @@
-use anyhow::{Context as _, Result};
+use std::env;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context as _, Result, bail};
+use chrono::TimeDelta;
+use clap::builder::BoolishValueParser;
+use clap::error::ErrorKind;
 use clap::{CommandFactory as _, Parser, Subcommand};
-use clap_complete::{Shell, generate};
 
 use crate::commands;
+use crate::commands::common::CredentialFilter;
+use crate::commands::completions::CompletionShell;
+use crate::commands::docker_credential::DockerCredentialAction;
+use crate::commands::env::PackageFormat;
+use crate::commands::handoff::ForgeCli;
+use crate::commands::login::LoginOptions;
+use crate::config::ScopePreset;
+use crate::context::AppContext;
+use crate::output::OutputFormat;
+use crate::profile::Scope;
 use crate::profile::rule::ProfileRef;
+use crate::transfer::ExportTarget;
+use crate::utils::parse_duration;
 
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+#[command(
+    author,
+    version,
+    about,
+    long_about = None,
+    disable_version_flag = true,
+    arg_required_else_help = true
+)]
 pub struct Cli {
+    /// Print version
+    #[clap(short = 'V', long)]
+    pub version: bool,
+    /// Print the version with build metadata as JSON
+    #[clap(long, requires = "version")]
+    pub json: bool,
     /// Use OAuth device flow or fail
     #[clap(short, long, global = true)]
     pub device: bool,
     /// Logging verbosity
     #[command(flatten)]
     pub verbosity: clap_verbosity_flag::Verbosity,
+    /// Append the credential protocol exchanged with Git to this file, with
+    /// secrets redacted
+    #[clap(long, global = true, env = "WARDEN_TRACE_IO", value_name = "FILE")]
+    pub trace_io: Option<PathBuf>,
+    /// Run as if started in this directory, which decides the repository
+    /// whose git config and profiles apply
+    #[clap(long, global = true, env = "WARDEN_CWD", value_name = "PATH")]
+    pub cwd: Option<PathBuf>,
+    /// The command to run, `None` only with `--version`
     #[command(subcommand)]
-    pub command: Command,
+    pub command: Option<Command>,
+}
+
+impl Cli {
+    /// Parse the command line. Invoked as `docker-credential-warden <action>`,
+    /// e.g. through a link, this is `warden docker-credential <action>`.
+    pub fn parse_args() -> Self {
+        let mut args: Vec<OsString> = env::args_os().collect();
+        let as_docker_helper = args
+            .first()
+            .and_then(|arg| Path::new(arg).file_name())
+            .is_some_and(|name| name.to_string_lossy().starts_with("docker-credential-"));
+        if as_docker_helper {
+            args.insert(1, "docker-credential".into());
+        }
+        // git runs `$GIT_ASKPASS <prompt>` in commands started by `exec`
+        let as_askpass = env::var_os(commands::exec::ASKPASS_HOST_ENV).is_some()
+            && args.len() == 2
+            && args[1].to_str().is_some_and(|arg| {
+                arg.starts_with("Username for ") || arg.starts_with("Password for ")
+            });
+        if as_askpass {
+            args.insert(1, "askpass".into());
+        }
+        let cli = Self::parse_from(args);
+        if cli.command.is_none() && !cli.version {
+            Self::command()
+                .error(ErrorKind::MissingSubcommand, "a subcommand is required")
+                .exit();
+        }
+        cli
+    }
 }
 
 #[derive(Subcommand, Debug)]
 pub enum Command {
     /// Retrieve credentials
     #[command(hide = true)]
-    Get,
+    Get {
+        /// Log in again instead of using the stored token, e.g. when the
+        /// server rejects a token that hasn't expired yet
+        #[clap(long, env = "WARDEN_FORCE_LOGIN", value_parser = BoolishValueParser::new())]
+        force_login: bool,
+    },
     /// Store credentials
     #[command(hide = true)]
     Store,
@@ -34,26 +110,115 @@ pub enum Command {
         /// Shows only the name of the profiles
         #[clap(short, long)]
         short: bool,
+        /// Print the profiles as structured data instead
+        #[clap(short, long, value_enum, default_value_t)]
+        output: OutputFormat,
     },
     /// Shows a profile in TOML format.
-    Show { profile: String },
+    Show {
+        profile: String,
+        /// Note where the global and the current repository's git config
+        /// set each key, and whether to the same value
+        #[clap(long)]
+        provenance: bool,
+        /// Print the profile as structured data instead
+        #[clap(short, long, value_enum, default_value_t)]
+        output: OutputFormat,
+    },
     /// Apply a profile.
-    Apply { profile: Option<String> },
+    Apply {
+        profile: Option<String>,
+        /// The git config file to write to
+        #[clap(long, value_enum, default_value_t)]
+        scope: Scope,
+        /// The repository to apply to, defaults to the current one
+        #[clap(long, value_name = "PATH")]
+        repo: Option<PathBuf>,
+        /// Choose the profile for this remote instead of `origin`
+        #[clap(long, value_name = "NAME", conflicts_with_all = ["profile", "url"])]
+        remote: Option<String>,
+        /// Choose the profile for this URL instead of a remote's
+        #[clap(long, conflicts_with = "profile")]
+        url: Option<String>,
+    },
+    /// Remove the files and includes an applied profile set up.
+    Unapply {
+        /// The git config file to clean up
+        #[clap(long, value_enum, default_value_t)]
+        scope: Scope,
+        /// The repository to clean up, defaults to the current one
+        #[clap(long, value_name = "PATH")]
+        repo: Option<PathBuf>,
+    },
     /// Login to a provider and store the credentials.
-    Login,
+    Login {
+        /// The hostname to log in to
+        #[clap(long)]
+        hostname: Option<String>,
+        /// The credential name to store the token under
+        #[clap(short, long)]
+        name: Option<String>,
+        /// Replace the token of an existing credential without confirmation
+        #[clap(short, long)]
+        reauth: bool,
+        /// Log in to every configured provider in turn
+        #[clap(short, long, conflicts_with_all = ["hostname", "reauth"])]
+        all: bool,
+        /// Request a preset set of scopes instead of the configured ones
+        #[clap(long, value_enum)]
+        scopes_preset: Option<ScopePreset>,
+        /// Print the token encrypted for `import-token` instead of storing it
+        #[clap(long, conflicts_with_all = ["all", "reauth"])]
+        print_only: bool,
+        /// Write the encrypted token to this file instead of stdout
+        #[clap(long, requires = "print_only")]
+        token_file: Option<PathBuf>,
+        /// Erase the token after this long, e.g. 2h or 1d12h
+        #[clap(long, value_name = "DURATION", value_parser = parse_duration, conflicts_with = "reauth")]
+        ephemeral: Option<TimeDelta>,
+        /// Ask the forge whose the new token is
+        #[clap(long, conflicts_with = "print_only")]
+        verify: bool,
+    },
+    /// Import profiles, rules and patterns from another tool.
+    Import {
+        /// Read ghr's config, `ghr.toml` in `$GHR_ROOT` or `~/.ghr`
+        #[clap(long, required = true)]
+        from_ghr: bool,
+        /// The config file to read instead
+        #[clap(long, value_name = "FILE")]
+        file: Option<PathBuf>,
+        /// Only show what would be imported
+        #[clap(long)]
+        dry_run: bool,
+    },
+    /// Store a token exported by `login --print-only` on another machine.
+    ImportToken {
+        /// The file holding the encrypted token, read from stdin if omitted
+        file: Option<PathBuf>,
+        /// Store the token under this credential name instead
+        #[clap(short, long)]
+        name: Option<String>,
+    },
     /// Logout from a provider and erase the credentials.
     Logout {
         /// The hostname to logout from
-        #[clap(short, long)]
+        #[clap(long)]
         hostname: Option<String>,
         /// The credential name to logout from
         #[clap(short, long)]
         name: Option<String>,
+        /// Log out every credential, only those of --hostname if given
+        #[clap(long, conflicts_with = "name")]
+        all: bool,
+        /// Only show what would be removed
+        #[clap(long)]
+        dry_run: bool,
     },
     /// Refresh credentials for a provider.
     Refresh {
         /// The hostname to refresh credentials for
-        #[clap(short, long)]
+        #[clap(long)]
         hostname: Option<String>,
         /// The credential name to refresh
         #[clap(short, long)]
@@ -62,7 +227,7 @@ pub enum Command {
     /// Switch between credentials.
     Switch {
         /// The hostname to switch credentials for
-        #[clap(short, long)]
+        #[clap(long)]
         hostname: Option<String>,
         /// The credential name to switch to
         #[clap(short, long)]
@@ -70,54 +235,401 @@ pub enum Command {
         /// Do not attempt to infer and filter by the host from the remote URL
         #[clap(short, long)]
         all: bool,
+        /// The repository to infer the host from, defaults to the current one
+        #[clap(long, value_name = "PATH", conflicts_with = "all")]
+        repo: Option<PathBuf>,
+    },
+    /// Rewrite the identity of unpushed commits to match the profile.
+    FixAuthors {
+        /// Rewrite commits after this revision, defaults to the upstream
+        #[clap(long)]
+        since: Option<String>,
+        /// Only list the commits that would be rewritten
+        #[clap(long)]
+        dry_run: bool,
+        /// Do not ask for confirmation
+        #[clap(short, long)]
+        yes: bool,
     },
     /// Show the current status of the credentials.
-    Status,
+    Status {
+        /// Show the commit identity of the current repository instead
+        #[clap(long)]
+        repo: bool,
+        /// Print the status as structured data instead
+        #[clap(short, long, value_enum, default_value_t)]
+        output: OutputFormat,
+        /// Only show hosts matching this glob, e.g. "*.example.com"
+        #[clap(long, value_name = "GLOB")]
+        host: Option<String>,
+        /// Only show tokens that have expired
+        #[clap(long)]
+        expired: bool,
+        /// Only show tokens that expire within this time, e.g. "24h"
+        #[clap(long, value_name = "DURATION", value_parser = parse_duration)]
+        expiring_in: Option<TimeDelta>,
+        /// Only show credentials without a token in the keyring
+        #[clap(long)]
+        missing_keyring: bool,
+    },
+    /// Show local credential usage statistics.
+    Stats,
+    /// Revoke and erase every stored token and clear the hosts state.
+    Nuke {
+        /// Actually remove everything instead of listing it
+        #[clap(long)]
+        yes_i_mean_it: bool,
+        /// Only list what would be removed
+        #[clap(long)]
+        dry_run: bool,
+    },
+    /// Remove stale entries from the token store.
+    Prune {
+        /// Erase tokens no credential in the hosts state belongs to
+        #[clap(long, required = true)]
+        orphans: bool,
+        /// Only list what would be erased
+        #[clap(long)]
+        dry_run: bool,
+    },
+    /// Review the stored tokens.
+    Audit {
+        #[command(subcommand)]
+        command: AuditCommand,
+    },
+    /// Act as a Docker credential helper for registries of configured forges.
+    DockerCredential {
+        #[command(subcommand)]
+        action: DockerCredentialAction,
+    },
+    /// Print package manager credentials for a host's registry.
+    Env {
+        /// The hostname of the forge
+        #[clap(long, required_unless_present = "profile")]
+        hostname: Option<String>,
+        /// The credential name, defaults to the active credential
+        #[clap(short, long)]
+        name: Option<String>,
+        /// The package manager to print the configuration for
+        #[clap(long, value_enum, required_unless_present = "profile")]
+        format: Option<PackageFormat>,
+        /// Print the `env` table of this profile as shell exports instead
+        #[clap(long, conflicts_with_all = ["hostname", "name", "format"])]
+        profile: Option<String>,
+    },
+    /// Run a command with a host's token in its environment.
+    Exec {
+        /// The hostname of the forge
+        #[clap(long, alias = "host")]
+        hostname: String,
+        /// The credential name, defaults to the active credential
+        #[clap(short, long)]
+        name: Option<String>,
+        /// Also set the `env` table of this profile
+        #[clap(long)]
+        profile: Option<String>,
+        /// The command and its arguments, after `--`
+        #[clap(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+        command: Vec<String>,
+    },
+    /// Answer a git prompt for a command started by exec.
+    #[command(hide = true)]
+    Askpass { prompt: String },
+    /// Check that the token for a remote may push, for use as a pre-push hook.
+    Guard {
+        /// The remote being pushed to, defaults to origin
+        remote: Option<String>,
+        /// The URL being pushed to, defaults to the remote's URL
+        url: Option<String>,
+    },
+    /// Log the GitHub or GitLab CLI in with a warden token.
+    Handoff {
+        #[clap(value_enum)]
+        cli: ForgeCli,
+        /// The hostname, defaults to github.com or gitlab.com
+        #[clap(long)]
+        hostname: Option<String>,
+        /// The credential name, defaults to the active credential
+        #[clap(short, long)]
+        name: Option<String>,
+        /// Print the environment variables the CLI reads the token from instead
+        #[clap(long)]
+        print_env: bool,
+    },
+    /// Manage hosts in the credential state.
+    Hosts {
+        #[command(subcommand)]
+        command: HostsCommand,
+    },
+    /// Temporarily use a different credential on a host.
+    Session {
+        #[command(subcommand)]
+        command: SessionCommand,
+    },
+    /// Work with repositories on their forge.
+    Repo {
+        #[command(subcommand)]
+        command: RepoCommand,
+    },
+    /// Inspect the OAuth provider configuration.
+    Provider {
+        #[command(subcommand)]
+        command: ProviderCommand,
+    },
+    /// Diagnose how git reaches warden.
+    Debug {
+        #[command(subcommand)]
+        command: DebugCommand,
+    },
+    /// Debug repository URL patterns.
+    Pattern {
+        #[command(subcommand)]
+        command: PatternCommand,
+    },
+    /// Debug profile rules.
+    Rule {
+        #[command(subcommand)]
+        command: RuleCommand,
+    },
     /// Generate shell completions for the given shell.
     Completions {
-        #[clap(value_enum)]
-        shell: Shell,
+        #[clap(value_enum, required_unless_present = "install")]
+        shell: Option<CompletionShell>,
+        /// Write them to where the shell loads completions from instead,
+        /// detecting the shell from $SHELL unless given
+        #[clap(long)]
+        install: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum AuditCommand {
+    /// List every stored token with its scopes, expiry and recent use.
+    Secrets,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum HostsCommand {
+    /// Add a credential for a host without logging in.
+    Add {
+        /// The hostname, e.g. git.example.com or git.example.com:8443
+        host: String,
+        /// The credential name
+        credential: String,
+    },
+    /// Remove a host with all of its credentials and tokens.
+    Remove { host: String },
+    /// Move a host with its credentials and tokens to a new name.
+    Rename {
+        /// The current hostname
+        old: String,
+        /// The hostname the forge moved to
+        new: String,
+        /// Also point the remotes of the current repository at the new host
+        #[clap(long)]
+        remotes: bool,
+        /// Only show what would change
+        #[clap(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum SessionCommand {
+    /// Switch a host to a read-only credential, logging one in if needed.
+    Readonly {
+        /// The hostname
+        host: String,
+        /// The read-only credential to use, found by its scopes by default
+        #[clap(short, long)]
+        name: Option<String>,
+    },
+    /// Switch back to the credential active before the session.
+    End {
+        /// The hostname, defaults to every host in a session
+        host: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum RepoCommand {
+    /// Create a repository and make it the origin of the current repository.
+    Create {
+        /// The repository, e.g. owner/name or git.example.com/owner/name
+        repo: String,
+        /// Make the repository private
+        #[clap(long)]
+        private: bool,
+        /// The credential name, defaults to the active credential
+        #[clap(short, long)]
+        name: Option<String>,
+    },
+    /// Print a repository's full name, failing if it doesn't exist.
+    Exists {
+        /// The repository, e.g. owner/name or git.example.com/owner/name
+        repo: String,
+        /// The credential name, defaults to the active credential
+        #[clap(short, long)]
+        name: Option<String>,
+    },
+    /// Fork a repository and print the fork's clone URL.
+    Fork {
+        /// The repository, e.g. owner/name or git.example.com/owner/name
+        repo: String,
+        /// The organization or group to fork to, defaults to your account
+        #[clap(long)]
+        into: Option<String>,
+        /// The credential name, defaults to the active credential
+        #[clap(short, long)]
+        name: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ProviderCommand {
+    /// Validate every provider and show why invalid ones are discarded.
+    Lint,
+    /// Keep a provider's client secret in the keyring.
+    Secret {
+        #[command(subcommand)]
+        command: SecretCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum SecretCommand {
+    /// Store the client secret of the provider for a host, read from stdin
+    /// without a terminal.
+    Set {
+        /// The host of the provider, e.g. git.example.com
+        host: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum DebugCommand {
+    /// Show the credential helpers git asks, in order, and what may shadow
+    /// warden.
+    HelperChain {
+        /// The URL to resolve helpers for, defaults to the current
+        /// repository's origin
+        url: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum PatternCommand {
+    /// Show which pattern matches the input and what it captures.
+    Test { input: String },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum RuleCommand {
+    /// Show how each rule compares against a URL and which profile wins.
+    Explain {
+        /// The repository URL, defaults to the current repository's origin
+        url: Option<String>,
     },
 }
 
 impl Command {
     pub async fn run(self, force_device: bool) -> Result<()> {
+        let ctx = &AppContext::system();
         match self {
-            Self::Get => {
-                commands::get::handle_get(force_device)
+            Self::Get { force_login } => {
+                commands::get::handle_get(ctx, force_device, force_login)
                     .await
                     .context("Failed to handle 'get' command")?;
             },
             Self::Store => {
-                commands::store::handle_store()
+                commands::store::handle_store(ctx)
                     .await
                     .context("Failed to handle 'store' command")?;
             },
             Self::Erase => {
-                commands::erase::handle_erase()
+                commands::erase::handle_erase(ctx)
                     .await
                     .context("Failed to handle 'erase' command")?;
             },
-            Self::List { short } => {
-                commands::list::list(short).context("Failed to list profiles")?;
+            Self::List { short, output } => {
+                commands::list::list(ctx, short, output).context("Failed to list profiles")?;
             },
-            Self::Show { profile: name } => {
-                commands::show::show(&ProfileRef { name }).context("Failed to show profiles")?;
+            Self::Show {
+                profile: name,
+                provenance,
+                output,
+            } => {
+                commands::show::show(ctx, &ProfileRef { name }, provenance, output)
+                    .context("Failed to show profiles")?;
             },
-            Self::Apply { profile: name } => {
-                commands::apply::apply(name).context("Failed to apply profile")?;
+            Self::Apply {
+                profile: name,
+                scope,
+                repo,
+                remote,
+                url,
+            } => {
+                let target = url.as_deref().map_or(
+                    commands::apply::Target::Remote(remote.as_deref()),
+                    commands::apply::Target::Url,
+                );
+                commands::apply::apply(ctx, name, scope, repo.as_deref(), target)
+                    .context("Failed to apply profile")?;
             },
-            Self::Login => {
-                commands::login::login(force_device)
-                    .await
-                    .context("Failed to perform login")?;
+            Self::Unapply { scope, repo } => {
+                commands::apply::unapply(scope, repo.as_deref())
+                    .context("Failed to unapply profile")?;
             },
-            Self::Logout { hostname, name } => {
-                commands::logout::logout(hostname.as_ref(), name.as_ref())
+            Self::Login {
+                hostname,
+                name,
+                reauth,
+                all,
+                scopes_preset,
+                print_only,
+                token_file,
+                ephemeral,
+                verify,
+            } => {
+                let export =
+                    print_only.then(|| token_file.map_or(ExportTarget::Stdout, ExportTarget::File));
+                commands::login::login(
+                    ctx,
+                    hostname.as_deref(),
+                    name.as_deref(),
+                    LoginOptions {
+                        reauth,
+                        all,
+                        scopes_preset,
+                        export,
+                        ephemeral,
+                        verify,
+                    },
+                    force_device,
+                )
+                .await
+                .context("Failed to perform login")?;
+            },
+            Self::Import { file, dry_run, .. } => {
+                commands::import::from_ghr(file.as_deref(), dry_run)
+                    .context("Failed to import from ghr")?;
+            },
+            Self::ImportToken { file, name } => {
+                commands::import_token::import_token(ctx, file.as_deref(), name.as_deref())
+                    .context("Failed to import token")?;
+            },
+            Self::Logout {
+                hostname,
+                name,
+                all,
+                dry_run,
+            } => {
+                commands::logout::logout(ctx, hostname.as_ref(), name.as_ref(), all, dry_run)
+                    .await
                     .context("Failed to perform logout")?;
             },
             Self::Refresh { hostname, name } => {
-                commands::refresh::refresh(hostname.as_deref(), name.as_deref(), force_device)
+                commands::refresh::refresh(ctx, hostname.as_deref(), name.as_deref(), force_device)
                     .await
                     .context("Failed to refresh credential")?;
             },
@@ -125,22 +637,228 @@ impl Command {
                 hostname,
                 name,
                 all,
+                repo,
             } => {
-                commands::switch::switch(hostname.as_ref(), name.as_ref(), all)
-                    .context("Failed to switch credential")?;
+                commands::switch::switch(
+                    ctx,
+                    hostname.as_ref(),
+                    name.as_ref(),
+                    all,
+                    repo.as_deref(),
+                )
+                .context("Failed to switch credential")?;
             },
-            Self::Status => {
-                commands::status::status().context("Failed to show credential status")?;
+            Self::FixAuthors {
+                since,
+                dry_run,
+                yes,
+            } => {
+                commands::fix_authors::fix_authors(ctx, since.as_deref(), dry_run, yes)
+                    .context("Failed to fix commit authors")?;
             },
-            Self::Completions { shell } => {
-                let mut cmd = Cli::command();
-                generate(
-                    shell,
-                    &mut cmd,
-                    env!("CARGO_PKG_NAME"),
-                    &mut std::io::stdout(),
-                );
+            Self::Status {
+                repo,
+                output,
+                host,
+                expired,
+                expiring_in,
+                missing_keyring,
+            } => {
+                let filter = CredentialFilter {
+                    host,
+                    expired,
+                    expiring_in,
+                    missing_keyring,
+                };
+                commands::status::status(ctx, repo, output, &filter)
+                    .context("Failed to show credential status")?;
+            },
+            Self::Stats => {
+                commands::stats::stats(ctx).context("Failed to show usage statistics")?;
+            },
+            Self::Nuke {
+                yes_i_mean_it,
+                dry_run,
+            } => {
+                commands::nuke::nuke(ctx, yes_i_mean_it, dry_run)
+                    .await
+                    .context("Failed to remove all credentials")?;
+            },
+            Self::Prune { dry_run, .. } => {
+                commands::prune::orphans(ctx, dry_run)
+                    .context("Failed to prune orphaned tokens")?;
+            },
+            Self::Audit {
+                command: AuditCommand::Secrets,
+            } => {
+                commands::audit::secrets(ctx).context("Failed to audit secrets")?;
+            },
+            Self::DockerCredential { action } => {
+                commands::docker_credential::docker_credential(ctx, action)
+                    .await
+                    .context("Failed to handle Docker credential request")?;
+            },
+            Self::Env {
+                hostname,
+                name,
+                format,
+                profile,
+            } => {
+                match (profile, hostname, format) {
+                    (Some(name), _, _) => {
+                        commands::env::profile_env(ctx, &ProfileRef { name })
+                            .context("Failed to print the profile's environment")?;
+                    },
+                    (None, Some(hostname), Some(format)) => {
+                        commands::env::env(ctx, &hostname, name.as_deref(), format)
+                            .await
+                            .context("Failed to print package manager credentials")?;
+                    },
+                    (None, _, _) => bail!("--hostname and --format are required"),
+                }
+            },
+            Self::Exec {
+                hostname,
+                name,
+                profile,
+                command,
+            } => {
+                let profile = profile.map(|name| ProfileRef { name });
+                commands::exec::exec(ctx, &hostname, name.as_deref(), profile.as_ref(), &command)
+                    .await
+                    .context("Failed to run command")?;
+            },
+            Self::Askpass { prompt } => {
+                commands::exec::askpass(ctx, &prompt).context("Failed to answer prompt")?;
+            },
+            Self::Guard { remote, url } => {
+                commands::guard::guard(ctx, remote.as_deref(), url.as_deref())
+                    .await
+                    .context("Failed to verify push access")?;
+            },
+            Self::Handoff {
+                cli,
+                hostname,
+                name,
+                print_env,
+            } => {
+                commands::handoff::handoff(
+                    ctx,
+                    cli,
+                    hostname.as_deref(),
+                    name.as_deref(),
+                    print_env,
+                )
+                .await
+                .context("Failed to hand off token")?;
+            },
+            Self::Hosts {
+                command: HostsCommand::Add { host, credential },
+            } => {
+                commands::hosts::add(ctx, &host, &credential).context("Failed to add host")?;
+            },
+            Self::Hosts {
+                command: HostsCommand::Remove { host },
+            } => {
+                commands::hosts::remove(ctx, &host).context("Failed to remove host")?;
+            },
+            Self::Hosts {
+                command:
+                    HostsCommand::Rename {
+                        old,
+                        new,
+                        remotes,
+                        dry_run,
+                    },
+            } => {
+                commands::hosts::rename(ctx, &old, &new, remotes, dry_run)
+                    .context("Failed to rename host")?;
+            },
+            Self::Session {
+                command: SessionCommand::Readonly { host, name },
+            } => {
+                commands::session::readonly(ctx, &host, name.as_deref(), force_device)
+                    .await
+                    .context("Failed to start read-only session")?;
+            },
+            Self::Session {
+                command: SessionCommand::End { host },
+            } => {
+                commands::session::end(ctx, host.as_deref()).context("Failed to end session")?;
+            },
+            Self::Repo {
+                command:
+                    RepoCommand::Create {
+                        repo,
+                        private,
+                        name,
+                    },
+            } => {
+                commands::repo::create(ctx, &repo, private, name.as_deref())
+                    .await
+                    .context("Failed to create repository")?;
+            },
+            Self::Repo {
+                command: RepoCommand::Exists { repo, name },
+            } => {
+                commands::repo::exists(ctx, &repo, name.as_deref())
+                    .await
+                    .context("Failed to look up repository")?;
+            },
+            Self::Repo {
+                command: RepoCommand::Fork { repo, into, name },
+            } => {
+                commands::repo::fork(ctx, &repo, into.as_deref(), name.as_deref())
+                    .await
+                    .context("Failed to fork repository")?;
+            },
+            Self::Provider {
+                command: ProviderCommand::Lint,
+            } => {
+                commands::provider::lint().context("Failed to lint OAuth providers")?;
+            },
+            Self::Provider {
+                command:
+                    ProviderCommand::Secret {
+                        command: SecretCommand::Set { host },
+                    },
+            } => {
+                commands::provider::set_secret(&host)
+                    .context("Failed to store the client secret")?;
+            },
+            Self::Debug {
+                command: DebugCommand::HelperChain { url },
+            } => {
+                commands::debug::helper_chain(url.as_deref())
+                    .context("Failed to inspect credential helpers")?;
+            },
+            Self::Pattern {
+                command: PatternCommand::Test { input },
+            } => {
+                commands::pattern::test(ctx, &input).context("Failed to test pattern")?;
+            },
+            Self::Rule {
+                command: RuleCommand::Explain { url },
+            } => {
+                commands::rule::explain(ctx, url.as_deref()).context("Failed to explain rules")?;
+            },
+            Self::Completions {
+                shell,
+                install: true,
+            } => {
+                commands::completions::install(shell, &mut Cli::command())
+                    .context("Failed to install completions")?;
+            },
+            Self::Completions {
+                shell: Some(shell),
+                install: false,
+            } => {
+                commands::completions::completions(shell, &mut Cli::command());
             },
+            Self::Completions {
+                shell: None,
+                install: false,
+            } => unreachable!("clap requires a shell without --install"),
         }
         Ok(())
     }
@@ -0,0 +1,254 @@
+//! Coalescing of the credential requests of one Git operation.
+//!
+//! Git can ask for the same credential several times within seconds, e.g.
+//! when it probes the remote before pushing. With `coalesce_window` set, `get`
+//! keeps what it served in a file only the user can read, keyed by the Git
+//! process that asked and the request, and answers repeated requests from it
+//! until the window has passed, without another keyring lookup or prompt.
+//! The refresh token is never kept, erasing a token removes everything kept,
+//! and every run of warden removes what outlived [`MAX_WINDOW`].
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::{Context as _, Result};
+use tracing::{info, warn};
+use zeroize::Zeroizing;
+
+use crate::config::persist::write_atomic_private;
+use crate::keyring::index::fingerprint;
+use crate::utils::{CredentialRequest, state_dir};
+
+/// Directory in the state dir holding the served credentials
+const DIR: &str = "coalesce";
+/// Set by Git for the processes it runs when `trace2` is enabled
+const SESSION_VAR: &str = "GIT_TRACE2_PARENT_SID";
+/// Field of the served lines that is never kept
+const REFRESH_TOKEN_FIELD: &str = "oauth_refresh_token=";
+
+/// The longest `coalesce_window`, the repeated requests of one Git operation
+/// come within seconds
+pub const MAX_WINDOW: Duration = Duration::from_secs(60);
+
+/// Where the credential served for this request goes once `get` serves one
+static PENDING: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// The lines served for the same request of the same Git operation within
+/// `window`, unless a `fresh` credential is wanted. Without any, the lines
+/// `get` serves next are kept for repeated requests.
+pub fn begin(
+    window: Option<Duration>,
+    req: &CredentialRequest,
+    fresh: bool,
+) -> Option<Zeroizing<String>> {
+    begin_in(&state_dir().ok()?.join(DIR), window, req, fresh)
+}
+
+/// [`begin`] with the served credentials in `dir`
+fn begin_in(
+    dir: &Path,
+    window: Option<Duration>,
+    req: &CredentialRequest,
+    fresh: bool,
+) -> Option<Zeroizing<String>> {
+    let Some(window) = window else {
+        // nothing may linger once coalescing is turned off
+        prune(dir, Duration::ZERO);
+        return None;
+    };
+    prune(dir, window);
+    let path = dir.join(request_key(&session()?, req));
+    // a rejected token must not be served again
+    if !fresh
+        && req.bearer_error().is_none()
+        && let Some(lines) = cached(&path, window)
+    {
+        info!("Serving the credential of a request of the same Git operation");
+        return Some(lines);
+    }
+    *PENDING.lock().expect("Coalesce lock poisoned") = Some(path);
+    None
+}
+
+/// Keep the `lines` served to Git for repeated requests, if [`begin`] asked
+/// for it
+pub fn record(lines: &str) {
+    let Some(path) = PENDING.lock().expect("Coalesce lock poisoned").take() else {
+        return;
+    };
+    // coalescing must never break the helper
+    if let Err(err) = write_atomic_private(&path, &without_refresh_token(lines)) {
+        warn!("Failed to keep the served credential: {err:#}");
+    }
+}
+
+/// Remove the served credentials older than [`MAX_WINDOW`], whatever the
+/// configured window, so none outlives it even if `get` never runs again
+pub fn prune_expired() {
+    if let Ok(dir) = state_dir() {
+        prune(&dir.join(DIR), MAX_WINDOW);
+    }
+}
+
+/// Remove all served credentials, returning whether there were any
+pub fn clear() -> Result<bool> {
+    clear_in(&state_dir()?.join(DIR))
+}
+
+/// Remove all served credentials once a token is erased, so neither a token
+/// Git rejected nor one logged out is served again within the window
+pub fn forget() {
+    // coalescing must never break the helper
+    if let Err(err) = clear() {
+        warn!("Failed to remove the served credentials: {err:#}");
+    }
+}
+
+fn clear_in(dir: &Path) -> Result<bool> {
+    match fs::remove_dir_all(dir) {
+        Ok(()) => Ok(true),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(false),
+        Err(err) => Err(err).with_context(|| format!("Failed to remove {}", dir.display())),
+    }
+}
+
+/// `lines` without the long-lived refresh token, which repeated requests
+/// don't need
+fn without_refresh_token(lines: &str) -> Zeroizing<String> {
+    lines
+        .split_inclusive('\n')
+        .filter(|line| !line.starts_with(REFRESH_TOKEN_FIELD))
+        .fold(Zeroizing::new(String::new()), |mut kept, line| {
+            kept.push_str(line);
+            kept
+        })
+}
+
+/// The Git operation asking, Git's trace2 session or else the parent process
+#[cfg_attr(
+    unix,
+    expect(
+        clippy::unnecessary_wraps,
+        reason = "other platforms have no parent process id"
+    )
+)]
+fn session() -> Option<String> {
+    if let Some(sid) = std::env::var_os(SESSION_VAR) {
+        return Some(sid.to_string_lossy().into_owned());
+    }
+    #[cfg(unix)]
+    return Some(format!("pid {}", std::os::unix::process::parent_id()));
+    #[cfg(not(unix))]
+    None
+}
+
+/// File name of the credential served for `req` in `session`
+fn request_key(session: &str, req: &CredentialRequest) -> String {
+    fingerprint(&format!(
+        "{session}\n{}\n{}\n{}\n{}",
+        req.protocol,
+        req.host,
+        req.path.as_deref().unwrap_or_default(),
+        req.username.as_deref().unwrap_or_default()
+    ))
+}
+
+/// The content of `path` if it was written within `window`
+fn cached(path: &Path, window: Duration) -> Option<Zeroizing<String>> {
+    let age = fs::metadata(path).ok()?.modified().ok()?.elapsed().ok()?;
+    if age >= window {
+        return None;
+    }
+    fs::read_to_string(path).ok().map(Zeroizing::new)
+}
+
+/// Remove the credentials in `dir` older than `window`
+fn prune(dir: &Path, window: Duration) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let expired = entry
+            .metadata()
+            .and_then(|meta| meta.modified())
+            .ok()
+            .and_then(|modified| modified.elapsed().ok())
+            .is_none_or(|age| age >= window);
+        if expired {
+            let _ = fs::remove_file(entry.path());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(path: &str) -> CredentialRequest {
+        CredentialRequest {
+            protocol: "https".into(),
+            host: "github.com".into(),
+            path: Some(path.into()),
+            ..CredentialRequest::default()
+        }
+    }
+
+    #[test]
+    fn repeated_requests_of_a_session_are_coalesced() {
+        let dir = std::env::temp_dir().join(format!("warden-coalesce-{}", std::process::id()));
+        let key = request_key("sid", &request("org/repo.git"));
+        assert_ne!(
+            key,
+            request_key("other", &request("org/repo.git")),
+            "sessions"
+        );
+        assert_ne!(
+            key,
+            request_key("sid", &request("org/other.git")),
+            "requests"
+        );
+
+        let path = dir.join(key);
+        let window = Duration::from_secs(5);
+        assert!(cached(&path, window).is_none(), "nothing served yet");
+        write_atomic_private(&path, "username=oauth\npassword=x\n").unwrap();
+        assert_eq!(
+            without_refresh_token("username=oauth\npassword=x\noauth_refresh_token=r\n").as_str(),
+            "username=oauth\npassword=x\n",
+            "the refresh token is not kept"
+        );
+        assert_eq!(
+            cached(&path, window).as_deref().map(String::as_str),
+            Some("username=oauth\npassword=x\n")
+        );
+
+        prune(&dir, Duration::ZERO);
+        assert!(cached(&path, window).is_none(), "expired ones are removed");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn erased_credentials_are_not_served_again() {
+        let dir = std::env::temp_dir().join(format!("warden-forget-{}", std::process::id()));
+        let req = request("org/repo.git");
+        let window = Some(Duration::from_secs(30));
+
+        assert!(begin_in(&dir, window, &req, false).is_none(), "first get");
+        record("username=oauth\npassword=rejected\n");
+        assert!(
+            begin_in(&dir, window, &req, false).is_some(),
+            "repeated get is coalesced"
+        );
+
+        // what erasing the token does
+        assert!(clear_in(&dir).unwrap());
+        assert!(
+            begin_in(&dir, window, &req, false).is_none(),
+            "get after erase looks the token up again"
+        );
+        PENDING.lock().unwrap().take();
+        assert!(!clear_in(&dir).unwrap(), "nothing was recorded since");
+    }
+}
@@ -5,22 +5,39 @@
 // Local modifications:
 // Copyright (c) 2025 Adolar0042
 
+use std::path::Path;
+
 use anyhow::{Result, anyhow, bail};
 use colored::Colorize as _;
-use git2::Repository;
 use tracing::instrument;
 
-use crate::commands::common::styled_error;
+use crate::commands::common::{fetch_url, load_profiles, parse_repo_url, remote_url, styled_error};
 use crate::config::ProfileConfig;
-use crate::load_cfg;
+use crate::context::AppContext;
 use crate::profile::rule::ProfileRef;
-use crate::profile::url::{Patterns, Url as RepoUrl};
+use crate::profile::url::Url as RepoUrl;
+use crate::profile::{self, Scope};
 
 const INHERIT: &str = "(inherit)";
 
-#[instrument]
-pub fn apply(profile_name: Option<String>) -> Result<()> {
-    let profile_config = load_cfg!(ProfileConfig)?;
+/// What the rules choose a profile for
+#[derive(Clone, Copy, Debug)]
+pub enum Target<'a> {
+    /// A remote of the repository, `origin` or the only one if `None`
+    Remote(Option<&'a str>),
+    /// A URL, e.g. of a remote that doesn't exist yet
+    Url(&'a str),
+}
+
+#[instrument(skip(ctx))]
+pub fn apply(
+    ctx: &AppContext,
+    profile_name: Option<String>,
+    scope: Scope,
+    repo: Option<&Path>,
+    target: Target<'_>,
+) -> Result<()> {
+    let profile_config = load_profiles(ctx)?;
     if let Some(name) = profile_name {
         let profile_ref = ProfileRef { name };
         let profile = profile_config
@@ -28,64 +45,81 @@ pub fn apply(profile_name: Option<String>) -> Result<()> {
             .get(&profile_ref.name)
             .ok_or_else(|| anyhow!("Unknown profile: {}", profile_ref.name))?;
 
-        profile.apply()?;
+        profile.apply(&profile_ref.name, scope, repo)?;
 
         eprintln!("Attached profile {} successfully.", profile_ref.name.bold());
     } else {
-        let repo = Repository::open_from_env();
-        let Ok(repo) = repo else {
-            styled_error("Not a git repository!");
-            bail!("Not a git repository!");
+        let url = match target {
+            Target::Remote(name) => remote_url(&profile_config.patterns, repo, name)?,
+            Target::Url(url) => parse_repo_url(&fetch_url(url, repo), &profile_config.patterns)?,
         };
+        apply_matching(&profile_config, &url, scope, repo)?;
+    }
 
-        let remote = repo.find_remote("origin");
-        let Ok(remote) = remote else {
-            styled_error("No remote named 'origin' found");
-            bail!("No remote named 'origin' found");
-        };
-        let remote_url = remote.url().expect("No remote url");
-        let url: RepoUrl = match RepoUrl::from_str(remote_url, &profile_config.patterns, None) {
-            Ok(u) => u,
-            Err(_) => RepoUrl::from_str(remote_url, &Patterns::default(), None)?,
-        };
+    Ok(())
+}
 
-        let rule = profile_config.rules.resolve(&url);
-        match rule {
-            None => {
+/// Remove the managed files and includes of the profile applied to `scope`.
+/// Plain config keys stay, warden can't tell them apart from the user's.
+#[instrument]
+pub fn unapply(scope: Scope, repo: Option<&Path>) -> Result<()> {
+    profile::unapply(scope, repo)?;
+    eprintln!("Removed the managed files and includes of warden.");
+    Ok(())
+}
+
+/// Apply the profile the rules choose for `url` to the repository of `repo`,
+/// or of the current directory
+pub fn apply_matching(
+    profile_config: &ProfileConfig,
+    url: &RepoUrl,
+    scope: Scope,
+    repo: Option<&Path>,
+) -> Result<()> {
+    match profile_config.profile_for(url) {
+        None => {
+            styled_error(format!(
+                "No profile found for [{}].",
+                url.to_string().bold()
+            ));
+            bail!("No rule matched for remote {url}");
+        },
+        Some((profile_ref, fallback)) => {
+            if fallback {
+                eprintln!(
+                    "No rule matched [{}], using the default profile.",
+                    url.to_string().bold()
+                );
+            }
+            let Some(profile) = profile_config.profiles.resolve(&profile_ref) else {
                 styled_error(format!(
-                    "No profile found for [{}].",
+                    "Profile [{}] selected for [{}] does not exist.",
+                    profile_ref.name.bold(),
                     url.to_string().bold()
                 ));
-                bail!("No rule matched for remote {url}");
-            },
-            Some(rule) => {
-                let profile = profile_config
-                    .profiles
-                    .resolve(&rule.profile)
-                    .expect("No profile found");
-                profile.1.apply()?;
-                eprintln!("Attached profile {} successfully.", profile.0.bold());
-                println!(
-                    "  {}: {} {}",
-                    profile.0.bold(),
+                bail!("Unknown profile: {}", profile_ref.name);
+            };
+            profile.1.apply(profile.0, scope, repo)?;
+            eprintln!("Attached profile {} successfully.", profile.0.bold());
+            eprintln!(
+                "  {}: {} {}",
+                profile.0.bold(),
+                profile
+                    .1
+                    .configs
+                    .get("user.name")
+                    .map_or(INHERIT, |name| name.as_str()),
+                format!(
+                    "<{}>",
                     profile
                         .1
                         .configs
-                        .get("user.name")
-                        .map_or(INHERIT, |name| name.as_str()),
-                    format!(
-                        "<{}>",
-                        profile
-                            .1
-                            .configs
-                            .get("user.email")
-                            .map_or(INHERIT, |email| email.as_str()),
-                    )
-                    .dimmed(),
-                );
-            },
-        }
+                        .get("user.email")
+                        .map_or(INHERIT, |email| email.as_str()),
+                )
+                .dimmed(),
+            );
+        },
     }
-
     Ok(())
 }
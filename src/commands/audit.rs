@@ -0,0 +1,166 @@
+use std::fmt::Write as _;
+
+use anyhow::{Result, bail};
+use chrono::{TimeDelta, Utc};
+use colored::Colorize as _;
+use tracing::instrument;
+
+use crate::commands::common::{
+    collect_all_pairs, human_duration, sort_pairs, styled_error, styled_note,
+};
+use crate::config::Stats;
+use crate::context::AppContext;
+use crate::keyring::Token;
+use crate::load_cfg;
+
+/// Scopes granting more than reading and pushing repositories
+const BROAD_SCOPES: &[&str] = &[
+    // GitHub
+    "repo",
+    "workflow",
+    "write:org",
+    "admin:org",
+    "admin:repo_hook",
+    "admin:public_key",
+    "delete_repo",
+    // GitLab
+    "api",
+    "sudo",
+    "admin_mode",
+    // Forgejo and Gitea
+    "write:organization",
+    "write:user",
+    "write:admin",
+    "all",
+];
+
+/// List every token warden stores in the keyring with what is known about its
+/// scopes, lifetime and recent use, flagging the ones that never expire and
+/// carry broad scopes.
+#[instrument(skip(ctx))]
+pub fn secrets(ctx: &AppContext) -> Result<()> {
+    let hosts_config = ctx.hosts.load()?;
+    let mut pairs = collect_all_pairs(&hosts_config);
+    if pairs.is_empty() {
+        styled_error(format!(
+            "No credentials found. Add credentials by running {}.",
+            format!("{} login", env!("CARGO_PKG_NAME")).blue()
+        ));
+        bail!("No credentials found");
+    }
+    sort_pairs(&mut pairs);
+    let oauth_config = ctx.oauth.load().ok();
+    let display = oauth_config
+        .as_ref()
+        .and_then(|cfg| cfg.token_display)
+        .unwrap_or_default();
+    let stats = oauth_config
+        .as_ref()
+        .is_some_and(|cfg| cfg.stats.unwrap_or(false))
+        .then(|| load_cfg!(Stats).ok())
+        .flatten();
+
+    let mut high_risk = 0;
+    let mut current_host = None;
+    for pair in &pairs {
+        if current_host != Some(&pair.host) {
+            eprintln!("{}", pair.host.bold());
+            current_host = Some(&pair.host);
+        }
+        let active =
+            hosts_config.get_active_credential(&pair.host) == Some(pair.credential.as_str());
+        let marker = if active { " (active)" } else { "" };
+        let Ok(token) = ctx.token_store.get(&pair.credential, &pair.host) else {
+            eprintln!(
+                "  {}{marker}: {}",
+                pair.credential,
+                "no token in keyring".red()
+            );
+            continue;
+        };
+        eprintln!("  {}{marker}: {}", pair.credential, token.masked(display));
+
+        // providers omit the granted scopes when they match the requested ones
+        let scopes = token.scopes.clone().map(|s| (s, "granted")).or_else(|| {
+            let (_, provider) = oauth_config.as_ref()?.find_provider(&pair.host)?;
+            provider
+                .scopes
+                .requested()
+                .map(|s| (s.to_vec(), "configured"))
+        });
+        match &scopes {
+            Some((scopes, source)) if !scopes.is_empty() => {
+                eprintln!("    scopes: {} ({source})", scopes.join(", "));
+            },
+            Some(_) | None => eprintln!("    scopes: {}", "unknown".dimmed()),
+        }
+        eprintln!("    expires: {}", expiry(&token));
+        if token.refresh_token().is_some() {
+            let refresh = token.refresh_expires_in().map_or_else(
+                || "no reported expiry".to_string(),
+                |left| format!("expires in {}", human_duration(left)),
+            );
+            eprintln!("    refresh token: {refresh}");
+        }
+        if let Some(usage) = stats
+            .as_ref()
+            .and_then(|s| s.get(&pair.host, &pair.credential))
+        {
+            let mut used = format!("{} requests", usage.count);
+            if let Some(last) = usage.last_used {
+                let _ = write!(used, ", last {} ago", human_duration(Utc::now() - last));
+            }
+            if let Some(path) = &usage.last_path {
+                let _ = write!(used, " ({path})");
+            }
+            eprintln!("    used: {used}");
+        }
+
+        let broad: Vec<&str> = scopes
+            .iter()
+            .flat_map(|(scopes, _)| scopes)
+            .map(String::as_str)
+            .filter(|s| BROAD_SCOPES.contains(s))
+            .collect();
+        if token.expires_at.is_none() && !broad.is_empty() {
+            high_risk += 1;
+            eprintln!(
+                "    {} - never expires and grants {}",
+                "High risk".red().bold(),
+                broad.join(", ")
+            );
+        }
+    }
+
+    eprintln!();
+    eprintln!(
+        "{} tokens, {} high risk.",
+        pairs.len(),
+        if high_risk == 0 {
+            high_risk.to_string().green()
+        } else {
+            high_risk.to_string().red().bold()
+        }
+    );
+    if stats.is_none() {
+        styled_note(format!(
+            "Enable usage statistics with {} to see where tokens are used.",
+            "git config --global warden.stats true".blue()
+        ));
+    }
+    Ok(())
+}
+
+fn expiry(token: &Token) -> String {
+    token.expires_at.map_or_else(
+        || "never".to_string(),
+        |at| {
+            let left = at - Utc::now();
+            if left < TimeDelta::zero() {
+                format!("expired {} ago", human_duration(-left))
+            } else {
+                format!("in {}", human_duration(left))
+            }
+        },
+    )
+}
@@ -1,6 +1,22 @@
+use std::io::{self, IsTerminal as _};
+use std::path::Path;
+
+use anyhow::{Context as _, Result, bail};
+use chrono::{TimeDelta, Utc};
 use colored::Colorize as _;
+use dialoguer::Confirm;
+use git2::Repository;
 
-use crate::config::Hosts;
+use crate::config::{ConfirmPolicy, Hosts, OAuthConfig, ProfileConfig};
+use crate::context::AppContext;
+use crate::keyring::{STATE_HOST, Token};
+use crate::oauth::revoke_token;
+use crate::profile::rule::ProfileRef;
+use crate::profile::url::{Patterns, Url as RepoUrl};
+use crate::theme::InputTheme;
+use crate::utils::{
+    RepoContext, UrlUse, git_config, normalize_host, remote_urls, repo_context, rewrite_url,
+};
 
 /// Represents one credential associated with a host
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -32,14 +48,89 @@ pub fn collect_all_pairs(hosts: &Hosts) -> Vec<CredentialPair> {
     hosts
         .hosts()
         .flat_map(|(host, cfg)| {
-            cfg.credentials
-                .iter()
-                .cloned()
-                .map(move |credential| CredentialPair::new(host.to_string(), credential))
+            cfg.names()
+                .map(move |credential| CredentialPair::new(host, credential))
         })
         .collect()
 }
 
+/// The `confirm_destructive` setting, `tty` when it can't be read
+pub fn confirm_policy(ctx: &AppContext) -> ConfirmPolicy {
+    ctx.oauth
+        .load()
+        .ok()
+        .and_then(|cfg| cfg.confirm_destructive)
+        .unwrap_or_default()
+}
+
+/// Ask `prompt` before a destructive change as `confirm_destructive` says,
+/// failing unless the answer is yes
+pub fn confirm_destructive(ctx: &AppContext, prompt: &str) -> Result<()> {
+    let policy = confirm_policy(ctx);
+    if policy == ConfirmPolicy::Never {
+        return Ok(());
+    }
+    match (ctx.io.confirm(prompt)?, policy) {
+        (Some(true), _) | (None, ConfirmPolicy::Never | ConfirmPolicy::Tty) => Ok(()),
+        (Some(false), _) => {
+            eprintln!("Cancelled.");
+            bail!("Not confirmed");
+        },
+        (None, ConfirmPolicy::Always) => {
+            styled_error(format!(
+                "{} needs confirmation, but there is no terminal to ask on.",
+                "confirm_destructive = \"always\"".bold()
+            ));
+            bail!("Not confirmed");
+        },
+    }
+}
+
+/// Entries of the token store the hosts state doesn't know, e.g. left behind
+/// by editing `hosts.toml` by hand. Sorted, without warden's own state.
+pub fn orphaned_pairs(ctx: &AppContext, hosts: &Hosts) -> Result<Vec<CredentialPair>> {
+    let mut orphans: Vec<CredentialPair> = ctx
+        .token_store
+        .list()?
+        .into_iter()
+        .filter(|pair| {
+            pair.host != STATE_HOST && !hosts.has_credential(&pair.host, &pair.credential)
+        })
+        .collect();
+    sort_pairs(&mut orphans);
+    Ok(orphans)
+}
+
+/// Revoke the token of `pair` where its provider supports it and erase it,
+/// describing what happened, e.g. "revoked, erased"
+pub async fn revoke_and_erase(
+    ctx: &AppContext,
+    oauth_config: Option<&OAuthConfig>,
+    pair: &CredentialPair,
+) -> String {
+    let Ok(token) = ctx.token_store.get(&pair.credential, &pair.host) else {
+        return "no token in keyring".dimmed().to_string();
+    };
+    let provider = oauth_config
+        .and_then(|cfg| cfg.find_provider(&pair.host))
+        .map(|(_, provider)| provider);
+    let revoked = match provider {
+        Some(provider) => {
+            match revoke_token(provider, &token).await {
+                Ok(true) => "revoked".green(),
+                Ok(false) => "revocation not supported".yellow(),
+                Err(err) => format!("revocation failed ({err:#})").red(),
+            }
+        },
+        None => "no provider to revoke at".yellow(),
+    };
+    let erased = match ctx.token_store.erase(&pair.credential, &pair.host) {
+        Ok(()) => "erased".green(),
+        Err(err) => format!("erasing failed ({err:#})").red(),
+    };
+    format!("{revoked}, {erased}")
+}
+
 /// Sort pairs by (host ASC, credential ASC).
 pub fn sort_pairs(pairs: &mut [CredentialPair]) {
     pairs.sort_by(|a, b| {
@@ -52,26 +143,324 @@ pub fn sort_pairs(pairs: &mut [CredentialPair]) {
 /// Filter pairs by optional host and/or credential constraints.
 ///
 /// If both filters are `None`, returns the original slice cloned.
-/// If a filter removes all pairs, the returned vec is empty
+/// If a filter removes all pairs, the returned vec is empty.
+/// The host filter is normalized before comparing.
 pub fn filter_pairs<'a, T: IntoIterator<Item = &'a CredentialPair>>(
     pairs: T,
     host: Option<&str>,
     credential: Option<&str>,
 ) -> Vec<CredentialPair> {
+    let host = host.map(normalize_host);
     pairs
         .into_iter()
-        .filter(|p| host.is_none_or(|h| p.host == h))
+        .filter(|p| host.as_ref().is_none_or(|h| p.host == *h))
         .filter(|p| credential.is_none_or(|c| p.credential == c))
         .cloned()
         .collect()
 }
 
+/// Which credentials `status` shows. All given conditions must hold.
+#[derive(Debug, Clone, Default)]
+pub struct CredentialFilter {
+    /// Glob the host has to match, with `*` and `?` as wildcards
+    pub host: Option<String>,
+    /// Only credentials whose token has expired or whose ephemeral login has
+    /// ended
+    pub expired: bool,
+    /// Only credentials whose token expires or whose ephemeral login ends
+    /// within this time
+    pub expiring_in: Option<TimeDelta>,
+    /// Only credentials without a token in the keyring
+    pub missing_keyring: bool,
+}
+
+impl CredentialFilter {
+    /// Whether credentials are filtered by their token, not only by host
+    pub const fn checks_token(&self) -> bool {
+        self.expired || self.expiring_in.is_some() || self.missing_keyring
+    }
+
+    pub fn matches_host(&self, host: &str) -> bool {
+        self.host
+            .as_deref()
+            .is_none_or(|pattern| glob_match(&pattern.to_ascii_lowercase(), host))
+    }
+
+    /// Whether a credential of `host` with `token` in the keyring, if any,
+    /// is shown
+    pub fn matches(&self, host: &str, token: Option<&Token>) -> bool {
+        if !self.matches_host(host) {
+            return false;
+        }
+        let Some(token) = token else {
+            return !self.expired && self.expiring_in.is_none();
+        };
+        if self.missing_keyring
+            || (self.expired && !token.is_expired() && !token.is_past_deadline())
+        {
+            return false;
+        }
+        self.expiring_in.is_none_or(|within| {
+            token
+                .expiry_for_git()
+                .is_some_and(|ends| ends <= Utc::now() + within)
+        })
+    }
+}
+
+/// Whether `text` matches `pattern`, where `*` stands for any number of
+/// characters and `?` for exactly one
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // position of the last `*` and the text it was tried against
+    let mut star = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            },
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            },
+            Some(_) | None => {
+                let Some((star_p, star_t)) = star else {
+                    return false;
+                };
+                // let the last `*` swallow one more character
+                p = star_p + 1;
+                t = star_t + 1;
+                star = Some((star_p, t));
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+// Output discipline: what a command produces (credentials for Git, profile
+// TOML, pattern and rule reports, profile names) goes to stdout so it can be
+// piped. Everything addressed to the person at the terminal, i.e. progress,
+// prompts, notes, warnings and errors, goes to stderr through the helpers
+// below. Diagnostics use `tracing`, shown from `-v` (warn) to `-vvvv` (trace).
+
 /// Print a standardized styled error line.
 #[inline]
 pub fn styled_error<T: AsRef<str>>(msg: T) {
     eprintln!("  {} - {}", "Error".red().bold(), msg.as_ref());
 }
 
+/// Print a standardized styled warning line.
+#[inline]
+pub fn styled_warning<T: AsRef<str>>(msg: T) {
+    eprintln!("  {} - {}", "Warning".yellow().bold(), msg.as_ref());
+}
+
+/// Print a standardized styled note line.
+#[inline]
+pub fn styled_note<T: AsRef<str>>(msg: T) {
+    eprintln!("  {} - {}", "Note".blue().bold(), msg.as_ref());
+}
+
+/// Refresh tokens expiring within this window trigger a warning
+const REFRESH_EXPIRY_WARNING: TimeDelta = TimeDelta::days(3);
+
+/// Warn when the refresh token of `credential` on `host` has expired or is
+/// about to, so it can be renewed before a full login is forced.
+pub fn warn_refresh_expiry(token: &Token, credential: &str, host: &str) {
+    let Some(left) = token.refresh_expires_in() else {
+        return;
+    };
+    if left > REFRESH_EXPIRY_WARNING {
+        return;
+    }
+    let when = if left <= TimeDelta::zero() {
+        "has expired".to_string()
+    } else {
+        format!("expires in {}", human_duration(left))
+    };
+    styled_warning(format!(
+        "The refresh token of {} on {} {when}, run {} to renew it.",
+        credential.bold(),
+        host.bold(),
+        format!("{} refresh", env!("CARGO_PKG_NAME")).blue()
+    ));
+}
+
+/// Round `delta` down to whole days, hours or minutes
+pub fn human_duration(delta: TimeDelta) -> String {
+    let (n, unit) = if delta.num_days() > 0 {
+        (delta.num_days(), "day")
+    } else if delta.num_hours() > 0 {
+        (delta.num_hours(), "hour")
+    } else {
+        (delta.num_minutes().max(0), "minute")
+    };
+    let plural = if n == 1 { "" } else { "s" };
+    format!("{n} {unit}{plural}")
+}
+
+/// Refuse a token whose ephemeral login has ended: revoke it where the
+/// provider supports it and forget the credential.
+pub async fn check_deadline(ctx: &AppContext, pair: &CredentialPair, token: &Token) -> Result<()> {
+    if !token.is_past_deadline() {
+        return Ok(());
+    }
+    let label = pair.label_credential_host();
+    if let Ok(oauth_config) = ctx.oauth.load()
+        && let Some((_, provider)) = oauth_config.find_provider(&pair.host)
+        && let Err(err) = revoke_token(provider, token).await
+    {
+        styled_warning(format!("Failed to revoke the token of {label}: {err:#}"));
+    }
+    let mut hosts_config = ctx.hosts.load()?;
+    let _ = ctx.token_store.erase(&pair.credential, &pair.host);
+    if hosts_config.remove_credential(&pair.host, &pair.credential) {
+        ctx.hosts
+            .save(&hosts_config)
+            .context("Failed to remove credential from hosts state")?;
+    }
+    styled_error(format!(
+        "The ephemeral login of {} has ended, its token was erased.",
+        label.bold()
+    ));
+    bail!("Ephemeral credential {label} has ended");
+}
+
+/// The token of credential `name` on `host`, or of its active credential,
+/// refreshed and stored again if it has expired
+pub async fn fresh_token(
+    ctx: &AppContext,
+    host: &str,
+    name: Option<&str>,
+) -> Result<(CredentialPair, Token)> {
+    let host = ctx
+        .oauth
+        .load()
+        .map_or_else(|_| normalize_host(host), |cfg| cfg.canonical_host(host));
+    let hosts_config = ctx.hosts.load()?;
+    let credential = match name {
+        Some(name) => name,
+        None => {
+            hosts_config
+                .get_active_credential(&host)
+                .filter(|c| !c.is_empty())
+                .with_context(|| format!("No active credential for {host}"))?
+        },
+    };
+    let pair = CredentialPair::new(host.as_str(), credential);
+    let mut token = ctx
+        .token_store
+        .get(&pair.credential, &pair.host)
+        .with_context(|| format!("No token for {}", pair.label_credential_host()))?;
+    check_deadline(ctx, &pair, &token).await?;
+    if token.is_expired() {
+        let oauth_config = ctx.oauth.load()?;
+        let (_, provider) = oauth_config
+            .find_provider(&pair.host)
+            .with_context(|| format!("No OAuth provider configured for {}", pair.host))?;
+        token.access_token_checked(provider).await?;
+        ctx.token_store
+            .store(&pair.credential, &pair.host, &token)
+            .context("Failed to store refreshed token in keyring")?;
+    }
+    Ok((pair, token))
+}
+
+/// Load `profiles.toml` for a command that needs profiles. When the file
+/// doesn't exist yet, offer to create a commented starter file.
+pub fn load_profiles(ctx: &AppContext) -> Result<ProfileConfig> {
+    let path = ProfileConfig::path()?;
+    if !path.exists() {
+        let interactive = io::stdin().is_terminal() && io::stderr().is_terminal();
+        if !interactive {
+            styled_error(format!(
+                "No profiles found, {} does not exist.",
+                path.display()
+            ));
+            bail!("Missing {}", path.display());
+        }
+        let create = Confirm::with_theme(&InputTheme::default())
+            .with_prompt(format!(
+                "{} does not exist. Create a starter file?",
+                path.display()
+            ))
+            .default(true)
+            .interact_opt()
+            .context("Failed to confirm creating profiles.toml")?;
+        if !create.is_some_and(|b| b) {
+            bail!("Missing {}", path.display());
+        }
+        let path = ProfileConfig::scaffold()?;
+        eprintln!(
+            "Created {}, add your profiles there.",
+            path.display().to_string().bold()
+        );
+    }
+    ctx.profiles.load()
+}
+
+/// Parse a repository URL with the configured patterns, falling back to the
+/// built-in ones.
+pub fn parse_repo_url(input: &str, patterns: &Patterns) -> Result<RepoUrl> {
+    RepoUrl::from_str(input, patterns, None)
+        .or_else(|_| RepoUrl::from_str(input, &Patterns::default(), None))
+}
+
+/// Parse the remote `name` of the repository of `repo`, or of the current
+/// directory. Without a name that is `origin`, or the only remote of a
+/// repository without one.
+pub fn remote_url(patterns: &Patterns, repo: Option<&Path>, name: Option<&str>) -> Result<RepoUrl> {
+    let Ok(RepoContext { repo, .. }) = repo_context(repo) else {
+        styled_error("Not a git repository!");
+        bail!("Not a git repository!");
+    };
+    let remotes = repo.remotes().context("Failed to list the remotes")?;
+    let remotes: Vec<&str> = remotes.iter().flatten().collect();
+    let name = match (name, remotes.as_slice()) {
+        (Some(name), _) => name,
+        (None, _) if remotes.contains(&"origin") => "origin",
+        (None, [only]) => only,
+        (None, []) => {
+            styled_error("The repository has no remotes, pass the URL with --url");
+            bail!("No remotes found");
+        },
+        (None, _) => {
+            styled_error(format!(
+                "No remote named 'origin' found, pick one of {} with --remote",
+                remotes.join(", ")
+            ));
+            bail!("No remote named 'origin' found");
+        },
+    };
+    let Some(remote_url) = remote_urls(&repo, name, UrlUse::Fetch)?.into_iter().next() else {
+        styled_error(format!("No remote named '{name}' found"));
+        bail!("No remote named '{name}' found");
+    };
+    parse_repo_url(&remote_url, patterns)
+}
+
+/// `url` as Git would fetch from it, rewritten by the `url.<base>.insteadOf`
+/// settings of the repository of `repo` or the current directory, or the
+/// global ones outside a repository
+pub fn fetch_url(url: &str, repo: Option<&Path>) -> String {
+    git_config(repo).map_or_else(
+        |_| url.to_string(),
+        |cfg| rewrite_url(&cfg, url, UrlUse::Fetch),
+    )
+}
+
+/// The profile the rules choose for the `origin` remote of `repo`, with a
+/// flag telling whether `default_profile` was used. `None` without an
+/// origin remote.
+pub fn repo_profile(repo: &Repository, config: &ProfileConfig) -> Option<(ProfileRef, bool)> {
+    let remote = remote_urls(repo, "origin", UrlUse::Fetch).ok()?;
+    let url = parse_repo_url(remote.first()?, &config.patterns).ok()?;
+    config.profile_for(&url)
+}
+
 /// Turn a slice of `CredentialPair` into "credential (host)" labels
 pub fn labels_credential_host(pairs: &[CredentialPair]) -> Vec<String> {
     pairs
@@ -98,6 +487,7 @@ pub fn labels_host_active(pairs: &[CredentialPair], hosts: &Hosts) -> Vec<String
 #[cfg(test)]
 mod tests {
     use std::collections::HashMap;
+    use std::fs;
 
     use super::*;
     use crate::config::Hosts;
@@ -110,6 +500,7 @@ mod tests {
                 HostConfig {
                     active: "alice".into(),
                     credentials: vec!["alice".into(), "bob".into()],
+                    session_previous: None,
                 },
             ),
             (
@@ -117,11 +508,59 @@ mod tests {
                 HostConfig {
                     active: "carol".into(),
                     credentials: vec!["carol".into()],
+                    session_previous: None,
                 },
             ),
         ]))
     }
 
+    #[test]
+    fn globs_match_hosts() {
+        assert!(glob_match("*.example.com", "git.example.com"));
+        assert!(glob_match("git*", "github.com"));
+        assert!(glob_match(
+            "gitlab.example.com:????",
+            "gitlab.example.com:8443"
+        ));
+        assert!(glob_match("*", "github.com"));
+        assert!(!glob_match("*.example.com", "example.com"));
+        assert!(!glob_match("git?.com", "github.com"));
+    }
+
+    #[test]
+    fn credential_filters_check_the_token() {
+        let expired = Token::new("a".into(), None, Some(Utc::now() - TimeDelta::hours(1)));
+        let soon = Token::new("b".into(), None, Some(Utc::now() + TimeDelta::hours(2)));
+        let forever = Token::new("c".into(), None, None);
+
+        let filter = CredentialFilter {
+            expired: true,
+            ..CredentialFilter::default()
+        };
+        assert!(filter.matches("github.com", Some(&expired)), "expired");
+        assert!(!filter.matches("github.com", Some(&soon)), "still valid");
+        assert!(!filter.matches("github.com", None), "no token");
+
+        let filter = CredentialFilter {
+            host: Some("*.COM".into()),
+            expiring_in: Some(TimeDelta::hours(24)),
+            ..CredentialFilter::default()
+        };
+        assert!(filter.matches("github.com", Some(&soon)), "within a day");
+        assert!(
+            !filter.matches("github.com", Some(&forever)),
+            "never expires"
+        );
+        assert!(!filter.matches("codeberg.org", Some(&soon)), "other host");
+
+        let filter = CredentialFilter {
+            missing_keyring: true,
+            ..CredentialFilter::default()
+        };
+        assert!(filter.matches("github.com", None), "missing");
+        assert!(!filter.matches("github.com", Some(&forever)), "stored");
+    }
+
     #[test]
     fn test_collect_all_pairs() {
         let h = hosts_fixture();
@@ -167,6 +606,13 @@ mod tests {
         assert_eq!(filtered, vec![CredentialPair::new("github.com", "bob")]);
     }
 
+    #[test]
+    fn test_human_duration() {
+        assert_eq!(human_duration(TimeDelta::hours(50)), "2 days");
+        assert_eq!(human_duration(TimeDelta::minutes(61)), "1 hour");
+        assert_eq!(human_duration(TimeDelta::seconds(30)), "0 minutes");
+    }
+
     #[test]
     fn test_labels() {
         let h = hosts_fixture();
@@ -175,4 +621,25 @@ mod tests {
         let labels = labels_credential_host(&pairs);
         assert!(labels.iter().any(|l| l == "alice (github.com)"));
     }
+
+    #[test]
+    fn remote_url_falls_back_to_the_only_remote() {
+        let dir = std::env::temp_dir().join(format!("warden-remotes-{}", std::process::id()));
+        let repo = Repository::init(&dir).unwrap();
+        let patterns = Patterns::default();
+        let owner =
+            |name: Option<&str>| remote_url(&patterns, Some(&dir), name).map(|url| url.owner);
+        owner(None).unwrap_err();
+
+        repo.remote("upstream", "git@github.com:upstream/app.git")
+            .unwrap();
+        assert_eq!(owner(None).unwrap(), "upstream", "only remote");
+        repo.remote("fork", "git@github.com:alice/app.git").unwrap();
+        owner(None).unwrap_err();
+        assert_eq!(owner(Some("fork")).unwrap(), "alice");
+        repo.remote("origin", "git@github.com:org/app.git").unwrap();
+        assert_eq!(owner(None).unwrap(), "org", "origin first");
+        owner(Some("missing")).unwrap_err();
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
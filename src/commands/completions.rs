@@ -0,0 +1,337 @@
+use std::env;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context as _, Result};
+use clap::{Arg, ArgAction, ValueEnum};
+use clap_complete::{Shell, generate};
+use colored::Colorize as _;
+use tracing::instrument;
+
+use crate::commands::common::styled_note;
+use crate::config::persist::write_atomic;
+
+/// Shells completions can be generated for
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum CompletionShell {
+    Bash,
+    Elvish,
+    Fish,
+    #[value(name = "powershell")]
+    PowerShell,
+    Zsh,
+    Nushell,
+}
+
+/// Where completions for a shell are installed and how the shell finds them
+#[derive(Debug, PartialEq, Eq)]
+struct Target {
+    path: PathBuf,
+    /// What to add to the shell's configuration, `None` if the shell picks
+    /// the file up by itself
+    setup: Option<String>,
+}
+
+/// Print completions for `shell` to stdout.
+#[instrument(skip(cmd))]
+pub fn completions(shell: CompletionShell, cmd: &mut clap::Command) {
+    print!("{}", String::from_utf8_lossy(&render(shell, cmd)));
+}
+
+/// Write completions for `shell`, or the shell of `$SHELL`, to the directory
+/// the shell loads completions from, and report what is left to set up.
+#[instrument(skip(cmd))]
+pub fn install(shell: Option<CompletionShell>, cmd: &mut clap::Command) -> Result<()> {
+    let shell = match shell {
+        Some(shell) => shell,
+        None => {
+            env::var("SHELL")
+                .ok()
+                .and_then(|path| detect(&path))
+                .context("Failed to detect your shell from $SHELL, pass it as an argument")?
+        },
+    };
+    let home = dirs::home_dir().context("Failed to get home directory")?;
+    let xdg = |var: &str, default: &str| {
+        env::var_os(var)
+            .map(PathBuf::from)
+            .filter(|dir| dir.is_absolute())
+            .unwrap_or_else(|| home.join(default))
+    };
+    let target = target(
+        shell,
+        &home,
+        &xdg("XDG_CONFIG_HOME", ".config"),
+        &xdg("XDG_DATA_HOME", ".local/share"),
+    );
+    let script = String::from_utf8_lossy(&render(shell, cmd)).into_owned();
+    write_atomic(&target.path, &script)?;
+    eprintln!(
+        "Installed {} completions to {}.",
+        shell
+            .to_possible_value()
+            .map_or_default(|v| v.get_name().to_string()),
+        target.path.display().to_string().bold()
+    );
+    match target.setup {
+        Some(setup) => {
+            styled_note(format!(
+                "Add this to your shell configuration and start a new shell:\n    {}",
+                setup.blue()
+            ));
+        },
+        None => styled_note("Start a new shell to use them."),
+    }
+    Ok(())
+}
+
+/// The completion script for `shell`
+fn render(shell: CompletionShell, cmd: &mut clap::Command) -> Vec<u8> {
+    let name = env!("CARGO_PKG_NAME");
+    let shell = match shell {
+        CompletionShell::Bash => Shell::Bash,
+        CompletionShell::Elvish => Shell::Elvish,
+        CompletionShell::Fish => Shell::Fish,
+        CompletionShell::PowerShell => Shell::PowerShell,
+        CompletionShell::Zsh => Shell::Zsh,
+        CompletionShell::Nushell => {
+            cmd.set_bin_name(name);
+            cmd.build();
+            return nushell(cmd).into_bytes();
+        },
+    };
+    let mut script = Vec::new();
+    generate(shell, cmd, name, &mut script);
+    script
+}
+
+/// The shell `path`, e.g. `$SHELL`, points to
+fn detect(path: &str) -> Option<CompletionShell> {
+    let name = Path::new(path).file_stem()?.to_str()?;
+    match name {
+        "bash" => Some(CompletionShell::Bash),
+        "elvish" => Some(CompletionShell::Elvish),
+        "fish" => Some(CompletionShell::Fish),
+        "pwsh" | "powershell" => Some(CompletionShell::PowerShell),
+        "zsh" => Some(CompletionShell::Zsh),
+        "nu" => Some(CompletionShell::Nushell),
+        _ => None,
+    }
+}
+
+/// Where completions for `shell` go, given the home, XDG config and XDG data
+/// directories
+fn target(shell: CompletionShell, home: &Path, config: &Path, data: &Path) -> Target {
+    let name = env!("CARGO_PKG_NAME");
+    match shell {
+        // bash-completion loads completions of a command on first use
+        CompletionShell::Bash => {
+            Target {
+                path: data.join("bash-completion/completions").join(name),
+                setup: None,
+            }
+        },
+        CompletionShell::Fish => {
+            Target {
+                path: config.join("fish/completions").join(format!("{name}.fish")),
+                setup: None,
+            }
+        },
+        // $fpath isn't exported, so the directory has to be added to it
+        CompletionShell::Zsh => {
+            Target {
+                path: home.join(".zfunc").join(format!("_{name}")),
+                setup: Some("fpath=(~/.zfunc $fpath) # before compinit".into()),
+            }
+        },
+        CompletionShell::Elvish => {
+            Target {
+                path: config.join("elvish/lib").join(format!("{name}.elv")),
+                setup: Some(format!("use {name}")),
+            }
+        },
+        CompletionShell::Nushell => {
+            let path = config
+                .join("nushell")
+                .join(format!("{name}-completions.nu"));
+            Target {
+                setup: Some(format!("use {} *", path.display())),
+                path,
+            }
+        },
+        CompletionShell::PowerShell => {
+            let path = config.join("powershell").join(format!("{name}.ps1"));
+            Target {
+                setup: Some(format!(". {}", path.display())),
+                path,
+            }
+        },
+    }
+}
+
+/// Nushell `extern` definitions for `cmd` and all of its visible subcommands,
+/// wrapped in a module to `use` from config.nu.
+///
+/// `cmd` has to be built, so global arguments are propagated.
+fn nushell(cmd: &clap::Command) -> String {
+    let mut out = String::from("module completions {\n");
+    nushell_command(&mut out, cmd, cmd.get_name());
+    out.push_str("}\n\nexport use completions *\n");
+    out
+}
+
+fn nushell_command(out: &mut String, cmd: &clap::Command, path: &str) {
+    // value completers have to be defined before the extern using them
+    for arg in visible_args(cmd) {
+        if let Some(values) = possible_values(arg) {
+            let _ = writeln!(
+                out,
+                "  def \"nu-complete {path} {}\" [] {{\n    [{}]\n  }}\n",
+                arg.get_id(),
+                values
+                    .iter()
+                    .map(|v| format!("\"{v}\""))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            );
+        }
+    }
+
+    if let Some(about) = cmd.get_about() {
+        let _ = writeln!(out, "  # {}", first_line(&about.to_string()));
+    }
+    let _ = writeln!(out, "  export extern \"{path}\" [");
+    for arg in visible_args(cmd) {
+        let mut param = String::new();
+        let takes_value = arg.get_action().takes_values();
+        if arg.is_positional() {
+            let many = arg.get_num_args().is_some_and(|n| n.max_values() > 1);
+            if many {
+                param.push_str("...");
+            }
+            param.push_str(arg.get_id().as_str());
+            if !arg.is_required_set() && !many {
+                param.push('?');
+            }
+        } else if let Some(long) = arg.get_long() {
+            let _ = write!(param, "--{long}");
+            if let Some(short) = arg.get_short() {
+                let _ = write!(param, "(-{short})");
+            }
+        } else if let Some(short) = arg.get_short() {
+            let _ = write!(param, "-{short}");
+        } else {
+            continue;
+        }
+        if takes_value {
+            param.push_str(": string");
+            if possible_values(arg).is_some() {
+                let _ = write!(param, "@\"nu-complete {path} {}\"", arg.get_id());
+            }
+        }
+        match arg.get_help() {
+            Some(help) => {
+                let _ = writeln!(out, "    {param}  # {}", first_line(&help.to_string()));
+            },
+            None => {
+                let _ = writeln!(out, "    {param}");
+            },
+        }
+    }
+    out.push_str("  ]\n\n");
+
+    // `help` repeats the whole command tree, completing its first level is enough
+    if cmd.get_name() == "help" {
+        return;
+    }
+    for sub in cmd.get_subcommands().filter(|sub| !sub.is_hide_set()) {
+        nushell_command(out, sub, &format!("{path} {}", sub.get_name()));
+    }
+}
+
+fn visible_args(cmd: &clap::Command) -> impl Iterator<Item = &Arg> {
+    cmd.get_arguments().filter(|arg| !arg.is_hide_set())
+}
+
+/// The values `arg` accepts, if it is limited to a fixed set
+fn possible_values(arg: &Arg) -> Option<Vec<String>> {
+    if !arg.get_action().takes_values() || matches!(arg.get_action(), ArgAction::Help) {
+        return None;
+    }
+    let values: Vec<String> = arg
+        .get_possible_values()
+        .iter()
+        .filter(|v| !v.is_hide_set())
+        .map(|v| v.get_name().to_string())
+        .collect();
+    (!values.is_empty()).then_some(values)
+}
+
+fn first_line(s: &str) -> &str {
+    s.lines().next().unwrap_or_default().trim()
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::CommandFactory as _;
+
+    use super::*;
+    use crate::cli::Cli;
+
+    #[test]
+    fn completions_are_installed_where_shells_look() {
+        assert_eq!(detect("/usr/bin/zsh"), Some(CompletionShell::Zsh));
+        assert_eq!(
+            detect("/opt/bin/pwsh.exe"),
+            Some(CompletionShell::PowerShell)
+        );
+        assert_eq!(detect("/bin/sh"), None);
+
+        let (home, config, data) = (
+            Path::new("/home/a"),
+            Path::new("/home/a/.config"),
+            Path::new("/home/a/.local/share"),
+        );
+        let bash = target(CompletionShell::Bash, home, config, data);
+        assert_eq!(
+            bash.path,
+            Path::new("/home/a/.local/share/bash-completion/completions/warden")
+        );
+        assert_eq!(bash.setup, None, "loaded by bash-completion");
+        assert_eq!(
+            target(CompletionShell::Fish, home, config, data).path,
+            Path::new("/home/a/.config/fish/completions/warden.fish")
+        );
+        let zsh = target(CompletionShell::Zsh, home, config, data);
+        assert_eq!(zsh.path, Path::new("/home/a/.zfunc/_warden"));
+        assert!(zsh.setup.unwrap().contains("fpath"), "fpath extended");
+    }
+
+    #[test]
+    fn nushell_externs_cover_subcommands_and_values() {
+        let mut cmd = Cli::command();
+        cmd.set_bin_name("warden");
+        cmd.build();
+        let script = nushell(&cmd);
+        assert!(
+            script.contains("  export extern \"warden list\" [\n"),
+            "subcommands should get an extern"
+        );
+        assert!(
+            script.contains("    --short(-s)  # Shows only the name of the profiles\n"),
+            "flags should keep their help"
+        );
+        assert!(
+            script.contains("--output(-o): string@\"nu-complete warden status output\""),
+            "value enums should be completed"
+        );
+        assert!(
+            script.contains("\"json\" \"nuon\""),
+            "completers should list the values"
+        );
+        assert!(
+            !script.contains("\"warden get\""),
+            "hidden subcommands should be skipped"
+        );
+    }
+}
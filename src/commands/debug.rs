@@ -0,0 +1,321 @@
+use std::path::Path;
+
+use anyhow::{Context as _, Result, bail};
+use colored::Colorize as _;
+use git2::{Config, Repository};
+use tracing::instrument;
+
+use crate::commands::common::{styled_error, styled_note, styled_warning};
+use crate::utils::{UrlUse, remote_urls, repo_context};
+
+/// A `credential.*` setting from one of git's config files
+#[derive(Debug, Clone)]
+struct Entry {
+    /// Config file level, e.g. `global`
+    level: String,
+    /// URL of a `credential.<url>.*` section
+    context: Option<String>,
+    /// The key after the section, e.g. `helper`
+    key: String,
+    value: String,
+}
+
+/// A helper git asks for credentials
+#[derive(Debug, Clone)]
+struct Helper {
+    value: String,
+    level: String,
+    context: Option<String>,
+}
+
+/// Helpers dropped by an empty `helper` value
+#[derive(Debug)]
+struct Reset {
+    level: String,
+    dropped: Vec<Helper>,
+}
+
+impl Helper {
+    /// Name of the helper program: `warden` for `warden`, `!warden` and
+    /// `/usr/bin/git-credential-warden`
+    fn program(&self) -> String {
+        let value = self.value.trim();
+        let command = value.strip_prefix('!').unwrap_or(value);
+        let program = command.split_whitespace().next().unwrap_or_default();
+        let program = Path::new(program)
+            .file_name()
+            .map_or_else(|| program.to_string(), |n| n.to_string_lossy().to_string());
+        program
+            .strip_prefix("git-credential-")
+            .unwrap_or(&program)
+            .trim_end_matches(".exe")
+            .to_string()
+    }
+
+    fn is_warden(&self) -> bool {
+        self.program().contains(env!("CARGO_PKG_NAME"))
+    }
+
+    /// Whether this is the Git Credential Manager
+    fn is_gcm(&self) -> bool {
+        matches!(self.program().as_str(), "manager" | "manager-core")
+    }
+
+    fn origin(&self) -> String {
+        self.context.as_ref().map_or_else(
+            || self.level.clone(),
+            |context| format!("{}, {context}", self.level),
+        )
+    }
+}
+
+/// List the `credential.*` settings git sees, the helpers it asks for `url`
+/// (the `origin` remote by default) in order, and what may keep warden from
+/// being asked.
+#[instrument]
+pub fn helper_chain(url: Option<&str>) -> Result<()> {
+    let repo = repo_context(None).ok().map(|ctx| ctx.repo);
+    let url = url.map(str::to_string).or_else(|| {
+        remote_urls(repo.as_ref()?, "origin", UrlUse::Fetch)
+            .ok()?
+            .into_iter()
+            .next()
+    });
+    let cfg = repo
+        .as_ref()
+        .map_or_else(Config::open_default, Repository::config)
+        .and_then(|mut cfg| cfg.snapshot())
+        .context("Failed to open git config")?;
+    let entries = credential_entries(&cfg)?;
+
+    println!("{} (lowest priority first):", "Credential settings".bold());
+    if entries.is_empty() {
+        println!("  {}", "none".dimmed());
+    }
+    for entry in &entries {
+        let key = entry.context.as_ref().map_or_else(
+            || format!("credential.{}", entry.key),
+            |context| format!("credential.{context}.{}", entry.key),
+        );
+        println!("  {:<9}{key} = {}", entry.level.dimmed(), entry.value);
+    }
+
+    let parsed = url.as_deref().and_then(|u| url::Url::parse(u).ok());
+    let (helpers, resets) = chain(&entries, parsed.as_ref());
+    println!();
+    println!(
+        "{} for {}, asked in this order:",
+        "Helpers".bold(),
+        url.as_deref().unwrap_or("URLs without a matching section")
+    );
+    for (index, helper) in helpers.iter().enumerate() {
+        let value = if helper.is_warden() {
+            helper.value.green().bold().to_string()
+        } else {
+            helper.value.clone()
+        };
+        println!(
+            "  {}. {value} {}",
+            index + 1,
+            format!("({})", helper.origin()).dimmed()
+        );
+    }
+    if helpers.is_empty() {
+        println!("  {}", "none".dimmed());
+    }
+
+    for reset in &resets {
+        let dropped: Vec<_> = reset.dropped.iter().map(|h| h.value.as_str()).collect();
+        let message = format!(
+            "An empty helper in the {} config drops the helpers set before it: {}",
+            reset.level,
+            dropped.join(", ")
+        );
+        if reset.dropped.iter().any(Helper::is_warden) {
+            styled_warning(message);
+        } else {
+            styled_note(message);
+        }
+    }
+    let Some(position) = helpers.iter().position(Helper::is_warden) else {
+        styled_error(format!(
+            "warden isn't a credential helper here, add it with {}",
+            "git config --global credential.helper warden".blue()
+        ));
+        bail!("warden is not in the credential helper chain");
+    };
+    for (index, helper) in helpers.iter().enumerate() {
+        if helper.is_warden() {
+            if index != position {
+                styled_note(format!(
+                    "warden is configured again ({}), git asks it twice.",
+                    helper.origin()
+                ));
+            }
+        } else if helper.is_gcm() {
+            styled_warning(format!(
+                "Git Credential Manager is configured too ({}). It {} and stores the tokens \
+                 warden returns.",
+                helper.origin(),
+                if index < position {
+                    "is asked before warden"
+                } else {
+                    "is asked when warden has no credential"
+                }
+            ));
+        } else if index < position {
+            styled_warning(format!(
+                "{} ({}) is asked before warden, git uses its credentials when it has any.",
+                helper.value,
+                helper.origin()
+            ));
+        } else {
+            styled_note(format!(
+                "{} ({}) is asked after warden and gets warden's tokens when git stores them.",
+                helper.value,
+                helper.origin()
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// The `credential.*` entries of `cfg`, lowest priority first like git reads
+/// them
+fn credential_entries(cfg: &Config) -> Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    let mut iter = cfg
+        .entries(Some(r"^credential\..*"))
+        .context("Failed to read git config")?;
+    while let Some(entry) = iter.next() {
+        let entry = entry.context("Failed to read git config entry")?;
+        let (Some(name), Some(value)) = (entry.name(), entry.value()) else {
+            continue;
+        };
+        let Some(rest) = name.strip_prefix("credential.") else {
+            continue;
+        };
+        // the URL may contain dots, the key never does
+        let (context, key) = rest
+            .rsplit_once('.')
+            .map_or((None, rest), |(context, key)| (Some(context), key));
+        entries.push(Entry {
+            level: format!("{:?}", entry.level()).to_lowercase(),
+            context: context.map(str::to_string),
+            key: key.to_lowercase(),
+            value: value.to_string(),
+        });
+    }
+    Ok(entries)
+}
+
+/// The helpers git asks for `url`, in order, and the resets on the way: each
+/// `helper` value of a section matching `url` is appended, an empty one
+/// clears the list
+fn chain(entries: &[Entry], url: Option<&url::Url>) -> (Vec<Helper>, Vec<Reset>) {
+    let mut helpers: Vec<Helper> = Vec::new();
+    let mut resets = Vec::new();
+    for entry in entries.iter().filter(|e| e.key == "helper") {
+        let applies = match (&entry.context, url) {
+            (None, _) => true,
+            (Some(context), Some(url)) => url_matches(context, url),
+            (Some(_), None) => false,
+        };
+        if !applies {
+            continue;
+        }
+        if entry.value.trim().is_empty() {
+            if !helpers.is_empty() {
+                resets.push(Reset {
+                    level: entry.level.clone(),
+                    dropped: std::mem::take(&mut helpers),
+                });
+            }
+        } else {
+            helpers.push(Helper {
+                value: entry.value.clone(),
+                level: entry.level.clone(),
+                context: entry.context.clone(),
+            });
+        }
+    }
+    (helpers, resets)
+}
+
+/// Whether the `credential.<context>` section applies to `url`, following
+/// git's URL matching: same scheme, host (`*` matches one label), port and
+/// user if given, and a path prefix
+fn url_matches(context: &str, url: &url::Url) -> bool {
+    let Ok(pattern) = url::Url::parse(context) else {
+        return false;
+    };
+    let hosts_match = match (pattern.host_str(), url.host_str()) {
+        (Some(pattern), Some(host)) => {
+            let labels: Vec<_> = pattern.split('.').collect();
+            let host_labels: Vec<_> = host.split('.').collect();
+            labels.len() == host_labels.len()
+                && labels
+                    .iter()
+                    .zip(&host_labels)
+                    .all(|(p, h)| *p == "*" || p.eq_ignore_ascii_case(h))
+        },
+        (None, None) => true,
+        (Some(_) | None, _) => false,
+    };
+    let path = pattern.path().trim_end_matches('/');
+    pattern.scheme() == url.scheme()
+        && hosts_match
+        && pattern.port_or_known_default() == url.port_or_known_default()
+        && (pattern.username().is_empty() || pattern.username() == url.username())
+        && (path.is_empty() || url.path() == path || url.path().starts_with(&format!("{path}/")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(level: &str, context: Option<&str>, value: &str) -> Entry {
+        Entry {
+            level: level.to_string(),
+            context: context.map(str::to_string),
+            key: "helper".to_string(),
+            value: value.to_string(),
+        }
+    }
+
+    #[test]
+    fn chain_follows_git() {
+        let entries = [
+            entry("system", None, "manager"),
+            entry("global", None, ""),
+            entry("global", None, "!/home/u/.cargo/bin/warden"),
+            entry("global", Some("https://*.example.com"), "store"),
+            entry("local", Some("https://gitlab.com"), "cache"),
+        ];
+        let url = url::Url::parse("https://git.example.com/o/r.git").unwrap();
+        let (helpers, resets) = chain(&entries, Some(&url));
+        let values: Vec<_> = helpers.iter().map(|h| h.value.as_str()).collect();
+        assert_eq!(
+            values,
+            ["!/home/u/.cargo/bin/warden", "store"],
+            "reset drops GCM and other hosts don't apply"
+        );
+        assert!(helpers[0].is_warden(), "shell commands are recognized");
+        assert!(
+            resets.len() == 1 && resets[0].dropped[0].is_gcm(),
+            "the reset drops GCM"
+        );
+    }
+
+    #[test]
+    fn urls_match_like_git() {
+        let url = url::Url::parse("https://user@github.com/owner/repo.git").unwrap();
+        assert!(url_matches("https://github.com", &url), "host");
+        assert!(url_matches("https://github.com/owner", &url), "path prefix");
+        assert!(url_matches("https://user@github.com/", &url), "user");
+        assert!(!url_matches("https://github.com/own", &url), "partial path");
+        assert!(!url_matches("http://github.com", &url), "scheme");
+        assert!(!url_matches("https://*.github.com", &url), "label count");
+        assert!(!url_matches("https://other@github.com", &url), "other user");
+    }
+}
@@ -0,0 +1,166 @@
+use std::collections::HashMap;
+use std::io::{Read as _, stdin};
+
+use anyhow::{Context as _, Result, bail};
+use clap::Subcommand;
+use serde::Serialize;
+use tracing::{info, instrument};
+
+use crate::commands::common::fresh_token;
+use crate::context::AppContext;
+use crate::utils::normalize_host;
+
+/// Registries that accept the tokens of a forge, by registry host
+const REGISTRIES: &[(&str, &str)] = &[
+    ("ghcr.io", "github.com"),
+    ("docker.pkg.github.com", "github.com"),
+    ("registry.gitlab.com", "gitlab.com"),
+];
+/// Username sent along with the token, GitLab requires it for OAuth tokens
+/// and GitHub accepts any
+const USERNAME: &str = "oauth2";
+/// Reply Docker expects on stdout when there are no credentials
+const NOT_FOUND: &str = "credentials not found in native keychain";
+
+/// Actions of the Docker credential helper protocol
+#[derive(Subcommand, Debug, Clone, Copy)]
+pub enum DockerCredentialAction {
+    /// Print the credentials for the registry read from stdin
+    Get,
+    /// Accept credentials from `docker login`, which are not stored
+    Store,
+    /// Accept an erase request, warden tokens are erased with `logout`
+    Erase,
+    /// List the registries credentials are available for
+    List,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct Credentials<'a> {
+    #[serde(rename = "ServerURL")]
+    server_url: &'a str,
+    username: &'a str,
+    secret: &'a str,
+}
+
+/// Answer a Docker credential helper request with the token of the forge the
+/// registry belongs to.
+#[instrument(skip(ctx))]
+pub async fn docker_credential(ctx: &AppContext, action: DockerCredentialAction) -> Result<()> {
+    let oauth_config = ctx.oauth.load().ok();
+    let registries = oauth_config
+        .as_ref()
+        .map(|cfg| &cfg.registries)
+        .cloned()
+        .unwrap_or_default();
+    match action {
+        DockerCredentialAction::Get => {
+            let mut server_url = String::new();
+            stdin()
+                .read_to_string(&mut server_url)
+                .context("Failed to read the registry from stdin")?;
+            let server_url = server_url.trim();
+            let Some(host) = forge_host(server_url, &registries) else {
+                println!("{NOT_FOUND}");
+                bail!("No forge known for registry {server_url}");
+            };
+            let (_, token) = match fresh_token(ctx, &host, None).await {
+                Ok(found) => found,
+                Err(err) => {
+                    println!("{NOT_FOUND}");
+                    return Err(err);
+                },
+            };
+            let credentials = Credentials {
+                server_url,
+                username: USERNAME,
+                secret: token.access_token(),
+            };
+            println!(
+                "{}",
+                serde_json::to_string(&credentials).context("Failed to serialize credentials")?
+            );
+        },
+        DockerCredentialAction::Store | DockerCredentialAction::Erase => {
+            // drain the request so Docker doesn't see a broken pipe
+            let mut request = String::new();
+            let _ = stdin().read_to_string(&mut request);
+            info!("Ignoring {action:?}, registry credentials come from the forge tokens");
+        },
+        DockerCredentialAction::List => {
+            let hosts_config = ctx.hosts.load()?;
+            let listed: HashMap<&str, &str> =
+                REGISTRIES
+                    .iter()
+                    .copied()
+                    .chain(registries.iter().map(|(r, h)| (r.as_str(), h.as_str())))
+                    .filter(|(_, host)| {
+                        hosts_config
+                            .get_active_credential(&oauth_config.as_ref().map_or_else(
+                                || normalize_host(host),
+                                |cfg| cfg.canonical_host(host),
+                            ))
+                            .is_some_and(|c| !c.is_empty())
+                    })
+                    .map(|(registry, _)| (registry, USERNAME))
+                    .collect();
+            println!(
+                "{}",
+                serde_json::to_string(&listed).context("Failed to serialize registries")?
+            );
+        },
+    }
+    Ok(())
+}
+
+/// The forge host whose tokens `server_url` accepts: configured in
+/// `registries`, a well-known registry, or the host without a `registry.`
+/// prefix
+fn forge_host(server_url: &str, registries: &HashMap<String, String>) -> Option<String> {
+    let registry = server_url
+        .split_once("://")
+        .map_or(server_url, |(_, rest)| rest)
+        .split('/')
+        .next()
+        .filter(|r| !r.is_empty())?
+        .to_lowercase();
+    if let Some((_, host)) = registries
+        .iter()
+        .find(|(r, _)| r.to_lowercase() == registry)
+    {
+        return Some(host.clone());
+    }
+    if let Some((_, host)) = REGISTRIES.iter().find(|(r, _)| *r == registry) {
+        return Some((*host).to_string());
+    }
+    registry.strip_prefix("registry.").map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registries_map_to_forges() {
+        let configured = HashMap::from([("cr.example.com".into(), "git.example.com".into())]);
+        let host = |url| forge_host(url, &configured);
+        assert_eq!(host("ghcr.io").as_deref(), Some("github.com"), "builtin");
+        assert_eq!(
+            host("https://registry.gitlab.com/v2/").as_deref(),
+            Some("gitlab.com"),
+            "scheme and path are ignored"
+        );
+        assert_eq!(
+            host("CR.example.com").as_deref(),
+            Some("git.example.com"),
+            "configured"
+        );
+        assert_eq!(
+            host("registry.gitlab.example.com").as_deref(),
+            Some("gitlab.example.com"),
+            "registry. prefix"
+        );
+        assert_eq!(host("docker.io"), None, "unknown registry");
+    }
+}
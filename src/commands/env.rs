@@ -0,0 +1,175 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+
+use anyhow::{Result, bail};
+use clap::ValueEnum;
+use tracing::instrument;
+
+use crate::commands::common::{fresh_token, load_profiles, styled_error, styled_warning};
+use crate::context::AppContext;
+use crate::profile::rule::ProfileRef;
+
+/// Package managers `env` emits credentials for
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum PackageFormat {
+    /// A `.npmrc` line
+    Npm,
+    /// A `<server>` entry for Maven's settings.xml
+    Maven,
+    /// A `.netrc` entry, which pip and twine use for the host
+    Pip,
+}
+
+/// Print the configuration `format` needs to authenticate to the package
+/// registry of `host` with the token of the active credential, or `name`.
+#[instrument(skip(ctx))]
+pub async fn env(
+    ctx: &AppContext,
+    host: &str,
+    name: Option<&str>,
+    format: PackageFormat,
+) -> Result<()> {
+    let (pair, token) = fresh_token(ctx, host, name).await?;
+    let provider_type = ctx.oauth.load().ok().and_then(|cfg| {
+        cfg.find_provider(&pair.host)
+            .and_then(|(_, provider)| provider.provider_type.clone())
+    });
+    ctx.io.print(&format!(
+        "{}\n",
+        snippet(
+            format,
+            &pair.host,
+            provider_type.as_deref(),
+            token.access_token()
+        )
+    ));
+    Ok(())
+}
+
+/// Print the environment variables of a profile as `export` lines for a
+/// POSIX shell, to be `eval`ed
+#[instrument(skip(ctx))]
+pub fn profile_env(ctx: &AppContext, profile_ref: &ProfileRef) -> Result<()> {
+    let profile_config = load_profiles(ctx)?;
+    let Some(profile) = profile_config.profiles.get(&profile_ref.name) else {
+        styled_error(format!("Unknown profile: {}", profile_ref.name));
+        bail!("Unknown profile: {}", profile_ref.name);
+    };
+    if profile.env.is_empty() {
+        styled_warning(format!(
+            "Profile '{}' sets no environment variables.",
+            profile_ref.name
+        ));
+    }
+    ctx.io.print(&exports(&profile.env));
+    Ok(())
+}
+
+/// `export NAME='value'` lines for `vars`
+fn exports(vars: &BTreeMap<String, String>) -> String {
+    let mut out = String::new();
+    for (name, value) in vars {
+        let _ = writeln!(out, "export {name}='{}'", value.replace('\'', r"'\''"));
+    }
+    out
+}
+
+/// The configuration for `format`, registry paths depend on the provider type
+fn snippet(format: PackageFormat, host: &str, provider_type: Option<&str>, token: &str) -> String {
+    let provider_type = provider_type.map(str::to_lowercase);
+    match format {
+        PackageFormat::Npm => {
+            let registry = match provider_type.as_deref() {
+                Some("github") if host == "github.com" => "npm.pkg.github.com/".to_string(),
+                Some("gitlab") => format!("{host}/api/v4/"),
+                Some("forgejo" | "gitea") => format!("{host}/api/packages/"),
+                Some(_) | None => format!("{host}/"),
+            };
+            format!("//{registry}:_authToken={token}")
+        },
+        PackageFormat::Maven => {
+            // GitLab only takes OAuth tokens as bearer tokens
+            if provider_type.as_deref() == Some("gitlab") {
+                format!(
+                    "<server>\n  <id>{host}</id>\n  <configuration>\n    <httpHeaders>\n      \
+                     <property>\n        <name>Authorization</name>\n        <value>Bearer \
+                     {token}</value>\n      </property>\n    </httpHeaders>\n  \
+                     </configuration>\n</server>"
+                )
+            } else {
+                format!(
+                    "<server>\n  <id>{host}</id>\n  <username>oauth2</username>\n  \
+                     <password>{token}</password>\n</server>"
+                )
+            }
+        },
+        PackageFormat::Pip => format!("machine {host} login oauth2 password {token}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn profile_env_is_quoted_for_the_shell() {
+        let profile: crate::profile::Profile = toml::from_str(
+            r#"
+            user.email = "alice@example.com"
+            [env]
+            GIT_AUTHOR_EMAIL = "alice@example.com"
+            CARGO_NET_GIT_FETCH_WITH_CLI = true
+            NOTE = "it's quoted"
+            "#,
+        )
+        .unwrap();
+        assert!(
+            !profile.configs.contains_key("env.NOTE"),
+            "env is no git config"
+        );
+        assert_eq!(
+            exports(&profile.env),
+            "export CARGO_NET_GIT_FETCH_WITH_CLI='true'\nexport \
+             GIT_AUTHOR_EMAIL='alice@example.com'\nexport NOTE='it'\\''s quoted'\n"
+        );
+    }
+
+    #[test]
+    fn snippets_follow_the_provider_type() {
+        assert_eq!(
+            snippet(
+                PackageFormat::Npm,
+                "gitlab.example.com",
+                Some("GitLab"),
+                "t"
+            ),
+            "//gitlab.example.com/api/v4/:_authToken=t",
+            "gitlab npm"
+        );
+        assert_eq!(
+            snippet(PackageFormat::Npm, "github.com", Some("github"), "t"),
+            "//npm.pkg.github.com/:_authToken=t",
+            "github npm"
+        );
+        assert_eq!(
+            snippet(PackageFormat::Npm, "git.example.com", None, "t"),
+            "//git.example.com/:_authToken=t",
+            "unknown type"
+        );
+        assert!(
+            snippet(PackageFormat::Maven, "gitlab.com", Some("gitlab"), "t")
+                .contains("<value>Bearer t</value>"),
+            "gitlab maven uses a bearer header"
+        );
+        assert!(
+            snippet(PackageFormat::Maven, "codeberg.org", Some("forgejo"), "t")
+                .contains("<password>t</password>"),
+            "others use basic auth"
+        );
+        assert_eq!(
+            snippet(PackageFormat::Pip, "gitlab.com", Some("gitlab"), "t"),
+            "machine gitlab.com login oauth2 password t",
+            "pip uses netrc"
+        );
+    }
+}
@@ -1,21 +1,20 @@
 use anyhow::{Context as _, Result, bail};
 use tracing::{info, instrument, warn};
 
-use crate::config::OAuthConfig;
-use crate::keyring::erase_keyring_token;
-use crate::load_cfg;
+use crate::context::AppContext;
 use crate::utils::parse_credential_request;
 
-#[instrument]
-pub async fn handle_erase() -> Result<()> {
-    let oauth_config = load_cfg!(OAuthConfig)?;
+#[instrument(skip(ctx))]
+pub async fn handle_erase(ctx: &AppContext) -> Result<()> {
+    let oauth_config = ctx.oauth.load()?;
     if oauth_config.oauth_only.is_some_and(|x| x) {
         return Ok(());
     }
     info!("Erasing credentials...");
     let req = parse_credential_request().context("Failed to parse credential request")?;
     if let Some(credential) = &req.username {
-        erase_keyring_token(credential, &req.host)
+        ctx.token_store
+            .erase(credential, &oauth_config.canonical_host(&req.host))
             .context("Failed to erase credential from keyring")?;
         Ok(())
     } else {
@@ -0,0 +1,224 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::process::{Command, exit};
+
+use anyhow::{Context as _, Result, bail};
+use tracing::{debug, instrument};
+use zeroize::Zeroizing;
+
+use crate::commands::common::{fresh_token, load_profiles};
+use crate::context::AppContext;
+use crate::forge::ForgeKind;
+use crate::profile::rule::ProfileRef;
+use crate::utils::normalize_host;
+
+/// Set for the child of `exec` to the host its `GIT_ASKPASS` answers for;
+/// warden started with it and a single argument answers the prompt
+pub const ASKPASS_HOST_ENV: &str = "WARDEN_ASKPASS_HOST";
+const ASKPASS_USERNAME_ENV: &str = "WARDEN_ASKPASS_USERNAME";
+const ASKPASS_TOKEN_ENV: &str = "WARDEN_ASKPASS_TOKEN";
+
+/// What a `GIT_ASKPASS` prompt asks for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Prompt {
+    Username,
+    Password,
+}
+
+/// Run `command` with the token of the active credential, or `name`, on
+/// `host` in its environment: in the variables the forge's tools read and
+/// behind `GIT_ASKPASS`. The `env` table of `profile` is set as well. Exits
+/// with the command's exit code.
+#[instrument(skip(ctx, command))]
+pub async fn exec(
+    ctx: &AppContext,
+    host: &str,
+    name: Option<&str>,
+    profile: Option<&ProfileRef>,
+    command: &[String],
+) -> Result<()> {
+    let Some((program, args)) = command.split_first() else {
+        bail!("No command to run");
+    };
+    let profile_env = match profile {
+        Some(profile_ref) => {
+            load_profiles(ctx)?
+                .profiles
+                .get(&profile_ref.name)
+                .with_context(|| format!("Unknown profile: {}", profile_ref.name))?
+                .env
+                .clone()
+        },
+        None => BTreeMap::new(),
+    };
+    let (pair, token) = fresh_token(ctx, host, name).await?;
+    let secret = Zeroizing::new(token.access_token().to_string());
+    drop(token);
+    let provider_type = ctx.oauth.load().ok().and_then(|cfg| {
+        cfg.find_provider(&pair.host)
+            .and_then(|(_, provider)| provider.provider_type.clone())
+    });
+    let kind = ForgeKind::detect(provider_type.as_deref(), &pair.host);
+    let vars = forge_env(kind, &pair.host, &secret);
+    let askpass = env::current_exe().context("Failed to locate the warden executable")?;
+
+    let mut child = Command::new(program);
+    child
+        .args(args)
+        .envs(&profile_env)
+        .envs(vars.iter().map(|(key, value)| (key, value.as_str())))
+        .env("GIT_ASKPASS", askpass)
+        .env(ASKPASS_HOST_ENV, &pair.host)
+        .env(ASKPASS_USERNAME_ENV, &pair.credential)
+        .env(ASKPASS_TOKEN_ENV, secret.as_str());
+    debug!(
+        "Running {program} with {} for {}",
+        vars.iter()
+            .map(|(key, _)| *key)
+            .collect::<Vec<_>>()
+            .join(", "),
+        pair.label_credential_host()
+    );
+    let status = child
+        .status()
+        .with_context(|| format!("Failed to run {program}"))?;
+    // the token only lives in the child's environment, scrub our copies
+    drop(child);
+    drop(vars);
+    drop(secret);
+    if !status.success() {
+        exit(status.code().unwrap_or(1));
+    }
+    Ok(())
+}
+
+/// Answer a `GIT_ASKPASS` prompt of a command run by `exec`, only for the
+/// host the token belongs to
+#[instrument(skip(ctx))]
+pub fn askpass(ctx: &AppContext, prompt: &str) -> Result<()> {
+    let host = env::var(ASKPASS_HOST_ENV).context("Not started by warden exec")?;
+    let Some((kind, prompt_host)) = parse_prompt(prompt) else {
+        bail!("Unexpected prompt: {prompt}");
+    };
+    let prompt_host = ctx.oauth.load().map_or_else(
+        |_| normalize_host(&prompt_host),
+        |cfg| cfg.canonical_host(&prompt_host),
+    );
+    if prompt_host != host {
+        bail!("The token is for {host}, not {prompt_host}");
+    }
+    let key = match kind {
+        Prompt::Username => ASKPASS_USERNAME_ENV,
+        Prompt::Password => ASKPASS_TOKEN_ENV,
+    };
+    let answer = Zeroizing::new(env::var(key).with_context(|| format!("{key} is not set"))?);
+    ctx.io.print(&answer);
+    ctx.io.print("\n");
+    Ok(())
+}
+
+/// What git's `Username for 'https://host': ` or `Password for
+/// 'https://user@host': ` prompt asks for, and the host
+fn parse_prompt(prompt: &str) -> Option<(Prompt, String)> {
+    let kind = if prompt.starts_with("Username") {
+        Prompt::Username
+    } else if prompt.starts_with("Password") {
+        Prompt::Password
+    } else {
+        return None;
+    };
+    let (_, rest) = prompt.split_once('\'')?;
+    let (url, _) = rest.split_once('\'')?;
+    let url = url::Url::parse(url).ok()?;
+    let host = url.host_str()?;
+    let host = url
+        .port()
+        .map_or_else(|| host.to_string(), |port| format!("{host}:{port}"));
+    Some((kind, host))
+}
+
+/// Environment variables the tools of `kind` take the token of `host` from
+fn forge_env(
+    kind: Option<ForgeKind>,
+    host: &str,
+    token: &str,
+) -> Vec<(&'static str, Zeroizing<String>)> {
+    let token = || Zeroizing::new(token.to_string());
+    let base_url = Zeroizing::new(format!("https://{host}"));
+    match kind {
+        Some(ForgeKind::GitHub) if host == "github.com" => {
+            vec![("GH_TOKEN", token()), ("GITHUB_TOKEN", token())]
+        },
+        Some(ForgeKind::GitHub) => {
+            vec![
+                ("GH_HOST", Zeroizing::new(host.to_string())),
+                ("GH_ENTERPRISE_TOKEN", token()),
+                ("GITHUB_TOKEN", token()),
+                ("GITHUB_BASE_URL", base_url),
+            ]
+        },
+        Some(ForgeKind::GitLab) => {
+            vec![
+                ("GITLAB_HOST", Zeroizing::new(host.to_string())),
+                ("GITLAB_TOKEN", token()),
+                ("GITLAB_BASE_URL", base_url),
+            ]
+        },
+        Some(ForgeKind::Forgejo) => {
+            vec![
+                ("GITEA_BASE_URL", base_url),
+                ("GITEA_TOKEN", token()),
+                ("FORGEJO_TOKEN", token()),
+            ]
+        },
+        None => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prompts_name_their_host() {
+        assert_eq!(
+            parse_prompt("Username for 'https://gitlab.example.com:8443': "),
+            Some((Prompt::Username, "gitlab.example.com:8443".into())),
+            "username"
+        );
+        assert_eq!(
+            parse_prompt("Password for 'https://alice@github.com': "),
+            Some((Prompt::Password, "github.com".into())),
+            "password with user"
+        );
+        assert_eq!(
+            parse_prompt("Enter passphrase for key '/home/u/.ssh/id_ed25519': "),
+            None,
+            "other prompts"
+        );
+    }
+
+    #[test]
+    fn env_follows_the_forge() {
+        let keys = |kind, host| {
+            forge_env(kind, host, "t")
+                .into_iter()
+                .map(|(key, _)| key)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            keys(Some(ForgeKind::GitHub), "github.com"),
+            ["GH_TOKEN", "GITHUB_TOKEN"],
+            "github.com"
+        );
+        assert!(
+            keys(Some(ForgeKind::GitHub), "ghe.example.com").contains(&"GH_ENTERPRISE_TOKEN"),
+            "enterprise"
+        );
+        assert!(
+            keys(Some(ForgeKind::GitLab), "gitlab.com").contains(&"GITLAB_TOKEN"),
+            "gitlab"
+        );
+        assert!(keys(None, "git.example.com").is_empty(), "unknown forge");
+    }
+}
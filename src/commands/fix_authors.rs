@@ -0,0 +1,307 @@
+use std::collections::HashMap;
+
+use anyhow::{Context as _, Result, bail};
+use colored::Colorize as _;
+use dialoguer::Confirm;
+use git2::{BranchType, Commit, Oid, Repository, Signature, Sort};
+use tracing::instrument;
+
+use crate::commands::common::{load_profiles, repo_profile, styled_error};
+use crate::context::AppContext;
+use crate::theme::InputTheme;
+use crate::utils::{RepoContext, repo_context};
+
+/// Revision used when `--since` is not given
+const UPSTREAM: &str = "@{upstream}";
+
+/// Rewrites the author and committer of the commits after `since` (the
+/// upstream by default) to the identity of the profile the rules choose.
+///
+/// Only local commits are touched, the range must not be reachable from any
+/// remote-tracking branch. Commit and author dates are kept, signatures are
+/// dropped.
+#[instrument(skip(ctx))]
+pub fn fix_authors(ctx: &AppContext, since: Option<&str>, dry_run: bool, yes: bool) -> Result<()> {
+    let Ok(RepoContext { repo, .. }) = repo_context(None) else {
+        styled_error("Not a git repository!");
+        bail!("Not a git repository!");
+    };
+    let (name, email) = expected_identity(ctx, &repo)?;
+    rewrite_since(
+        &repo,
+        since.unwrap_or(UPSTREAM),
+        &name,
+        &email,
+        dry_run,
+        yes,
+    )
+}
+
+/// Rewrite the commits of `repo` after `since` to `name` and `email`, see
+/// [`fix_authors`]
+fn rewrite_since(
+    repo: &Repository,
+    since: &str,
+    name: &str,
+    email: &str,
+    dry_run: bool,
+    yes: bool,
+) -> Result<()> {
+    let base = repo
+        .revparse_single(since)
+        .with_context(|| format!("Failed to resolve '{since}'"))?
+        .peel_to_commit()?
+        .id();
+    let head = repo.head().context("Failed to read HEAD")?;
+    let tip = head.peel_to_commit()?.id();
+
+    let mut revwalk = repo.revwalk()?;
+    revwalk.push(tip)?;
+    revwalk.hide(base)?;
+    revwalk.set_sorting(Sort::TOPOLOGICAL | Sort::REVERSE)?;
+    let range = revwalk.collect::<Result<Vec<Oid>, _>>()?;
+    if range.is_empty() {
+        eprintln!("No commits after {}.", since.bold());
+        return Ok(());
+    }
+    ensure_unpushed(repo, &range)?;
+
+    let wrong: Vec<Commit> = range
+        .iter()
+        .map(|oid| repo.find_commit(*oid))
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .filter(|c| !has_identity(c, name, email))
+        .collect();
+    if wrong.is_empty() {
+        eprintln!("All commits after {} match {name} <{email}>.", since.bold());
+        return Ok(());
+    }
+
+    let verb = if dry_run {
+        "Would rewrite"
+    } else {
+        "Rewriting"
+    };
+    eprintln!("{verb} to {}:", format!("{name} <{email}>").bold());
+    for commit in &wrong {
+        eprintln!(
+            "  {} {} <{}> {}",
+            &commit.id().to_string()[..7],
+            commit.author().name().unwrap_or("-"),
+            commit.author().email().unwrap_or("-").red(),
+            commit.summary().unwrap_or_default().dimmed()
+        );
+    }
+    if dry_run {
+        return Ok(());
+    }
+    if !yes && !confirm(wrong.len(), range.len())? {
+        bail!("Aborted");
+    }
+
+    let new_tip = rewrite(repo, &range, base, name, email)?;
+    if head.is_branch() {
+        let branch = head.name().context("HEAD is not valid UTF-8")?;
+        repo.reference(branch, new_tip, true, "warden fix-authors")?;
+    } else {
+        repo.set_head_detached(new_tip)?;
+    }
+    // the commits after a re-authored one get new ids as well
+    eprintln!(
+        "Re-authored {} commits, recreating {}, {} is now {}.",
+        wrong.len(),
+        range.len(),
+        head.shorthand().unwrap_or("HEAD").bold(),
+        &new_tip.to_string()[..7]
+    );
+    Ok(())
+}
+
+/// The user.name and user.email of the profile the rules choose, falling
+/// back to the repository config for values the profile doesn't set.
+fn expected_identity(ctx: &AppContext, repo: &Repository) -> Result<(String, String)> {
+    let profile_config = load_profiles(ctx)?;
+    let Some((profile_ref, _)) = repo_profile(repo, &profile_config) else {
+        styled_error("No profile found for this repository.");
+        bail!("No profile found for this repository");
+    };
+    let Some((_, profile)) = profile_config.profiles.resolve(&profile_ref) else {
+        bail!("Unknown profile: {}", profile_ref.name);
+    };
+    let cfg = repo.config()?.snapshot()?;
+    let value = |key: &str| {
+        profile
+            .configs
+            .get(key)
+            .cloned()
+            .or_else(|| cfg.get_string(key).ok())
+            .with_context(|| format!("Neither the profile nor git config set '{key}'"))
+    };
+    Ok((value("user.name")?, value("user.email")?))
+}
+
+fn has_identity(commit: &Commit, name: &str, email: &str) -> bool {
+    let is = |sig: Signature| sig.name() == Some(name) && sig.email() == Some(email);
+    is(commit.author()) && is(commit.committer())
+}
+
+/// Refuse to rewrite commits that are already on a remote-tracking branch.
+fn ensure_unpushed(repo: &Repository, range: &[Oid]) -> Result<()> {
+    for branch in repo.branches(Some(BranchType::Remote))? {
+        let Some(remote_tip) = branch?.0.get().target() else {
+            continue;
+        };
+        for oid in range {
+            if *oid == remote_tip || repo.graph_descendant_of(remote_tip, *oid)? {
+                styled_error(format!(
+                    "Commit {} has already been pushed.",
+                    &oid.to_string()[..7]
+                ));
+                bail!("Refusing to rewrite pushed commits");
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Ask whether to re-author `wrong` commits, recreating all `range` commits
+fn confirm(wrong: usize, range: usize) -> Result<bool> {
+    let confirmed = Confirm::with_theme(&InputTheme::default())
+        .with_prompt(format!("Re-author {wrong} commits, recreating {range}?"))
+        .default(false)
+        .interact_opt()
+        .context("Failed to confirm rewrite")?;
+    Ok(confirmed.is_some_and(|b| b))
+}
+
+/// Recreate every commit of `range` (oldest first) with the new identity and
+/// rewritten parents, returning the new tip.
+fn rewrite(repo: &Repository, range: &[Oid], base: Oid, name: &str, email: &str) -> Result<Oid> {
+    let mut rewritten: HashMap<Oid, Oid> = HashMap::new();
+    let mut tip = base;
+    for oid in range {
+        let commit = repo.find_commit(*oid)?;
+        let author = Signature::new(name, email, &commit.author().when())?;
+        let committer = Signature::new(name, email, &commit.committer().when())?;
+        let parents = commit
+            .parent_ids()
+            .map(|p| repo.find_commit(rewritten.get(&p).copied().unwrap_or(p)))
+            .collect::<Result<Vec<_>, _>>()?;
+        let parents: Vec<&Commit> = parents.iter().collect();
+        let message = commit
+            .message_raw()
+            .context("Commit message is not valid UTF-8")?;
+        tip = repo.commit(
+            None,
+            &author,
+            &committer,
+            message,
+            &commit.tree()?,
+            &parents,
+        )?;
+        rewritten.insert(*oid, tip);
+    }
+    Ok(tip)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    const NAME: &str = "Alice";
+    const EMAIL: &str = "alice@example.com";
+
+    /// A commit by someone else adding `file` on top of `parents`
+    fn commit(repo: &Repository, parents: &[Oid], file: &str) -> Oid {
+        let sig = Signature::now("Someone", "someone@example.com").unwrap();
+        let mut tree = repo.treebuilder(None).unwrap();
+        let blob = repo.blob(file.as_bytes()).unwrap();
+        tree.insert(file, blob, 0o100_644).unwrap();
+        let tree = repo.find_tree(tree.write().unwrap()).unwrap();
+        let parents: Vec<Commit> = parents
+            .iter()
+            .map(|p| repo.find_commit(*p).unwrap())
+            .collect();
+        let parents: Vec<&Commit> = parents.iter().collect();
+        repo.commit(None, &sig, &sig, file, &tree, &parents)
+            .unwrap()
+    }
+
+    /// The commit HEAD points at
+    fn head(repo: &Repository) -> Commit<'_> {
+        repo.head().unwrap().peel_to_commit().unwrap()
+    }
+
+    #[test]
+    fn merges_are_rewritten_with_their_parents() {
+        let dir = std::env::temp_dir().join(format!("warden-fix-authors-{}", std::process::id()));
+        let repo = Repository::init(&dir).unwrap();
+        let base = commit(&repo, &[], "base");
+        let main = commit(&repo, &[base], "main");
+        let side = commit(&repo, &[base], "side");
+        let merge = commit(&repo, &[main, side], "merge");
+        repo.reference("refs/heads/main", merge, true, "test")
+            .unwrap();
+        repo.set_head("refs/heads/main").unwrap();
+
+        // a remote-tracking branch containing the range refuses the rewrite
+        let mut pushed = repo
+            .reference("refs/remotes/origin/main", side, true, "test")
+            .unwrap();
+        let since = base.to_string();
+        assert!(
+            rewrite_since(&repo, &since, NAME, EMAIL, false, true).is_err(),
+            "pushed commits are kept"
+        );
+        assert_eq!(head(&repo).id(), merge, "nothing rewritten");
+        pushed.delete().unwrap();
+
+        rewrite_since(&repo, &since, NAME, EMAIL, false, true).unwrap();
+        let tip = head(&repo);
+        assert!(repo.head().unwrap().is_branch(), "branch moved");
+        assert_ne!(tip.id(), merge);
+        assert_eq!(tip.tree_id(), repo.find_commit(merge).unwrap().tree_id());
+        let parents: Vec<Commit> = tip.parents().collect();
+        assert_eq!(parents.len(), 2, "still a merge");
+        for commit in parents.iter().chain([&tip]) {
+            assert!(
+                has_identity(commit, NAME, EMAIL),
+                "{} re-authored",
+                commit.id()
+            );
+        }
+        assert!(
+            parents.iter().all(|p| p.parent_id(0).unwrap() == base),
+            "both sides rewritten onto the base"
+        );
+        assert_eq!(
+            head(&repo).author().when(),
+            repo.find_commit(merge).unwrap().author().when(),
+            "dates kept"
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn detached_head_is_moved() {
+        let dir = std::env::temp_dir().join(format!("warden-fix-detached-{}", std::process::id()));
+        let repo = Repository::init(&dir).unwrap();
+        let base = commit(&repo, &[], "base");
+        let tip = commit(&repo, &[base], "tip");
+        repo.set_head_detached(tip).unwrap();
+
+        rewrite_since(&repo, &base.to_string(), NAME, EMAIL, true, true).unwrap();
+        assert_eq!(head(&repo).id(), tip, "dry run changes nothing");
+
+        rewrite_since(&repo, &base.to_string(), NAME, EMAIL, false, true).unwrap();
+        assert!(repo.head_detached().unwrap(), "still detached");
+        let rewritten = head(&repo);
+        assert_ne!(rewritten.id(), tip);
+        assert!(has_identity(&rewritten, NAME, EMAIL), "re-authored");
+        assert_eq!(rewritten.parent_id(0).unwrap(), base, "base kept");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
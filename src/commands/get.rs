@@ -1,31 +1,44 @@
+use std::io::{IsTerminal as _, stderr};
+
 use anyhow::{Context as _, Result, bail};
 use chrono::{DateTime, Utc};
+use colored::Colorize as _;
 use tracing::{debug, error, info, instrument, warn};
 
-use crate::commands::common::styled_error;
-use crate::commands::login::login;
-use crate::commands::{print_token, print_token_checked};
-use crate::config::{Hosts, OAuthConfig, ProviderConfig};
-use crate::keyring::{Token, get_keyring_token};
-use crate::load_cfg;
-use crate::oauth::{device_code, get_access_token};
-use crate::utils::{CredentialRequest, parse_credential_request};
+use crate::commands::common::{
+    CredentialPair, check_deadline, styled_error, styled_note, styled_warning, warn_refresh_expiry,
+};
+use crate::commands::login::{LoginOptions, login, reauthenticate};
+use crate::commands::onboard::onboard;
+use crate::commands::{emit, print_token, print_token_checked};
+use crate::config::provider::StaticCredential;
+use crate::config::{Hosts, OAuthConfig, ProviderConfig, Stats};
+use crate::context::AppContext;
+use crate::keyring::Token;
+use crate::oauth::{device_code, get_access_token, oidc};
+use crate::utils::{
+    CredentialRequest, configured_username, git_config, parse_credential_request, select_index,
+};
+use crate::{coalesce, load_cfg, signal};
 
-#[instrument(skip(req, provider))]
+#[instrument(skip(ctx, req, provider))]
 async fn maybe_print_with_refresh_token(
+    ctx: &AppContext,
     req: &CredentialRequest,
     provider: &ProviderConfig,
 ) -> Result<bool> {
     if let Some(refresh_token) = req.oauth_refresh_token.as_ref()
         && req.password.is_none()
+        && provider.refreshes()
     {
         info!("Using provided refresh token to get access token");
         let mut token = Token::new(
-            req.password.clone().unwrap_or_default(),
-            Some(refresh_token.clone()),
+            String::new(),
+            Some(refresh_token.expose().to_string()),
             DateTime::<Utc>::from_timestamp(0, 0),
         );
         print_token_checked(
+            &*ctx.io,
             &mut token,
             &req.username.clone().unwrap_or_else(|| "oauth".to_string()),
             provider,
@@ -37,102 +50,393 @@ async fn maybe_print_with_refresh_token(
     Ok(false)
 }
 
-#[instrument]
-pub async fn handle_get(force_device: bool) -> Result<()> {
+/// Answer Git's request for a credential. With `force_login`, the stored
+/// token is replaced by a fresh OAuth flow before it is served.
+#[instrument(skip(ctx))]
+pub async fn handle_get(ctx: &AppContext, force_device: bool, force_login: bool) -> Result<()> {
     info!("Retrieving credentials...");
-    let req = parse_credential_request().context("Failed to parse credential request")?;
+    let mut req = parse_credential_request().context("Failed to parse credential request")?;
+    if req.username.is_none()
+        && let Some(username) = git_config(None)
+            .ok()
+            .and_then(|cfg| configured_username(&cfg, &req))
+    {
+        info!("Using the username '{username}' configured for the request URL");
+        req.username = Some(username);
+    }
     debug!("{:#?}", &req);
 
-    let oauth_config = load_cfg!(OAuthConfig)?;
-    let mut hosts_config = load_cfg!(Hosts)?;
+    for challenge in req.challenges() {
+        info!(
+            "Server challenge: {} {:?}",
+            challenge.scheme, challenge.params
+        );
+    }
+
+    let mut oauth_config = ctx.oauth.load()?;
+    if let Some(deadline) = oauth_config.get_deadline() {
+        signal::watchdog(deadline);
+    }
+    // served before coalescing, which would write the password to disk again
+    if let Some((host, credential)) = oauth_config.static_credential(&req.host) {
+        return serve_static(ctx, &req, host, credential);
+    }
+    if let Some(lines) = coalesce::begin(oauth_config.coalesce_window(), &req, force_login) {
+        emit(&*ctx.io, &lines);
+        return Ok(());
+    }
+    let mut hosts_config = ctx.hosts.load()?;
+
+    if oauth_config.find_provider(&req.host).is_none()
+        && oauth_config.discarded_for(&req.host).is_none()
+        && onboard(&oauth_config, &req.host)?
+    {
+        oauth_config = ctx.oauth.load()?;
+    }
 
     // Lookup OAuth provider by host
-    let Some(provider) = oauth_config.providers.get(&req.host) else {
+    let Some((host, provider)) = oauth_config.find_provider(&req.host) else {
         // No config for this host, allow Git to try the next helper
-        warn!("No OAuth provider configuration found for {}", req.host);
+        if oauth_config.discarded_for(&req.host).is_some() {
+            styled_warning(oauth_config.missing_provider_message(&req.host));
+        } else {
+            warn!("No OAuth provider configuration found for {}", req.host);
+        }
         return Ok(());
     };
 
     if force_device {
+        let provider = &*oidc::discover(provider).await?;
         if provider.device_auth_url.is_none() {
             error!("Device code flow is not supported for this provider");
             bail!("Device code flow is not supported for this provider");
         }
-        if maybe_print_with_refresh_token(&req, provider).await? {
+        if maybe_print_with_refresh_token(ctx, &req, provider).await? {
             return Ok(());
         }
         let token = device_code::exchange_device_code(provider)
             .await
             .context("Failed to authenticate with device flow")?;
-        print_token(&token, &req.username.unwrap_or_else(|| "oauth".to_string()));
+        print_token(
+            &*ctx.io,
+            &token,
+            &req.username.unwrap_or_else(|| "oauth".to_string()),
+        );
         return Ok(());
     }
 
     if oauth_config.oauth_only.unwrap_or(false) {
         debug!("OAuth-only mode is enabled");
-        if maybe_print_with_refresh_token(&req, provider).await? {
+        if maybe_print_with_refresh_token(ctx, &req, provider).await? {
             return Ok(());
         }
-        let token = get_access_token(&oauth_config, &req.host, force_device).await?;
-        print_token(&token, &req.username.unwrap_or_else(|| "oauth".to_string()));
+        let token = get_access_token(&oauth_config, &host, force_device).await?;
+        print_token(
+            &*ctx.io,
+            &token,
+            &req.username.unwrap_or_else(|| "oauth".to_string()),
+        );
         return Ok(());
     }
 
     // if a username was provided, and we know it, return its credential
     if let Some(credential) = &req.username
         && !credential.is_empty()
-        && hosts_config.has_credential(&req.host, credential)
+        && hosts_config.has_credential(&host, credential)
     {
         info!("Username was in request and in hosts config");
-        let mut token = get_keyring_token(credential, &req.host)
+        note_other_than_active(&hosts_config, &host, credential);
+        let pair = CredentialPair::new(host.as_str(), credential);
+        if force_login {
+            login_again(ctx, &oauth_config, &pair, force_device).await?;
+        }
+        let mut token = ctx
+            .token_store
+            .get(credential, &host)
             .context("Failed to retrieve token from keyring")?;
-        print_token_checked(&mut token, credential, provider)
+        check_deadline(ctx, &pair, &token).await?;
+        warn_refresh_expiry(&token, credential, &host);
+        handle_rejection(&req, &mut token, provider, credential, &host);
+        renew_without_refresh(
+            ctx,
+            &oauth_config,
+            provider,
+            &pair,
+            &mut token,
+            force_device,
+        )
+        .await?;
+        print_token_checked(&*ctx.io, &mut token, credential, provider)
             .await
             .context("Failed to output token")?;
+        record_usage(&oauth_config, &req, &host, credential);
+        record_last_use(ctx, &mut hosts_config, &host, credential);
         return Ok(());
     }
     // if no username is provided, check if there is an active user for the host
-    let mut active_credential = hosts_config.get_active_credential(&req.host);
+    let mut active_credential = hosts_config.get_active_credential(&host);
     if active_credential.is_none_or(str::is_empty) {
         // if there is no active credential, prompt the user to input a credential name
         // and then perform first use login flow
-        eprintln!(
-            " No active credential found for host {}.\n Please login first.",
-            req.host
-        );
-        login(force_device).await.context("Failed to login")?;
-        hosts_config = load_cfg!(Hosts)?;
-        active_credential = hosts_config.get_active_credential(&req.host);
+        eprintln!(" No active credential found for host {host}.\n Please login first.");
+        login(
+            ctx,
+            Some(&host),
+            None,
+            LoginOptions::default(),
+            force_device,
+        )
+        .await
+        .context("Failed to login")?;
+        hosts_config = ctx.hosts.load()?;
+        active_credential = hosts_config.get_active_credential(&host);
         if active_credential.is_none_or(str::is_empty) {
-            error!("No active credential found for host {}", req.host);
-            bail!(
-                "No active credential found for host {}. Please login first.",
-                req.host
-            );
+            error!("No active credential found for host {host}");
+            bail!("No active credential found for host {host}. Please login first.");
         }
     }
     let active_credential = active_credential.unwrap();
-    let username = req.username.as_deref().unwrap_or(active_credential);
+    let chosen = if req.username.is_none() {
+        choose_credential(&oauth_config, &hosts_config, &host, active_credential)?
+    } else {
+        None
+    };
+    let username = req
+        .username
+        .as_deref()
+        .or(chosen.as_deref())
+        .unwrap_or(active_credential);
 
-    if let Ok(mut token) = get_keyring_token(username, &req.host) {
-        info!(
-            "Using cached credential for '{username}' on '{}'.",
-            req.host
-        );
-        print_token_checked(&mut token, username, provider)
+    if force_login && hosts_config.has_credential(&host, username) {
+        let pair = CredentialPair::new(host.as_str(), username);
+        login_again(ctx, &oauth_config, &pair, force_device).await?;
+    }
+    if let Ok(mut token) = ctx.token_store.get(username, &host) {
+        info!("Using cached credential for '{username}' on '{host}'.");
+        let pair = CredentialPair::new(host.as_str(), username);
+        check_deadline(ctx, &pair, &token).await?;
+        warn_refresh_expiry(&token, username, &host);
+        handle_rejection(&req, &mut token, provider, username, &host);
+        renew_without_refresh(
+            ctx,
+            &oauth_config,
+            provider,
+            &pair,
+            &mut token,
+            force_device,
+        )
+        .await?;
+        print_token_checked(&*ctx.io, &mut token, username, provider)
             .await
             .context("Failed to output token")?;
+        record_usage(&oauth_config, &req, &host, username);
+        let username = username.to_string();
+        record_last_use(ctx, &mut hosts_config, &host, &username);
         return Ok(());
     }
 
-    warn!(
-        "No credential found for '{username}' on host '{}'.",
-        req.host
-    );
+    warn!("No credential found for '{username}' on host '{host}'.");
     styled_error(format!(
-        "No credential found for user '{username}' on host '{}'.",
-        req.host
+        "No credential found for user '{username}' on host '{host}'."
     ));
+    // Git asked for a name none of the host's credentials has
+    if req.username.is_some()
+        && let Ok(credentials) = hosts_config.get_credentials(&host)
+        && !credentials.is_empty()
+    {
+        styled_note(format!(
+            "Git asked for '{username}', {host} has {}. Change the username of the remote URL or \
+             credential.username to use one of them.",
+            credentials.join(", ")
+        ));
+    }
 
     Ok(())
 }
+
+/// Replace the token of `pair` by a fresh OAuth flow, for `--force-login`
+async fn login_again(
+    ctx: &AppContext,
+    oauth_config: &OAuthConfig,
+    pair: &CredentialPair,
+    force_device: bool,
+) -> Result<()> {
+    info!(
+        "Logging in again for {} as asked, not using the stored token",
+        pair.label_credential_host()
+    );
+    reauthenticate(ctx, oauth_config, pair, force_device)
+        .await
+        .context("Failed to log in again")
+}
+
+/// Explain why `credential`, which Git asked for by name, answers instead of
+/// the active credential of `host`
+fn note_other_than_active(hosts_config: &Hosts, host: &str, credential: &str) {
+    let Some(active) = hosts_config.get_active_credential(host) else {
+        return;
+    };
+    if active == credential {
+        return;
+    }
+    styled_note(format!(
+        "Git asked for '{credential}', so its token is used instead of the active '{active}' on \
+         {host}. Run {} to make it the active one.",
+        format!(
+            "{} switch --hostname {host} --name {credential}",
+            env!("CARGO_PKG_NAME")
+        )
+        .blue()
+    ));
+}
+
+/// Serve the `insecure_static` credential of `host`, unless Git asks for
+/// another user
+fn serve_static(
+    ctx: &AppContext,
+    req: &CredentialRequest,
+    host: &str,
+    credential: &StaticCredential,
+) -> Result<()> {
+    if req
+        .username
+        .as_deref()
+        .is_some_and(|username| username != credential.username)
+    {
+        warn!("Not serving the insecure_static credential of {host} for another user");
+        return Ok(());
+    }
+    styled_warning(format!(
+        "Serving the insecure_static credential of [{host}], its password is kept in plain text. \
+         Use it for throwaway mirrors only."
+    ));
+    let password = credential
+        .password()
+        .with_context(|| format!("Invalid insecure_static credential for {host}"))?;
+    let token = Token::new(password.expose().to_string(), None, None);
+    print_token(&*ctx.io, &token, &credential.username);
+    Ok(())
+}
+
+/// Count the request in the usage statistics if enabled. Failures are only
+/// logged, they must not break the Git operation.
+fn record_usage(oauth_config: &OAuthConfig, req: &CredentialRequest, host: &str, credential: &str) {
+    if !oauth_config.stats.unwrap_or(false) {
+        return;
+    }
+    let result = load_cfg!(Stats).and_then(|mut stats| {
+        stats.record(host, credential, &req.protocol, req.path.as_deref());
+        stats.write()
+    });
+    if let Err(err) = result {
+        warn!("Failed to record usage statistics: {err:#}");
+    }
+}
+
+/// Note in the hosts state that `credential` answered a request. Failures are
+/// only logged, they must not break the Git operation.
+fn record_last_use(ctx: &AppContext, hosts_config: &mut Hosts, host: &str, credential: &str) {
+    if !hosts_config.touch(host, credential) {
+        return;
+    }
+    if let Err(err) = ctx.hosts.save(hosts_config) {
+        warn!("Failed to record the last use of {credential}: {err:#}");
+    }
+}
+
+/// React to the server having rejected the token Git sent with the previous
+/// attempt, as reported in its `WWW-Authenticate` challenge.
+fn handle_rejection(
+    req: &CredentialRequest,
+    token: &mut Token,
+    provider: &ProviderConfig,
+    credential: &str,
+    host: &str,
+) {
+    let challenges = req.challenges();
+    match req.bearer_error().as_deref() {
+        Some("invalid_token") if provider.refreshes() && token.refresh_token().is_some() => {
+            info!("{host} rejected the token of '{credential}', refreshing it");
+            token.expires_at = Some(DateTime::UNIX_EPOCH);
+        },
+        Some("invalid_token") => {
+            styled_warning(format!(
+                "{host} rejected the token of {credential}, run {} to log in again.",
+                format!(
+                    "{} login --hostname {host} --name {credential} --reauth",
+                    env!("CARGO_PKG_NAME")
+                )
+                .blue()
+            ));
+        },
+        Some("insufficient_scope") => {
+            let scope = challenges
+                .iter()
+                .find_map(|c| c.params.get("scope"))
+                .map_or_else(String::new, |s| format!(" ({s})"));
+            styled_warning(format!(
+                "The token of {credential} lacks a scope {host} requires{scope}."
+            ));
+        },
+        Some(_) | None => {},
+    }
+}
+
+/// Log in again for an expired token of a provider whose refresh tokens
+/// aren't used, instead of trying to refresh it
+async fn renew_without_refresh(
+    ctx: &AppContext,
+    oauth_config: &OAuthConfig,
+    provider: &ProviderConfig,
+    pair: &CredentialPair,
+    token: &mut Token,
+    force_device: bool,
+) -> Result<()> {
+    if provider.refreshes() || !token.is_expired() {
+        return Ok(());
+    }
+    info!(
+        "Token of {} expired and refreshing is disabled, logging in again",
+        pair.label_credential_host()
+    );
+    reauthenticate(ctx, oauth_config, pair, force_device)
+        .await
+        .context("Failed to log in again")?;
+    *token = ctx
+        .token_store
+        .get(&pair.credential, &pair.host)
+        .context("Failed to retrieve token from keyring")?;
+    Ok(())
+}
+
+/// Let the user pick one of the host's credentials when `choose_credential`
+/// is enabled, the host has several and a terminal is attached. Git's
+/// credential protocol has no way to hand a list of accounts back, so the
+/// choice is made here.
+fn choose_credential(
+    oauth_config: &OAuthConfig,
+    hosts_config: &Hosts,
+    host: &str,
+    active: &str,
+) -> Result<Option<String>> {
+    if !oauth_config.choose_credential.unwrap_or(false) || !stderr().is_terminal() {
+        return Ok(None);
+    }
+    let credentials = hosts_config.get_credentials(host)?;
+    info!("Candidate credentials for '{host}': {credentials:?}");
+    if credentials.len() < 2 {
+        return Ok(None);
+    }
+    let labels: Vec<String> = credentials
+        .iter()
+        .map(|c| {
+            if c == active {
+                format!("{c} (active)")
+            } else {
+                c.clone()
+            }
+        })
+        .collect();
+    let selection = select_index(&labels, format!("Credential for {host}"))?;
+    Ok(Some(credentials[selection].clone()))
+}
@@ -0,0 +1,152 @@
+use std::io::{IsTerminal as _, Read as _, stdin};
+
+use anyhow::{Result, bail};
+use colored::Colorize as _;
+use tracing::instrument;
+
+use crate::commands::common::{parse_repo_url, styled_error, styled_note, styled_warning};
+use crate::context::AppContext;
+use crate::forge::Forge;
+use crate::profile::url::{Patterns, Scheme};
+use crate::utils::{RepoContext, UrlUse, remote_urls, repo_context};
+
+/// Scopes that allow pushing on GitHub, GitLab and Forgejo/Gitea
+pub const PUSH_SCOPES: &[&str] = &[
+    "repo",
+    "public_repo",
+    "api",
+    "write_repository",
+    "write:repository",
+    "all",
+];
+
+/// Check that the active token for the host of `remote`, or `url`, may push
+/// to the repository. Meant to run as a pre-push hook, which passes both.
+/// Only a definite answer from the forge blocks the push, when it can't be
+/// reached the push goes ahead.
+#[instrument(skip(ctx))]
+pub async fn guard(ctx: &AppContext, remote: Option<&str>, url: Option<&str>) -> Result<()> {
+    // git writes the refs to push to the hook's stdin
+    if !stdin().is_terminal() {
+        let mut refs = String::new();
+        let _ = stdin().read_to_string(&mut refs);
+    }
+
+    let urls = if let Some(url) = url {
+        // the pre-push hook passes the URL as Git rewrote it
+        vec![url.to_string()]
+    } else {
+        let remote = remote.unwrap_or("origin");
+        let Ok(RepoContext { repo, .. }) = repo_context(None) else {
+            styled_error("Not a git repository!");
+            bail!("Not a git repository!");
+        };
+        let urls = remote_urls(&repo, remote, UrlUse::Push)?;
+        if urls.is_empty() {
+            styled_error(format!("No remote named '{remote}' found"));
+            bail!("No remote named '{remote}' found");
+        }
+        urls
+    };
+    let patterns = ctx
+        .profiles
+        .load()
+        .map_or_else(|_| Patterns::default(), |c| c.patterns);
+    // git pushes to every push URL of the remote
+    for url in &urls {
+        check_url(ctx, url, &patterns).await?;
+    }
+    Ok(())
+}
+
+/// Check that the active token for the host of `url` may push to it
+async fn check_url(ctx: &AppContext, url: &str, patterns: &Patterns) -> Result<()> {
+    let repo_url = parse_repo_url(url, patterns)?;
+    if repo_url.scheme == Scheme::Ssh {
+        styled_note(format!("{url} uses SSH, warden tokens aren't involved."));
+        return Ok(());
+    }
+
+    let host = repo_url.host.to_string();
+    let forge = match Forge::connect(ctx, &host, None).await {
+        Ok(forge) => forge,
+        Err(err) => {
+            styled_note(format!("Not checking {host}: {err:#}"));
+            return Ok(());
+        },
+    };
+    let label = forge.pair.label_credential_host();
+
+    match forge.token_scopes().await {
+        // GitHub App tokens have no scopes, their permissions show on the repo
+        Ok(Some(scopes)) if !scopes.is_empty() => check_scopes(&scopes, &label)?,
+        Ok(Some(_) | None) => {},
+        Err(err) => {
+            styled_warning(format!("Could not check the token scopes: {err:#}"));
+        },
+    }
+    let repo = match forge.repo(&repo_url.owner, &repo_url.repo).await {
+        Ok(repo) => repo,
+        Err(err) => {
+            styled_warning(format!("Could not check push access: {err:#}"));
+            return Ok(());
+        },
+    };
+    let Some(repo) = repo else {
+        let name = format!("{}/{}", repo_url.owner, repo_url.repo);
+        styled_error(format!(
+            "{} doesn't exist or isn't visible to {label}.",
+            name.bold()
+        ));
+        bail!("Repository {name} not found");
+    };
+    let name = repo.full_name;
+    match repo.can_push {
+        Some(false) => {
+            styled_error(format!("{label} may not push to {}.", name.bold()));
+            bail!("No push access to {name}");
+        },
+        Some(true) | None => {
+            eprintln!("{label} may push to {}.", name.bold());
+        },
+    }
+    Ok(())
+}
+
+/// Fail unless `scopes` contains one that allows pushing
+fn check_scopes(scopes: &[String], label: &str) -> Result<()> {
+    if scopes.iter().any(|s| PUSH_SCOPES.contains(&s.as_str())) {
+        return Ok(());
+    }
+    styled_error(format!(
+        "The token of {label} can't push, its scopes are: {}",
+        scopes.join(", ")
+    ));
+    styled_note(format!(
+        "Log in again with a push scope: {}",
+        format!("{} login --reauth", env!("CARGO_PKG_NAME")).blue()
+    ));
+    bail!("Token lacks a push scope");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn push_scopes_are_required() {
+        let scopes = |s: &[&str]| s.iter().map(ToString::to_string).collect::<Vec<_>>();
+        assert!(
+            check_scopes(&scopes(&["read:org", "repo"]), "t").is_ok(),
+            "repo can push"
+        );
+        assert!(
+            check_scopes(&scopes(&["write_repository"]), "t").is_ok(),
+            "gitlab scope"
+        );
+        assert!(
+            check_scopes(&scopes(&["read:user", "gist"]), "t").is_err(),
+            "no push scope"
+        );
+    }
+}
@@ -0,0 +1,158 @@
+use std::io::Write as _;
+use std::process::{Command, Stdio};
+
+use anyhow::{Context as _, Result, bail};
+use clap::ValueEnum;
+use colored::Colorize as _;
+use tracing::instrument;
+
+use crate::commands::common::{fresh_token, styled_note, styled_warning};
+use crate::context::AppContext;
+
+/// Forge CLIs a token can be handed to
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ForgeCli {
+    /// GitHub CLI
+    Gh,
+    /// GitLab CLI
+    Glab,
+}
+
+impl ForgeCli {
+    const fn program(self) -> &'static str {
+        match self {
+            Self::Gh => "gh",
+            Self::Glab => "glab",
+        }
+    }
+
+    const fn default_host(self) -> &'static str {
+        match self {
+            Self::Gh => "github.com",
+            Self::Glab => "gitlab.com",
+        }
+    }
+
+    /// Environment variables the CLI takes the token and host from
+    fn env(self, host: &str, token: &str) -> Vec<(&'static str, String)> {
+        match self {
+            Self::Gh if host == "github.com" => vec![("GH_TOKEN", token.to_string())],
+            Self::Gh => {
+                vec![
+                    ("GH_HOST", host.to_string()),
+                    ("GH_ENTERPRISE_TOKEN", token.to_string()),
+                ]
+            },
+            Self::Glab => {
+                vec![
+                    ("GITLAB_HOST", host.to_string()),
+                    ("GITLAB_TOKEN", token.to_string()),
+                ]
+            },
+        }
+    }
+}
+
+/// Hand the token of the active credential, or `name`, on `host` to the
+/// forge CLI: log it in with the token, or with `print_env` print the
+/// environment variables it reads the token from instead.
+#[instrument(skip(ctx))]
+pub async fn handoff(
+    ctx: &AppContext,
+    cli: ForgeCli,
+    host: Option<&str>,
+    name: Option<&str>,
+    print_env: bool,
+) -> Result<()> {
+    let (pair, token) = fresh_token(ctx, host.unwrap_or_else(|| cli.default_host()), name).await?;
+
+    if print_env {
+        for (key, value) in cli.env(&pair.host, token.access_token()) {
+            ctx.io.print(&format!("export {key}={value}\n"));
+        }
+        return Ok(());
+    }
+
+    let args: &[&str] = match cli {
+        ForgeCli::Gh => &["auth", "login", "--hostname", &pair.host, "--with-token"],
+        ForgeCli::Glab => &["auth", "login", "--hostname", &pair.host, "--stdin"],
+    };
+    run_with_stdin(cli.program(), args, token.access_token())?;
+    if cli == ForgeCli::Glab {
+        // glab sends tokens as personal access tokens unless told otherwise
+        let args = ["config", "set", "--host", &pair.host, "is_oauth2", "true"];
+        if let Err(err) = run_with_stdin(cli.program(), &args, "") {
+            styled_warning(format!("{err:#}"));
+        }
+    }
+    eprintln!(
+        "Logged {} in to {} as {}.",
+        cli.program(),
+        pair.host.bold(),
+        pair.credential.bold()
+    );
+    if token.expires_at.is_some() {
+        styled_note(format!(
+            "The token expires, run {} again after refreshing it.",
+            format!("{} handoff {}", env!("CARGO_PKG_NAME"), cli.program()).blue()
+        ));
+    }
+    Ok(())
+}
+
+fn run_with_stdin(program: &str, args: &[&str], input: &str) -> Result<()> {
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to run {program}, is it installed?"))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(input.as_bytes())
+            .with_context(|| format!("Failed to pass the token to {program}"))?;
+    }
+    let output = child
+        .wait_with_output()
+        .with_context(|| format!("Failed to wait for {program}"))?;
+    if !output.status.success() {
+        bail!(
+            "{program} {} exited with {}: {}",
+            args.join(" "),
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn env_depends_on_host() {
+        assert_eq!(
+            ForgeCli::Gh.env("github.com", "t"),
+            [("GH_TOKEN", "t".to_string())],
+            "github.com only needs the token"
+        );
+        assert_eq!(
+            ForgeCli::Gh.env("ghe.example.com", "t"),
+            [
+                ("GH_HOST", "ghe.example.com".to_string()),
+                ("GH_ENTERPRISE_TOKEN", "t".to_string())
+            ],
+            "enterprise hosts use their own variable"
+        );
+        assert_eq!(
+            ForgeCli::Glab.env("gitlab.example.com", "t"),
+            [
+                ("GITLAB_HOST", "gitlab.example.com".to_string()),
+                ("GITLAB_TOKEN", "t".to_string())
+            ],
+            "glab takes the host"
+        );
+    }
+}
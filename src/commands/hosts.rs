@@ -0,0 +1,318 @@
+use anyhow::{Context as _, Result, bail};
+use colored::Colorize as _;
+use tracing::{instrument, warn};
+
+use crate::commands::common::{confirm_destructive, styled_error, styled_warning};
+use crate::commands::onboard::{has_provider, rename_provider};
+use crate::config::Stats;
+use crate::config::provider::client_secret_entry;
+use crate::context::AppContext;
+use crate::keyring::{STATE_HOST, erase_keyring_secret, get_keyring_secret, store_keyring_secret};
+use crate::load_cfg;
+use crate::utils::{RepoContext, normalize_host, repo_context, split_port, validate_host};
+
+/// Record `credential` for `host` without logging in, after checking that the
+/// host name is well-formed and not already known under another spelling.
+#[instrument(skip(ctx))]
+pub fn add(ctx: &AppContext, host: &str, credential: &str) -> Result<()> {
+    let trimmed = host.trim();
+    if trimmed.contains("://") {
+        styled_warning(format!(
+            "Ignoring the scheme of [{}], hosts are stored without one.",
+            trimmed.bold()
+        ));
+    }
+    let without_scheme = trimmed.split_once("://").map_or(trimmed, |(_, rest)| rest);
+    let mut canonical = normalize_host(without_scheme);
+    if let Err(err) = validate_host(&canonical) {
+        styled_error(format!("{err}"));
+        return Err(err);
+    }
+    let oauth_config = ctx.oauth.load().ok();
+    match oauth_config
+        .as_ref()
+        .map(|cfg| cfg.find_provider(&canonical))
+    {
+        Some(Some((key, _))) if key != canonical => {
+            eprintln!(
+                "[{}] is served by the provider [{}], adding to it.",
+                canonical,
+                key.bold()
+            );
+            canonical = key;
+        },
+        Some(None) => {
+            styled_warning(
+                oauth_config
+                    .as_ref()
+                    .map_or_else(String::new, |cfg| cfg.missing_provider_message(&canonical)),
+            );
+        },
+        Some(Some(_)) | None => {},
+    }
+    if credential.trim().is_empty() {
+        styled_error("The credential name must not be empty.");
+        bail!("Empty credential name");
+    }
+
+    let mut hosts = ctx.hosts.load()?;
+    if hosts.has_credential(&canonical, credential) {
+        styled_error(format!(
+            "[{}] already has a credential named [{}].",
+            canonical.bold(),
+            credential.bold()
+        ));
+        bail!("Duplicate credential {credential} for {canonical}");
+    }
+    if canonical != without_scheme && hosts.has_host(&canonical) {
+        eprintln!(
+            "[{}] is the same host as [{}], adding to it.",
+            without_scheme,
+            canonical.bold()
+        );
+    }
+    hosts.add_credential(&canonical, credential);
+    ctx.hosts.save(&hosts)?;
+    eprintln!(
+        "Added credential {} for {}.",
+        credential.bold(),
+        canonical.bold()
+    );
+    Ok(())
+}
+
+/// Forget a host, all of its credentials and their tokens.
+#[instrument(skip(ctx))]
+pub fn remove(ctx: &AppContext, host: &str) -> Result<()> {
+    let canonical = normalize_host(host);
+    let mut hosts = ctx.hosts.load()?;
+    let Some(removed) = hosts.remove_host(&canonical) else {
+        styled_error(format!("Unknown host [{}].", canonical.bold()));
+        bail!("Unknown host {canonical}");
+    };
+    for credential in removed.names() {
+        let _ = ctx.token_store.erase(credential, &canonical);
+    }
+    ctx.hosts.save(&hosts)?;
+    eprintln!("Removed {}.", canonical.bold());
+    Ok(())
+}
+
+/// Move a host to a new name after its forge moved: the hosts state, the
+/// tokens of its credentials, its usage statistics and a provider configured
+/// for exactly that host in oauth.toml. With `remotes`, also point the
+/// remotes of the current repository at the new host. With `dry_run`, only
+/// shows what would change.
+#[instrument(skip(ctx))]
+pub fn rename(ctx: &AppContext, old: &str, new: &str, remotes: bool, dry_run: bool) -> Result<()> {
+    let old = normalize_host(old);
+    let new = normalize_host(new);
+    if let Err(err) = validate_host(&new) {
+        styled_error(format!("{err}"));
+        return Err(err);
+    }
+    let mut hosts = ctx.hosts.load()?;
+    if !hosts.has_host(&old) {
+        styled_error(format!("Unknown host [{}].", old.bold()));
+        bail!("Unknown host {old}");
+    }
+    if hosts.has_host(&new) {
+        styled_error(format!(
+            "[{}] is already known, remove it first.",
+            new.bold()
+        ));
+        bail!("Host {new} already exists");
+    }
+
+    if dry_run {
+        let tokens = hosts
+            .get_credentials(&old)?
+            .iter()
+            .filter(|credential| ctx.token_store.get(credential, &old).is_ok())
+            .count();
+        eprintln!(
+            "Would rename {} to {}, moving {tokens} tokens.",
+            old.bold(),
+            new.bold()
+        );
+        if has_provider(&old)? {
+            eprintln!(
+                "Would rename the provider for {} in oauth.toml.",
+                old.bold()
+            );
+            if get_keyring_secret(&client_secret_entry(&old), STATE_HOST).is_ok() {
+                eprintln!("Would move its client secret in the keyring.");
+            }
+        }
+        if remotes {
+            rewrite_remotes(&old, &new, true)?;
+        }
+        return Ok(());
+    }
+    confirm_destructive(ctx, &format!("Rename {old} to {new}?"))?;
+
+    // copy the tokens first, the old ones are only erased once the state
+    // points at the new host
+    let mut moved: Vec<String> = Vec::new();
+    for credential in &hosts.get_credentials(&old)? {
+        let Ok(token) = ctx.token_store.get(credential, &old) else {
+            continue;
+        };
+        if let Err(err) = ctx.token_store.store(credential, &new, &token) {
+            for copied in &moved {
+                let _ = ctx.token_store.erase(copied, &new);
+            }
+            return Err(err).context(format!("Failed to move the token of {credential}"));
+        }
+        moved.push(credential.clone());
+    }
+    hosts.rename_host(&old, &new);
+    ctx.hosts.save(&hosts)?;
+    for credential in &moved {
+        if let Err(err) = ctx.token_store.erase(credential, &old) {
+            warn!("Failed to erase the token of {credential} on {old}: {err:#}");
+        }
+    }
+    eprintln!(
+        "Renamed {} to {}, moving {} tokens.",
+        old.bold(),
+        new.bold(),
+        moved.len()
+    );
+
+    if rename_provider(&old, &new)? {
+        eprintln!("Renamed the provider for {} in oauth.toml.", old.bold());
+        if move_client_secret(&old, &new)? {
+            eprintln!("Moved its client secret in the keyring.");
+        }
+    }
+    if let Ok(cfg) = ctx.oauth.load()
+        && cfg.find_provider(&new).is_none()
+    {
+        styled_warning(cfg.missing_provider_message(&new));
+    }
+    if let Ok(mut stats) = load_cfg!(Stats)
+        && stats.rename_host(&old, &new)
+    {
+        stats.write()?;
+    }
+    if remotes {
+        rewrite_remotes(&old, &new, false)?;
+    }
+    Ok(())
+}
+
+/// Move the client secret `client_secret = "keyring"` takes from the keyring
+/// from the provider of `old` to that of `new`, returning whether there was one
+fn move_client_secret(old: &str, new: &str) -> Result<bool> {
+    let Ok(secret) = get_keyring_secret(&client_secret_entry(old), STATE_HOST) else {
+        return Ok(false);
+    };
+    store_keyring_secret(&client_secret_entry(new), STATE_HOST, &secret)
+        .context("Failed to move the client secret")?;
+    if let Err(err) = erase_keyring_secret(&client_secret_entry(old), STATE_HOST) {
+        warn!("Failed to erase the client secret of {old}: {err:#}");
+    }
+    Ok(true)
+}
+
+/// Point the remotes of the current repository that use `old` at `new`, or
+/// only list them with `dry_run`
+fn rewrite_remotes(old: &str, new: &str, dry_run: bool) -> Result<()> {
+    let Ok(RepoContext { repo, .. }) = repo_context(None) else {
+        styled_error("Not a git repository!");
+        bail!("Not a git repository!");
+    };
+    let names = repo.remotes().context("Failed to list remotes")?;
+    for name in names.iter().flatten() {
+        let remote = repo.find_remote(name)?;
+        let url = remote.url().and_then(|url| replace_host(url, old, new));
+        let push_url = remote.pushurl().and_then(|url| replace_host(url, old, new));
+        if url.is_none() && push_url.is_none() {
+            continue;
+        }
+        if dry_run {
+            eprintln!("Would point remote {} at {}.", name.bold(), new.bold());
+            continue;
+        }
+        if let Some(url) = url {
+            repo.remote_set_url(name, &url)?;
+        }
+        if let Some(url) = push_url {
+            repo.remote_set_pushurl(name, Some(&url))?;
+        }
+        eprintln!("Pointed remote {} at {}.", name.bold(), new.bold());
+    }
+    Ok(())
+}
+
+/// `url` with its host replaced by `new` if it is `old`, for URLs like
+/// `https://host/path`, `ssh://user@host:port/path` and `user@host:path`. A
+/// port on an SSH remote is kept.
+fn replace_host(url: &str, old: &str, new: &str) -> Option<String> {
+    let (prefix, rest) = url
+        .split_once("://")
+        .map_or(("", url), |(scheme, rest)| (scheme, rest));
+    let (authority, path) = if prefix.is_empty() {
+        // scp-like syntax, no scheme and the path after the first colon
+        let (authority, path) = rest.split_once(':')?;
+        (authority, format!(":{path}"))
+    } else {
+        let (authority, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+        (authority, path.to_string())
+    };
+    let (user, host) = authority
+        .rsplit_once('@')
+        .map_or((String::new(), authority), |(user, host)| {
+            (format!("{user}@"), host)
+        });
+    let host = if normalize_host(host) == old {
+        new.to_string()
+    } else {
+        let (name, port) = split_port(host);
+        let (old_name, old_port) = split_port(old);
+        let port = port.filter(|_| old_port.is_none() && name.eq_ignore_ascii_case(old_name))?;
+        format!("{}:{port}", split_port(new).0)
+    };
+    let scheme = if prefix.is_empty() {
+        String::new()
+    } else {
+        format!("{prefix}://")
+    };
+    Some(format!("{scheme}{user}{host}{path}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remote_urls_follow_the_host() {
+        for (url, expected) in [
+            (
+                "https://old.example.com/group/repo.git",
+                Some("https://new.example.com/group/repo.git"),
+            ),
+            (
+                "https://oauth@Old.Example.com:443/repo",
+                Some("https://oauth@new.example.com/repo"),
+            ),
+            (
+                "ssh://git@old.example.com:2222/group/repo.git",
+                Some("ssh://git@new.example.com:2222/group/repo.git"),
+            ),
+            (
+                "git@old.example.com:group/repo.git",
+                Some("git@new.example.com:group/repo.git"),
+            ),
+            ("https://other.example.com/old.example.com/repo", None),
+            ("/srv/git/repo.git", None),
+        ] {
+            assert_eq!(
+                replace_host(url, "old.example.com", "new.example.com").as_deref(),
+                expected,
+                "{url}"
+            );
+        }
+    }
+}
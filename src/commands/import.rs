@@ -0,0 +1,326 @@
+use std::path::{Path, PathBuf};
+use std::{env, fs};
+
+use anyhow::{Context as _, Result, anyhow};
+use colored::Colorize as _;
+use serde::de::DeserializeOwned;
+use toml_edit::{ArrayOfTables, DocumentMut, Item, Table};
+use tracing::instrument;
+
+use crate::commands::common::{styled_note, styled_warning};
+use crate::config::ProfileConfig;
+use crate::config::persist::write_with_backup;
+use crate::profile::Profile;
+use crate::profile::rule::Rule;
+use crate::profile::url::Pattern;
+
+/// Keys of a ghr rule warden understands
+const RULE_KEYS: [&str; 4] = ["profile", "host", "owner", "repo"];
+/// Keys of a ghr pattern warden understands
+const PATTERN_KEYS: [&str; 8] = [
+    "regex", "vcs", "scheme", "user", "host", "owner", "url", "infer",
+];
+
+/// What a ghr config translated to
+#[derive(Debug, Default)]
+struct Report {
+    profiles: Vec<String>,
+    rules: usize,
+    patterns: usize,
+    /// What couldn't be translated, and why
+    skipped: Vec<String>,
+}
+
+/// Copy the profiles, rules and patterns of ghr's `ghr.toml` into
+/// profiles.toml, reporting what has no counterpart in warden. Only lists the
+/// changes with `dry_run`.
+#[instrument]
+pub fn from_ghr(path: Option<&Path>, dry_run: bool) -> Result<()> {
+    let source = match path {
+        Some(path) => path.to_path_buf(),
+        None => ghr_config_path()?,
+    };
+    let ghr = read_document(&source)?.context(format!("{} not found", source.display()))?;
+    let target = ProfileConfig::path()?;
+    let mut doc = read_document(&target)?.unwrap_or_default();
+
+    let report = translate(&ghr, &mut doc)?;
+    for skipped in &report.skipped {
+        styled_warning(format!("Not imported: {skipped}"));
+    }
+    if report.profiles.is_empty() && report.rules == 0 && report.patterns == 0 {
+        eprintln!("Nothing to import from {}.", source.display());
+        return Ok(());
+    }
+    if !report.profiles.is_empty() {
+        eprintln!("{}: {}", "profiles".bold(), report.profiles.join(", "));
+    }
+    let summary = format!(
+        "{} profiles, {} rules and {} patterns",
+        report.profiles.len(),
+        report.rules,
+        report.patterns
+    );
+    if dry_run {
+        eprintln!("Would import {summary} into {}.", target.display());
+        return Ok(());
+    }
+    write_with_backup(&target, &doc.to_string())?;
+    eprintln!(
+        "Imported {summary} into {}.",
+        target.display().to_string().bold()
+    );
+    styled_note(format!(
+        "Check the result with {}.",
+        format!("{} list", env!("CARGO_PKG_NAME")).blue()
+    ));
+    Ok(())
+}
+
+/// `ghr.toml` in ghr's root, `$GHR_ROOT` or `~/.ghr`
+fn ghr_config_path() -> Result<PathBuf> {
+    let root = match env::var_os("GHR_ROOT") {
+        Some(root) => PathBuf::from(root),
+        None => {
+            dirs::home_dir()
+                .context("Failed to get home directory")?
+                .join(".ghr")
+        },
+    };
+    Ok(root.join("ghr.toml"))
+}
+
+/// The TOML document at `path`, `None` if there is no such file
+fn read_document(path: &Path) -> Result<Option<DocumentMut>> {
+    match fs::read_to_string(path) {
+        Ok(contents) => {
+            Ok(Some(contents.parse::<DocumentMut>().with_context(
+                || format!("Failed to parse {}", path.display()),
+            )?))
+        },
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err).with_context(|| format!("Failed to read {}", path.display())),
+    }
+}
+
+/// Add what `ghr` configures to the profiles.toml `doc`. Profiles `doc`
+/// already has and rules or patterns it already contains are left alone.
+fn translate(ghr: &DocumentMut, doc: &mut DocumentMut) -> Result<Report> {
+    let mut report = Report::default();
+    for (key, item) in ghr.iter() {
+        match key {
+            "profiles" => translate_profiles(item, doc, &mut report)?,
+            "rules" => {
+                report.rules = append_tables(item, doc, "rules", &RULE_KEYS, &mut report, |t| {
+                    parse::<Rule>(t).map(|_| ())
+                })?;
+            },
+            "patterns" => {
+                report.patterns =
+                    append_tables(item, doc, "patterns", &PATTERN_KEYS, &mut report, |t| {
+                        parse::<Pattern>(t)?.regex().map(|_| ())
+                    })?;
+            },
+            other => {
+                report
+                    .skipped
+                    .push(format!("[{other}], warden has no such settings"));
+            },
+        }
+    }
+    Ok(report)
+}
+
+fn translate_profiles(item: &Item, doc: &mut DocumentMut, report: &mut Report) -> Result<()> {
+    let Some(profiles) = item.as_table_like() else {
+        report.skipped.push("profiles, not a table".into());
+        return Ok(());
+    };
+    let target = doc
+        .entry("profiles")
+        .or_insert_with(|| {
+            let mut table = Table::new();
+            table.set_implicit(true);
+            Item::Table(table)
+        })
+        .as_table_mut()
+        .context("profiles in profiles.toml is not a table")?;
+    for (name, profile) in profiles.iter() {
+        let Some(table) = profile.clone().into_table().ok() else {
+            report
+                .skipped
+                .push(format!("profile '{name}', not a table"));
+            continue;
+        };
+        if target.contains_key(name) {
+            report
+                .skipped
+                .push(format!("profile '{name}', profiles.toml already has one"));
+            continue;
+        }
+        if let Err(err) = parse::<Profile>(&table) {
+            report.skipped.push(format!("profile '{name}', {err:#}"));
+            continue;
+        }
+        target.insert(name, Item::Table(table));
+        report.profiles.push(name.to_string());
+    }
+    Ok(())
+}
+
+/// Append the tables of the array `item` to the array of tables `key` of
+/// `doc`, checking each with `check`. Returns how many were appended.
+fn append_tables<F: Fn(&Table) -> Result<()>>(
+    item: &Item,
+    doc: &mut DocumentMut,
+    key: &str,
+    known: &[&str],
+    report: &mut Report,
+    check: F,
+) -> Result<usize> {
+    let tables: Vec<Table> = match item {
+        Item::ArrayOfTables(array) => array.iter().cloned().collect(),
+        Item::Value(value) if value.is_array() => {
+            let array = value.as_array().context("Not an array")?;
+            array
+                .iter()
+                .filter_map(|v| v.as_inline_table().map(|t| t.clone().into_table()))
+                .collect()
+        },
+        Item::None | Item::Value(_) | Item::Table(_) => {
+            report.skipped.push(format!("{key}, not a list"));
+            return Ok(0);
+        },
+    };
+    let target = doc
+        .entry(key)
+        .or_insert_with(|| Item::ArrayOfTables(ArrayOfTables::new()))
+        .as_array_of_tables_mut()
+        .with_context(|| format!("{key} in profiles.toml is not an array of tables"))?;
+    let existing: Vec<String> = target.iter().map(canonical).collect();
+
+    let mut appended = 0;
+    for (i, mut table) in tables.into_iter().enumerate() {
+        let label = format!("{} #{}", key.trim_end_matches('s'), i + 1);
+        if let Err(err) = check(&table) {
+            report.skipped.push(format!("{label}, {err:#}"));
+            continue;
+        }
+        let unknown: Vec<String> = table
+            .iter()
+            .map(|(k, _)| k.to_string())
+            .filter(|k| !known.contains(&k.as_str()))
+            .collect();
+        for k in &unknown {
+            table.remove(k);
+        }
+        if !unknown.is_empty() {
+            report
+                .skipped
+                .push(format!("{} of {label}", unknown.join(", ")));
+        }
+        if existing.contains(&canonical(&table)) {
+            continue;
+        }
+        table.decor_mut().clear();
+        target.push(table);
+        appended += 1;
+    }
+    Ok(appended)
+}
+
+/// `table` as TOML without comments and formatting, to spot duplicates
+fn canonical(table: &Table) -> String {
+    let mut table = table.clone();
+    table.fmt();
+    DocumentMut::from(table).to_string().trim().to_string()
+}
+
+fn parse<T: DeserializeOwned>(table: &Table) -> Result<T> {
+    let text = DocumentMut::from(table.clone()).to_string();
+    toml::from_str(&text).map_err(|err| anyhow!("{}", err.message()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GHR: &str = r#"
+[defaults]
+owner = "alice"
+
+[profiles.default]
+user.name = "Alice"
+user.email = "alice@example.com"
+
+[profiles.work]
+user.name = "Alice"
+user.email = "alice@company.example"
+
+[[rules]]
+profile.name = "work"
+host = "github.com"
+owner = "company"
+
+[[rules]]
+profile.name = "default"
+host = "github.com"
+owner = "alice"
+
+[[patterns]]
+regex = "^(?P<host>[^:]+):(?P<owner>[^/]+)/(?P<repo>[^/]+)$"
+scheme = "ssh"
+user = "git"
+
+[[patterns]]
+regex = "(unclosed"
+
+[applications.vscode]
+cmd = "code"
+"#;
+
+    #[test]
+    fn ghr_config_is_translated() {
+        let ghr: DocumentMut = GHR.parse().unwrap();
+        let mut doc: DocumentMut = r#"
+# mine
+[profiles.work]
+user.email = "kept@company.example"
+
+[[rules]]
+profile.name = "default"
+host = "github.com"
+owner = "alice"
+"#
+        .parse()
+        .unwrap();
+
+        let report = translate(&ghr, &mut doc).unwrap();
+        assert_eq!(report.profiles, ["default"], "existing profile kept");
+        assert_eq!(report.rules, 1, "duplicate rule skipped");
+        assert_eq!(report.patterns, 1, "invalid pattern skipped");
+        let skipped = report.skipped.join("\n");
+        for reason in [
+            "[defaults]",
+            "[applications]",
+            "profile 'work'",
+            "pattern #2",
+        ] {
+            assert!(skipped.contains(reason), "{reason} reported: {skipped}");
+        }
+
+        let text = doc.to_string();
+        assert!(text.starts_with("\n# mine"), "comments kept: {text}");
+        let config: ProfileConfig = toml::from_str(&text).unwrap();
+        assert_eq!(
+            config.profiles["work"].configs.get("user.email").unwrap(),
+            "kept@company.example"
+        );
+        assert_eq!(
+            config.profiles["default"].configs.get("user.name").unwrap(),
+            "Alice"
+        );
+        assert_eq!(config.rules.iter().count(), 2);
+        assert!(config.validate().is_empty(), "{:?}", config.validate());
+    }
+}
@@ -0,0 +1,50 @@
+use std::fs;
+use std::io::{Read as _, stdin};
+use std::path::Path;
+
+use anyhow::{Context as _, Result, bail};
+use colored::Colorize as _;
+use tracing::instrument;
+
+use crate::commands::common::CredentialPair;
+use crate::commands::login::{confirm_overwrite, save_token};
+use crate::context::AppContext;
+use crate::transfer::{open, read_passphrase};
+use crate::utils::normalize_host;
+
+/// Store a token exported by `login --print-only`, read from `file` or stdin,
+/// under its credential name or `name`.
+#[instrument(skip(ctx))]
+pub fn import_token(ctx: &AppContext, file: Option<&Path>, name: Option<&str>) -> Result<()> {
+    let blob = if let Some(path) = file {
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?
+    } else {
+        let mut blob = String::new();
+        stdin()
+            .read_to_string(&mut blob)
+            .context("Failed to read the token from stdin")?;
+        blob
+    };
+    let passphrase = read_passphrase(false)?;
+    let transfer = open(&blob, &passphrase)?;
+
+    let pair = CredentialPair::new(
+        normalize_host(&transfer.host),
+        name.map_or_else(|| transfer.credential.clone(), str::to_string),
+    );
+    let mut hosts_config = ctx.hosts.load()?;
+    if hosts_config.has_credential(&pair.host, &pair.credential)
+        && !confirm_overwrite(&pair.host, &pair.credential)?
+    {
+        bail!("Not overwriting {}", pair.label_credential_host());
+    }
+    save_token(
+        ctx,
+        &mut hosts_config,
+        &pair.host,
+        &pair.credential,
+        &transfer.token,
+    )?;
+    eprintln!("Imported {}.", pair.label_credential_host().bold());
+    Ok(())
+}
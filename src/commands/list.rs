@@ -5,44 +5,74 @@
 // Local modifications:
 // Copyright (c) 2025 Adolar0042
 
+use std::io::stdout;
+
 use anyhow::{Result, bail};
-use colored::Colorize as _;
+use serde::Serialize;
 use tracing::instrument;
 
-use crate::commands::common::styled_error;
-use crate::config::ProfileConfig;
-use crate::load_cfg;
+use crate::commands::common::{load_profiles, styled_error};
+use crate::context::AppContext;
+use crate::output::OutputFormat;
+use crate::profile::Profile;
+use crate::table::{Cell, Style, Table};
 
 const INHERIT: &str = "(inherit)";
 
-#[instrument]
-pub fn list(short: bool) -> Result<()> {
-    let profile_config = load_cfg!(ProfileConfig)?;
+/// A profile as printed by `list --output`
+#[derive(Serialize)]
+struct ListedProfile<'a> {
+    name: &'a str,
+    user_name: Option<&'a str>,
+    user_email: Option<&'a str>,
+    configs: &'a Profile,
+}
+
+#[instrument(skip(ctx))]
+pub fn list(ctx: &AppContext, short: bool, output: OutputFormat) -> Result<()> {
+    let profile_config = load_profiles(ctx)?;
     if profile_config.profiles.is_empty() {
         styled_error("No profiles found");
         bail!("No profiles found");
     }
-    profile_config.profiles.iter().for_each(|(name, profile)| {
-        if short {
+    if !output.is_text() {
+        let mut profiles: Vec<ListedProfile> = profile_config
+            .profiles
+            .iter()
+            .map(|(name, profile)| {
+                ListedProfile {
+                    name,
+                    user_name: profile.configs.get("user.name").map(String::as_str),
+                    user_email: profile.configs.get("user.email").map(String::as_str),
+                    configs: profile,
+                }
+            })
+            .collect();
+        profiles.sort_by_key(|p| p.name);
+        return output.print(&*ctx.io, &profiles);
+    }
+    let mut profiles: Vec<_> = profile_config.profiles.iter().collect();
+    profiles.sort_by_key(|(name, _)| *name);
+    if short {
+        for (name, _) in profiles {
             println!("{name}");
-        } else {
-            println!(
-                "  {}: {} {}",
-                name.bold(),
-                profile
-                    .configs
-                    .get("user.name")
-                    .map_or(INHERIT, |name| name.as_str()),
-                format!(
-                    "<{}>",
-                    profile
-                        .configs
-                        .get("user.email")
-                        .map_or(INHERIT, |email| email.as_str()),
-                )
-                .dimmed()
-            );
         }
-    });
+        return Ok(());
+    }
+    let mut table = Table::new(&["PROFILE", "NAME", "EMAIL"]);
+    for (name, profile) in profiles {
+        let config = |key: &str| {
+            profile.configs.get(key).map_or_else(
+                || Cell::styled(INHERIT, Style::Dimmed),
+                |value| Cell::new(value.as_str()),
+            )
+        };
+        table.push(vec![
+            Cell::styled(name.as_str(), Style::Bold),
+            config("user.name"),
+            config("user.email"),
+        ]);
+    }
+    print!("{}", table.render(&stdout()));
     Ok(())
 }
@@ -1,36 +1,92 @@
-use std::io::stderr;
+use std::io::{IsTerminal as _, stderr, stdin};
 use std::process::exit;
 
 use anyhow::{Context as _, Result, bail};
-use crossterm::cursor::Show;
-use crossterm::execute;
+use chrono::{Local, TimeDelta, Utc};
 use crossterm::style::Stylize as _;
 use dialoguer::{Confirm, Input};
 use tracing::instrument;
 
-use crate::config::{Hosts, OAuthConfig};
-use crate::keyring::store_keyring_token;
-use crate::load_cfg;
+use crate::commands::common::{
+    CredentialPair, check_deadline, collect_all_pairs, filter_pairs, labels_credential_host,
+    sort_pairs, styled_error, styled_note, styled_warning,
+};
+use crate::config::persist::write_atomic_private;
+use crate::config::{Hosts, OAuthConfig, ScopePreset};
+use crate::context::AppContext;
+use crate::forge::Forge;
+use crate::keyring::{Token, backend};
 use crate::oauth::get_access_token;
+use crate::oauth::progress::Progress;
 use crate::theme::InputTheme;
-use crate::utils::{config_dir, select_index};
-
-#[instrument]
-pub async fn login(force_device: bool) -> Result<()> {
-    let oauth_config = load_cfg!(OAuthConfig)?;
-    let mut hosts_config = load_cfg!(Hosts)?;
-    let _ = ctrlc::set_handler(|| {
-        let _ = execute!(stderr(), Show);
-        exit(130);
-    });
-    let credential_name: String = Input::with_theme(&InputTheme::default())
-        .with_prompt("Credential Name")
-        .default("oauth".to_string())
-        .interact_text()
-        .context("Failed to read credential name")?;
-    let credential_name = credential_name.trim();
-    if credential_name.is_empty() {
-        bail!("Credential name cannot be empty!");
+use crate::transfer::{ExportTarget, TokenTransfer, read_passphrase, seal};
+use crate::utils::{config_dir, normalize_host, select_index};
+
+/// Name of credentials when neither `--name` nor a template gives one
+const DEFAULT_NAME: &str = "oauth";
+/// Placeholder of credential name templates for the user the forge reports
+const USER_API_LOGIN: &str = "{{user_api_login}}";
+
+/// Variations of [`login`]
+#[derive(Debug, Default)]
+pub struct LoginOptions {
+    pub reauth: bool,
+    pub all: bool,
+    pub scopes_preset: Option<ScopePreset>,
+    pub export: Option<ExportTarget>,
+    /// How long the login lasts before its token is erased
+    pub ephemeral: Option<TimeDelta>,
+    /// Ask the forge whose the new token is
+    pub verify: bool,
+}
+
+/// Log in to a provider and store the token under a credential name. The
+/// host and name are prompted for unless given, the name defaulting to the
+/// provider's `credential_name_template`.
+///
+/// With `reauth`, an existing credential is logged in again and its token
+/// replaced, without touching the hosts state. With `all`, every configured
+/// provider is logged in to in turn under the same credential name.
+///
+/// `scopes_preset` requests the scopes of that preset for the provider type
+/// instead of the configured ones.
+///
+/// With `export`, the token is encrypted for `import-token` on another machine
+/// instead of being stored.
+///
+/// With `ephemeral`, the token is erased once that much time has passed.
+///
+/// The stored token is read back to catch token stores that lose it. With
+/// `verify`, the forge is also asked whose token it is.
+#[instrument(skip(ctx))]
+pub async fn login(
+    ctx: &AppContext,
+    hostname: Option<&str>,
+    name: Option<&str>,
+    options: LoginOptions,
+    force_device: bool,
+) -> Result<()> {
+    let LoginOptions {
+        reauth,
+        all,
+        scopes_preset,
+        ref export,
+        ephemeral,
+        verify,
+    } = options;
+    let mut oauth_config = ctx.oauth.load()?;
+    let mut hosts_config = ctx.hosts.load()?;
+    if reauth {
+        let pair = select_existing(&hosts_config, hostname, name)?;
+        if let Some(preset) = scopes_preset {
+            use_scope_preset(&mut oauth_config, &pair.host, preset)?;
+        }
+        reauthenticate(ctx, &oauth_config, &pair, force_device).await?;
+        if verify {
+            let token = ctx.token_store.get(&pair.credential, &pair.host)?;
+            probe(&oauth_config, &pair, &token).await?;
+        }
+        return Ok(());
     }
     let mut providers = oauth_config.providers.keys().collect::<Vec<_>>();
     if providers.is_empty() {
@@ -42,41 +98,546 @@ pub async fn login(force_device: bool) -> Result<()> {
                 .display()
         );
     }
-    providers.sort();
-    let selection = select_index(&providers, "Host").context("Failed to select host")?;
+    if all {
+        providers.sort();
+        let providers: Vec<String> = providers.into_iter().cloned().collect();
+        if let Some(preset) = scopes_preset {
+            for provider in &providers {
+                let host = normalize_host(provider);
+                if let Err(err) = use_scope_preset(&mut oauth_config, &host, preset) {
+                    styled_warning(format!("{err:#}, requesting the configured scopes."));
+                }
+            }
+        }
+        let credential_name = choose_name(&oauth_config, None, name)?;
+        return login_all(
+            ctx,
+            &oauth_config,
+            &mut hosts_config,
+            &providers,
+            &credential_name,
+            &options,
+            force_device,
+        )
+        .await;
+    }
+    let host = if let Some(hostname) = hostname {
+        let Some((host, _)) = oauth_config.find_provider(hostname) else {
+            styled_error(oauth_config.missing_provider_message(hostname));
+            bail!("No OAuth provider configured for {hostname}");
+        };
+        host
+    } else {
+        providers.sort();
+        let selection = select_index(&providers, "Host").context("Failed to select host")?;
+        normalize_host(providers[selection])
+    };
+    if let Some(preset) = scopes_preset {
+        use_scope_preset(&mut oauth_config, &host, preset)?;
+    }
+
+    let choice = choose_name(&oauth_config, Some(&host), name)?;
 
     // if host already has a credential under that name, ask for confirmation
-    if hosts_config.has_credential(providers[selection], credential_name) {
-        let _ = ctrlc::set_handler(|| {
-            let _ = execute!(stderr(), Show);
-            exit(130);
+    if let CredentialName::Chosen(credential_name) = &choice
+        && export.is_none()
+        && hosts_config.has_credential(&host, credential_name)
+        && !confirm_overwrite(&host, credential_name)?
+    {
+        exit(1);
+    }
+
+    let token = get_access_token(&oauth_config, &host, force_device)
+        .await
+        .context("Failed to get access token")?
+        .with_deadline(ephemeral.map(|lifetime| Utc::now() + lifetime));
+
+    let credential_name = match choice {
+        CredentialName::Chosen(credential_name) => credential_name,
+        CredentialName::Template => {
+            let credential_name = render_name(&oauth_config, &host, &token).await?;
+            if export.is_none()
+                && hosts_config.has_credential(&host, &credential_name)
+                && !confirm_overwrite(&host, &credential_name)?
+            {
+                exit(1);
+            }
+            credential_name
+        },
+    };
+
+    if let Some(target) = export {
+        return export_token(ctx, &host, &credential_name, token, target);
+    }
+    save_token(ctx, &mut hosts_config, &host, &credential_name, &token)?;
+    note_deadline(&token);
+    if verify {
+        probe(
+            &oauth_config,
+            &CredentialPair::new(host, credential_name),
+            &token,
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// Ask the forge whose `token` is and tell, failing if it doesn't accept the
+/// token
+async fn probe(oauth_config: &OAuthConfig, pair: &CredentialPair, token: &Token) -> Result<()> {
+    let provider = oauth_config
+        .find_provider(&pair.host)
+        .map(|(_, provider)| provider);
+    let forge = Forge::with_token(pair.clone(), token, provider)?;
+    let user = forge.current_user().await.with_context(|| {
+        format!(
+            "The forge didn't accept the stored token of {}",
+            pair.label_credential_host()
+        )
+    })?;
+    eprintln!(
+        "Verified {}, logged in as {}.",
+        pair.label_credential_host(),
+        user.bold()
+    );
+    Ok(())
+}
+
+/// How the credential of a new login is named
+#[derive(Debug, PartialEq, Eq)]
+enum CredentialName {
+    /// Given with `--name` or typed at the prompt
+    Chosen(String),
+    /// The provider's `credential_name_template`, filled in once the token
+    /// is there
+    Template,
+}
+
+/// The name for a login to `host`, or to every provider without one. Unless
+/// given, it is prompted for with the credential name template as default.
+/// Without a terminal to prompt on, the template is used.
+fn choose_name(
+    oauth_config: &OAuthConfig,
+    host: Option<&str>,
+    name: Option<&str>,
+) -> Result<CredentialName> {
+    let name = if let Some(name) = name {
+        name.to_string()
+    } else {
+        if !stdin().is_terminal() || !stderr().is_terminal() {
+            return Ok(CredentialName::Template);
+        }
+        let template = host.map_or(oauth_config.credential_name_template.as_deref(), |host| {
+            name_template(oauth_config, host)
         });
-        eprintln!(
-            "{}",
+        let default = template.unwrap_or(DEFAULT_NAME);
+        let name: String = Input::with_theme(&InputTheme::default())
+            .with_prompt("Credential Name")
+            .default(default.to_string())
+            .interact_text()
+            .context("Failed to read credential name")?;
+        if name.trim() == default {
+            return Ok(CredentialName::Template);
+        }
+        name
+    };
+    let name = name.trim();
+    if name.is_empty() {
+        bail!("Credential name cannot be empty!");
+    }
+    Ok(CredentialName::Chosen(name.to_string()))
+}
+
+/// The `credential_name_template` of the provider of `host`, or the
+/// top-level one
+fn name_template<'a>(oauth_config: &'a OAuthConfig, host: &str) -> Option<&'a str> {
+    oauth_config
+        .find_provider(host)
+        .and_then(|(_, provider)| provider.credential_name_template.as_deref())
+        .or(oauth_config.credential_name_template.as_deref())
+}
+
+/// The credential name template of `host` filled in, asking the forge who
+/// `token` belongs to if the template wants to know
+async fn render_name(oauth_config: &OAuthConfig, host: &str, token: &Token) -> Result<String> {
+    let template = name_template(oauth_config, host).unwrap_or(DEFAULT_NAME);
+    let user = if template.contains(USER_API_LOGIN) {
+        let provider = oauth_config
+            .find_provider(host)
+            .map(|(_, provider)| provider);
+        let forge = Forge::with_token(CredentialPair::new(host, template), token, provider)?;
+        let user = forge
+            .current_user()
+            .await
+            .context(format!("Failed to name the credential after '{template}'"))?;
+        Some(user)
+    } else {
+        None
+    };
+    fill_template(template, host, user.as_deref())
+}
+
+/// `template` with `{{host}}` and `{{user_api_login}}` replaced by `host` and
+/// `user`
+fn fill_template(template: &str, host: &str, user: Option<&str>) -> Result<String> {
+    let mut name = template.replace("{{host}}", host);
+    if let Some(user) = user {
+        name = name.replace(USER_API_LOGIN, user);
+    }
+    if name.contains("{{") {
+        bail!(
+            "Unknown placeholder in the credential name template '{template}', use {{{{host}}}} \
+             or {USER_API_LOGIN}"
+        );
+    }
+    let name = name.trim();
+    if name.is_empty() {
+        bail!("The credential name template '{template}' gives an empty name");
+    }
+    Ok(name.to_string())
+}
+
+/// Tell when the token of an ephemeral login will be erased
+fn note_deadline(token: &Token) {
+    if let Some(deadline) = token.deadline {
+        styled_note(format!(
+            "This login ends at {}, its token is erased when used afterwards.",
+            deadline
+                .with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M")
+                .to_string()
+                .bold()
+        ));
+    }
+}
+
+/// Store `token` in the keyring and read it back, failing if the keyring
+/// returns something else
+fn store_verified(
+    ctx: &AppContext,
+    credential_name: &str,
+    host: &str,
+    token: &Token,
+) -> Result<()> {
+    ctx.token_store
+        .store(credential_name, host, token)
+        .context("Failed to store token in keyring")?;
+    let stored = ctx
+        .token_store
+        .get(credential_name, host)
+        .with_context(|| {
             format!(
-                "A credential with the name '{}' already exists for host '{}'.",
-                credential_name, providers[selection]
+                "The {} token store lost the token of {credential_name} ({host}) right after \
+                 storing it",
+                backend()
             )
-            .bold()
+        })?;
+    if stored.fingerprint() != token.fingerprint() {
+        bail!(
+            "The {} token store returned another token for {credential_name} ({host}) than the \
+             one just stored",
+            backend()
         );
-        let confirm = Confirm::with_theme(&InputTheme::default())
-            .with_prompt("Do you want to overwrite it?")
-            .default(false)
-            .interact_opt()
-            .context("Failed to confirm overwrite")?;
-        if confirm.is_none_or(|b| !b) {
-            exit(1);
+    }
+    Ok(())
+}
+
+/// Store `token` in the keyring and record the credential in the hosts state.
+pub fn save_token(
+    ctx: &AppContext,
+    hosts_config: &mut Hosts,
+    host: &str,
+    credential_name: &str,
+    token: &Token,
+) -> Result<()> {
+    let progress = Progress::start("Storing the token");
+    store_verified(ctx, credential_name, host, token)?;
+    let added = hosts_config.add_credential(host, credential_name);
+    if hosts_config.set_scopes(host, credential_name, token.scopes.clone()) || added {
+        ctx.hosts
+            .save(hosts_config)
+            .context("Failed to add credential to hosts state")?;
+    }
+    progress.finish();
+    Ok(())
+}
+
+/// Encrypt `token` with a passphrase and print it or write it to a file.
+fn export_token(
+    ctx: &AppContext,
+    host: &str,
+    credential_name: &str,
+    token: Token,
+    target: &ExportTarget,
+) -> Result<()> {
+    let passphrase = read_passphrase(true)?;
+    let blob = seal(
+        &TokenTransfer {
+            host: host.to_string(),
+            credential: credential_name.to_string(),
+            token,
+        },
+        &passphrase,
+    )?;
+    match target {
+        ExportTarget::Stdout => ctx.io.print(&format!("{blob}\n")),
+        ExportTarget::File(path) => {
+            write_atomic_private(path, &format!("{blob}\n"))?;
+            eprintln!("Wrote the encrypted token to {}.", path.display());
+        },
+    }
+    eprintln!(
+        "Run {} on the other machine to store it.",
+        format!("{} import-token", env!("CARGO_PKG_NAME")).blue()
+    );
+    Ok(())
+}
+
+/// Log in to each of `providers` under `credential_name`, or the name its
+/// template gives, carrying on past failures and declined overwrites. Only
+/// the `ephemeral` and `verify` of `options` apply.
+async fn login_all(
+    ctx: &AppContext,
+    oauth_config: &OAuthConfig,
+    hosts_config: &mut Hosts,
+    providers: &[String],
+    credential_name: &CredentialName,
+    options: &LoginOptions,
+    force_device: bool,
+) -> Result<()> {
+    let mut failed = Vec::new();
+    for (i, provider) in providers.iter().enumerate() {
+        let host = normalize_host(provider);
+        eprintln!(
+            "{}",
+            format!("[{}/{}] {host}", i + 1, providers.len()).bold()
+        );
+        if let CredentialName::Chosen(name) = credential_name
+            && hosts_config.has_credential(&host, name)
+            && !confirm_overwrite(&host, name)?
+        {
+            eprintln!("Skipped {host}.");
+            continue;
+        }
+        let token = match get_access_token(oauth_config, &host, force_device).await {
+            Ok(token) => {
+                token.with_deadline(options.ephemeral.map(|lifetime| Utc::now() + lifetime))
+            },
+            Err(err) => {
+                styled_error(format!(
+                    "{host}: {:#}",
+                    err.context("Failed to get access token")
+                ));
+                failed.push(host);
+                continue;
+            },
+        };
+        let name = match credential_name {
+            CredentialName::Chosen(name) => Ok(name.clone()),
+            CredentialName::Template => render_name(oauth_config, &host, &token).await,
+        };
+        let name = match name {
+            Ok(name) => name,
+            Err(err) => {
+                styled_error(format!("{host}: {err:#}"));
+                failed.push(host);
+                continue;
+            },
+        };
+        if matches!(credential_name, CredentialName::Template)
+            && hosts_config.has_credential(&host, &name)
+            && !confirm_overwrite(&host, &name)?
+        {
+            eprintln!("Skipped {host}.");
+            continue;
+        }
+        let mut result =
+            save_token(ctx, hosts_config, &host, &name, &token).inspect(|()| note_deadline(&token));
+        if options.verify && result.is_ok() {
+            result = probe(
+                oauth_config,
+                &CredentialPair::new(host.clone(), name),
+                &token,
+            )
+            .await;
         }
+        if let Err(err) = result {
+            styled_error(format!("{host}: {err:#}"));
+            failed.push(host);
+        }
+    }
+    if !failed.is_empty() {
+        bail!("Login failed for {}", failed.join(", "));
     }
+    Ok(())
+}
 
-    let token = get_access_token(&oauth_config, providers[selection], force_device)
-        .await
-        .context("Failed to get access token")?;
+/// Request the scopes of `preset` from the provider of the canonical `host`
+fn use_scope_preset(oauth_config: &mut OAuthConfig, host: &str, preset: ScopePreset) -> Result<()> {
+    let provider = oauth_config
+        .providers
+        .iter_mut()
+        .find_map(|(key, provider)| (normalize_host(key) == host).then_some(provider))
+        .with_context(|| format!("No OAuth provider configured for {host}"))?;
+    provider
+        .apply_scope_preset(preset)
+        .with_context(|| format!("No scope preset for {host}"))
+}
 
-    store_keyring_token(credential_name, providers[selection], &token)
-        .context("Failed to store token in keyring")?;
-    hosts_config
-        .add_credential(providers[selection], credential_name)
-        .context("Failed to add credential to hosts state")?;
+pub fn confirm_overwrite(host: &str, credential_name: &str) -> Result<bool> {
+    eprintln!(
+        "{}",
+        format!("A credential with the name '{credential_name}' already exists for host '{host}'.")
+            .bold()
+    );
+    let confirm = Confirm::with_theme(&InputTheme::default())
+        .with_prompt("Do you want to overwrite it?")
+        .default(false)
+        .interact_opt()
+        .context("Failed to confirm overwrite")?;
+    Ok(confirm.is_some_and(|b| b))
+}
+
+/// The existing credential matching `host` and `name`, prompting when several
+/// do.
+fn select_existing(
+    hosts_config: &Hosts,
+    host: Option<&str>,
+    name: Option<&str>,
+) -> Result<CredentialPair> {
+    let mut pairs = collect_all_pairs(hosts_config);
+    sort_pairs(&mut pairs);
+    let mut filtered = filter_pairs(&pairs, host, name);
+    match filtered.len() {
+        0 => {
+            styled_error("No matching credential found to log in again.");
+            bail!("No matching credential found");
+        },
+        1 => Ok(filtered.remove(0)),
+        _ => {
+            let labels = labels_credential_host(&filtered);
+            let selection = select_index(&labels, "Select a credential to log in again")?;
+            Ok(filtered.remove(selection))
+        },
+    }
+}
+
+/// Run a full OAuth flow for an existing credential and replace its token.
+/// An ephemeral login keeps its deadline.
+pub async fn reauthenticate(
+    ctx: &AppContext,
+    oauth_config: &OAuthConfig,
+    pair: &CredentialPair,
+    force_device: bool,
+) -> Result<()> {
+    let deadline = match ctx.token_store.get(&pair.credential, &pair.host) {
+        Ok(previous) => {
+            check_deadline(ctx, pair, &previous).await?;
+            previous.deadline
+        },
+        Err(_) => None,
+    };
+    let token = get_access_token(oauth_config, &pair.host, force_device)
+        .await
+        .context("Failed to get access token")?
+        .with_deadline(deadline);
+    let progress = Progress::start("Storing the token");
+    store_verified(ctx, &pair.credential, &pair.host, &token)?;
+    progress.finish();
+    eprintln!(
+        "Replaced the token of {}.",
+        pair.label_credential_host().bold()
+    );
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::context::{TokenStore, mock};
+
+    /// A token store that hands back a stale token
+    struct Stale;
+
+    impl TokenStore for Stale {
+        fn get(&self, _: &str, _: &str) -> Result<Token> {
+            Ok(Token::new("stale".into(), None, None))
+        }
+
+        fn store(&self, _: &str, _: &str, _: &Token) -> Result<()> {
+            Ok(())
+        }
+
+        fn erase(&self, _: &str, _: &str) -> Result<()> {
+            Ok(())
+        }
+
+        fn list(&self) -> Result<Vec<CredentialPair>> {
+            Ok(Vec::new())
+        }
+    }
+
+    #[test]
+    fn stored_tokens_are_read_back() {
+        let (mut ctx, _) = mock::context(Hosts::from_map(HashMap::new()));
+        let mut hosts_config = ctx.hosts.load().unwrap();
+        let token = Token::new("gho_token".into(), Some("ghr_refresh".into()), None);
+        save_token(&ctx, &mut hosts_config, "github.com", "alice", &token).unwrap();
+        assert!(hosts_config.has_credential("github.com", "alice"));
+
+        ctx.token_store = Box::new(Stale);
+        let mut hosts_config = Hosts::from_map(HashMap::new());
+        let err = save_token(&ctx, &mut hosts_config, "github.com", "alice", &token).unwrap_err();
+        assert!(format!("{err:#}").contains("another token"), "{err:#}");
+        assert!(
+            !hosts_config.has_credential("github.com", "alice"),
+            "not recorded"
+        );
+    }
+
+    #[test]
+    fn credential_names_are_filled_from_the_template() {
+        let oauth_config: OAuthConfig = toml::from_str(
+            r#"
+            credential_name_template = "{{host}}-main"
+            [providers."github.com"]
+            client_id = "id"
+            credential_name_template = "{{user_api_login}}"
+            [providers."gitlab.com"]
+            client_id = "id"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            name_template(&oauth_config, "github.com"),
+            Some("{{user_api_login}}")
+        );
+        assert_eq!(
+            name_template(&oauth_config, "gitlab.com"),
+            Some("{{host}}-main"),
+            "top-level default"
+        );
+        assert_eq!(
+            choose_name(&oauth_config, Some("github.com"), None).unwrap(),
+            CredentialName::Template,
+            "no terminal to prompt on"
+        );
+        assert_eq!(
+            choose_name(&oauth_config, None, Some(" work ")).unwrap(),
+            CredentialName::Chosen("work".into())
+        );
+
+        assert_eq!(
+            fill_template("{{host}}-main", "gitlab.com", None).unwrap(),
+            "gitlab.com-main"
+        );
+        assert_eq!(
+            fill_template(USER_API_LOGIN, "github.com", Some("alice")).unwrap(),
+            "alice"
+        );
+        fill_template("{{owner}}", "github.com", None).unwrap_err();
+        fill_template(" ", "github.com", None).unwrap_err();
+    }
+}
@@ -3,21 +3,40 @@ use colored::Colorize as _;
 use tracing::instrument;
 
 use crate::commands::common::{
-    collect_all_pairs, filter_pairs, labels_credential_host, sort_pairs, styled_error,
+    CredentialPair, collect_all_pairs, confirm_destructive, filter_pairs, labels_credential_host,
+    revoke_and_erase, sort_pairs, styled_error,
 };
 use crate::config::Hosts;
-use crate::load_cfg;
+use crate::context::AppContext;
 use crate::utils::select_index;
 
-#[instrument]
-pub fn logout(hostname: Option<&String>, name: Option<&String>) -> Result<()> {
-    let mut hosts_config = load_cfg!(Hosts)?;
+/// Log out one credential, or with `all` every credential, on `hostname` if
+/// given. Only lists what would be removed with `dry_run`.
+#[instrument(skip(ctx))]
+pub async fn logout(
+    ctx: &AppContext,
+    hostname: Option<&String>,
+    name: Option<&String>,
+    all: bool,
+    dry_run: bool,
+) -> Result<()> {
+    let mut hosts_config = ctx.hosts.load()?;
     let mut pairs = collect_all_pairs(&hosts_config);
     if pairs.is_empty() {
         styled_error("No credentials found to logout");
         bail!("No credentials found to logout");
     }
     sort_pairs(&mut pairs);
+    if all {
+        return logout_all(
+            ctx,
+            hosts_config,
+            &pairs,
+            hostname.map(String::as_str),
+            dry_run,
+        )
+        .await;
+    }
 
     let filtered = filter_pairs(
         &pairs,
@@ -61,10 +80,22 @@ pub fn logout(hostname: Option<&String>, name: Option<&String>) -> Result<()> {
         let selection = select_index(&labels, prompt).context("Failed to select host")?;
         filtered[selection].clone()
     };
-    if !hosts_config
-        .remove_credential(&target.host, &target.credential)
-        .context("Failed to remove credential from hosts configuration")?
-    {
+    if dry_run {
+        eprintln!(
+            "Would log out {} and erase its token.",
+            target.label_credential_host()
+        );
+        return Ok(());
+    }
+    confirm_destructive(
+        ctx,
+        &format!(
+            "Log out {} and erase its token?",
+            target.label_credential_host()
+        ),
+    )?;
+    let _ = ctx.token_store.erase(&target.credential, &target.host);
+    if !hosts_config.remove_credential(&target.host, &target.credential) {
         let msg = format!(
             "Failed to remove credential {} for host {} from hosts configuration.",
             target.credential, target.host
@@ -72,6 +103,9 @@ pub fn logout(hostname: Option<&String>, name: Option<&String>) -> Result<()> {
         styled_error(&msg);
         bail!(msg);
     }
+    ctx.hosts
+        .save(&hosts_config)
+        .context("Failed to remove credential from hosts configuration")?;
     eprintln!(
         "Successfully logged out {} {}",
         target.credential,
@@ -79,3 +113,150 @@ pub fn logout(hostname: Option<&String>, name: Option<&String>) -> Result<()> {
     );
     Ok(())
 }
+
+/// Revoke where configured and erase the tokens of all `pairs` on `host`,
+/// then remove them from the hosts state, after one confirmation
+async fn logout_all(
+    ctx: &AppContext,
+    mut hosts_config: Hosts,
+    pairs: &[CredentialPair],
+    host: Option<&str>,
+    dry_run: bool,
+) -> Result<()> {
+    let targets = filter_pairs(pairs, host, None);
+    if targets.is_empty() {
+        let msg = format!("No credentials found for {}", host.unwrap_or_default());
+        styled_error(&msg);
+        bail!(msg);
+    }
+    eprintln!("This revokes where supported and erases:");
+    for target in &targets {
+        eprintln!("  {}", target.label_credential_host());
+    }
+    if dry_run {
+        return Ok(());
+    }
+    confirm_destructive(ctx, &format!("Log out all {} credentials?", targets.len()))?;
+
+    let oauth_config = ctx.oauth.load().ok();
+    for target in &targets {
+        let outcome = revoke_and_erase(ctx, oauth_config.as_ref(), target).await;
+        eprintln!("{}: {outcome}", target.label_credential_host());
+        hosts_config.remove_credential(&target.host, &target.credential);
+    }
+    ctx.hosts
+        .save(&hosts_config)
+        .context("Failed to remove credentials from hosts configuration")?;
+    eprintln!("Successfully logged out {} credentials", targets.len());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::hosts::HostConfig;
+    use crate::context::mock;
+    use crate::keyring::Token;
+
+    /// A context with alice and bob on each of `hosts`, all with tokens
+    fn context(hosts: &[&str]) -> AppContext {
+        let (ctx, _) = mock::context(Hosts::from_map(
+            hosts
+                .iter()
+                .map(|host| {
+                    (
+                        (*host).to_string(),
+                        HostConfig {
+                            active: "alice".into(),
+                            credentials: vec!["alice".into(), "bob".into()],
+                            session_previous: None,
+                        },
+                    )
+                })
+                .collect(),
+        ));
+        for host in hosts {
+            for credential in ["alice", "bob"] {
+                let token = Token::new(format!("gho_{credential}"), None, None);
+                ctx.token_store.store(credential, host, &token).unwrap();
+            }
+        }
+        ctx
+    }
+
+    #[tokio::test]
+    async fn logout_forgets_the_credential_and_its_token() {
+        let ctx = context(&["github.com"]);
+
+        logout(
+            &ctx,
+            Some(&"github.com".into()),
+            Some(&"bob".into()),
+            false,
+            true,
+        )
+        .await
+        .unwrap();
+        assert!(
+            ctx.hosts
+                .load()
+                .unwrap()
+                .has_credential("github.com", "bob"),
+            "dry run changes nothing"
+        );
+
+        logout(
+            &ctx,
+            Some(&"github.com".into()),
+            Some(&"alice".into()),
+            false,
+            false,
+        )
+        .await
+        .unwrap();
+        let hosts = ctx.hosts.load().unwrap();
+        assert!(!hosts.has_credential("github.com", "alice"), "removed");
+        assert_eq!(
+            hosts.get_active_credential("github.com"),
+            Some("bob"),
+            "next credential becomes active"
+        );
+        assert!(
+            ctx.token_store.get("alice", "github.com").is_err(),
+            "erased"
+        );
+        assert!(ctx.token_store.get("bob", "github.com").is_ok(), "kept");
+    }
+
+    #[tokio::test]
+    async fn logout_all_removes_every_credential_of_the_host() {
+        let ctx = context(&["github.com", "gitlab.com"]);
+        logout(&ctx, Some(&"GitHub.com".into()), None, true, true)
+            .await
+            .unwrap();
+        assert!(
+            ctx.hosts.load().unwrap().has_host("github.com"),
+            "dry run changes nothing"
+        );
+
+        logout(&ctx, Some(&"github.com".into()), None, true, false)
+            .await
+            .unwrap();
+        let hosts = ctx.hosts.load().unwrap();
+        assert!(!hosts.has_host("github.com"), "all credentials removed");
+        for credential in ["alice", "bob"] {
+            assert!(
+                ctx.token_store.get(credential, "github.com").is_err(),
+                "{credential} erased"
+            );
+            assert!(
+                ctx.token_store.get(credential, "gitlab.com").is_ok(),
+                "{credential} kept on other hosts"
+            );
+        }
+        assert!(
+            hosts.has_credential("gitlab.com", "bob"),
+            "other hosts kept"
+        );
+    }
+}
@@ -1,48 +1,178 @@
+use std::fmt::Write as _;
+
 use anyhow::{Context as _, Result};
+use tracing::debug;
 
+use crate::coalesce;
 use crate::config::ProviderConfig;
+use crate::context::Io;
 use crate::keyring::Token;
+use crate::trace_io::{self, Direction};
 
 pub mod apply;
+pub mod audit;
 pub mod common;
+pub mod completions;
+pub mod debug;
+pub mod docker_credential;
+pub mod env;
 pub mod erase;
+pub mod exec;
+pub mod fix_authors;
 pub mod get;
+pub mod guard;
+pub mod handoff;
+pub mod hosts;
+pub mod import;
+pub mod import_token;
 pub mod list;
 pub mod login;
 pub mod logout;
+pub mod nuke;
+pub mod onboard;
+pub mod pattern;
+pub mod provider;
+pub mod prune;
 pub mod refresh;
+pub mod repo;
+pub mod rule;
+pub mod session;
 pub mod show;
+pub mod stats;
 pub mod status;
 pub mod store;
 pub mod switch;
+pub mod version;
 
-fn emit_token_lines(username: &str, token: &Token) {
-    println!("username={username}");
-    println!("password={}", token.access_token());
-    if let Some(timestamp) = token.expires_at {
-        println!("password_expiry_utc={}", timestamp.timestamp());
+/// The lines Git reads the credential from: username and password, then the
+/// expiry and refresh token when there are any
+fn token_lines(username: &str, token: &Token) -> String {
+    let mut lines = format!("username={username}\npassword={}\n", token.access_token());
+    if let Some(timestamp) = token.expiry_for_git() {
+        let _ = writeln!(lines, "password_expiry_utc={}", timestamp.timestamp());
     }
     if let Some(refresh_token) = token.refresh_token() {
-        println!("oauth_refresh_token={refresh_token}");
+        let _ = writeln!(lines, "oauth_refresh_token={refresh_token}");
     }
+    lines
+}
+
+/// [`token_lines`] after refreshing the token when needed and possible
+async fn checked_token_lines(
+    token: &mut Token,
+    username: &str,
+    provider: &ProviderConfig,
+) -> Result<String> {
+    let _ = token
+        .access_token_checked(provider)
+        .await
+        .context("Failed to get access token")?;
+    debug!("Serving token {token} as {username}");
+    Ok(token_lines(username, token))
+}
+
+fn emit(io: &dyn Io, lines: &str) {
+    io.print(lines);
+    trace_io::record(Direction::Out, lines);
+    coalesce::record(lines);
 }
 
 /// Prints the token in the format expected by Git
-pub fn print_token(token: &Token, username: &str) {
-    emit_token_lines(username, token);
+pub fn print_token(io: &dyn Io, token: &Token, username: &str) {
+    debug!("Serving token {token} as {username}");
+    emit(io, &token_lines(username, token));
 }
 
 /// Prints the token in the format expected by Git, refreshing the token when
 /// needed and possible
 pub async fn print_token_checked(
+    io: &dyn Io,
     token: &mut Token,
     username: &str,
     provider: &ProviderConfig,
 ) -> Result<()> {
-    let _ = token
-        .access_token_checked(provider)
-        .await
-        .context("Failed to get access token")?;
-    emit_token_lines(username, token);
+    emit(io, &checked_token_lines(token, username, provider).await?);
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use chrono::{DateTime, Utc};
+
+    use super::*;
+
+    /// 2030-03-17T17:46:40Z
+    const EXPIRY: i64 = 1_900_000_000;
+
+    /// Compare `actual` byte for byte with `tests/golden/<name>`, or write it
+    /// there when `UPDATE_GOLDEN` is set
+    fn assert_golden(name: &str, actual: &str) {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/golden")
+            .join(name);
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            std::fs::write(&path, actual).unwrap();
+            return;
+        }
+        let expected = std::fs::read(&path)
+            .unwrap_or_else(|err| panic!("Failed to read {}: {err}", path.display()));
+        assert_eq!(
+            actual.as_bytes(),
+            expected.as_slice(),
+            "{name} changed, rerun with UPDATE_GOLDEN=1 if that is intended:\n{actual}"
+        );
+    }
+
+    fn token(expiry: bool, refresh: bool) -> Token {
+        Token::new(
+            "gho_access".into(),
+            refresh.then(|| "ghr_refresh".into()),
+            expiry.then(|| DateTime::<Utc>::from_timestamp(EXPIRY, 0).unwrap()),
+        )
+    }
+
+    fn provider(toml: &str) -> ProviderConfig {
+        toml::from_str(toml).unwrap()
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    #[test]
+    fn token_output_matches_golden_files() {
+        for (name, expiry, refresh) in [
+            ("plain.txt", false, false),
+            ("expiry.txt", true, false),
+            ("refresh.txt", false, true),
+            ("expiry_refresh.txt", true, true),
+        ] {
+            assert_golden(name, &token_lines("alice", &token(expiry, refresh)));
+        }
+
+        let ephemeral =
+            token(true, true).with_deadline(DateTime::<Utc>::from_timestamp(EXPIRY - 3600, 0));
+        assert_golden("deadline.txt", &token_lines("alice", &ephemeral));
+    }
+
+    #[test]
+    fn checked_output_matches_golden_files() {
+        let provider = provider("client_id = \"id\"\nrefresh = false");
+        let mut valid = token(true, true);
+        let lines = block_on(checked_token_lines(&mut valid, "alice", &provider)).unwrap();
+        assert_golden("expiry_refresh.txt", &lines);
+
+        let mut expired = token(false, true);
+        expired.expires_at = DateTime::<Utc>::from_timestamp(0, 0);
+        assert!(
+            block_on(checked_token_lines(&mut expired, "alice", &provider)).is_err(),
+            "nothing is printed for a token that can't be renewed"
+        );
+    }
+}
@@ -0,0 +1,88 @@
+use anyhow::{Result, bail};
+use colored::Colorize as _;
+use tracing::{debug, instrument};
+
+use crate::coalesce;
+use crate::commands::common::{
+    collect_all_pairs, confirm_destructive, confirm_policy, orphaned_pairs, revoke_and_erase,
+    sort_pairs, styled_error,
+};
+use crate::config::{ConfirmPolicy, Hosts, Stats};
+use crate::context::AppContext;
+
+/// Revoke where the provider supports it and erase every stored token, then
+/// clear the hosts state, its backups, the usage statistics and the
+/// credentials kept by `coalesce_window`.
+///
+/// Without `confirmed`, lists what would be removed and asks on a terminal
+/// unless `confirm_destructive` is `never`; with `dry_run` only lists it.
+#[instrument(skip(ctx))]
+pub async fn nuke(ctx: &AppContext, confirmed: bool, dry_run: bool) -> Result<()> {
+    let mut hosts_config = ctx.hosts.load()?;
+    let mut pairs = collect_all_pairs(&hosts_config);
+    // tokens the hosts state lost track of go too
+    match orphaned_pairs(ctx, &hosts_config) {
+        Ok(orphans) => pairs.extend(orphans),
+        Err(err) => debug!("Not looking for orphaned tokens: {err:#}"),
+    }
+    sort_pairs(&mut pairs);
+
+    if !confirmed || dry_run {
+        if pairs.is_empty() {
+            eprintln!("No stored tokens.");
+        } else {
+            eprintln!("This revokes and erases:");
+            for pair in &pairs {
+                eprintln!("  {}", pair.label_credential_host());
+            }
+        }
+        eprintln!(
+            "and clears the hosts state, its backups, the usage statistics and the credentials \
+             kept for repeated requests."
+        );
+    }
+    if dry_run {
+        return Ok(());
+    }
+    let prompt = "Revoke and erase all of this?";
+    match (confirmed, confirm_policy(ctx)) {
+        (true, ConfirmPolicy::Always) => confirm_destructive(ctx, prompt)?,
+        (true, ConfirmPolicy::Never | ConfirmPolicy::Tty) => {},
+        (false, ConfirmPolicy::Always | ConfirmPolicy::Tty)
+            if ctx.io.confirm(prompt)? == Some(true) => {},
+        (false, _) => {
+            styled_error(format!(
+                "Run {} to proceed.",
+                format!("{} nuke --yes-i-mean-it", env!("CARGO_PKG_NAME")).blue()
+            ));
+            bail!("Not confirmed");
+        },
+    }
+
+    let oauth_config = ctx.oauth.load().ok();
+    for pair in &pairs {
+        let outcome = revoke_and_erase(ctx, oauth_config.as_ref(), pair).await;
+        eprintln!("{}: {outcome}", pair.label_credential_host());
+    }
+
+    let hosts: Vec<String> = hosts_config
+        .hosts()
+        .map(|(host, _)| host.to_string())
+        .collect();
+    for host in &hosts {
+        hosts_config.remove_host(host);
+    }
+    ctx.hosts.save(&hosts_config)?;
+    eprintln!("Removed {} hosts from the hosts state.", hosts.len());
+    let backups = Hosts::remove_backups()?;
+    if backups > 0 {
+        eprintln!("Removed {backups} hosts state backups.");
+    }
+    if Stats::remove()? {
+        eprintln!("Removed the usage statistics.");
+    }
+    if coalesce::clear()? {
+        eprintln!("Removed the credentials kept for repeated requests.");
+    }
+    Ok(())
+}
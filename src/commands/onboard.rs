@@ -0,0 +1,148 @@
+use std::fs;
+use std::io::{IsTerminal as _, stderr};
+use std::path::PathBuf;
+
+use anyhow::{Context as _, Result, bail};
+use colored::Colorize as _;
+use dialoguer::{Confirm, Input, Password};
+use toml_edit::{DocumentMut, Item, Table, value};
+use tracing::{info, instrument};
+
+use crate::config::OAuthConfig;
+use crate::config::persist::write_with_backup;
+use crate::theme::InputTheme;
+use crate::utils::{config_dir, normalize_host, select_index};
+
+/// Provider types that can be set up with nothing but a client ID
+const PROVIDER_TYPES: [&str; 5] = ["github", "gitlab", "forgejo", "gitea", "oidc"];
+
+/// Offer to configure a provider for `host`, which has none, when
+/// `interactive_onboarding` is enabled and a terminal is attached.
+///
+/// Returns whether a provider was added to oauth.toml, in which case the
+/// configuration has to be loaded again.
+#[instrument(skip(oauth_config))]
+pub fn onboard(oauth_config: &OAuthConfig, host: &str) -> Result<bool> {
+    if !oauth_config.interactive_onboarding.unwrap_or(false) || !stderr().is_terminal() {
+        return Ok(false);
+    }
+    let host = normalize_host(host);
+    let confirmed = Confirm::with_theme(&InputTheme::default())
+        .with_prompt(format!(
+            "No provider configured for {} - configure one now?",
+            host.bold()
+        ))
+        .default(true)
+        .interact_opt()
+        .context("Failed to confirm onboarding")?;
+    if !confirmed.is_some_and(|b| b) {
+        return Ok(false);
+    }
+
+    let selection = select_index(&PROVIDER_TYPES, "Provider type")?;
+    let provider_type = PROVIDER_TYPES[selection];
+    let mut provider = Table::new();
+    provider.insert("type", value(provider_type));
+    if provider_type == "oidc" {
+        let issuer: String = Input::with_theme(&InputTheme::default())
+            .with_prompt("Issuer URL")
+            .interact_text()
+            .context("Failed to read issuer")?;
+        provider.insert("issuer", value(issuer.trim()));
+    }
+    let client_id: String = Input::with_theme(&InputTheme::default())
+        .with_prompt("Client ID")
+        .interact_text()
+        .context("Failed to read client ID")?;
+    if client_id.trim().is_empty() {
+        bail!("Client ID cannot be empty!");
+    }
+    provider.insert("client_id", value(client_id.trim()));
+    let client_secret = Password::with_theme(&InputTheme::default())
+        .with_prompt("Client secret (empty for public clients)")
+        .allow_empty_password(true)
+        .interact()
+        .context("Failed to read client secret")?;
+    if !client_secret.trim().is_empty() {
+        provider.insert("client_secret", value(client_secret.trim()));
+    }
+
+    add_provider(&host, provider)?;
+    eprintln!(
+        "Added a {provider_type} provider for {} to oauth.toml.",
+        host.bold()
+    );
+    Ok(true)
+}
+
+/// Insert `provider` as `[providers."<host>"]` into oauth.toml, keeping the
+/// rest of the file as it is.
+fn add_provider(host: &str, provider: Table) -> Result<()> {
+    let (path, mut doc) = oauth_document()?;
+    let providers = doc
+        .entry("providers")
+        .or_insert_with(|| {
+            let mut table = Table::new();
+            table.set_implicit(true);
+            Item::Table(table)
+        })
+        .as_table_mut()
+        .context("`providers` in oauth.toml is not a table")?;
+    if providers.contains_key(host) {
+        bail!("oauth.toml already has a provider for {host}");
+    }
+    providers.insert(host, Item::Table(provider));
+    info!("Writing provider for {host} to {}", path.display());
+    write_with_backup(&path, &doc.to_string())
+}
+
+/// Move the provider configured in oauth.toml for exactly `old` to `new`,
+/// keeping the rest of the file as it is. Returns whether there was one.
+pub fn rename_provider(old: &str, new: &str) -> Result<bool> {
+    let (path, mut doc) = oauth_document()?;
+    let Some(key) = provider_key(&doc, old) else {
+        return Ok(false);
+    };
+    let Some(providers) = doc.get_mut("providers").and_then(Item::as_table_mut) else {
+        return Ok(false);
+    };
+    if providers.contains_key(new) {
+        bail!("oauth.toml already has a provider for {new}");
+    }
+    let provider = providers.remove(&key).context("Provider vanished")?;
+    providers.insert(new, provider);
+    info!("Renaming provider {key} to {new} in {}", path.display());
+    write_with_backup(&path, &doc.to_string())?;
+    Ok(true)
+}
+
+/// Whether oauth.toml configures a provider for exactly `host`
+pub fn has_provider(host: &str) -> Result<bool> {
+    let (_, doc) = oauth_document()?;
+    Ok(provider_key(&doc, host).is_some())
+}
+
+/// The key of the provider for exactly `host` in the oauth.toml `doc`
+fn provider_key(doc: &DocumentMut, host: &str) -> Option<String> {
+    doc.get("providers")?
+        .as_table()?
+        .iter()
+        .map(|(key, _)| key.to_string())
+        .find(|key| normalize_host(key) == host)
+}
+
+/// oauth.toml in the config directory, empty if there is none yet
+fn oauth_document() -> Result<(PathBuf, DocumentMut)> {
+    let path = config_dir()?.join("oauth.toml");
+    let doc = match fs::read_to_string(&path) {
+        Ok(s) => {
+            s.parse::<DocumentMut>()
+                .with_context(|| format!("Failed to parse {}", path.display()))?
+        },
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => DocumentMut::new(),
+        Err(err) => {
+            return Err(err).with_context(|| format!("Failed to read {}", path.display()));
+        },
+    };
+    Ok((path, doc))
+}
@@ -0,0 +1,91 @@
+use anyhow::{Result, bail};
+use colored::Colorize as _;
+use tracing::instrument;
+
+use crate::commands::common::styled_error;
+use crate::context::AppContext;
+use crate::profile::url::{Match, Pattern, Patterns, Url as RepoUrl};
+
+/// Runs `input` through the configured patterns, then the built-in ones, the
+/// same way `apply` and `switch` do, and explains the outcome.
+#[instrument(skip(ctx))]
+pub fn test(ctx: &AppContext, input: &str) -> Result<()> {
+    let profile_config = ctx.profiles.load()?;
+    let builtin = Patterns::default();
+    let sources = [
+        ("profiles.toml", &profile_config.patterns),
+        ("built-in", &builtin),
+    ];
+
+    for (source, patterns) in sources {
+        for (index, pattern) in patterns.iter().enumerate() {
+            let label = format!("{source} #{}", index + 1);
+            let Some(m) = pattern.matches(input) else {
+                println!("{} {}: {}", "✗".red(), label, pattern.source().dimmed());
+                continue;
+            };
+            println!("{} {}: {}", "✓".green(), label.bold(), pattern.source());
+            print_fields(input, pattern, &m);
+
+            let Some(url) = RepoUrl::from_match(m, None) else {
+                styled_error("The pattern matched but neither captured nor defaulted an owner.");
+                bail!("Pattern {label} matched without an owner");
+            };
+            println!("  {}: {url}", "url".bold());
+            return Ok(());
+        }
+    }
+
+    // Url::from_str falls back to parsing full URLs when no pattern matches
+    if input.contains("://")
+        && let Ok(parsed) = url::Url::parse(input)
+    {
+        let url = RepoUrl::from_url(&parsed)?;
+        println!("{} parsed as a plain URL", "✓".green());
+        println!("  {}: {}", "scheme".bold(), url.scheme);
+        println!(
+            "  {}: {}",
+            "user".bold(),
+            url.user.as_deref().unwrap_or("-")
+        );
+        println!("  {}: {}", "host".bold(), url.host);
+        println!("  {}: {}", "owner".bold(), url.owner);
+        println!("  {}: {}", "repo".bold(), url.repo);
+        println!("  {}: {url}", "url".bold());
+        return Ok(());
+    }
+
+    styled_error(format!("No pattern matched [{}].", input.bold()));
+    bail!("No pattern matched {input}");
+}
+
+/// Print every field of the match, noting whether it was captured by the
+/// regex or filled in from the pattern's defaults.
+fn print_fields(input: &str, pattern: &Pattern, m: &Match) {
+    let captures = pattern.regex().ok().and_then(|re| re.captures(input));
+    let origin = |name: &str, present: bool| {
+        if captures.as_ref().is_some_and(|c| c.name(name).is_some()) {
+            "captured".green()
+        } else if present {
+            "default".yellow()
+        } else {
+            "unset".dimmed()
+        }
+    };
+    let fields = [
+        ("vcs", m.vcs.map(|v| v.to_string())),
+        ("scheme", m.scheme.map(|s| s.to_string())),
+        ("user", m.user.clone()),
+        ("host", m.host.as_ref().map(ToString::to_string)),
+        ("owner", m.owner.clone()),
+        ("repo", Some(m.repo.clone())),
+    ];
+    for (name, value) in fields {
+        println!(
+            "  {}: {} ({})",
+            name.bold(),
+            value.as_deref().unwrap_or("-"),
+            origin(name, value.is_some())
+        );
+    }
+}
@@ -0,0 +1,124 @@
+use std::collections::BTreeMap;
+use std::io::{IsTerminal as _, stdin};
+
+use anyhow::{Context as _, Result, bail};
+use colored::Colorize as _;
+use dialoguer::Password;
+use tracing::instrument;
+use zeroize::Zeroizing;
+
+use crate::commands::common::{styled_note, styled_warning};
+use crate::config::OAuthConfig;
+use crate::config::provider::{CLIENT_SECRET_IN_KEYRING, client_secret_entry};
+use crate::keyring::{STATE_HOST, store_keyring_secret};
+use crate::theme::InputTheme;
+use crate::utils::normalize_host;
+
+/// Validate the merged provider configuration and print every provider with
+/// what is wrong with the discarded ones, and the fields its sources disagree
+/// on. Fails if any was discarded.
+#[instrument]
+pub fn lint() -> Result<()> {
+    let cfg = OAuthConfig::load_report()?;
+    if cfg.providers.is_empty() && cfg.discarded.is_empty() {
+        styled_note("No OAuth providers are configured in oauth.toml or git config.");
+        return Ok(());
+    }
+
+    let mut keys: Vec<&String> = cfg.providers.keys().chain(cfg.discarded.keys()).collect();
+    keys.sort();
+    for key in &keys {
+        match (cfg.discarded.get(*key), cfg.providers.get(*key)) {
+            (Some(errs), _) => {
+                println!("{} {}", "✗".red(), key.bold());
+                for err in errs {
+                    println!("    {err}");
+                }
+            },
+            (None, Some(provider)) => {
+                let kind = provider.provider_type.as_deref().unwrap_or("custom");
+                println!(
+                    "{} {} {}",
+                    "✓".green(),
+                    key.bold(),
+                    format!("({kind})").dimmed()
+                );
+            },
+            (None, None) => {},
+        }
+    }
+
+    let mut spellings: BTreeMap<String, Vec<&str>> = BTreeMap::new();
+    for key in &keys {
+        spellings
+            .entry(normalize_host(key))
+            .or_default()
+            .push(key.as_str());
+    }
+    for (host, keys) in spellings.iter().filter(|(_, keys)| keys.len() > 1) {
+        styled_warning(format!(
+            "{} all configure [{host}], only one of them is used.",
+            keys.join(", ")
+        ));
+    }
+
+    for conflict in &cfg.conflicts {
+        let winner = conflict
+            .values
+            .last()
+            .map(|(layer, _)| layer.to_string())
+            .unwrap_or_default();
+        styled_warning(format!("{conflict}, the {winner} wins."));
+    }
+    if !cfg.conflicts.is_empty() {
+        styled_note(
+            "Set merge_strategy in oauth.toml to git-wins, file-wins or error to choose how they \
+             are merged.",
+        );
+    }
+
+    if !cfg.discarded.is_empty() {
+        bail!(
+            "{} of {} providers are invalid",
+            cfg.discarded.len(),
+            keys.len()
+        );
+    }
+    Ok(())
+}
+
+/// Store the client secret of the provider for `host` in the keyring, where
+/// `client_secret = "keyring"` takes it from. Prompted for on a terminal,
+/// read from stdin otherwise.
+#[instrument]
+pub fn set_secret(host: &str) -> Result<()> {
+    let host = normalize_host(host);
+    let secret = if stdin().is_terminal() {
+        Zeroizing::new(
+            Password::with_theme(&InputTheme::default())
+                .with_prompt(format!("Client secret for {host}"))
+                .interact()
+                .context("Failed to read client secret")?,
+        )
+    } else {
+        let mut line = Zeroizing::new(String::new());
+        stdin()
+            .read_line(&mut line)
+            .context("Failed to read client secret from stdin")?;
+        line
+    };
+    let secret = secret.trim();
+    if secret.is_empty() {
+        bail!("The client secret cannot be empty");
+    }
+    store_keyring_secret(&client_secret_entry(&host), STATE_HOST, secret)?;
+    eprintln!(
+        "Stored the client secret for {} in the keyring.",
+        host.bold()
+    );
+    styled_note(format!(
+        "Set {} for [{host}] in oauth.toml to use it.",
+        format!("client_secret = \"{CLIENT_SECRET_IN_KEYRING}\"").blue()
+    ));
+    Ok(())
+}
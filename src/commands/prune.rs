@@ -0,0 +1,94 @@
+use anyhow::{Context as _, Result};
+use colored::Colorize as _;
+use tracing::instrument;
+
+use crate::commands::common::{confirm_destructive, orphaned_pairs};
+use crate::context::AppContext;
+
+/// Erase the tokens in the token store that no credential in the hosts state
+/// belongs to, or only list them with `dry_run`
+#[instrument(skip(ctx))]
+pub fn orphans(ctx: &AppContext, dry_run: bool) -> Result<()> {
+    let hosts_config = ctx.hosts.load()?;
+    let orphans = orphaned_pairs(ctx, &hosts_config).context("Failed to find orphaned tokens")?;
+    if orphans.is_empty() {
+        eprintln!("No orphaned tokens.");
+        return Ok(());
+    }
+    if dry_run {
+        eprintln!("Would erase:");
+        for pair in &orphans {
+            eprintln!("  {}", pair.label_credential_host());
+        }
+        return Ok(());
+    }
+    confirm_destructive(ctx, &format!("Erase {} orphaned tokens?", orphans.len()))?;
+    let mut erased = 0;
+    for pair in &orphans {
+        let label = pair.label_credential_host();
+        match ctx.token_store.erase(&pair.credential, &pair.host) {
+            Ok(()) => {
+                erased += 1;
+                eprintln!("{label}: {}", "erased".green());
+            },
+            Err(err) => eprintln!("{label}: {}", format!("erasing failed ({err:#})").red()),
+        }
+    }
+    eprintln!("Erased {erased} of {} orphaned tokens.", orphans.len());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::config::hosts::HostConfig;
+    use crate::config::{Hosts, OAuthConfig};
+    use crate::context::mock;
+    use crate::keyring::{STATE_HOST, Token};
+
+    #[test]
+    fn pruning_erases_only_unknown_tokens() {
+        let (mut ctx, _) = mock::context(Hosts::from_map(HashMap::from([(
+            "github.com".to_string(),
+            HostConfig {
+                active: "alice".into(),
+                credentials: vec!["alice".into()],
+                session_previous: None,
+            },
+        )])));
+        let token = Token::new("gho_token".into(), None, None);
+        for (credential, host) in [
+            ("alice", "github.com"),
+            ("bob", "github.com"),
+            ("alice", "gitlab.com"),
+            ("hosts", STATE_HOST),
+        ] {
+            ctx.token_store.store(credential, host, &token).unwrap();
+        }
+
+        orphans(&ctx, true).unwrap();
+        assert_eq!(ctx.token_store.list().unwrap().len(), 4, "dry run");
+
+        ctx.oauth = Box::new(mock::Memory::new(Some(
+            toml::from_str::<OAuthConfig>("confirm_destructive = \"always\"").unwrap(),
+        )));
+        assert!(orphans(&ctx, false).is_err(), "no terminal to confirm on");
+        assert_eq!(ctx.token_store.list().unwrap().len(), 4, "not confirmed");
+
+        ctx.oauth = Box::new(mock::Memory::new(Some(
+            toml::from_str::<OAuthConfig>("confirm_destructive = \"tty\"").unwrap(),
+        )));
+        orphans(&ctx, false).unwrap();
+        let mut left: Vec<_> = ctx
+            .token_store
+            .list()
+            .unwrap()
+            .into_iter()
+            .map(|pair| pair.label_credential_host())
+            .collect();
+        left.sort();
+        assert_eq!(left, ["alice (github.com)", "hosts ((state))"]);
+    }
+}
@@ -1,24 +1,24 @@
-use std::io::stderr;
-use std::process::exit;
-
 use anyhow::{Context as _, Result, bail};
-use crossterm::cursor::Show;
-use crossterm::execute;
 use dialoguer::Confirm;
 
 use crate::commands::common::{
     CredentialPair, collect_all_pairs, filter_pairs, sort_pairs, styled_error,
 };
-use crate::config::{Hosts, OAuthConfig};
-use crate::keyring::{get_keyring_token, store_keyring_token};
-use crate::load_cfg;
-use crate::oauth::{get_access_token, refresh_access_token};
+use crate::commands::login::reauthenticate;
+use crate::config::OAuthConfig;
+use crate::context::AppContext;
+use crate::oauth::refresh_access_token;
 use crate::theme::InputTheme;
 use crate::utils::select_index;
 
-pub async fn refresh(host: Option<&str>, name: Option<&str>, force_device: bool) -> Result<()> {
-    let oauth_config = load_cfg!(OAuthConfig)?;
-    let hosts_config = load_cfg!(Hosts)?;
+pub async fn refresh(
+    ctx: &AppContext,
+    host: Option<&str>,
+    name: Option<&str>,
+    force_device: bool,
+) -> Result<()> {
+    let oauth_config = ctx.oauth.load()?;
+    let hosts_config = ctx.hosts.load()?;
     let mut pairs = collect_all_pairs(&hosts_config);
     if pairs.is_empty() {
         styled_error("No credentials found to refresh");
@@ -59,7 +59,7 @@ pub async fn refresh(host: Option<&str>, name: Option<&str>, force_device: bool)
         let labels: Vec<String> = filtered
             .iter()
             .map(|p| {
-                match get_keyring_token(&p.credential, &p.host) {
+                match ctx.token_store.get(&p.credential, &p.host) {
                     Ok(_) => format!("{} ({})", p.credential, p.host),
                     Err(_) => format!("{} ({}) - not in keyring", p.credential, p.host),
                 }
@@ -69,28 +69,25 @@ pub async fn refresh(host: Option<&str>, name: Option<&str>, force_device: bool)
         filtered[selection].clone()
     };
 
-    refresh_one(&oauth_config, &target, force_device).await
+    refresh_one(ctx, &oauth_config, &target, force_device).await
 }
 
 /// Refresh a single credential, use refresh token if present and approved,
 /// otherwise run a full OAuth flow
 async fn refresh_one(
+    ctx: &AppContext,
     oauth_config: &OAuthConfig,
     pair: &CredentialPair,
     force_device: bool,
 ) -> Result<()> {
-    let provider = oauth_config
-        .providers
-        .get(&pair.host)
+    let (_, provider) = oauth_config
+        .find_provider(&pair.host)
         .context("Provider not found")?;
 
-    if let Ok(token) = get_keyring_token(&pair.credential, &pair.host)
+    if provider.refreshes()
+        && let Ok(token) = ctx.token_store.get(&pair.credential, &pair.host)
         && token.refresh_token().is_some()
     {
-        let _ = ctrlc::set_handler(|| {
-            let _ = execute!(stderr(), Show);
-            exit(130);
-        });
         let use_refresh = Confirm::with_theme(&InputTheme::default())
             .with_prompt("A refresh token is available. Use it?")
             .default(true)
@@ -100,15 +97,12 @@ async fn refresh_one(
             let token = refresh_access_token(provider, &token)
                 .await
                 .context("Failed to refresh access token")?;
-            store_keyring_token(pair.credential.as_str(), &pair.host, &token)
+            ctx.token_store
+                .store(pair.credential.as_str(), &pair.host, &token)
                 .context("Failed to store refreshed token in keyring")?;
             return Ok(());
         }
     }
-    let token = get_access_token(oauth_config, &pair.host, force_device)
-        .await
-        .context("Failed to get access token")?;
-    store_keyring_token(pair.credential.as_str(), &pair.host, &token)
-        .context("Failed to store token in keyring")?;
-    Ok(())
+    // without a usable refresh token this is a re-login of the credential
+    reauthenticate(ctx, oauth_config, pair, force_device).await
 }
@@ -0,0 +1,109 @@
+use anyhow::{Context as _, Result, bail};
+use colored::Colorize as _;
+use tracing::instrument;
+
+use crate::commands::apply::apply_matching;
+use crate::commands::common::{
+    load_profiles, parse_repo_url, styled_error, styled_note, styled_warning,
+};
+use crate::context::AppContext;
+use crate::forge::Forge;
+use crate::profile::Scope;
+use crate::profile::url::{Patterns, Url as RepoUrl};
+use crate::utils::{RepoContext, repo_context};
+
+/// Parse `target` like the profile rules do, e.g. `owner/name` or
+/// `host/owner/name`
+fn parse_target(ctx: &AppContext, target: &str) -> Result<RepoUrl> {
+    let patterns = ctx
+        .profiles
+        .load()
+        .map_or_else(|_| Patterns::default(), |c| c.patterns);
+    parse_repo_url(target, &patterns)
+}
+
+/// Create the repository `target` on its forge with the token of the active
+/// credential, or `name`. Inside a git repository without an `origin`, the
+/// new repository becomes `origin` and the matching profile is applied.
+#[instrument(skip(ctx))]
+pub async fn create(
+    ctx: &AppContext,
+    target: &str,
+    private: bool,
+    name: Option<&str>,
+) -> Result<()> {
+    let url = parse_target(ctx, target)?;
+    let forge = Forge::connect(ctx, &url.host.to_string(), name).await?;
+    let repo = forge.create_repo(&url.owner, &url.repo, private).await?;
+    let clone_url = repo.clone_url.unwrap_or_else(|| url.to_string());
+    eprintln!(
+        "Created {} {} as {}.",
+        if repo.private { "private" } else { "public" },
+        repo.full_name.bold(),
+        forge.pair.label_credential_host()
+    );
+    ctx.io.print(&format!("{clone_url}\n"));
+
+    let Ok(RepoContext { repo: local, .. }) = repo_context(None) else {
+        return Ok(());
+    };
+    if let Ok(origin) = local.find_remote("origin") {
+        styled_note(format!(
+            "Not changing origin, it already points to {}.",
+            origin.url().unwrap_or_default()
+        ));
+        return Ok(());
+    }
+    local
+        .remote("origin", &clone_url)
+        .context("Failed to add the origin remote")?;
+    eprintln!("Added {} as origin.", clone_url.bold());
+
+    let profile_config = load_profiles(ctx)?;
+    let remote_url = parse_repo_url(&clone_url, &profile_config.patterns)?;
+    if let Err(err) = apply_matching(&profile_config, &remote_url, Scope::Local, None) {
+        styled_warning(format!("No profile applied: {err:#}"));
+    }
+    Ok(())
+}
+
+/// Print the full name of `target` if it exists and is visible to the active
+/// credential, or `name`, and fail otherwise.
+#[instrument(skip(ctx))]
+pub async fn exists(ctx: &AppContext, target: &str, name: Option<&str>) -> Result<()> {
+    let url = parse_target(ctx, target)?;
+    let forge = Forge::connect(ctx, &url.host.to_string(), name).await?;
+    let Some(repo) = forge.repo(&url.owner, &url.repo).await? else {
+        styled_error(format!(
+            "{} doesn't exist or isn't visible to {}.",
+            format!("{}/{}", url.owner, url.repo).bold(),
+            forge.pair.label_credential_host()
+        ));
+        bail!("Repository {}/{} not found", url.owner, url.repo);
+    };
+    ctx.io.print(&format!("{}\n", repo.full_name));
+    Ok(())
+}
+
+/// Fork `target` to the account of the active credential, or `name`, or to
+/// the organization or group `into`, and print the fork's clone URL.
+#[instrument(skip(ctx))]
+pub async fn fork(
+    ctx: &AppContext,
+    target: &str,
+    into: Option<&str>,
+    name: Option<&str>,
+) -> Result<()> {
+    let url = parse_target(ctx, target)?;
+    let forge = Forge::connect(ctx, &url.host.to_string(), name).await?;
+    let repo = forge.fork(&url.owner, &url.repo, into).await?;
+    eprintln!(
+        "Forked {} to {}.",
+        format!("{}/{}", url.owner, url.repo).bold(),
+        repo.full_name.bold()
+    );
+    if let Some(clone_url) = repo.clone_url {
+        ctx.io.print(&format!("{clone_url}\n"));
+    }
+    Ok(())
+}
@@ -0,0 +1,91 @@
+use anyhow::{Result, bail};
+use colored::Colorize as _;
+use tracing::instrument;
+
+use crate::commands::common::{
+    fetch_url, load_profiles, parse_repo_url, remote_url, styled_error, styled_warning,
+};
+use crate::context::AppContext;
+use crate::profile::rule::ProfileRef;
+
+/// Walks the rules for `url` (or the current repository's origin) in order,
+/// showing why each one did or did not match and which profile wins.
+#[instrument(skip(ctx))]
+pub fn explain(ctx: &AppContext, url: Option<&str>) -> Result<()> {
+    let profile_config = load_profiles(ctx)?;
+    for issue in profile_config.validate() {
+        styled_warning(issue);
+    }
+    let url = match url {
+        Some(input) => parse_repo_url(&fetch_url(input, None), &profile_config.patterns)?,
+        None => remote_url(&profile_config.patterns, None, None)?,
+    };
+    println!(
+        "{} host={} owner={} repo={}",
+        url.to_string().bold(),
+        url.host,
+        url.owner,
+        url.repo
+    );
+
+    let mut winner = None;
+    for (index, rule) in profile_config.rules.iter().enumerate() {
+        let checks = rule.check(&url);
+        let matched = checks.iter().all(|check| check.matched);
+        let mark = if matched { "✓".green() } else { "✗".red() };
+        let label = if rule.priority == 0 {
+            format!("rule #{} -> {}", index + 1, rule.profile.name)
+        } else {
+            format!(
+                "rule #{} (priority {}) -> {}",
+                index + 1,
+                rule.priority,
+                rule.profile.name
+            )
+        };
+        if winner.is_none() && matched {
+            println!("{mark} {}", label.bold());
+            winner = Some(rule);
+        } else {
+            println!("{mark} {label}");
+        }
+        if checks.is_empty() {
+            println!("    {}", "no constraints, matches anything".dimmed());
+        }
+        for check in checks {
+            let outcome = match (check.negated, check.matched) {
+                (false, true) => "==".green(),
+                (false, false) => "!=".red(),
+                (true, true) => "!=".green(),
+                (true, false) => "==".red(),
+            };
+            let field = if check.negated {
+                format!("not_{}", check.field)
+            } else {
+                check.field.to_string()
+            };
+            println!("    {field}: {} {outcome} {}", check.expected, check.actual);
+        }
+    }
+
+    let profile_ref = match (winner, &profile_config.default_profile) {
+        (Some(rule), _) => rule.profile.render(&url),
+        (None, Some(name)) => {
+            println!("{} default_profile -> {}", "✓".green(), name.bold());
+            ProfileRef { name: name.clone() }
+        },
+        (None, None) => {
+            styled_error(format!("No rule matched [{}].", url.to_string().bold()));
+            bail!("No rule matched for remote {url}");
+        },
+    };
+    if profile_config.profiles.resolve(&profile_ref).is_none() {
+        styled_error(format!(
+            "Rule selects profile [{}], which does not exist.",
+            profile_ref.name.bold()
+        ));
+        bail!("Unknown profile: {}", profile_ref.name);
+    }
+    println!("Profile: {}", profile_ref.name.bold());
+    Ok(())
+}
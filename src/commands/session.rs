@@ -0,0 +1,152 @@
+use anyhow::{Context as _, Result, bail};
+use colored::Colorize as _;
+use tracing::instrument;
+
+use crate::commands::common::{styled_error, styled_note, styled_warning};
+use crate::commands::guard::PUSH_SCOPES;
+use crate::commands::login::{LoginOptions, login};
+use crate::config::{Hosts, ScopePreset};
+use crate::context::AppContext;
+use crate::utils::normalize_host;
+
+/// Credential name a read-only login is stored under when none exists yet
+const READONLY_NAME: &str = "readonly";
+
+/// Make a read-only credential active on `host` until `session end`: `name`,
+/// or a stored credential granted only read-only scopes, or a new login with
+/// the `readonly` scope preset.
+#[instrument(skip(ctx))]
+pub async fn readonly(
+    ctx: &AppContext,
+    host: &str,
+    name: Option<&str>,
+    force_device: bool,
+) -> Result<()> {
+    let oauth_config = ctx.oauth.load()?;
+    let Some((host, provider)) = oauth_config.find_provider(host) else {
+        styled_error(oauth_config.missing_provider_message(host));
+        bail!("No OAuth provider configured for {host}");
+    };
+    let Some(readonly_scopes) = provider.preset_scopes(ScopePreset::Readonly) else {
+        bail!(
+            "Read-only sessions are only available for the github, gitlab, forgejo and gitea \
+             provider types"
+        );
+    };
+
+    let mut hosts_config = ctx.hosts.load()?;
+    let credential = name
+        .map(str::to_string)
+        .or_else(|| find_readonly(ctx, &hosts_config, &host, readonly_scopes))
+        .unwrap_or_else(|| READONLY_NAME.to_string());
+    if !hosts_config.has_credential(&host, &credential) {
+        eprintln!(
+            "No read-only credential for {} yet, logging in as {}.",
+            host.bold(),
+            credential.bold()
+        );
+        let options = LoginOptions {
+            scopes_preset: Some(ScopePreset::Readonly),
+            ..LoginOptions::default()
+        };
+        login(ctx, Some(&host), Some(&credential), options, force_device)
+            .await
+            .context("Failed to log in with read-only scopes")?;
+        hosts_config = ctx.hosts.load()?;
+    }
+    if let Ok(token) = ctx.token_store.get(&credential, &host)
+        && let Some(scopes) = &token.scopes
+        && scopes.iter().any(|s| PUSH_SCOPES.contains(&s.as_str()))
+    {
+        styled_warning(format!(
+            "The token of {credential} can still push ({}), the provider can't grant read access \
+             alone.",
+            scopes.join(", ")
+        ));
+    }
+
+    hosts_config.start_session(&host, &credential)?;
+    ctx.hosts.save(&hosts_config)?;
+    eprintln!(
+        "Using {} on {} until {}.",
+        credential.bold(),
+        host.bold(),
+        format!("{} session end", env!("CARGO_PKG_NAME")).blue()
+    );
+    Ok(())
+}
+
+/// End the session on `host`, or on every host, making the credential from
+/// before it active again.
+#[instrument(skip(ctx))]
+pub fn end(ctx: &AppContext, host: Option<&str>) -> Result<()> {
+    let mut hosts_config = ctx.hosts.load()?;
+    let hosts: Vec<String> = host.map_or_else(
+        || {
+            hosts_config
+                .hosts()
+                .filter(|(_, cfg)| cfg.session_previous.is_some())
+                .map(|(host, _)| host.to_string())
+                .collect()
+        },
+        |host| vec![normalize_host(host)],
+    );
+    let mut ended = false;
+    for host in hosts {
+        if let Some(previous) = hosts_config.end_session(&host) {
+            eprintln!("Switched {} back to {}.", host.bold(), previous.bold());
+            ended = true;
+        }
+    }
+    if !ended {
+        styled_note("No session to end.");
+        return Ok(());
+    }
+    ctx.hosts.save(&hosts_config)
+}
+
+/// A credential of `host` whose token was granted no scopes beyond
+/// `readonly_scopes`
+fn find_readonly(
+    ctx: &AppContext,
+    hosts_config: &Hosts,
+    host: &str,
+    readonly_scopes: &[&str],
+) -> Option<String> {
+    hosts_config
+        .get_credentials(host)
+        .ok()?
+        .iter()
+        .find(|credential| {
+            ctx.token_store
+                .get(credential, host)
+                .ok()
+                .and_then(|token| token.scopes.clone())
+                .is_some_and(|scopes| is_readonly(&scopes, readonly_scopes))
+        })
+        .cloned()
+}
+
+fn is_readonly(scopes: &[String], readonly_scopes: &[&str]) -> bool {
+    !scopes.is_empty() && scopes.iter().all(|s| readonly_scopes.contains(&s.as_str()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_readonly_scopes_qualify() {
+        let readonly = ["read_repository"];
+        let scopes = |s: &[&str]| s.iter().map(ToString::to_string).collect::<Vec<_>>();
+        assert!(
+            is_readonly(&scopes(&["read_repository"]), &readonly),
+            "preset scopes"
+        );
+        assert!(
+            !is_readonly(&scopes(&["read_repository", "write_repository"]), &readonly),
+            "write scope"
+        );
+        assert!(!is_readonly(&[], &readonly), "unknown scopes");
+    }
+}
@@ -5,25 +5,301 @@
 // Local modifications:
 // Copyright (c) 2025 Adolar0042
 
-use anyhow::{Result, bail};
-use tracing::instrument;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
 
-use crate::commands::common::styled_error;
-use crate::config::ProfileConfig;
-use crate::load_cfg;
+use anyhow::{Context as _, Result, bail};
+use git2::{Config, ConfigLevel};
+use serde::Serialize;
+use tracing::{debug, instrument};
+
+use crate::commands::common::{glob_match, load_profiles, styled_error};
+use crate::context::AppContext;
+use crate::output::OutputFormat;
 use crate::profile::rule::ProfileRef;
+use crate::profile::{Configs, ManagedFiles, Profile};
+use crate::utils::repo_context;
+
+/// Keys whose values are masked, as lowercase globs
+const SENSITIVE_KEYS: &[&str] = &["*token*", "*password*", "*secret*", "*.extraheader"];
+/// What a masked value is shown as
+const MASKED: &str = "***";
+
+/// A key of a profile as printed by `show`
+#[derive(Debug, PartialEq, Eq, Serialize)]
+struct ShownKey {
+    key: String,
+    values: Vec<String>,
+    /// The value in the global git config, with `--provenance`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    global: Option<Setting>,
+    /// The value in the current repository's git config, with `--provenance`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    local: Option<Setting>,
+}
+
+/// A key as set in a git config file
+#[derive(Debug, PartialEq, Eq, Serialize)]
+struct Setting {
+    values: Vec<String>,
+    /// Whether the values differ from the profile's
+    differs: bool,
+}
 
-#[instrument]
-pub fn show(profile_ref: &ProfileRef) -> Result<()> {
-    let profile_config = load_cfg!(ProfileConfig)?;
+/// A profile as printed by `show --output`
+#[derive(Serialize)]
+struct ShownProfile<'a> {
+    name: &'a str,
+    configs: Vec<ShownKey>,
+    includes: BTreeMap<&'a str, Vec<ShownKey>>,
+    unset: &'a [String],
+    env: BTreeMap<&'a str, String>,
+    files: &'a ManagedFiles,
+}
+
+/// Print a profile as TOML with sensitive values masked. With `provenance`,
+/// each key is annotated with its value in the global and the current
+/// repository's git config.
+#[instrument(skip(ctx))]
+pub fn show(
+    ctx: &AppContext,
+    profile_ref: &ProfileRef,
+    provenance: bool,
+    output: OutputFormat,
+) -> Result<()> {
+    let profile_config = load_profiles(ctx)?;
     let Some(profile) = profile_config.profiles.get(&profile_ref.name) else {
         styled_error(format!("Unknown profile: {}", profile_ref.name));
         bail!("Unknown profile: {}", profile_ref.name);
     };
 
-    for (k, v) in &profile.configs {
-        println!("{k} = \"{v}\"");
+    let (global, local) = if provenance {
+        (global_config(), local_config())
+    } else {
+        (None, None)
+    };
+    let configs = shown_keys(&profile.configs, global.as_ref(), local.as_ref());
+    let includes: BTreeMap<&str, Vec<ShownKey>> = profile
+        .includes
+        .iter()
+        .map(|(name, configs)| (name.as_str(), shown_keys(configs, None, None)))
+        .collect();
+    if !output.is_text() {
+        let shown = ShownProfile {
+            name: &profile_ref.name,
+            configs,
+            includes,
+            unset: &profile.unset,
+            env: profile
+                .env
+                .iter()
+                .map(|(name, value)| (name.as_str(), shown_value(name, value)))
+                .collect(),
+            files: &profile.files,
+        };
+        return output.print(&*ctx.io, &shown);
     }
-
+    ctx.io.print(&render(&configs, &includes, profile));
     Ok(())
 }
+
+/// The profile as TOML, with the git config values as comments
+fn render(
+    configs: &[ShownKey],
+    includes: &BTreeMap<&str, Vec<ShownKey>>,
+    profile: &Profile,
+) -> String {
+    let mut out = String::new();
+    for key in configs {
+        let _ = writeln!(out, "{}", render_key(key));
+    }
+    for (name, configs) in includes {
+        let _ = writeln!(out, "\n[includes.{name}]");
+        for key in configs {
+            let _ = writeln!(out, "{}", render_key(key));
+        }
+    }
+    if !profile.unset.is_empty() {
+        let keys: Vec<_> = profile.unset.iter().map(|k| format!("\"{k}\"")).collect();
+        let _ = writeln!(out, "unset = [{}]", keys.join(", "));
+    }
+    if !profile.env.is_empty() {
+        let _ = writeln!(out, "\n[env]");
+        for (name, value) in &profile.env {
+            let _ = writeln!(out, "{name} = \"{}\"", shown_value(name, value));
+        }
+    }
+    let files = [
+        ("commit_template", &profile.files.commit_template),
+        ("hooks", &profile.files.hooks),
+    ];
+    if !profile.files.is_empty() {
+        let _ = writeln!(out, "\n[files]");
+        for (key, path) in files {
+            if let Some(path) = path {
+                let _ = writeln!(out, "{key} = \"{}\"", path.display());
+            }
+        }
+    }
+    out
+}
+
+/// `key = "value"`, and where else it is set
+fn render_key(key: &ShownKey) -> String {
+    let mut line = format!("{} = {}", key.key, toml_values(&key.values));
+    let origins: Vec<String> = [("global", &key.global), ("local", &key.local)]
+        .into_iter()
+        .filter_map(|(level, setting)| {
+            setting.as_ref().map(|setting| {
+                if setting.differs {
+                    format!("{level}: {}", toml_values(&setting.values))
+                } else {
+                    format!("{level}: same")
+                }
+            })
+        })
+        .collect();
+    if !origins.is_empty() {
+        let _ = write!(line, "  # {}", origins.join(", "));
+    }
+    line
+}
+
+/// A single value as a TOML string, several as an array
+fn toml_values(values: &[String]) -> String {
+    let quoted: Vec<String> = values.iter().map(|v| format!("\"{v}\"")).collect();
+    match quoted.as_slice() {
+        [single] => single.clone(),
+        _ => format!("[{}]", quoted.join(", ")),
+    }
+}
+
+/// The keys of `configs` sorted, with sensitive values masked and their
+/// values in `global` and `local`
+fn shown_keys(configs: &Configs, global: Option<&Config>, local: Option<&Config>) -> Vec<ShownKey> {
+    let scalars = configs.into_iter().map(|(k, v)| (k, vec![v.clone()]));
+    let multi = configs.multi_values().map(|(k, v)| (k, v.clone()));
+    let mut keys: Vec<ShownKey> = scalars
+        .chain(multi)
+        .map(|(key, values)| {
+            let setting = |cfg: Option<&Config>| {
+                let set = config_values(cfg?, key);
+                (!set.is_empty()).then(|| {
+                    Setting {
+                        differs: set != values,
+                        values: mask(key, set),
+                    }
+                })
+            };
+            ShownKey {
+                key: key.clone(),
+                global: setting(global),
+                local: setting(local),
+                values: mask(key, values),
+            }
+        })
+        .collect();
+    keys.sort_unstable_by(|a, b| a.key.cmp(&b.key));
+    keys
+}
+
+/// Whether the value of `key` is a secret not to be shown
+fn is_sensitive(key: &str) -> bool {
+    let key = key.to_ascii_lowercase();
+    SENSITIVE_KEYS
+        .iter()
+        .any(|pattern| glob_match(pattern, &key))
+}
+
+/// `values` of `key`, masked if it is sensitive
+fn mask(key: &str, values: Vec<String>) -> Vec<String> {
+    if is_sensitive(key) {
+        values.iter().map(|_| MASKED.to_string()).collect()
+    } else {
+        values
+    }
+}
+
+/// `value` of `key`, masked if it is sensitive
+fn shown_value(key: &str, value: &str) -> String {
+    if is_sensitive(key) {
+        MASKED.to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+/// All values of `key` in `cfg`
+fn config_values(cfg: &Config, key: &str) -> Vec<String> {
+    let mut values = Vec::new();
+    let Ok(mut entries) = cfg.multivar(key, None) else {
+        return values;
+    };
+    while let Some(Ok(entry)) = entries.next() {
+        values.extend(entry.value().map(str::to_string));
+    }
+    values
+}
+
+/// The global git config, `None` if there is none
+fn global_config() -> Option<Config> {
+    Config::open_default()
+        .and_then(|mut cfg| cfg.open_global())
+        .inspect_err(|err| debug!("No global git config: {err}"))
+        .ok()
+}
+
+/// The current repository's git config, `None` outside a repository
+fn local_config() -> Option<Config> {
+    repo_context(None)
+        .and_then(|context| {
+            context
+                .repo
+                .config()
+                .and_then(|cfg| cfg.open_level(ConfigLevel::Local))
+                .context("Failed to open git config")
+        })
+        .inspect_err(|err| debug!("No repository git config: {err:#}"))
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn keys_show_provenance_with_secrets_masked() {
+        let path = std::env::temp_dir().join(format!("warden-show-{}", std::process::id()));
+        let mut local = Config::open(&path).unwrap();
+        local.set_str("user.email", "alice@example.com").unwrap();
+        local.set_str("user.name", "Someone Else").unwrap();
+        local
+            .set_str("http.https://git.example.com.extraHeader", "Bearer local")
+            .unwrap();
+        let profile: Profile = toml::from_str(
+            r#"
+            user.name = "Alice"
+            user.email = "alice@example.com"
+            core.editor = "vim"
+            "http.https://git.example.com.extraHeader" = "Bearer s3cret"
+            "#,
+        )
+        .unwrap();
+
+        let keys = shown_keys(&profile.configs, None, Some(&local));
+        let lines: Vec<String> = keys.iter().map(render_key).collect();
+        assert_eq!(
+            lines,
+            [
+                r#"core.editor = "vim""#,
+                r#"http.https://git.example.com.extraHeader = "***"  # local: "***""#,
+                r#"user.email = "alice@example.com"  # local: same"#,
+                r#"user.name = "Alice"  # local: "Someone Else""#,
+            ]
+        );
+        assert!(keys[1].local.as_ref().unwrap().differs, "secrets compared");
+        fs::remove_file(&path).unwrap();
+    }
+}
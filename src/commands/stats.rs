@@ -0,0 +1,49 @@
+use std::fmt::Write as _;
+
+use anyhow::Result;
+use chrono::Utc;
+use colored::Colorize as _;
+use tracing::instrument;
+
+use crate::commands::common::{human_duration, styled_note};
+use crate::config::Stats;
+use crate::context::AppContext;
+use crate::load_cfg;
+
+/// Show how often each credential answered a Git request and what it was last
+/// used for.
+#[instrument(skip(ctx))]
+pub fn stats(ctx: &AppContext) -> Result<()> {
+    let enabled = ctx.oauth.load().is_ok_and(|cfg| cfg.stats.unwrap_or(false));
+    let stats = load_cfg!(Stats)?;
+    if !enabled {
+        styled_note(format!(
+            "Usage statistics are off, set {} in oauth.toml or run {}.",
+            "stats = true".bold(),
+            "git config --global warden.stats true".blue()
+        ));
+    }
+    if stats.is_empty() {
+        eprintln!("No usage recorded yet.");
+        return Ok(());
+    }
+
+    for (host, credentials) in stats.iter_sorted() {
+        eprintln!("{}", host.bold());
+        for (credential, usage) in credentials {
+            let plural = if usage.count == 1 { "" } else { "s" };
+            let mut line = format!("  {credential}: {} use{plural}", usage.count);
+            if let Some(last) = usage.last_used {
+                let _ = write!(line, ", last {} ago", human_duration(Utc::now() - last));
+            }
+            if let Some(protocol) = &usage.last_protocol {
+                let _ = write!(line, " over {protocol}");
+            }
+            if let Some(path) = &usage.last_path {
+                let _ = write!(line, " ({path})");
+            }
+            eprintln!("{line}");
+        }
+    }
+    Ok(())
+}
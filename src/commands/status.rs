@@ -1,16 +1,94 @@
-use anyhow::{Result, bail};
+use std::io::stderr;
+
+use anyhow::{Context as _, Result, bail};
+use chrono::{DateTime, Local, Utc};
 use colored::Colorize as _;
-use tracing::instrument;
+use serde::Serialize;
+use tracing::{debug, instrument};
 
-use crate::commands::common::styled_error;
+use crate::commands::common::{
+    CredentialFilter, labels_credential_host, orphaned_pairs, repo_profile, styled_error,
+    styled_warning, warn_refresh_expiry,
+};
 use crate::config::Hosts;
-use crate::keyring::get_keyring_token;
-use crate::load_cfg;
+use crate::context::AppContext;
+use crate::keyring::Token;
+use crate::output::OutputFormat;
+use crate::table::{Cell, Style, Table};
+use crate::utils::{RepoContext, repo_context};
+
+/// Number of commits inspected by `status --repo`
+const RECENT_COMMITS: usize = 5;
+
+/// A credential as printed by `status --output`
+#[derive(Serialize)]
+struct CredentialStatus<'a> {
+    host: &'a str,
+    credential: &'a str,
+    active: bool,
+    /// Whether a token for the credential is in the keyring
+    stored: bool,
+    /// Salted SHA-256 of the token, to tell tokens apart
+    fingerprint: Option<String>,
+    expires_at: Option<DateTime<Utc>>,
+    refresh_expires_at: Option<DateTime<Utc>>,
+    scopes: Option<Vec<String>>,
+    /// When an ephemeral login ends
+    deadline: Option<DateTime<Utc>>,
+    label: Option<&'a str>,
+    created_at: Option<DateTime<Utc>>,
+    last_used: Option<DateTime<Utc>>,
+    /// Whether it is an `insecure_static` credential of the configuration,
+    /// whose password is never shown
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    insecure_static: bool,
+}
+
+/// The repository identity as printed by `status --repo --output`
+#[derive(Serialize)]
+struct RepoStatus {
+    user_name: Option<String>,
+    user_email: Option<String>,
+    profile: Option<String>,
+    default_profile: bool,
+    expected_user_name: Option<String>,
+    expected_user_email: Option<String>,
+    commits: Vec<CommitStatus>,
+}
+
+#[derive(Serialize)]
+struct CommitStatus {
+    id: String,
+    author_name: Option<String>,
+    author_email: Option<String>,
+    mismatch: bool,
+}
 
-#[instrument]
-pub fn status() -> Result<()> {
-    let hosts_config = load_cfg!(Hosts)?;
-    if hosts_config.is_empty() {
+/// Show the credentials of every host that `filter` lets through
+#[instrument(skip(ctx))]
+pub fn status(
+    ctx: &AppContext,
+    repo: bool,
+    output: OutputFormat,
+    filter: &CredentialFilter,
+) -> Result<()> {
+    if repo {
+        return repo_status(ctx, output);
+    }
+
+    let hosts_config = ctx.hosts.load()?;
+    let oauth_config = ctx.oauth.load().ok();
+    let mut statics: Vec<(&str, &str)> = oauth_config
+        .iter()
+        .flat_map(|cfg| &cfg.insecure_static)
+        .filter(|(host, _)| filter.matches_host(host) && !filter.checks_token())
+        .map(|(host, credential)| (host.as_str(), credential.username.as_str()))
+        .collect();
+    statics.sort_unstable();
+    if hosts_config.is_empty() && statics.is_empty() {
+        if output.is_text() {
+            warn_orphans(ctx, &hosts_config);
+        }
         styled_error(format!(
             "No credentials found. Add credentials by running {}.",
             format!("{} login", env!("CARGO_PKG_NAME")).blue()
@@ -18,39 +96,396 @@ pub fn status() -> Result<()> {
         bail!("No credentials found");
     }
 
+    if !output.is_text() {
+        let mut credentials = Vec::new();
+        for (host, config) in hosts_config.iter_sorted() {
+            let mut names: Vec<&str> = config.names().collect();
+            names.sort_unstable();
+            for credential in names {
+                let token = ctx.token_store.get(credential, host).ok();
+                if !filter.matches(host, token.as_ref()) {
+                    continue;
+                }
+                let known = hosts_config.credential(host, credential);
+                credentials.push(CredentialStatus {
+                    host,
+                    credential,
+                    active: *credential == config.active,
+                    stored: token.is_some(),
+                    fingerprint: token.as_ref().map(ToString::to_string),
+                    expires_at: token.as_ref().and_then(|t| t.expires_at),
+                    refresh_expires_at: token.as_ref().and_then(|t| t.refresh_expires_at),
+                    scopes: token.as_ref().and_then(|t| t.scopes.clone()),
+                    deadline: token.as_ref().and_then(|t| t.deadline),
+                    label: known.and_then(|c| c.label.as_deref()),
+                    created_at: known.and_then(|c| c.created_at),
+                    last_used: known.and_then(|c| c.last_used),
+                    insecure_static: false,
+                });
+            }
+        }
+        for (host, username) in statics {
+            credentials.push(CredentialStatus {
+                host,
+                credential: username,
+                active: true,
+                stored: false,
+                fingerprint: None,
+                expires_at: None,
+                refresh_expires_at: None,
+                scopes: None,
+                deadline: None,
+                label: None,
+                created_at: None,
+                last_used: None,
+                insecure_static: true,
+            });
+        }
+        return output.print(&*ctx.io, &credentials);
+    }
+
+    let display = oauth_config
+        .as_ref()
+        .and_then(|cfg| cfg.token_display)
+        .unwrap_or_default();
+    let mut table = Table::new(&["HOST", "CREDENTIAL", "TOKEN", "EXPIRES"]);
+    let mut tokens = Vec::new();
     for (host, config) in hosts_config.iter_sorted() {
+        if !filter.matches_host(host) {
+            continue;
+        }
         if config.credentials.is_empty() {
-            eprintln!("{}: No credentials found.", host.bold());
+            if filter.checks_token() {
+                continue;
+            }
+            table.push(vec![
+                Cell::new(host),
+                Cell::styled("no credentials", Style::Dimmed),
+            ]);
             continue;
         }
-
-        let active_credential = &config.active;
-        if active_credential.is_empty() {
-            eprintln!("{}: no active credential", host.bold());
-        } else {
-            let token = get_keyring_token(active_credential, host);
-            if let Ok(token) = token {
-                eprintln!("{}: {active_credential} ({token})", host.bold());
+        // the active credential first, then the others alphabetically
+        let mut names: Vec<&str> = config.names().collect();
+        names.sort_by_key(|name| (**name != config.active, *name));
+        for credential in names {
+            let name = if *credential == config.active {
+                Cell::styled(format!("{credential} (active)"), Style::Bold)
             } else {
-                eprintln!("{}: {}", host.bold(), active_credential.red());
+                Cell::new(credential)
+            };
+            let token = ctx.token_store.get(credential, host).ok();
+            if !filter.matches(host, token.as_ref()) {
+                continue;
             }
+            let Some(token) = token else {
+                table.push(vec![
+                    Cell::new(host),
+                    name,
+                    Cell::styled("missing", Style::Red),
+                ]);
+                continue;
+            };
+            table.push(vec![
+                Cell::new(host),
+                name,
+                Cell::new(token.masked(display)),
+                expiry_cell(&token),
+            ]);
+            tokens.push((host, credential, token));
         }
+    }
+    for (host, username) in statics {
+        table.push(vec![
+            Cell::new(host),
+            Cell::new(username),
+            Cell::styled("insecure static, not shown", Style::Yellow),
+        ]);
+    }
+    if table.is_empty() {
+        eprintln!("No credentials match the filters.");
+    } else {
+        eprint!("{}", table.render(&stderr()));
+    }
+    for (host, credential, token) in &tokens {
+        warn_refresh_expiry(token, credential, host);
+    }
+    warn_orphans(ctx, &hosts_config);
+    Ok(())
+}
 
-        let mut credentials: Vec<&String> = config
-            .credentials
-            .iter()
-            .filter(|u| *u != active_credential)
-            .collect();
-        credentials.sort();
+/// Point out tokens in the token store no credential belongs to, where the
+/// store can list its entries
+fn warn_orphans(ctx: &AppContext, hosts_config: &Hosts) {
+    match orphaned_pairs(ctx, hosts_config) {
+        Ok(orphans) if !orphans.is_empty() => {
+            styled_warning(format!(
+                "{} tokens belong to no credential: {}. Run {} to erase them.",
+                orphans.len(),
+                labels_credential_host(&orphans).join(", "),
+                format!("{} prune --orphans", env!("CARGO_PKG_NAME")).blue()
+            ));
+        },
+        Ok(_) => {},
+        Err(err) => debug!("Not looking for orphaned tokens: {err:#}"),
+    }
+}
 
-        for credential_name in credentials {
-            let token = get_keyring_token(credential_name, host);
-            if let Ok(token) = token {
-                eprintln!("  - {credential_name} ({token})");
-            } else {
-                eprintln!("  - {}", credential_name.red());
-            }
+/// When the token expires, or the ephemeral login ends
+fn expiry_cell(token: &Token) -> Cell {
+    let local = |at: DateTime<Utc>| at.with_timezone(&Local).format("%Y-%m-%d %H:%M");
+    match (token.deadline, token.expires_at) {
+        (Some(deadline), _) => {
+            Cell::styled(format!("{} (ephemeral)", local(deadline)), Style::Yellow)
+        },
+        (None, Some(expires_at)) if token.is_expired() && token.refresh_token().is_none() => {
+            Cell::styled(format!("{} (expired)", local(expires_at)), Style::Red)
+        },
+        (None, Some(expires_at)) => Cell::new(local(expires_at).to_string()),
+        (None, None) => Cell::styled("never", Style::Dimmed),
+    }
+}
+
+/// Show the identity git will commit with in the current repository, the one
+/// the rules expect and who authored the last few commits.
+fn repo_status(ctx: &AppContext, output: OutputFormat) -> Result<()> {
+    let Ok(RepoContext { repo, .. }) = repo_context(None) else {
+        styled_error("Not a git repository!");
+        bail!("Not a git repository!");
+    };
+    let cfg = repo
+        .config()
+        .and_then(|mut cfg| cfg.snapshot())
+        .context("Failed to open git config")?;
+    let name = cfg.get_string("user.name").ok();
+    let email = cfg.get_string("user.email").ok();
+
+    let profile_config = ctx.profiles.load()?;
+    let resolved = repo_profile(&repo, &profile_config);
+    let expected = resolved.as_ref().and_then(|(profile_ref, _)| {
+        profile_config
+            .profiles
+            .resolve(profile_ref)
+            .map(|(_, profile)| profile)
+    });
+    let expected_name = expected.and_then(|p| p.configs.get("user.name"));
+    let expected_email = expected.and_then(|p| p.configs.get("user.email"));
+
+    // compare authors against the profile if one applies, otherwise against the
+    // configured identity
+    let reference = expected_email.or(email.as_ref());
+    let mut commits = Vec::new();
+    let mut has_head = false;
+    if let Ok(mut revwalk) = repo.revwalk() {
+        has_head = revwalk.push_head().is_ok();
+        for oid in revwalk.take(RECENT_COMMITS) {
+            let commit = repo.find_commit(oid?)?;
+            let author = commit.author();
+            commits.push(CommitStatus {
+                id: commit.id().to_string(),
+                author_name: author.name().map(str::to_string),
+                author_email: author.email().map(str::to_string),
+                mismatch: reference.is_some_and(|r| author.email() != Some(r.as_str())),
+            });
+        }
+    }
+
+    if !output.is_text() {
+        return output.print(
+            &*ctx.io,
+            &RepoStatus {
+                user_name: name,
+                user_email: email,
+                profile: resolved.as_ref().map(|(r, _)| r.name.clone()),
+                default_profile: resolved.as_ref().is_some_and(|(_, fallback)| *fallback),
+                expected_user_name: expected_name.cloned(),
+                expected_user_email: expected_email.cloned(),
+                commits,
+            },
+        );
+    }
+
+    eprintln!(
+        "{}: {} <{}>",
+        "identity".bold(),
+        name.as_deref().unwrap_or("-"),
+        email.as_deref().unwrap_or("-")
+    );
+    match &resolved {
+        Some((profile_ref, fallback)) => {
+            let note = if *fallback { " (default_profile)" } else { "" };
+            eprintln!("{}: {}{note}", "profile".bold(), profile_ref.name);
+        },
+        None => eprintln!("{}: {}", "profile".bold(), "no rule matched".dimmed()),
+    }
+    let name_mismatch = expected_name.is_some_and(|n| name.as_ref() != Some(n));
+    let email_mismatch = expected_email.is_some_and(|e| email.as_ref() != Some(e));
+    if name_mismatch || email_mismatch {
+        styled_error(format!(
+            "The profile expects {} <{}>, run {} to fix it.",
+            expected_name.map_or("-", String::as_str),
+            expected_email.map_or("-", String::as_str),
+            format!("{} apply", env!("CARGO_PKG_NAME")).blue()
+        ));
+    }
+
+    if !has_head {
+        eprintln!("{}", "No commits yet.".dimmed());
+        return Ok(());
+    }
+    eprintln!("{}:", "recent commits".bold());
+    for commit in &commits {
+        let line = format!(
+            "{} {} <{}>",
+            &commit.id[..7],
+            commit.author_name.as_deref().unwrap_or("-"),
+            commit.author_email.as_deref().unwrap_or("-")
+        );
+        if commit.mismatch {
+            eprintln!("  {} {}", line.red(), "(mismatch)".red());
+        } else {
+            eprintln!("  {line}");
         }
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use chrono::TimeDelta;
+    use serde_json::Value;
+
+    use super::*;
+    use crate::config::hosts::{Credential, HostConfig};
+    use crate::context::mock;
+
+    #[test]
+    fn json_status_reports_stored_tokens() {
+        let (ctx, output) = mock::context(Hosts::from_map(HashMap::from([(
+            "github.com".to_string(),
+            HostConfig {
+                active: "alice".into(),
+                credentials: vec![
+                    "bob".into(),
+                    Credential {
+                        label: Some("work".into()),
+                        ..Credential::from("alice")
+                    },
+                ],
+                session_previous: None,
+            },
+        )])));
+        let token =
+            Token::new("gho_alice".into(), None, None).with_scopes(Some(vec!["read:user".into()]));
+        ctx.token_store
+            .store("alice", "github.com", &token)
+            .unwrap();
+
+        status(
+            &ctx,
+            false,
+            OutputFormat::Json,
+            &CredentialFilter::default(),
+        )
+        .unwrap();
+        let printed: Value = serde_json::from_str(&output.text()).unwrap();
+        assert_eq!(
+            printed,
+            serde_json::json!([
+                {
+                    "host": "github.com",
+                    "credential": "alice",
+                    "active": true,
+                    "stored": true,
+                    "fingerprint": token.to_string(),
+                    "expires_at": null,
+                    "refresh_expires_at": null,
+                    "scopes": ["read:user"],
+                    "deadline": null,
+                    "label": "work",
+                    "created_at": null,
+                    "last_used": null,
+                },
+                {
+                    "host": "github.com",
+                    "credential": "bob",
+                    "active": false,
+                    "stored": false,
+                    "fingerprint": null,
+                    "expires_at": null,
+                    "refresh_expires_at": null,
+                    "scopes": null,
+                    "deadline": null,
+                    "label": null,
+                    "created_at": null,
+                    "last_used": null,
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn filters_narrow_the_json_status() {
+        let (ctx, output) = mock::context(Hosts::from_map(HashMap::from([
+            (
+                "github.com".to_string(),
+                HostConfig {
+                    active: "alice".into(),
+                    credentials: vec!["bob".into(), "alice".into()],
+                    session_previous: None,
+                },
+            ),
+            (
+                "git.example.com".to_string(),
+                HostConfig {
+                    active: "carol".into(),
+                    credentials: vec!["carol".into()],
+                    session_previous: None,
+                },
+            ),
+        ])));
+        let expired = Token::new(
+            "gho_alice".into(),
+            None,
+            Some(Utc::now() - TimeDelta::hours(1)),
+        );
+        ctx.token_store
+            .store("alice", "github.com", &expired)
+            .unwrap();
+        let lasting = Token::new("glpat_carol".into(), None, None);
+        ctx.token_store
+            .store("carol", "git.example.com", &lasting)
+            .unwrap();
+        let shown = |filter: CredentialFilter| {
+            let before = output.text().len();
+            status(&ctx, false, OutputFormat::Json, &filter).unwrap();
+            let printed: Value = serde_json::from_str(&output.text()[before..]).unwrap();
+            printed
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|c| c["credential"].as_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        let host = |glob: &str| {
+            CredentialFilter {
+                host: Some(glob.into()),
+                ..CredentialFilter::default()
+            }
+        };
+        assert_eq!(shown(host("*.example.com")), ["carol"], "host glob");
+        assert_eq!(shown(host("GitHub.*")), ["alice", "bob"], "any case");
+        let expired = CredentialFilter {
+            expired: true,
+            ..CredentialFilter::default()
+        };
+        assert_eq!(shown(expired), ["alice"], "expired");
+        let missing = CredentialFilter {
+            missing_keyring: true,
+            ..CredentialFilter::default()
+        };
+        assert_eq!(shown(missing), ["bob"], "missing");
+    }
+}
@@ -1,15 +1,14 @@
 use anyhow::{Context as _, Result, bail};
-use tracing::{info, instrument, warn};
+use tracing::{debug, info, instrument, warn};
 
 use crate::commands::common::styled_error;
-use crate::config::OAuthConfig;
-use crate::keyring::{Token, store_keyring_token};
-use crate::load_cfg;
+use crate::context::AppContext;
+use crate::keyring::Token;
 use crate::utils::parse_credential_request;
 
-#[instrument]
-pub async fn handle_store() -> Result<()> {
-    let oauth_config = load_cfg!(OAuthConfig)?;
+#[instrument(skip(ctx))]
+pub async fn handle_store(ctx: &AppContext) -> Result<()> {
+    let oauth_config = ctx.oauth.load()?;
     if oauth_config.oauth_only.is_some_and(|x| x) {
         return Ok(());
     }
@@ -19,13 +18,13 @@ pub async fn handle_store() -> Result<()> {
         && let Some(password) = &req.password
     {
         let token = Token::new(
-            password.clone(),
-            req.oauth_refresh_token,
+            password.expose().to_string(),
+            req.oauth_refresh_token
+                .map(|token| token.expose().to_string()),
             req.password_expiry_utc,
         );
-        store_keyring_token(credential, &req.host, &token)
-            .context("Failed to store token in keyring")?;
-        Ok(())
+        let host = oauth_config.canonical_host(&req.host);
+        store_token(ctx, credential, &host, token)
     } else {
         let msg = "No username or password provided in request; nothing to store.";
         warn!("{msg}");
@@ -33,3 +32,71 @@ pub async fn handle_store() -> Result<()> {
         bail!(msg)
     }
 }
+
+/// Store `token` for `credential` on `host` over what is stored already. Git
+/// stores every token after using it, so an unchanged one isn't written again,
+/// and a new one keeps what warden knows of the login besides the tokens, such
+/// as the deadline of an ephemeral one.
+fn store_token(ctx: &AppContext, credential: &str, host: &str, mut token: Token) -> Result<()> {
+    if let Ok(stored) = ctx.token_store.get(credential, host) {
+        if stored.access_token() == token.access_token() {
+            debug!("Token unchanged, not storing it again");
+            return Ok(());
+        }
+        token.keep_login(&stored);
+    }
+    ctx.token_store
+        .store(credential, host, &token)
+        .context("Failed to store token in keyring")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use chrono::{TimeDelta, Utc};
+
+    use super::*;
+    use crate::config::Hosts;
+    use crate::context::mock;
+
+    #[test]
+    fn storing_keeps_the_login() {
+        let (ctx, _) = mock::context(Hosts::from_map(HashMap::new()));
+        let deadline = Utc::now() + TimeDelta::hours(2);
+        let login = Token::new("gho_first".into(), Some("ghr_first".into()), None)
+            .with_scopes(Some(vec!["repo".into()]))
+            .with_deadline(Some(deadline));
+        ctx.token_store
+            .store("alice", "github.com", &login)
+            .unwrap();
+
+        let same = Token::new("gho_first".into(), None, None);
+        store_token(&ctx, "alice", "github.com", same).unwrap();
+        let stored = ctx.token_store.get("alice", "github.com").unwrap();
+        assert_eq!(stored.refresh_token(), Some("ghr_first"), "unchanged");
+
+        let renewed = Token::new("gho_second".into(), None, None);
+        store_token(&ctx, "alice", "github.com", renewed).unwrap();
+        let stored = ctx.token_store.get("alice", "github.com").unwrap();
+        assert_eq!(stored.access_token(), "gho_second");
+        assert_eq!(stored.deadline, Some(deadline), "deadline survives");
+        assert_eq!(stored.scopes, Some(vec!["repo".into()]));
+        assert_eq!(stored.refresh_token(), Some("ghr_first"));
+
+        store_token(
+            &ctx,
+            "bob",
+            "github.com",
+            Token::new("gho_bob".into(), None, None),
+        )
+        .unwrap();
+        assert!(
+            ctx.token_store
+                .get("bob", "github.com")
+                .unwrap()
+                .deadline
+                .is_none()
+        );
+    }
+}
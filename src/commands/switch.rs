@@ -1,69 +1,81 @@
+use std::path::Path;
+
 use anyhow::{Context as _, Result, bail};
 use colored::Colorize as _;
-use git2::Repository;
 use tracing::instrument;
 
 use crate::commands::common::{
     CredentialPair, collect_all_pairs, filter_pairs, labels_credential_host, labels_host_active,
     sort_pairs, styled_error,
 };
-use crate::config::{Hosts, ProfileConfig};
-use crate::load_cfg;
+use crate::config::Hosts;
+use crate::context::AppContext;
 use crate::profile::url::{Patterns, Url as RepoUrl};
-use crate::utils::select_index;
-
-#[instrument]
-pub fn switch(hostname: Option<&String>, name: Option<&String>, show_all: bool) -> Result<()> {
-    let hosts_config = &mut load_cfg!(Hosts)?;
-    let profile_config = load_cfg!(ProfileConfig)?;
-    if hostname.is_none_or(|h| h.trim().is_empty()) && !show_all {
-        let repo = Repository::open_from_env();
-        let Ok(repo) = repo else {
+use crate::utils::{RepoContext, UrlUse, normalize_host, remote_urls, repo_context, select_index};
+
+#[instrument(skip(ctx))]
+pub fn switch(
+    ctx: &AppContext,
+    hostname: Option<&String>,
+    name: Option<&String>,
+    show_all: bool,
+    repo: Option<&Path>,
+) -> Result<()> {
+    let hosts_config = &mut ctx.hosts.load()?;
+    let profile_config = ctx.profiles.load()?;
+    let hostname = hostname.map(|h| normalize_host(h));
+    if hostname.as_ref().is_none_or(|h| h.trim().is_empty()) && !show_all {
+        let Ok(RepoContext { repo, .. }) = repo_context(repo) else {
             styled_error("Not a git repository!");
             bail!("Not a git repository!");
         };
 
-        let remote = repo.find_remote("origin");
-        if let Ok(remote) = remote {
-            let remote_url = remote.url().expect("No remote url");
+        let remote = remote_urls(&repo, "origin", UrlUse::Fetch)?;
+        if let Some(remote_url) = remote.first() {
             let url: RepoUrl = match RepoUrl::from_str(remote_url, &profile_config.patterns, None) {
                 Ok(u) => u,
                 Err(_) => RepoUrl::from_str(remote_url, &Patterns::default(), None)?,
             };
-            let host = url.host.to_string();
+            let host = normalize_host(&url.host.to_string());
             if hosts_config.has_host(&host) {
                 // only use the repo host if it is known
-                return switch_by_host(hosts_config, &host);
+                return switch_by_host(ctx, hosts_config, &host);
             }
         }
     }
-    match (hostname, name) {
+    match (hostname.as_ref(), name) {
         (Some(host), Some(credential)) => {
-            activate(hosts_config, host, credential).with_context(|| {
+            activate(ctx, hosts_config, host, credential).with_context(|| {
                 format!("Failed to switch active credential for host '{host}' to '{credential}'")
             })
         },
         (Some(host), None) => {
-            switch_by_host(hosts_config, host)
+            switch_by_host(ctx, hosts_config, host)
                 .with_context(|| format!("Failed to switch active credential for host '{host}'"))
         },
         (None, Some(credential)) => {
-            switch_by_credential(hosts_config, credential)
+            switch_by_credential(ctx, hosts_config, credential)
                 .with_context(|| format!("Failed to switch to credential '{credential}'"))
         },
-        (None, None) => switch_any(hosts_config),
+        (None, None) => switch_any(ctx, hosts_config),
     }
 }
 
-fn activate(hosts_config: &mut Hosts, host: &str, credential: &str) -> Result<()> {
+fn activate(
+    ctx: &AppContext,
+    hosts_config: &mut Hosts,
+    host: &str,
+    credential: &str,
+) -> Result<()> {
     if !hosts_config.has_credential(host, credential) {
         styled_error(format!(
             "No credential named '{credential}' found for host '{host}'",
         ));
         bail!("No credential named '{credential}' found for host '{host}'");
     }
-    hosts_config
-        .set_active_credential(host, credential)
+    hosts_config.set_active_credential(host, credential);
+    ctx.hosts
+        .save(hosts_config)
         .context("Failed to set active credential")?;
     eprintln!(
         "Switched active credential for {} to {}",
@@ -73,11 +85,10 @@ fn activate(hosts_config: &mut Hosts, host: &str, credential: &str) -> Result<()
     Ok(())
 }
 
-fn switch_by_host(hosts_config: &mut Hosts, host: &str) -> Result<()> {
+fn switch_by_host(ctx: &AppContext, hosts_config: &mut Hosts, host: &str) -> Result<()> {
     let credentials = hosts_config
         .get_credentials(host)
-        .with_context(|| format!("Failed to get credentials for host '{host}'"))?
-        .to_owned();
+        .with_context(|| format!("Failed to get credentials for host '{host}'"))?;
 
     if credentials.is_empty() {
         let msg = format!("No credentials found for host '{host}'");
@@ -101,10 +112,14 @@ fn switch_by_host(hosts_config: &mut Hosts, host: &str) -> Result<()> {
         &credentials[selection]
     };
 
-    activate(hosts_config, host, target)
+    activate(ctx, hosts_config, host, target)
 }
 
-fn switch_by_credential(hosts_config: &mut Hosts, credential: &str) -> Result<()> {
+fn switch_by_credential(
+    ctx: &AppContext,
+    hosts_config: &mut Hosts,
+    credential: &str,
+) -> Result<()> {
     let mut pairs: Vec<CredentialPair> = collect_all_pairs(hosts_config);
     pairs = filter_pairs(pairs.iter(), None, Some(credential));
 
@@ -113,7 +128,7 @@ fn switch_by_credential(hosts_config: &mut Hosts, credential: &str) -> Result<()
     }
 
     if pairs.len() == 1 {
-        return activate(hosts_config, &pairs[0].host, credential);
+        return activate(ctx, hosts_config, &pairs[0].host, credential);
     }
 
     sort_pairs(&mut pairs);
@@ -124,10 +139,10 @@ fn switch_by_credential(hosts_config: &mut Hosts, credential: &str) -> Result<()
         format!("Select a host to switch to '{credential}'"),
     )?;
     let host = &pairs[selection].host;
-    activate(hosts_config, host, credential)
+    activate(ctx, hosts_config, host, credential)
 }
 
-fn switch_any(hosts_config: &mut Hosts) -> Result<()> {
+fn switch_any(ctx: &AppContext, hosts_config: &mut Hosts) -> Result<()> {
     let mut pairs = collect_all_pairs(hosts_config);
     if pairs.is_empty() {
         bail!("No credentials found to switch");
@@ -154,5 +169,51 @@ fn switch_any(hosts_config: &mut Hosts) -> Result<()> {
         pairs[selection].clone()
     };
 
-    activate(hosts_config, &target.host, &target.credential)
+    activate(ctx, hosts_config, &target.host, &target.credential)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::config::hosts::HostConfig;
+    use crate::context::mock;
+
+    #[test]
+    fn switching_saves_the_active_credential() {
+        let (ctx, _) = mock::context(Hosts::from_map(HashMap::from([(
+            "github.com".to_string(),
+            HostConfig {
+                active: "alice".into(),
+                credentials: vec!["alice".into(), "bob".into()],
+                session_previous: None,
+            },
+        )])));
+
+        switch(
+            &ctx,
+            Some(&"GitHub.com".into()),
+            Some(&"bob".into()),
+            false,
+            None,
+        )
+        .unwrap();
+        let hosts = ctx.hosts.load().unwrap();
+        assert_eq!(hosts.get_active_credential("github.com"), Some("bob"));
+
+        assert!(
+            switch(
+                &ctx,
+                Some(&"github.com".into()),
+                Some(&"carol".into()),
+                false,
+                None
+            )
+            .is_err(),
+            "unknown credential"
+        );
+        let hosts = ctx.hosts.load().unwrap();
+        assert_eq!(hosts.get_active_credential("github.com"), Some("bob"));
+    }
 }
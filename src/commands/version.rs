@@ -0,0 +1,114 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tracing::instrument;
+
+use crate::context::AppContext;
+use crate::keyring::backend;
+use crate::output::OutputFormat;
+use crate::utils::{config_dir, state_dir};
+
+/// What `--version --json` prints, for bug reports
+#[derive(Serialize)]
+struct VersionInfo {
+    name: &'static str,
+    version: &'static str,
+    /// Commit the binary was built from, `None` for packaged sources
+    commit: Option<&'static str>,
+    build_date: Option<DateTime<Utc>>,
+    target: &'static str,
+    features: Features,
+    /// Where tokens are kept on this machine, `keyring` or `file`
+    token_store: String,
+    config_dir: Option<PathBuf>,
+    state_dir: Option<PathBuf>,
+}
+
+/// How the binary was built
+#[derive(Serialize)]
+struct Features {
+    /// The platform keyring compiled in
+    keyring: Option<&'static str>,
+    /// TLS stack of HTTP requests
+    tls: &'static str,
+    /// Whether libgit2 and the TLS stack are linked statically
+    vendored: bool,
+}
+
+/// Print the version, with `json` along with build metadata and the paths in
+/// use
+#[instrument(skip(ctx))]
+pub fn version(ctx: &AppContext, json: bool) -> Result<()> {
+    if !json {
+        ctx.io.print(&format!(
+            "{} {}\n",
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION")
+        ));
+        return Ok(());
+    }
+    let commit = env!("WARDEN_GIT_COMMIT");
+    let info = VersionInfo {
+        name: env!("CARGO_PKG_NAME"),
+        version: env!("CARGO_PKG_VERSION"),
+        commit: (!commit.is_empty()).then_some(commit),
+        build_date: env!("WARDEN_BUILD_EPOCH")
+            .parse()
+            .ok()
+            .and_then(|epoch| DateTime::from_timestamp(epoch, 0)),
+        target: env!("WARDEN_TARGET"),
+        features: features(),
+        token_store: backend().to_string(),
+        config_dir: config_dir().ok(),
+        state_dir: state_dir().ok(),
+    };
+    OutputFormat::Json.print(&*ctx.io, &info)
+}
+
+const fn features() -> Features {
+    let keyring = if cfg!(any(
+        target_os = "linux",
+        target_os = "freebsd",
+        target_os = "openbsd",
+        target_os = "netbsd",
+        target_os = "dragonfly"
+    )) {
+        Some("secret-service")
+    } else if cfg!(target_os = "windows") {
+        Some("windows-credential-manager")
+    } else if cfg!(any(target_os = "macos", target_os = "ios")) {
+        Some("apple-keychain")
+    } else {
+        None
+    };
+    let vendored = cfg!(feature = "vendored");
+    Features {
+        keyring,
+        tls: if vendored { "rustls" } else { "native-tls" },
+        vendored,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde_json::Value;
+
+    use super::*;
+    use crate::config::Hosts;
+    use crate::context::mock;
+
+    #[test]
+    fn json_version_carries_build_metadata() {
+        let (ctx, output) = mock::context(Hosts::from_map(HashMap::new()));
+        version(&ctx, true).unwrap();
+        let printed: Value = serde_json::from_str(&output.text()).unwrap();
+        assert_eq!(printed["version"], env!("CARGO_PKG_VERSION"));
+        assert!(printed["build_date"].is_string(), "build date");
+        assert!(!printed["target"].as_str().unwrap().is_empty(), "target");
+        assert!(printed["features"]["tls"].is_string(), "tls stack");
+    }
+}
@@ -2,6 +2,8 @@ use std::collections::HashMap;
 
 use config::{ConfigError, Source, Value};
 
+use crate::utils::{normalize_host, repo_context};
+
 /// Git-based configuration source for OAuth provider configuration.
 ///
 /// This source inspects git configuration entries with keys matching the
@@ -9,18 +11,29 @@ use config::{ConfigError, Source, Value};
 ///
 /// Supported (case-insensitive) suffixes:
 ///   - `Type`
+///   - `Issuer`
 ///   - `ClientId`
 ///   - `ClientSecret`
 ///   - `AuthURL`
 ///   - `TokenURL`
 ///   - `DeviceAuthURL`
 ///   - `PreferredFlow`
+///   - `Flows`
 ///   - `Scopes`
+///   - `AuthParams`
+///   - `ServiceAccount`
+///   - `AuthStyle`
+///   - `ClockSkew`
+///   - `MatchSubdomains`
+///   - `Refresh`
+///   - `CredentialNameTemplate`
+///
+/// `AuthParams` holds `key=value` pairs separated by whitespace or comma.
 ///
-/// Scopes are split on whitespace or comma. If the parsed list is empty, we
-/// emit an explicit empty array (representing `Some(empty)`). If the Scopes key
-/// is absent entirely, the provider's `scopes` field will deserialize to
-/// `None`.
+/// Scopes are split on whitespace or comma. "default" and "none" are passed on
+/// as they are, and an empty list emits an explicit empty array, which sends
+/// an empty `scope` parameter. If the Scopes key is absent entirely, the
+/// provider uses its type's default scopes.
 ///
 /// `<base>` may include a scheme (`http://` or `https://`). For relative endpoint
 /// values (those beginning with `'/'`), the code joins them onto a
@@ -52,7 +65,8 @@ impl GitConfigSource {
         }
     }
 
-    /// Repository-local `.git/config` (discovered from current working dir)
+    /// Repository-local `.git/config` of the repository the commands work on,
+    /// honoring `GIT_DIR`, `GIT_WORK_TREE` and `--cwd`
     pub const fn repo() -> Self {
         Self {
             mode: GitSourceMode::RepoLocal,
@@ -70,15 +84,15 @@ impl Source for GitConfigSource {
         reason = "This is a single-source config reader, so it has to do a lot of work"
     )]
     fn collect(&self) -> Result<HashMap<String, Value>, ConfigError> {
-        use git2::{Config as Git2Config, Repository};
+        use git2::Config as Git2Config;
 
         // get appropriate Git configuration
         let git_cfg_opt = match self.mode {
             GitSourceMode::GlobalAndSystem => Git2Config::open_default().ok(),
             GitSourceMode::RepoLocal => {
-                Repository::open_from_env()
+                repo_context(None)
                     .ok()
-                    .and_then(|repo| repo.config().ok())
+                    .and_then(|ctx| ctx.repo.config().ok())
             },
         };
 
@@ -111,18 +125,7 @@ impl Source for GitConfigSource {
                     continue;
                 }
 
-                let trimmed = raw_base.trim_end_matches('/');
-                let canonical_base = trimmed
-                    .strip_prefix("https://")
-                    .or_else(|| trimmed.strip_prefix("http://"))
-                    .unwrap_or(trimmed);
-
-                let endpoint_base =
-                    if trimmed.starts_with("http://") || trimmed.starts_with("https://") {
-                        trimmed.to_string()
-                    } else {
-                        format!("https://{canonical_base}")
-                    };
+                let (canonical_base, endpoint_base) = provider_bases(raw_base);
 
                 let raw_value = entry.value().unwrap_or_default();
 
@@ -136,7 +139,7 @@ impl Source for GitConfigSource {
                 };
 
                 let value_entry = providers_table
-                    .entry(canonical_base.to_string())
+                    .entry(canonical_base.clone())
                     .or_insert_with(|| Value::from(HashMap::<String, Value>::new()));
 
                 let mut table = value_entry
@@ -163,18 +166,78 @@ impl Source for GitConfigSource {
                             Value::from(resolve_endpoint(raw_value)),
                         );
                     },
+                    "revocationurl" => {
+                        table.insert(
+                            "revocation_url".into(),
+                            Value::from(resolve_endpoint(raw_value)),
+                        );
+                    },
+                    "issuer" => {
+                        table.insert("issuer".into(), Value::from(raw_value.to_string()));
+                    },
                     "type" => {
                         table.insert("type".into(), Value::from(raw_value.to_string()));
                     },
                     "preferredflow" => {
                         table.insert("preferred_flow".into(), Value::from(raw_value.to_string()));
                     },
+                    "flows" => {
+                        let flows: Vec<Value> = raw_value
+                            .split(|c: char| c.is_whitespace() || c == ',')
+                            .filter(|s| !s.is_empty())
+                            .map(|s| Value::from(s.to_string()))
+                            .collect();
+                        table.insert("flows".into(), Value::from(flows));
+                    },
+                    "authparams" => {
+                        let params: HashMap<String, Value> = raw_value
+                            .split(|c: char| c.is_whitespace() || c == ',')
+                            .filter_map(|param| param.split_once('='))
+                            .map(|(key, value)| (key.to_string(), Value::from(value.to_string())))
+                            .collect();
+                        table.insert("auth_params".into(), Value::from(params));
+                    },
+                    "serviceaccount" => {
+                        table.insert("service_account".into(), Value::from(raw_value.to_string()));
+                    },
+                    "clockskew" => {
+                        if let Ok(seconds) = raw_value.parse::<u64>() {
+                            table.insert("clock_skew".into(), Value::from(seconds));
+                        }
+                    },
+                    "refresh" => {
+                        let enabled = matches!(
+                            raw_value.to_ascii_lowercase().as_str(),
+                            "1" | "true" | "yes" | "on"
+                        );
+                        table.insert("refresh".into(), Value::from(enabled));
+                    },
+                    "matchsubdomains" => {
+                        let enabled = matches!(
+                            raw_value.to_ascii_lowercase().as_str(),
+                            "1" | "true" | "yes" | "on"
+                        );
+                        table.insert("match_subdomains".into(), Value::from(enabled));
+                    },
+                    "credentialnametemplate" => {
+                        table.insert(
+                            "credential_name_template".into(),
+                            Value::from(raw_value.to_string()),
+                        );
+                    },
+                    "authstyle" => {
+                        table.insert("auth_style".into(), Value::from(raw_value.to_string()));
+                    },
                     "scopes" => {
                         let scopes: Vec<_> = raw_value
                             .split(|c: char| c.is_whitespace() || c == ',')
                             .filter(|s| !s.is_empty())
                             .collect();
-                        if scopes.is_empty() {
+                        if let [word] = scopes[..]
+                            && ["default", "none"].contains(&word.to_ascii_lowercase().as_str())
+                        {
+                            table.insert("scopes".into(), Value::from(word.to_string()));
+                        } else if scopes.is_empty() {
                             table.insert("scopes".into(), Value::from(Vec::<Value>::new()));
                         } else {
                             let scope_values: Vec<Value> = scopes
@@ -190,7 +253,7 @@ impl Source for GitConfigSource {
                     },
                 }
 
-                providers_table.insert(canonical_base.to_string(), Value::from(table));
+                providers_table.insert(canonical_base, Value::from(table));
             }
         }
 
@@ -206,7 +269,45 @@ impl Source for GitConfigSource {
             .ok()
             .and_then(|e| e.value().and_then(|v| v.parse::<u16>().ok()));
 
-        if providers_table.is_empty() && oauth_only.is_none() {
+        let clock_skew = git_cfg
+            .get_entry("warden.clock-skew")
+            .ok()
+            .and_then(|e| e.value().and_then(|v| v.parse::<u64>().ok()));
+        let get_timeout = git_cfg
+            .get_entry("warden.get-timeout")
+            .ok()
+            .and_then(|e| e.value().and_then(|v| v.parse::<u64>().ok()));
+        let coalesce_window = git_cfg
+            .get_entry("warden.coalesce-window")
+            .ok()
+            .and_then(|e| e.value().and_then(|v| v.parse::<u64>().ok()));
+
+        let stats = git_cfg.get_bool("warden.stats").ok();
+        let choose_credential = git_cfg.get_bool("warden.choose-credential").ok();
+        let interactive_onboarding = git_cfg.get_bool("warden.interactive-onboarding").ok();
+        let use_builtin_clients = git_cfg.get_bool("warden.use-builtin-clients").ok();
+        let state_encrypted = git_cfg.get_bool("warden.state-encrypted").ok();
+        let confirm_destructive = git_cfg.get_string("warden.confirm-destructive").ok();
+        let credential_name_template = git_cfg.get_string("warden.credential-name-template").ok();
+        let token_display = git_cfg.get_string("warden.token-display").ok();
+        let merge_strategy = git_cfg.get_string("warden.merge-strategy").ok();
+
+        if providers_table.is_empty()
+            && oauth_only.is_none()
+            && port.is_none()
+            && clock_skew.is_none()
+            && get_timeout.is_none()
+            && coalesce_window.is_none()
+            && stats.is_none()
+            && choose_credential.is_none()
+            && interactive_onboarding.is_none()
+            && use_builtin_clients.is_none()
+            && state_encrypted.is_none()
+            && confirm_destructive.is_none()
+            && credential_name_template.is_none()
+            && token_display.is_none()
+            && merge_strategy.is_none()
+        {
             return Ok(HashMap::new());
         }
 
@@ -217,6 +318,49 @@ impl Source for GitConfigSource {
         if let Some(port) = port {
             root.insert("port".into(), Value::from(port));
         }
+        if let Some(clock_skew) = clock_skew {
+            root.insert("clock_skew".into(), Value::from(clock_skew));
+        }
+        if let Some(get_timeout) = get_timeout {
+            root.insert("get_timeout".into(), Value::from(get_timeout));
+        }
+        if let Some(window) = coalesce_window {
+            root.insert("coalesce_window".into(), Value::from(window));
+        }
+        if let Some(stats) = stats {
+            root.insert("stats".into(), Value::from(stats));
+        }
+        if let Some(choose) = choose_credential {
+            root.insert("choose_credential".into(), Value::from(choose));
+        }
+        if let Some(onboarding) = interactive_onboarding {
+            root.insert("interactive_onboarding".into(), Value::from(onboarding));
+        }
+        if let Some(builtin) = use_builtin_clients {
+            root.insert("use_builtin_clients".into(), Value::from(builtin));
+        }
+        if let Some(policy) = confirm_destructive {
+            root.insert(
+                "confirm_destructive".into(),
+                Value::from(policy.to_ascii_lowercase()),
+            );
+        }
+        if let Some(template) = credential_name_template {
+            root.insert("credential_name_template".into(), Value::from(template));
+        }
+        if let Some(display) = token_display {
+            root.insert(
+                "token_display".into(),
+                Value::from(display.to_ascii_lowercase()),
+            );
+        }
+        if let Some(strategy) = merge_strategy {
+            root.insert("merge_strategy".into(), Value::from(strategy));
+        }
+        if let Some(encrypted) = state_encrypted {
+            let table = HashMap::from([("encrypted".to_string(), Value::from(encrypted))]);
+            root.insert("state".into(), Value::from(table));
+        }
         if !providers_table.is_empty() {
             root.insert("providers".into(), Value::from(providers_table));
         }
@@ -224,21 +368,47 @@ impl Source for GitConfigSource {
     }
 }
 
+/// The provider key and the base relative endpoints are joined to for the
+/// `<base>` of a `credential.<base>.oauth*` key. The key is normalized like
+/// request hosts so it merges with `oauth.toml` entries for the same host,
+/// custom ports are kept in both.
+fn provider_bases(raw_base: &str) -> (String, String) {
+    let trimmed = raw_base.trim_end_matches('/');
+    let canonical = normalize_host(trimmed);
+    let endpoint_base = if trimmed.starts_with("http://") {
+        format!("http://{canonical}")
+    } else {
+        format!("https://{canonical}")
+    };
+    (canonical, endpoint_base)
+}
+
 #[cfg(test)]
 mod tests {
     // NOTE: These tests are limited to transformation logic assumptions.
     // Full integration tests would require setting up temporary git config
     // files and making libgit2 read them, which is heavier than desired here.
+    use super::provider_bases;
 
     #[test]
     fn canonical_base_strip_scheme() {
-        let raw_base = "https://git.example.com";
-        let trimmed = raw_base.trim_end_matches('/');
-        let canonical = trimmed
-            .strip_prefix("https://")
-            .or_else(|| trimmed.strip_prefix("http://"))
-            .unwrap_or(trimmed);
-        assert_eq!(canonical, "git.example.com");
+        assert_eq!(
+            provider_bases("https://git.example.com"),
+            ("git.example.com".into(), "https://git.example.com".into())
+        );
+        assert_eq!(
+            provider_bases("git.example.com:443/"),
+            ("git.example.com".into(), "https://git.example.com".into()),
+            "default port"
+        );
+        assert_eq!(
+            provider_bases("http://git.example.com:8080"),
+            (
+                "git.example.com:8080".into(),
+                "http://git.example.com:8080".into()
+            ),
+            "custom port and scheme"
+        );
     }
 
     #[test]
@@ -1,13 +1,96 @@
 use std::collections::HashMap;
 use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
 
 use anyhow::{Context as _, Result};
-use config::{Config, File};
-use serde::{Deserialize, Serialize};
+use chrono::{DateTime, TimeDelta, Utc};
+use config::{Config, File, FileFormat, Source};
+use serde::{Deserialize, Deserializer, Serialize};
+use toml_edit::{Array, ArrayOfTables, DocumentMut, Item, Table, value};
+use tracing::{info, warn};
+use zeroize::Zeroizing;
 
-use crate::config::LoadableConfig;
-use crate::keyring::erase_keyring_token;
-use crate::utils::config_dir;
+use crate::commands::common::{styled_note, styled_warning};
+use crate::config::persist::{move_file, remove_backups, write_with_backup};
+use crate::config::provider::StateConfig;
+use crate::config::{LoadableConfig, OAuthConfig};
+use crate::keyring::{
+    Backend, STATE_HOST, backend, erase_keyring_secret, erase_keyring_token, get_keyring_secret,
+    get_keyring_token, store_keyring_secret, store_keyring_token,
+};
+use crate::utils::{config_dir, normalize_host, state_dir};
+
+/// Host state file in the state directory
+const HOSTS_FILE: &str = "hosts.toml";
+/// Host state file in the config directory, used before the state directory
+const CONFIG_DIR_HOSTS_FILE: &str = ".hosts.toml";
+/// Name the host state was stored under in the config directory by even
+/// older releases
+const LEGACY_HOSTS_FILE: &str = "hosts.toml";
+/// Keyring entry holding the host state with `[state] encrypted = true`
+const HOSTS_SECRET: &str = "hosts";
+/// Top-level key holding the schema version of the host state
+const VERSION_KEY: &str = "version";
+/// Schema version written, 2 keeps a table per credential where 1 only had
+/// their names
+const SCHEMA_VERSION: i64 = 2;
+/// How outdated `last_used` may get before serving a credential updates it,
+/// so Git requests don't rewrite the state every time
+const LAST_USED_RESOLUTION: TimeDelta = TimeDelta::hours(1);
+
+/// A credential of a host with what is known about it
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Credential {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<DateTime<Utc>>,
+    /// When the credential last answered a Git request, within
+    /// [`LAST_USED_RESOLUTION`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_used: Option<DateTime<Utc>>,
+    /// Scopes the provider granted at login, if it reported them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scopes: Option<Vec<String>>,
+    /// Free text telling the credential apart, e.g. "work laptop"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// Profile of `profiles.toml` used with the credential
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+}
+
+impl From<&str> for Credential {
+    fn from(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            ..Self::default()
+        }
+    }
+}
+
+/// A credential as stored by either schema version, a name or a table
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredCredential {
+    Name(String),
+    Table(Credential),
+}
+
+fn credential_list<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<Credential>, D::Error> {
+    let stored = Vec::<StoredCredential>::deserialize(deserializer)?;
+    Ok(stored
+        .into_iter()
+        .map(|credential| {
+            match credential {
+                StoredCredential::Name(name) => Credential::from(name.as_str()),
+                StoredCredential::Table(credential) => credential,
+            }
+        })
+        .collect())
+}
 
 /// Represents the stored state for a single host and its credentials
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -15,62 +98,376 @@ pub struct HostConfig {
     /// Currently active credential for this host
     pub active: String,
     /// All known credentials for this host
-    #[serde(alias = "users")]
-    pub credentials: Vec<String>,
+    #[serde(alias = "users", deserialize_with = "credential_list")]
+    pub credentials: Vec<Credential>,
+    /// Credential that was active before a `session`, restored when it ends
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_previous: Option<String>,
+}
+
+impl HostConfig {
+    /// The names of the host's credentials
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.credentials.iter().map(|c| c.name.as_str())
+    }
+
+    fn credential_mut(&mut self, name: &str) -> Option<&mut Credential> {
+        self.credentials.iter_mut().find(|c| c.name == name)
+    }
 }
 
 /// Collection of hosts keyed by their fully-qualified hostname
 #[derive(Debug, Clone, Default, Deserialize, Serialize)]
 pub struct Hosts {
     inner: HashMap<String, HostConfig>,
+    /// Whether the state is kept in the keyring rather than in `hosts.toml`
+    #[serde(skip)]
+    in_keyring: bool,
 }
 
 impl LoadableConfig for Hosts {
     const KIND: &'static str = "hosts";
 
+    /// Load host states from the keyring or the state directory, moving
+    /// them to where `[state] encrypted` asks for. When the OAuth
+    /// configuration can't be read they stay where they are.
+    fn load_raw() -> Result<Self> {
+        let encrypted = match state_encrypted() {
+            // the file store would put the state into plain tokens.json
+            Some(true) if backend() == Backend::File => {
+                styled_warning(
+                    "[state] encrypted = true is ignored, tokens are kept in the unencrypted \
+                     tokens.json. The host state stays in hosts.toml.",
+                );
+                Some(false)
+            },
+            encrypted => encrypted,
+        };
+        let path = state_dir()?.join(HOSTS_FILE);
+        let blob = if path.exists() && encrypted != Some(true) {
+            None
+        } else {
+            get_keyring_secret(HOSTS_SECRET, STATE_HOST).ok()
+        };
+        match (encrypted, blob) {
+            (Some(true), Some(blob)) if !path.exists() => Self::from_blob(&blob),
+            (Some(true), blob) => {
+                let mut hosts = Self::load_file()?;
+                hosts.in_keyring = true;
+                if !path.exists() {
+                    return Ok(hosts);
+                }
+                if let Some(blob) = blob {
+                    for (host, cfg) in Self::from_blob(&blob)?.inner {
+                        merge_host(&mut hosts.inner, host, cfg);
+                    }
+                }
+                hosts
+                    .write()
+                    .context("Failed to move hosts state into the keyring")?;
+                fs::remove_file(&path)
+                    .with_context(|| format!("Failed to remove {}", path.display()))?;
+                Self::remove_backups()?;
+                styled_note(format!(
+                    "Moved host state from {} into the keyring.",
+                    path.display()
+                ));
+                Ok(hosts)
+            },
+            (Some(false), Some(blob)) => {
+                let mut hosts = Self::from_blob(&blob)?;
+                hosts.in_keyring = false;
+                hosts
+                    .write()
+                    .context("Failed to move hosts state out of the keyring")?;
+                erase_keyring_secret(HOSTS_SECRET, STATE_HOST)?;
+                styled_note(format!(
+                    "Moved host state from the keyring to {}.",
+                    path.display()
+                ));
+                Ok(hosts)
+            },
+            (None, Some(blob)) => Self::from_blob(&blob),
+            (_, None) => Self::load_file(),
+        }
+    }
+
+    fn save(&self) -> Result<()> {
+        self.write()
+    }
+}
+
+/// `[state] encrypted` of the OAuth configuration loaded last, so the hosts
+/// state loaded after it doesn't read the configuration again
+static STATE_CONFIG: Mutex<Option<StateConfig>> = Mutex::new(None);
+
+/// `[state] encrypted` as recorded by [`Hosts::use_state_config`], or read
+/// from the OAuth configuration if none was loaded yet
+fn state_encrypted() -> Option<bool> {
+    let recorded = STATE_CONFIG
+        .lock()
+        .expect("State setting lock poisoned")
+        .clone();
+    recorded.map_or_else(
+        || {
+            OAuthConfig::load_report()
+                .ok()
+                .and_then(|cfg| cfg.state.encrypted)
+        },
+        |state| state.encrypted,
+    )
+}
+
+/// Read a hosts state file, accepting both the flat and the nested form. A
+/// missing file is empty.
+fn read_hosts_file(path: &Path) -> Result<HashMap<String, HostConfig>> {
+    read_hosts(File::from(path).required(false))
+}
+
+fn read_hosts<S: Source + Send + Sync + 'static>(source: S) -> Result<HashMap<String, HostConfig>> {
+    let builder = Config::builder().add_source(source);
+    let settings = builder
+        .build()
+        .context("Failed to build hosts configuration")?;
+
+    let mut value: serde_json::Value = settings
+        .try_deserialize()
+        .context("Malformed hosts configuration file")?;
+    if let Some(map) = value.as_object_mut() {
+        map.remove(VERSION_KEY);
+    }
+    // first try the straightforward flat map form
+    // (with lots of hopium)
+    if let Ok(flat) = serde_json::from_value::<HashMap<String, HostConfig>>(value.clone()) {
+        return Ok(flat);
+    }
+    // Fallback: recursively flatten arbitrary nesting
+
+    let mut flat: HashMap<String, HostConfig> = HashMap::new();
+    Hosts::flatten_hosts("", &value, &mut flat)
+        .context("Failed to flatten nested hosts configuration")?;
+    Ok(flat)
+}
+
+/// Whether the file still uses the old `users` key instead of `credentials`.
+fn uses_legacy_keys(path: &Path) -> bool {
+    fs::read_to_string(path).is_ok_and(|raw| {
+        raw.lines()
+            .any(|line| line.trim_start().starts_with("users"))
+    })
+}
+
+/// Whether the file holds host state of an older schema version
+fn uses_old_schema(path: &Path) -> bool {
+    fs::read_to_string(path).is_ok_and(|raw| {
+        !raw.trim().is_empty()
+            && raw
+                .parse::<DocumentMut>()
+                .ok()
+                .and_then(|doc| doc.get(VERSION_KEY).and_then(Item::as_integer))
+                != Some(SCHEMA_VERSION)
+    })
+}
+
+/// Insert `cfg` for `host`, merging its credentials into an existing entry
+/// whose active credential is kept.
+fn merge_host(map: &mut HashMap<String, HostConfig>, host: String, cfg: HostConfig) {
+    match map.get_mut(&host) {
+        Some(existing) => {
+            for credential in cfg.credentials {
+                if existing.credential_mut(&credential.name).is_none() {
+                    existing.credentials.push(credential);
+                }
+            }
+        },
+        None => {
+            map.insert(host, cfg);
+        },
+    }
+}
+
+/// Move keyring entries stored under a pre-normalization host name to the
+/// canonical one. Failures are logged and skipped, the old entry is only
+/// erased once the new one is stored.
+fn migrate_keyring_entries(hosts: &Hosts, renamed: &[(String, String)]) {
+    for (old, new) in renamed {
+        let Ok(credentials) = hosts.get_credentials(new) else {
+            continue;
+        };
+        for credential in &credentials {
+            let Ok(token) = get_keyring_token(credential, old) else {
+                continue;
+            };
+            match store_keyring_token(credential, new, &token) {
+                Ok(()) => {
+                    let _ = erase_keyring_token(credential, old);
+                    info!("Migrated keyring entry '{credential}' from '{old}' to '{new}'");
+                },
+                Err(err) => {
+                    warn!("Failed to migrate keyring entry '{credential}' from '{old}': {err:#}");
+                },
+            }
+        }
+    }
+}
+
+/// Render `credential` into `table`, leaving the fields that didn't change
+/// alone
+fn write_credential(table: &mut Table, credential: &Credential) {
+    let fields = [
+        (
+            "name",
+            Some(toml_edit::Value::from(credential.name.as_str())),
+        ),
+        (
+            "created_at",
+            credential.created_at.map(|at| at.to_rfc3339().into()),
+        ),
+        (
+            "last_used",
+            credential.last_used.map(|at| at.to_rfc3339().into()),
+        ),
+        (
+            "scopes",
+            credential
+                .scopes
+                .as_ref()
+                .map(|scopes| scopes.iter().map(String::as_str).collect::<Array>().into()),
+        ),
+        ("label", credential.label.as_deref().map(Into::into)),
+        ("profile", credential.profile.as_deref().map(Into::into)),
+    ];
+    for (key, field) in fields {
+        match field {
+            Some(field) => {
+                let unchanged = table
+                    .get(key)
+                    .and_then(Item::as_value)
+                    .is_some_and(|existing| existing.to_string().trim() == field.to_string());
+                if !unchanged {
+                    table.insert(key, Item::Value(field));
+                }
+            },
+            None => {
+                table.remove(key);
+            },
+        }
+    }
+}
+
+/// A credential created now
+fn new_credential(name: &str) -> Credential {
+    Credential {
+        created_at: Some(Utc::now()),
+        ..Credential::from(name)
+    }
+}
+
+impl Hosts {
+    /// Where the hosts state is kept according to `state` of the OAuth
+    /// configuration just loaded, for the next load of the hosts state
+    pub fn use_state_config(state: &StateConfig) {
+        *STATE_CONFIG.lock().expect("State setting lock poisoned") = Some(state.clone());
+    }
+
     /// Load host states from the standard config directory
     ///
-    /// The on-disk format is an (optionally nested) TOML map stored in
-    /// `.hosts.toml`:
+    /// The on-disk format is a versioned TOML map stored in `hosts.toml` in
+    /// the state directory:
     ///
     /// ```toml
-    /// [example.com]
+    /// version = 2
+    ///
+    /// ["example.com"]
     /// active = "alice"
-    /// users = ["alice", "bob"]
     ///
-    /// [git.example-url.com]
-    /// active = "carol"
-    /// users = ["carol"]
+    /// [["example.com".credentials]]
+    /// name = "alice"
+    /// created_at = "2025-01-01T00:00:00Z"
+    ///
+    /// [["example.com".credentials]]
+    /// name = "bob"
+    /// label = "work"
     /// ```
-    fn load_raw() -> Result<Self> {
-        let path = config_dir()?.join(".hosts.toml");
-        let builder = Config::builder().add_source(File::from(path).required(false));
-        let settings = builder
-            .build()
-            .context("Failed to build hosts configuration")?;
-
-        // first try the straightforward flat map form
-        // (with lots of hopium)
-        if let Ok(flat) = settings
-            .clone()
-            .try_deserialize::<HashMap<String, HostConfig>>()
-        {
-            return Ok(Self { inner: flat });
+    ///
+    /// Files without a version list only the credential names, as in
+    /// `credentials = ["alice", "bob"]` (or `users`, even older), and may be
+    /// nested. They are rewritten in the current format when loaded.
+    fn load_file() -> Result<Self> {
+        let config = config_dir()?;
+        let path = state_dir()?.join(HOSTS_FILE);
+        let in_config_dir = config.join(CONFIG_DIR_HOSTS_FILE);
+        if in_config_dir.exists() && !path.exists() {
+            move_file(&in_config_dir, &path)?;
+            styled_note(format!(
+                "Moved host state from {} to {}.",
+                in_config_dir.display(),
+                path.display()
+            ));
         }
+        let mut hosts = Self::from_map(read_hosts_file(&path)?);
+        let mut dirty = uses_legacy_keys(&path) || uses_old_schema(&path);
 
-        // Fallback: recursively flatten arbitrary nesting
-        let value: serde_json::Value = settings
-            .try_deserialize()
-            .context("Malformed hosts configuration file")?;
+        let legacy = config.join(LEGACY_HOSTS_FILE);
+        if legacy.exists() && legacy != path {
+            for (host, cfg) in read_hosts_file(&legacy)? {
+                merge_host(&mut hosts.inner, host, cfg);
+            }
+            let backup = legacy.with_extension("toml.bak");
+            fs::rename(&legacy, &backup)
+                .with_context(|| format!("Failed to move {}", legacy.display()))?;
+            styled_note(format!(
+                "Migrated host state from {} into {}, the old file was kept as {}.",
+                legacy.display(),
+                path.display(),
+                backup.display()
+            ));
+            dirty = true;
+        }
+
+        let renamed = hosts.normalize();
+        if !renamed.is_empty() {
+            migrate_keyring_entries(&hosts, &renamed);
+            dirty = true;
+        }
+        if dirty {
+            hosts
+                .write()
+                .context("Failed to write migrated hosts state")?;
+        }
+        Ok(hosts)
+    }
 
-        let mut flat: HashMap<String, HostConfig> = HashMap::new();
-        Self::flatten_hosts("", &value, &mut flat)
-            .context("Failed to flatten nested hosts configuration")?;
-        Ok(Self::from_map(flat))
+    /// Host states kept in the keyring
+    fn from_blob(blob: &str) -> Result<Self> {
+        let mut hosts = Self::from_map(read_hosts(File::from_str(blob, FileFormat::Toml))?);
+        hosts.in_keyring = true;
+        Ok(hosts)
+    }
+
+    /// Rewrite all host keys into their canonical form (see
+    /// [`normalize_host`]), merging hosts that collapse onto the same key.
+    ///
+    /// Returns the `(old, new)` names of every renamed host.
+    fn normalize(&mut self) -> Vec<(String, String)> {
+        let mut renamed = Vec::new();
+        let mut keys: Vec<String> = self.inner.keys().cloned().collect();
+        // already canonical keys first, so their active credential wins merges
+        keys.sort_by_key(|k| (normalize_host(k) != *k, k.clone()));
+
+        let mut normalized: HashMap<String, HostConfig> = HashMap::new();
+        for key in keys {
+            let cfg = self.inner.remove(&key).expect("key taken from same map");
+            let canonical = normalize_host(&key);
+            if canonical != key {
+                renamed.push((key, canonical.clone()));
+            }
+            merge_host(&mut normalized, canonical, cfg);
+        }
+        self.inner = normalized;
+        renamed
     }
-}
 
-impl Hosts {
     fn flatten_hosts(
         prefix: &str,
         v: &serde_json::Value,
@@ -96,22 +493,107 @@ impl Hosts {
         Ok(())
     }
 
-    /// Write the current state to the standard config directory
+    /// Write the current state to the state directory, or to the keyring
+    /// when it is kept there
+    ///
+    /// The file is updated in place, keeping comments and ordering of the
+    /// hosts that are still present, and replaced atomically with a backup
+    /// of the previous version.
     pub fn write(&self) -> Result<()> {
-        let path = config_dir()?.join(".hosts.toml");
-        let toml = self.to_toml_string()?;
-        fs::write(&path, toml).context("Failed to write hosts state")?;
+        if self.in_keyring {
+            let toml = Zeroizing::new(self.to_toml_document(""));
+            return store_keyring_secret(HOSTS_SECRET, STATE_HOST, &toml)
+                .context("Failed to write hosts state to the keyring");
+        }
+        let path = state_dir()?.join(HOSTS_FILE);
+        let existing = fs::read_to_string(&path).unwrap_or_default();
+        let toml = self.to_toml_document(&existing);
+        write_with_backup(&path, &toml).context("Failed to write hosts state")?;
         Ok(())
     }
 
-    /// Serialize to pretty TOML
-    pub fn to_toml_string(&self) -> Result<String> {
-        Ok(toml::to_string_pretty(&self.inner)?)
+    /// Delete the backups of previous versions of the state file, returning
+    /// how many there were
+    pub fn remove_backups() -> Result<usize> {
+        remove_backups(&state_dir()?.join(HOSTS_FILE))
+    }
+
+    /// Render the state into `existing`, a previously written hosts file.
+    ///
+    /// Nested or unparsable files are rewritten from scratch, as their keys
+    /// can't be matched up with the flat host names.
+    fn to_toml_document(&self, existing: &str) -> String {
+        let mut doc = existing
+            .parse::<DocumentMut>()
+            .ok()
+            .filter(|doc| {
+                doc.iter().all(|(key, item)| {
+                    key == VERSION_KEY || item.as_table().is_some_and(|t| t.contains_key("active"))
+                })
+            })
+            .unwrap_or_default();
+
+        doc.retain(|host, _| host == VERSION_KEY || self.inner.contains_key(host));
+        if doc.get(VERSION_KEY).and_then(Item::as_integer) != Some(SCHEMA_VERSION) {
+            // a comment heading the file stays above the version
+            let header = doc
+                .iter_mut()
+                .filter_map(|(_, item)| item.as_table_mut())
+                .min_by_key(|table| table.position())
+                .and_then(|table| {
+                    let prefix = table.decor().prefix()?.as_str()?.to_string();
+                    table.decor_mut().set_prefix("\n");
+                    Some(prefix)
+                });
+            doc.insert(VERSION_KEY, value(SCHEMA_VERSION));
+            if let (Some(header), Some(mut key)) = (header, doc.key_mut(VERSION_KEY)) {
+                key.leaf_decor_mut().set_prefix(header);
+            }
+        }
+        for (host, cfg) in self.iter_sorted() {
+            if !doc.contains_key(host) {
+                doc.insert(host, Item::Table(Table::new()));
+            }
+            let table = doc[host].as_table_mut().expect("only tables are kept");
+            table.remove("users");
+            if table.get("active").and_then(Item::as_str) != Some(cfg.active.as_str()) {
+                table.insert("active", value(cfg.active.as_str()));
+            }
+            let mut previous = match table.remove("credentials") {
+                Some(Item::ArrayOfTables(tables)) => tables.into_iter().collect(),
+                Some(_) | None => Vec::new(),
+            };
+            let mut credentials = ArrayOfTables::new();
+            for credential in &cfg.credentials {
+                // tables of known credentials are kept for their comments
+                let mut entry = previous
+                    .iter()
+                    .position(|t| t.get("name").and_then(Item::as_str) == Some(&credential.name))
+                    .map_or_else(Table::new, |index| previous.swap_remove(index));
+                write_credential(&mut entry, credential);
+                credentials.push(entry);
+            }
+            table.insert("credentials", Item::ArrayOfTables(credentials));
+            match &cfg.session_previous {
+                Some(previous) => {
+                    if table.get("session_previous").and_then(Item::as_str) != Some(previous) {
+                        table.insert("session_previous", value(previous.as_str()));
+                    }
+                },
+                None => {
+                    table.remove("session_previous");
+                },
+            }
+        }
+        doc.to_string()
     }
 
     /// Construct from an existing map (does not write to disk)
     pub const fn from_map(map: HashMap<String, HostConfig>) -> Self {
-        Self { inner: map }
+        Self {
+            inner: map,
+            in_keyring: false,
+        }
     }
 
     /// Returns true if no hosts are recorded
@@ -132,20 +614,30 @@ impl Hosts {
     }
 
     /// Get list of all credentials for a host
-    pub fn get_credentials(&self, host: &str) -> Result<&[String]> {
+    pub fn get_credentials(&self, host: &str) -> Result<Vec<String>> {
         Ok(self
             .inner
             .get(host)
             .with_context(|| format!("No credentials found for host '{host}'"))?
+            .names()
+            .map(str::to_string)
+            .collect())
+    }
+
+    /// What is known about `credential` of `host`
+    pub fn credential(&self, host: &str, credential: &str) -> Option<&Credential> {
+        self.inner
+            .get(host)?
             .credentials
-            .as_slice())
+            .iter()
+            .find(|c| c.name == credential)
     }
 
     /// True if `credential` is present for `host`
     pub fn has_credential(&self, host: &str, credential: &str) -> bool {
         self.inner
             .get(host)
-            .is_some_and(|h| h.credentials.iter().any(|u| u == credential))
+            .is_some_and(|h| h.names().any(|u| u == credential))
     }
 
     /// Iterate over (host, state) pairs in arbitrary order
@@ -155,61 +647,148 @@ impl Hosts {
 
     /// Set the active credential for a host, inserting the host if missing
     /// Ensures the credential is present in the `credentials` list
-    pub fn set_active_credential(&mut self, host: &str, credential: &str) -> Result<()> {
+    pub fn set_active_credential(&mut self, host: &str, credential: &str) {
         let entry = self.inner.entry(host.to_string()).or_insert_with(|| {
             HostConfig {
                 active: credential.to_string(),
                 credentials: vec![],
+                session_previous: None,
             }
         });
         entry.active = credential.to_string();
-        if !entry.credentials.iter().any(|u| u == credential) {
-            entry.credentials.push(credential.to_string());
+        if entry.credential_mut(credential).is_none() {
+            entry.credentials.push(new_credential(credential));
         }
-        self.write()
     }
 
     /// Add a credential to a host. Returns `true` if it was newly inserted
-    pub fn add_credential(&mut self, host: &str, credential: &str) -> Result<bool> {
+    pub fn add_credential(&mut self, host: &str, credential: &str) -> bool {
         let entry = self.inner.entry(host.to_string()).or_insert_with(|| {
             HostConfig {
                 active: credential.to_string(),
                 credentials: vec![],
+                session_previous: None,
             }
         });
-        if entry.credentials.iter().any(|u| u == credential) {
-            Ok(false)
+        if entry.credential_mut(credential).is_some() {
+            false
         } else {
-            entry.credentials.push(credential.to_string());
-            self.write()?;
-            Ok(true)
+            entry.credentials.push(new_credential(credential));
+            true
+        }
+    }
+
+    /// Record the scopes the provider granted `credential` of `host` at
+    /// login. Returns whether they changed.
+    pub fn set_scopes(
+        &mut self,
+        host: &str,
+        credential: &str,
+        scopes: Option<Vec<String>>,
+    ) -> bool {
+        let Some(entry) = self
+            .inner
+            .get_mut(host)
+            .and_then(|h| h.credential_mut(credential))
+        else {
+            return false;
+        };
+        let changed = entry.scopes != scopes;
+        entry.scopes = scopes;
+        changed
+    }
+
+    /// Note that `credential` of `host` answered a Git request. Returns
+    /// whether `last_used` changed, which it only does once it is
+    /// [`LAST_USED_RESOLUTION`] old.
+    pub fn touch(&mut self, host: &str, credential: &str) -> bool {
+        let Some(entry) = self
+            .inner
+            .get_mut(host)
+            .and_then(|h| h.credential_mut(credential))
+        else {
+            return false;
+        };
+        let now = Utc::now();
+        if entry
+            .last_used
+            .is_some_and(|last| now - last < LAST_USED_RESOLUTION)
+        {
+            return false;
+        }
+        entry.last_used = Some(now);
+        true
+    }
+
+    /// Make `credential` active for a session, remembering the active one for
+    /// [`Self::end_session`]. Switching within a session keeps the credential
+    /// from before it.
+    pub fn start_session(&mut self, host: &str, credential: &str) -> Result<()> {
+        let entry = self
+            .inner
+            .get_mut(host)
+            .with_context(|| format!("No credentials found for host '{host}'"))?;
+        if entry.session_previous.is_none() && entry.active != credential {
+            entry.session_previous = Some(entry.active.clone());
         }
+        self.set_active_credential(host, credential);
+        Ok(())
+    }
+
+    /// End the session on `host`, making the credential from before it active
+    /// again. Returns that credential, `None` without a session.
+    pub fn end_session(&mut self, host: &str) -> Option<String> {
+        let previous = self
+            .inner
+            .get_mut(host)
+            .and_then(|entry| entry.session_previous.take())?;
+        self.set_active_credential(host, &previous);
+        Some(previous)
     }
 
     /// Remove a credential; if it was the active credential and others remain,
     /// the first remaining credential becomes active. If no credentials
     /// remain the host entry is removed. Returns whether removal occurred.
-    pub fn remove_credential(&mut self, host: &str, credential: &str) -> Result<bool> {
+    /// The token is left to the caller.
+    pub fn remove_credential(&mut self, host: &str, credential: &str) -> bool {
         let Some(entry) = self.inner.get_mut(host) else {
-            return Ok(false);
+            return false;
         };
         let original_len = entry.credentials.len();
-        entry.credentials.retain(|u| u != credential);
-        let _ = erase_keyring_token(credential, host);
+        entry.credentials.retain(|u| u.name != credential);
         let removed = entry.credentials.len() != original_len;
+        if entry.session_previous.as_deref() == Some(credential) {
+            entry.session_previous = None;
+        }
 
-        if removed {
-            if entry.active == credential {
-                if let Some(first) = entry.credentials.first().cloned() {
-                    entry.active = first;
-                } else {
-                    // No credentialss left: drop the host entry entirely.
-                    self.inner.remove(host);
-                }
+        if removed && entry.active == credential {
+            if let Some(first) = entry.credentials.first() {
+                entry.active = first.name.clone();
+            } else {
+                // No credentialss left: drop the host entry entirely.
+                self.inner.remove(host);
             }
-            self.write()?;
         }
-        Ok(removed)
+        removed
+    }
+
+    /// Remove a host, returning its state if it existed. The tokens of its
+    /// credentials are left to the caller.
+    pub fn remove_host(&mut self, host: &str) -> Option<HostConfig> {
+        self.inner.remove(host)
+    }
+
+    /// Move the state of `old` to `new`, which must not be known yet.
+    /// Returns whether it was moved; the tokens are left to the caller.
+    pub fn rename_host(&mut self, old: &str, new: &str) -> bool {
+        if self.inner.contains_key(new) {
+            return false;
+        }
+        let Some(cfg) = self.inner.remove(old) else {
+            return false;
+        };
+        self.inner.insert(new.to_string(), cfg);
+        true
     }
 
     /// True if the host is present in the map
@@ -260,4 +839,221 @@ mod tests {
         assert!(out.contains_key("gitlab.example.com"));
         assert_eq!(out["gitlab.example.com"].active, "carol");
     }
+
+    #[test]
+    fn normalize_merges_hosts_differing_in_case_and_port() {
+        let mut hosts = Hosts::from_map(HashMap::from([
+            (
+                "gitlab.example.com".to_string(),
+                HostConfig {
+                    active: "alice".into(),
+                    credentials: vec!["alice".into()],
+                    session_previous: None,
+                },
+            ),
+            (
+                "GitLab.example.com:443".to_string(),
+                HostConfig {
+                    active: "bob".into(),
+                    credentials: vec!["bob".into(), "alice".into()],
+                    session_previous: None,
+                },
+            ),
+            (
+                "gitlab.example.com:8443".to_string(),
+                HostConfig {
+                    active: "carol".into(),
+                    credentials: vec!["carol".into()],
+                    session_previous: None,
+                },
+            ),
+        ]));
+
+        let renamed = hosts.normalize();
+        assert_eq!(
+            renamed,
+            vec![(
+                "GitLab.example.com:443".to_string(),
+                "gitlab.example.com".to_string()
+            )]
+        );
+        let merged = &hosts.inner["gitlab.example.com"];
+        assert_eq!(merged.active, "alice");
+        assert_eq!(merged.names().collect::<Vec<_>>(), ["alice", "bob"]);
+        assert!(
+            hosts.has_host("gitlab.example.com:8443"),
+            "custom ports are kept"
+        );
+    }
+
+    #[test]
+    fn legacy_file_with_users_key_merges() {
+        let path = std::env::temp_dir().join(format!("warden-legacy-{}.toml", std::process::id()));
+        fs::write(
+            &path,
+            "[\"github.com\"]\nactive = \"bob\"\nusers = [\"bob\", \"carol\"]\n",
+        )
+        .unwrap();
+        assert!(uses_legacy_keys(&path), "users key should be detected");
+
+        let mut map = HashMap::from([(
+            "github.com".to_string(),
+            HostConfig {
+                active: "alice".into(),
+                credentials: vec!["alice".into(), "bob".into()],
+                session_previous: None,
+            },
+        )]);
+        for (host, cfg) in read_hosts_file(&path).unwrap() {
+            merge_host(&mut map, host, cfg);
+        }
+        let _ = fs::remove_file(&path);
+
+        assert_eq!(map["github.com"].active, "alice");
+        assert_eq!(
+            map["github.com"].names().collect::<Vec<_>>(),
+            ["alice", "bob", "carol"]
+        );
+    }
+
+    #[test]
+    fn document_write_keeps_comments_and_drops_legacy_keys() {
+        let existing = r#"# managed by warden, edit with care
+["github.com"]
+# my main account
+active = "alice"
+users = ["alice"]
+
+["gone.example.com"]
+active = "x"
+credentials = ["x"]
+"#;
+        let hosts = Hosts::from_map(HashMap::from([
+            (
+                "github.com".to_string(),
+                HostConfig {
+                    active: "alice".into(),
+                    credentials: vec!["alice".into(), "bob".into()],
+                    session_previous: None,
+                },
+            ),
+            (
+                "codeberg.org".to_string(),
+                HostConfig {
+                    active: "carol".into(),
+                    credentials: vec!["carol".into()],
+                    session_previous: None,
+                },
+            ),
+        ]));
+
+        let out = hosts.to_toml_document(existing);
+        assert!(out.contains("# my main account"), "comments kept: {out}");
+        assert!(out.contains("# managed by warden"), "header kept: {out}");
+        assert!(!out.contains("users"), "legacy key dropped: {out}");
+        assert!(
+            !out.contains("gone.example.com"),
+            "removed host dropped: {out}"
+        );
+        let parsed = Hosts::from_blob(&out).unwrap().inner;
+        assert_eq!(
+            parsed["github.com"].names().collect::<Vec<_>>(),
+            ["alice", "bob"]
+        );
+        assert_eq!(parsed["codeberg.org"].active, "carol");
+    }
+
+    #[test]
+    fn session_is_written_and_cleared() {
+        let mut hosts = Hosts::from_map(HashMap::from([(
+            "github.com".to_string(),
+            HostConfig {
+                active: "readonly".into(),
+                credentials: vec!["alice".into(), "readonly".into()],
+                session_previous: Some("alice".into()),
+            },
+        )]));
+        let out = hosts.to_toml_document("");
+        let parsed = Hosts::from_blob(&out).unwrap().inner;
+        assert_eq!(
+            parsed["github.com"].session_previous.as_deref(),
+            Some("alice"),
+            "session written: {out}"
+        );
+
+        hosts.inner.get_mut("github.com").unwrap().session_previous = None;
+        let out = hosts.to_toml_document(&out);
+        assert!(!out.contains("session_previous"), "session cleared: {out}");
+    }
+
+    #[test]
+    fn keyring_blob_round_trips() {
+        let hosts = Hosts::from_map(HashMap::from([(
+            "gitlab.example.com".to_string(),
+            HostConfig {
+                active: "alice".into(),
+                credentials: vec!["alice".into(), "bob".into()],
+                session_previous: None,
+            },
+        )]));
+        let blob = hosts.to_toml_document("");
+        let loaded = Hosts::from_blob(&blob).unwrap();
+        assert!(loaded.in_keyring, "written back to the keyring");
+        assert_eq!(
+            loaded.inner["gitlab.example.com"]
+                .names()
+                .collect::<Vec<_>>(),
+            ["alice", "bob"]
+        );
+    }
+
+    #[test]
+    fn version_one_state_is_migrated_to_credential_tables() {
+        let v1 = r#"# my hosts
+["github.com"]
+active = "alice"
+credentials = ["alice", "bob"]
+"#;
+        let path = std::env::temp_dir().join(format!("warden-v1-{}.toml", std::process::id()));
+        fs::write(&path, v1).unwrap();
+        assert!(uses_old_schema(&path), "no version is version 1");
+        let mut hosts = Hosts::from_map(read_hosts_file(&path).unwrap());
+        let _ = fs::remove_file(&path);
+        assert_eq!(
+            hosts.inner["github.com"].credentials[1],
+            Credential::from("bob"),
+            "names become credentials"
+        );
+
+        assert!(hosts.set_scopes("github.com", "alice", Some(vec!["repo".into()])));
+        assert!(hosts.touch("github.com", "alice"), "first use recorded");
+        assert!(
+            !hosts.touch("github.com", "alice"),
+            "repeated use within the hour"
+        );
+        hosts.add_credential("github.com", "carol");
+        let out = hosts.to_toml_document(v1);
+        assert!(
+            out.starts_with("# my hosts\nversion = 2\n"),
+            "version after the header: {out}"
+        );
+        assert!(
+            out.contains("[[\"github.com\".credentials]]"),
+            "tables: {out}"
+        );
+
+        let reread = Hosts::from_blob(&out).unwrap();
+        let github = &reread.inner["github.com"];
+        assert_eq!(
+            github.names().collect::<Vec<_>>(),
+            ["alice", "bob", "carol"]
+        );
+        assert_eq!(
+            github.credentials[0].scopes.as_deref(),
+            Some(&["repo".to_string()][..])
+        );
+        assert!(github.credentials[0].last_used.is_some(), "last use kept");
+        assert!(github.credentials[2].created_at.is_some(), "creation kept");
+        assert_eq!(out, reread.to_toml_document(&out), "stable once migrated");
+    }
 }
@@ -0,0 +1,310 @@
+//! The sources of the OAuth configuration and how they are merged.
+//!
+//! `oauth.toml`, the global Git configuration and the repository's Git
+//! configuration are read separately first, so provider fields that several
+//! of them set to different values can be reported, or refused with
+//! `merge_strategy = "error"`.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{self, Display, Formatter};
+use std::path::Path;
+
+use anyhow::{Context as _, Result, bail};
+use config::{File, Source, Value, ValueKind};
+use serde::Deserialize;
+
+use crate::config::git_source::GitConfigSource;
+use crate::config::provider::CLIENT_SECRET_IN_KEYRING;
+use crate::keyring::index::fingerprint;
+use crate::utils::normalize_host;
+
+/// Provider fields holding endpoints, which may be relative to the host
+const ENDPOINT_FIELDS: &[&str] = &["auth_url", "token_url", "device_auth_url", "revocation_url"];
+
+/// Where a setting of the OAuth configuration comes from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Layer {
+    File,
+    GlobalGit,
+    RepoGit,
+}
+
+impl Display for Layer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::File => write!(f, "oauth.toml"),
+            Self::GlobalGit => write!(f, "global git config"),
+            Self::RepoGit => write!(f, "repository git config"),
+        }
+    }
+}
+
+/// Which source wins when several set a provider field differently
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MergeStrategy {
+    /// The repository's git config over the global one over `oauth.toml`
+    #[default]
+    GitWins,
+    /// `oauth.toml` over the repository's git config over the global one
+    FileWins,
+    /// Refuse to load the configuration
+    Error,
+}
+
+impl MergeStrategy {
+    fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "git-wins" => Some(Self::GitWins),
+            "file-wins" => Some(Self::FileWins),
+            "error" => Some(Self::Error),
+            _ => None,
+        }
+    }
+
+    /// The layers from the one overridden by all others to the one that wins
+    const fn order(self) -> [Layer; 3] {
+        match self {
+            Self::GitWins | Self::Error => [Layer::File, Layer::GlobalGit, Layer::RepoGit],
+            Self::FileWins => [Layer::GlobalGit, Layer::RepoGit, Layer::File],
+        }
+    }
+}
+
+/// A provider field several sources set to different values
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Conflict {
+    /// The normalized host of the provider
+    pub provider: String,
+    pub field: String,
+    /// The values in merge order, the last one is used
+    pub values: Vec<(Layer, String)>,
+}
+
+impl Display for Conflict {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let values: Vec<String> = self
+            .values
+            .iter()
+            .map(|(layer, value)| format!("{value:?} in {layer}"))
+            .collect();
+        write!(
+            f,
+            "[{}] {} is {}",
+            self.provider,
+            self.field,
+            values.join(", ")
+        )
+    }
+}
+
+/// A source of the OAuth configuration with what it holds
+struct Read {
+    layer: Layer,
+    source: Box<dyn Source + Send + Sync>,
+    values: HashMap<String, Value>,
+}
+
+/// The sources of the OAuth configuration, each read on its own
+pub struct Layers {
+    layers: Vec<Read>,
+}
+
+impl Layers {
+    /// Read `file` and the global and repository git config
+    pub fn read(file: &Path) -> Result<Self> {
+        let sources: [(Layer, Box<dyn Source + Send + Sync>); 3] = [
+            (
+                Layer::File,
+                Box::new(File::from(file.to_path_buf()).required(false)),
+            ),
+            (Layer::GlobalGit, Box::new(GitConfigSource::global())),
+            (Layer::RepoGit, Box::new(GitConfigSource::repo())),
+        ];
+        let mut layers = Vec::new();
+        for (layer, source) in sources {
+            let values = source
+                .collect()
+                .with_context(|| format!("Failed to read the {layer}"))?;
+            layers.push(Read {
+                layer,
+                source,
+                values,
+            });
+        }
+        Ok(Self { layers })
+    }
+
+    /// The configured `merge_strategy`, the repository's git config over the
+    /// global one over `oauth.toml`
+    pub fn strategy(&self) -> Result<MergeStrategy> {
+        let mut strategy = MergeStrategy::default();
+        for Read { layer, values, .. } in &self.layers {
+            let Some(value) = values.get("merge_strategy") else {
+                continue;
+            };
+            let text = value.to_string();
+            let Some(parsed) = MergeStrategy::parse(&text) else {
+                bail!(
+                    "Invalid merge_strategy {text:?} in the {layer}, use git-wins, file-wins or \
+                     error"
+                );
+            };
+            strategy = parsed;
+        }
+        Ok(strategy)
+    }
+
+    /// The sources in the order `strategy` merges them
+    pub fn sources(&self, strategy: MergeStrategy) -> Vec<Box<dyn Source + Send + Sync>> {
+        strategy
+            .order()
+            .iter()
+            .filter_map(|layer| {
+                self.layers
+                    .iter()
+                    .find(|read| read.layer == *layer)
+                    .map(|read| read.source.clone_into_box())
+            })
+            .collect()
+    }
+
+    /// The provider fields set to different values, in `strategy`'s merge
+    /// order
+    pub fn conflicts(&self, strategy: MergeStrategy) -> Vec<Conflict> {
+        let mut fields: BTreeMap<(String, String), Vec<(Layer, String)>> = BTreeMap::new();
+        for layer in strategy.order() {
+            let Some(read) = self.layers.iter().find(|read| read.layer == layer) else {
+                continue;
+            };
+            for (key, provider) in providers(&read.values) {
+                let host = normalize_host(&key);
+                for (field, value) in provider {
+                    let mut shown = show(&value);
+                    if ENDPOINT_FIELDS.contains(&field.as_str()) && shown.starts_with('/') {
+                        shown = format!("https://{host}{shown}");
+                    }
+                    if field == "client_secret" && shown != CLIENT_SECRET_IN_KEYRING {
+                        shown = format!("sha256:{}", &fingerprint(&shown)[..8]);
+                    }
+                    fields
+                        .entry((host.clone(), field))
+                        .or_default()
+                        .push((layer, shown));
+                }
+            }
+        }
+        fields
+            .into_iter()
+            .filter(|(_, values)| values.iter().any(|(_, value)| *value != values[0].1))
+            .map(|((provider, field), values)| {
+                Conflict {
+                    provider,
+                    field,
+                    values,
+                }
+            })
+            .collect()
+    }
+}
+
+/// The provider tables of a source, by key
+fn providers(values: &HashMap<String, Value>) -> Vec<(String, HashMap<String, Value>)> {
+    values
+        .get("providers")
+        .and_then(|providers| providers.clone().into_table().ok())
+        .into_iter()
+        .flatten()
+        .filter_map(|(key, provider)| provider.into_table().ok().map(|table| (key, table)))
+        .collect()
+}
+
+/// `value` as written in the configuration
+fn show(value: &Value) -> String {
+    match &value.kind {
+        ValueKind::Array(items) => {
+            let items: Vec<String> = items.iter().map(show).collect();
+            format!("[{}]", items.join(", "))
+        },
+        ValueKind::Nil
+        | ValueKind::Boolean(_)
+        | ValueKind::I64(_)
+        | ValueKind::I128(_)
+        | ValueKind::U64(_)
+        | ValueKind::U128(_)
+        | ValueKind::Float(_)
+        | ValueKind::String(_)
+        | ValueKind::Table(_) => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A layer with the provider tables of `toml`
+    fn layer(layer: Layer, toml: &str) -> Read {
+        let source = File::from_str(toml, config::FileFormat::Toml);
+        Read {
+            layer,
+            values: source.collect().unwrap(),
+            source: Box::new(source),
+        }
+    }
+
+    #[test]
+    fn differing_provider_fields_are_conflicts() {
+        let layers = Layers {
+            layers: vec![
+                layer(
+                    Layer::File,
+                    r#"
+                    merge_strategy = "file-wins"
+                    [providers."GitLab.Example.com"]
+                    client_id = "file"
+                    client_secret = "file-secret"
+                    token_url = "/oauth/token"
+                    scopes = ["api"]
+                    "#,
+                ),
+                layer(
+                    Layer::GlobalGit,
+                    r#"
+                    [providers."gitlab.example.com"]
+                    client_id = "git"
+                    client_secret = "git-secret"
+                    token_url = "https://gitlab.example.com/oauth/token"
+                    scopes = ["api"]
+                    "#,
+                ),
+                layer(Layer::RepoGit, ""),
+            ],
+        };
+        let strategy = layers.strategy().unwrap();
+        assert_eq!(strategy, MergeStrategy::FileWins);
+        let mut conflicts = layers.conflicts(strategy);
+        let secret = conflicts.remove(1);
+        assert_eq!(secret.field, "client_secret");
+        assert!(
+            secret.to_string().contains("sha256:") && !secret.to_string().contains("secret\""),
+            "secrets not shown: {secret}"
+        );
+        assert_eq!(
+            conflicts,
+            [Conflict {
+                provider: "gitlab.example.com".into(),
+                field: "client_id".into(),
+                values: vec![
+                    (Layer::GlobalGit, "git".into()),
+                    (Layer::File, "file".into())
+                ],
+            }],
+            "same endpoints and scopes agree"
+        );
+        assert_eq!(
+            conflicts[0].to_string(),
+            "[gitlab.example.com] client_id is \"git\" in global git config, \"file\" in \
+             oauth.toml"
+        );
+    }
+}
@@ -7,17 +7,24 @@
 //! - `git_source`: `config::Source` implementation for Git-based provider
 //!   overrides
 //! - `hosts`: host/credential state
+//! - `merge`: the sources of the OAuth configuration and conflicts between them
+//! - `persist`: atomic writes and backups of state files
 //! - `profiles`: profile, rule and pattern configuration
+//! - `stats`: opt-in local usage statistics
 
 pub mod git_source;
 pub mod hosts;
+pub mod merge;
+pub mod persist;
 pub mod profiles;
 pub mod provider;
+pub mod stats;
 
-use anyhow::{Context as _, Result};
+use anyhow::{Context as _, Result, bail};
 pub use hosts::Hosts;
 pub use profiles::ProfileConfig;
-pub use provider::{OAuthConfig, ProviderConfig};
+pub use provider::{ConfirmPolicy, OAuthConfig, ProviderConfig, ScopePreset, TokenDisplay};
+pub use stats::Stats;
 
 pub trait LoadableConfig: Sized {
     const KIND: &'static str;
@@ -28,6 +35,12 @@ pub trait LoadableConfig: Sized {
     }
 
     fn load_raw() -> Result<Self>;
+
+    /// Persist the configuration. Files only the user edits are never
+    /// written.
+    fn save(&self) -> Result<()> {
+        bail!("The {} configuration is not written by warden", Self::KIND)
+    }
 }
 
 #[macro_export]
@@ -0,0 +1,185 @@
+//! Crash-safe writes of warden's state files.
+//!
+//! Files are written to a temporary sibling and renamed over the original, so
+//! readers only ever see the old or the new content. Before a file is
+//! replaced, its previous content is copied to `backups/` next to it.
+
+use std::fs;
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context as _, Result};
+use chrono::Utc;
+use tracing::warn;
+
+/// Number of backups kept per file
+const BACKUP_RETENTION: usize = 10;
+
+/// Replace `path` with `contents` atomically, backing up the previous
+/// content if it differs.
+pub fn write_with_backup(path: &Path, contents: &str) -> Result<()> {
+    match fs::read_to_string(path) {
+        Ok(previous) if previous == contents => return Ok(()),
+        Ok(_) => {
+            // a failed backup must not prevent saving the new state
+            if let Err(err) = backup(path) {
+                warn!("Failed to back up {}: {err:#}", path.display());
+            }
+        },
+        Err(_) => {},
+    }
+    write_atomic(path, contents)
+}
+
+/// Write `contents` to a temporary file in the same directory, flush it to
+/// disk and rename it over `path`.
+pub fn write_atomic(path: &Path, contents: &str) -> Result<()> {
+    write_atomic_mode(path, contents, false)
+}
+
+/// [`write_atomic`] for files holding secrets, readable only by the user on
+/// Unix from the moment they are created
+pub fn write_atomic_private(path: &Path, contents: &str) -> Result<()> {
+    write_atomic_mode(path, contents, true)
+}
+
+fn write_atomic_mode(path: &Path, contents: &str, private: bool) -> Result<()> {
+    let dir = path.parent().context("Path has no parent directory")?;
+    fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let file_name = path
+        .file_name()
+        .context("Path has no file name")?
+        .to_string_lossy();
+    let tmp = dir.join(format!(".{file_name}.{}.tmp", std::process::id()));
+
+    let result = (|| {
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        if private {
+            use std::os::unix::fs::OpenOptionsExt as _;
+            options.mode(0o600);
+        }
+        #[cfg(not(unix))]
+        let _ = private;
+        let mut file = options.open(&tmp)?;
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&tmp, path)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result.with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Move `from` to `to`, falling back to copy and delete when both are on
+/// different file systems.
+pub fn move_file(from: &Path, to: &Path) -> Result<()> {
+    if let Some(dir) = to.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    let contents =
+        fs::read_to_string(from).with_context(|| format!("Failed to read {}", from.display()))?;
+    write_atomic(to, &contents)?;
+    fs::remove_file(from).with_context(|| format!("Failed to remove {}", from.display()))
+}
+
+/// Copy `path` to `backups/<name>.<timestamp>` and prune old backups.
+fn backup(path: &Path) -> Result<PathBuf> {
+    let dir = path
+        .parent()
+        .context("Path has no parent directory")?
+        .join("backups");
+    fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let name = path
+        .file_name()
+        .context("Path has no file name")?
+        .to_string_lossy()
+        .into_owned();
+
+    let stamp = Utc::now().format("%Y%m%dT%H%M%S%.9fZ");
+    let mut target = dir.join(format!("{name}.{stamp}"));
+    let mut n = 1;
+    while target.exists() {
+        target = dir.join(format!("{name}.{stamp}-{n}"));
+        n += 1;
+    }
+    fs::copy(path, &target).with_context(|| format!("Failed to copy {}", path.display()))?;
+
+    let backups = list_backups(&dir, &name)?;
+    let excess = backups.len().saturating_sub(BACKUP_RETENTION);
+    for old in &backups[..excess] {
+        let _ = fs::remove_file(old);
+    }
+    Ok(target)
+}
+
+/// Delete every backup of `path`, returning how many there were.
+pub fn remove_backups(path: &Path) -> Result<usize> {
+    let dir = path
+        .parent()
+        .context("Path has no parent directory")?
+        .join("backups");
+    if !dir.is_dir() {
+        return Ok(0);
+    }
+    let name = path
+        .file_name()
+        .context("Path has no file name")?
+        .to_string_lossy();
+    let backups = list_backups(&dir, &name)?;
+    for backup in &backups {
+        fs::remove_file(backup)
+            .with_context(|| format!("Failed to remove {}", backup.display()))?;
+    }
+    Ok(backups.len())
+}
+
+/// Backups of the file `name` in `dir`, oldest first
+fn list_backups(dir: &Path, name: &str) -> Result<Vec<PathBuf>> {
+    // timestamps sort lexicographically
+    let prefix = format!("{name}.");
+    let mut backups: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|p| {
+            p.file_name()
+                .is_some_and(|n| n.to_string_lossy().starts_with(&prefix))
+        })
+        .collect();
+    backups.sort();
+    Ok(backups)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_backs_up_previous_content_with_retention() {
+        let dir = std::env::temp_dir().join(format!("warden-persist-{}", std::process::id()));
+        let path = dir.join("state.toml");
+
+        write_with_backup(&path, "v0").unwrap();
+        assert!(!dir.join("backups").exists(), "nothing to back up yet");
+
+        for i in 1..=BACKUP_RETENTION + 2 {
+            write_with_backup(&path, &format!("v{i}")).unwrap();
+        }
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            format!("v{}", BACKUP_RETENTION + 2)
+        );
+        let backups = fs::read_dir(dir.join("backups")).unwrap().count();
+        assert_eq!(backups, BACKUP_RETENTION, "old backups are pruned");
+
+        assert_eq!(remove_backups(&path).unwrap(), BACKUP_RETENTION);
+        assert_eq!(fs::read_dir(dir.join("backups")).unwrap().count(), 0);
+        assert!(path.exists(), "the file itself is kept");
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -1,12 +1,23 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
 use anyhow::{Context as _, Result};
-use config::{Config, File};
+use config::{Config, File, FileFormat};
 use serde::Deserialize;
+use tracing::{debug, warn};
 
 use crate::config::LoadableConfig;
+use crate::config::persist::write_atomic;
+use crate::keyring::index::fingerprint;
 use crate::profile::Profiles;
-use crate::profile::rule::Rules;
-use crate::profile::url::Patterns;
-use crate::utils::config_dir;
+use crate::profile::rule::{ProfileRef, Rules};
+use crate::profile::url::{Pattern, Patterns, Url};
+use crate::utils::{config_dir, state_dir};
+
+/// File in the state dir with the fingerprint of the `profiles.toml` whose
+/// patterns last compiled
+const CHECKED_PATTERNS: &str = "patterns.checked";
 
 /// Profiles / rules / patterns configuration.
 ///
@@ -14,6 +25,7 @@ use crate::utils::config_dir;
 /// * `patterns` - Repository URL parsing patterns
 /// * `profiles` - Named profile definitions (git config key to value maps)
 /// * `rules` - Rules for matching repository URLs to profiles
+/// * `default_profile` - Profile applied when no rule matches
 ///
 /// Deserialization is intentionally lenient, unknown keys are ignored by
 /// `config`/`serde`
@@ -25,22 +37,301 @@ pub struct ProfileConfig {
     pub profiles: Profiles,
     #[serde(default)]
     pub rules: Rules,
+    #[serde(default)]
+    pub default_profile: Option<String>,
+}
+
+/// Written by `scaffold`, everything is commented out so nothing is applied
+/// until the user fills it in.
+const STARTER: &str = r#"# Profiles are sets of git config values, apply one with `warden apply <name>`
+# or let the rules below pick one with `warden apply`.
+
+# [profiles.personal]
+# user.name = "Your Name"
+# user.email = "you@example.com"
+
+# [profiles.work]
+# user.name = "Your Name"
+# user.email = "you@company.example"
+# commit.gpgsign = true
+
+# Profile applied when no rule matches
+# default_profile = "personal"
+
+# Rules are evaluated top to bottom, the first match wins.
+# [[rules]]
+# profile.name = "work"
+# host = "git.company.example"
+#
+# [[rules]]
+# profile.name = "work"
+# host = "github.com"
+# owner = "company"
+"#;
+
+impl ProfileConfig {
+    /// Location of `profiles.toml`
+    pub fn path() -> Result<PathBuf> {
+        Ok(config_dir()?.join("profiles.toml"))
+    }
+
+    /// Write a commented starter `profiles.toml`, returning its path.
+    pub fn scaffold() -> Result<PathBuf> {
+        let path = Self::path()?;
+        write_atomic(&path, STARTER)?;
+        Ok(path)
+    }
+
+    /// Check the configuration for mistakes that would only surface when a
+    /// rule is resolved: rules or `default_profile` naming unknown profiles,
+    /// profile names that only differ in case, keys a profile both sets and
+    /// unsets, and rules shadowed by an earlier one. Rules are numbered in
+    /// evaluation order.
+    pub fn validate(&self) -> Vec<String> {
+        let mut issues = Vec::new();
+
+        let mut names: Vec<&String> = self.profiles.keys().collect();
+        names.sort_unstable();
+        for (i, name) in names.iter().enumerate() {
+            for other in &names[i + 1..] {
+                if name.eq_ignore_ascii_case(other) {
+                    issues.push(format!(
+                        "Profiles '{name}' and '{other}' only differ in case"
+                    ));
+                }
+            }
+        }
+
+        for name in names {
+            for var in self.profiles[name].env.keys() {
+                if var.is_empty() || var.contains(['=', '\0']) {
+                    issues.push(format!(
+                        "Profile '{name}' sets the invalid environment variable '{var}'"
+                    ));
+                }
+            }
+            for key in &self.profiles[name].unset {
+                if self.profiles[name].configs.contains_key(key) {
+                    issues.push(format!("Profile '{name}' both sets and unsets '{key}'"));
+                }
+            }
+        }
+
+        if let Some(name) = &self.default_profile
+            && !self.profiles.contains_key(name)
+        {
+            issues.push(format!(
+                "default_profile refers to unknown profile '{name}'"
+            ));
+        }
+
+        let rules: Vec<_> = self.rules.iter().collect();
+        for (i, rule) in rules.iter().enumerate() {
+            if let Some(issue) = self.profile_ref_issue(&rule.profile) {
+                issues.push(format!("Rule #{} {issue}", i + 1));
+            }
+            if let Some(j) = rules[..i].iter().position(|earlier| earlier.shadows(rule)) {
+                issues.push(format!(
+                    "Rule #{} is unreachable, rule #{} matches everything it does",
+                    i + 1,
+                    j + 1
+                ));
+            }
+        }
+
+        issues
+    }
+
+    /// What is wrong with a rule's reference to a profile: unknown
+    /// placeholders, or no profile it can refer to
+    fn profile_ref_issue(&self, profile: &ProfileRef) -> Option<String> {
+        let unknown = profile.unknown_placeholders();
+        if !unknown.is_empty() {
+            return Some(format!(
+                "uses unknown placeholders in '{}': {}",
+                profile.name,
+                unknown.join(", ")
+            ));
+        }
+        if profile.is_template() {
+            let matched = self
+                .profiles
+                .keys()
+                .any(|name| profile.could_render_to(name));
+            return (!matched)
+                .then(|| format!("refers to '{}', which matches no profile", profile.name));
+        }
+        (!self.profiles.contains_key(&profile.name))
+            .then(|| format!("refers to unknown profile '{}'", profile.name))
+    }
+
+    /// The profile for `url`: the winning rule's, with its placeholders filled
+    /// in, or `default_profile` when no rule matches. The flag is `true` when
+    /// the default was used.
+    pub fn profile_for(&self, url: &Url) -> Option<(ProfileRef, bool)> {
+        self.rules.resolve(url).map_or_else(
+            || {
+                self.default_profile
+                    .clone()
+                    .map(|name| (ProfileRef { name }, true))
+            },
+            |rule| Some((rule.profile.render(url), false)),
+        )
+    }
 }
 
 impl LoadableConfig for ProfileConfig {
     const KIND: &'static str = "profile";
 
-    /// Load profile configuration from standard config directors. Missing file
-    /// is an error.
+    /// Load profile configuration from the standard config directory. A
+    /// missing file is an empty configuration, credential commands don't
+    /// need one.
     fn load_raw() -> Result<Self> {
-        let path = config_dir()?.join("profiles.toml");
-        let builder = Config::builder().add_source(File::from(path).required(true));
-        let settings = builder
-            .build()
-            .context("Failed to build profile configurations")?;
+        let path = Self::path()?;
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == ErrorKind::NotFound => String::new(),
+            Err(err) => {
+                return Err(err).with_context(|| format!("Failed to read {}", path.display()));
+            },
+        };
+        let builder = Config::builder().add_source(File::from_str(&contents, FileFormat::Toml));
+        let settings = builder.build().with_context(|| {
+            format!(
+                "Failed to build profile configurations from {}",
+                path.display()
+            )
+        })?;
         let cfg: Self = settings
             .try_deserialize()
             .context("Malformed profile configuration file")?;
+        let key = fingerprint(&format!("{}\n{contents}", path.display()));
+        let checked = state_dir().ok().map(|dir| dir.join(CHECKED_PATTERNS));
+        check_patterns(&cfg.patterns, checked.as_deref(), &key)
+            .with_context(|| format!("Malformed patterns in {}", path.display()))?;
+        for issue in cfg.validate() {
+            warn!("{issue}");
+        }
         Ok(cfg)
     }
 }
+
+/// Compile the patterns unless the `profiles.toml` fingerprinted `key`
+/// compiled before, as recorded in `checked`. Regexes can't be kept compiled
+/// across runs, but unchanged patterns then only compile when a URL is
+/// matched against them, up to the first that matches.
+fn check_patterns(patterns: &Patterns, checked: Option<&Path>, key: &str) -> Result<()> {
+    if patterns.iter().all(Pattern::is_compiled) {
+        return Ok(());
+    }
+    if let Some(checked) = checked
+        && fs::read_to_string(checked).is_ok_and(|known| known.trim() == key)
+    {
+        debug!("Patterns compiled before");
+        return Ok(());
+    }
+    patterns.compile()?;
+    // the next run just compiles them again
+    if let Some(checked) = checked
+        && let Err(err) = write_atomic(checked, key)
+    {
+        debug!("Failed to record the compiled patterns: {err:#}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_reports_dangling_refs_and_shadowed_rules() {
+        let cfg: ProfileConfig = toml::from_str(
+            r#"
+            default_profile = "missing"
+
+            [profiles.work]
+            [profiles.Work]
+            env."GIT=AUTHOR" = "x"
+
+            [[rules]]
+            profile.name = "work"
+            host = "github.com"
+
+            [[rules]]
+            profile.name = "work"
+            host = "github.com"
+            owner = "company"
+
+            [[rules]]
+            profile.name = "ghost"
+            host = "gitlab.com"
+
+            [[rules]]
+            profile.name = "client-{{owner}}"
+            host = "codeberg.org"
+
+            [[rules]]
+            profile.name = "wor{{repo}}"
+            host = "git.example.com"
+
+            [[rules]]
+            profile.name = "{{org}}"
+            host = "gitea.com"
+            "#,
+        )
+        .unwrap();
+
+        let issues = cfg.validate();
+        assert_eq!(issues.len(), 7, "unexpected issues: {issues:?}");
+        assert!(issues[0].contains("only differ in case"), "{issues:?}");
+        assert!(
+            issues[1].contains("invalid environment variable 'GIT=AUTHOR'"),
+            "{issues:?}"
+        );
+        assert!(issues[2].contains("default_profile"), "{issues:?}");
+        assert!(issues[3].contains("Rule #2 is unreachable"), "{issues:?}");
+        assert!(issues[4].contains("unknown profile 'ghost'"), "{issues:?}");
+        assert!(issues[5].contains("matches no profile"), "{issues:?}");
+        assert!(issues[6].contains("unknown placeholders"), "{issues:?}");
+    }
+
+    #[test]
+    fn starter_file_is_an_empty_valid_config() {
+        let cfg: ProfileConfig = toml::from_str(STARTER).unwrap();
+        assert!(cfg.profiles.is_empty(), "starter must not define profiles");
+        assert!(cfg.validate().is_empty(), "starter must validate");
+    }
+
+    #[test]
+    fn compiled_patterns_are_recorded() {
+        let dir = std::env::temp_dir().join(format!("warden-patterns-{}", std::process::id()));
+        let checked = dir.join(CHECKED_PATTERNS);
+        let parse = |regex: &str| -> ProfileConfig {
+            toml::from_str(&format!("[[patterns]]\nregex = '{regex}'\n")).unwrap()
+        };
+
+        let cfg = parse("^(?P<repo>[a-z]+)$");
+        check_patterns(&cfg.patterns, Some(&checked), "valid").unwrap();
+        assert!(cfg.patterns.iter().all(Pattern::is_compiled), "compiled");
+        assert_eq!(fs::read_to_string(&checked).unwrap(), "valid");
+
+        let cfg = parse("^(?P<repo>[a-z]+)$");
+        check_patterns(&cfg.patterns, Some(&checked), "valid").unwrap();
+        assert!(
+            !cfg.patterns.iter().any(Pattern::is_compiled),
+            "not compiled again"
+        );
+        assert!(cfg.patterns.matches("repo").is_some(), "compiled when used");
+
+        let cfg = parse("(unclosed");
+        let err = check_patterns(&cfg.patterns, Some(&checked), "invalid").unwrap_err();
+        assert!(format!("{err:#}").contains("pattern #1"), "{err:#}");
+        assert_eq!(
+            fs::read_to_string(&checked).unwrap(),
+            "valid",
+            "invalid ones not recorded"
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,77 +1,401 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
 
 use anyhow::{Context as _, Result, bail};
-use config::{Config, File};
+use base64::Engine as _;
+use base64::engine::general_purpose::STANDARD;
+use chrono::TimeDelta;
+use clap::ValueEnum;
+use config::Config;
+use oauth2::AuthType;
 use serde::Deserialize;
 use tracing::warn;
 use url::Url;
 
-use crate::config::LoadableConfig;
-use crate::config::git_source::GitConfigSource;
-use crate::utils::config_dir;
+use crate::coalesce;
+use crate::config::merge::{Conflict, Layers, MergeStrategy};
+use crate::config::{Hosts, LoadableConfig};
+use crate::keyring::{STATE_HOST, get_keyring_secret};
+use crate::secret::Secret;
+use crate::utils::{config_dir, normalize_host, split_port};
 
 struct ProviderDefaults {
     auth_path: &'static str,
     token_path: &'static str,
     device_auth_path: Option<&'static str>,
+    revocation_path: Option<&'static str>,
     scopes: &'static [&'static str],
     preferred_flow: &'static str,
+    presets: Option<ScopePresets>,
+}
+
+/// Scopes of each [`ScopePreset`] for a provider type
+struct ScopePresets {
+    readonly: &'static [&'static str],
+    standard: &'static [&'static str],
+    admin: &'static [&'static str],
+}
+
+/// Scope sets `login --scopes-preset` requests instead of the configured
+/// scopes, from least to most privileged.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ScopePreset {
+    /// Clone and fetch
+    Readonly,
+    /// Clone, fetch and push
+    Standard,
+    /// Additionally manage organizations, workflows or the API
+    Admin,
 }
 
 const GITHUB: ProviderDefaults = ProviderDefaults {
     auth_path: "/login/oauth/authorize",
     token_path: "/login/oauth/access_token",
     device_auth_path: Some("/login/device/code"),
+    revocation_path: None,
     scopes: &["repo", "read:org", "write:org", "workflow"],
     preferred_flow: "authcode",
+    // OAuth apps can't limit `repo` to reading
+    presets: Some(ScopePresets {
+        readonly: &["repo"],
+        standard: &["repo", "read:org"],
+        admin: &["repo", "read:org", "write:org", "workflow"],
+    }),
 };
 const GITLAB: ProviderDefaults = ProviderDefaults {
     auth_path: "/oauth/authorize",
     token_path: "/oauth/token",
     device_auth_path: Some("/oauth/authorize_device"),
+    revocation_path: Some("/oauth/revoke"),
     scopes: &["read_repository", "write_repository"],
     preferred_flow: "authcode",
+    presets: Some(ScopePresets {
+        readonly: &["read_repository"],
+        standard: &["read_repository", "write_repository"],
+        admin: &["api"],
+    }),
 };
 const FORGEJO: ProviderDefaults = ProviderDefaults {
     auth_path: "/login/oauth/authorize",
     token_path: "/login/oauth/access_token",
     device_auth_path: None,
+    revocation_path: None,
     scopes: &["read:repository", "write:repository"],
     preferred_flow: "authcode",
+    presets: Some(ScopePresets {
+        readonly: &["read:repository"],
+        standard: &["read:repository", "write:repository"],
+        admin: &["write:repository", "write:organization", "write:user"],
+    }),
+};
+// token acquisition goes through application default credentials, the flow is
+// never used
+const GCLOUD: ProviderDefaults = ProviderDefaults {
+    auth_path: "https://accounts.google.com/o/oauth2/auth",
+    token_path: "https://oauth2.googleapis.com/token",
+    device_auth_path: None,
+    revocation_path: None,
+    scopes: &["https://www.googleapis.com/auth/cloud-platform"],
+    preferred_flow: "authcode",
+    presets: None,
 };
+/// A public OAuth app warden may use for a well-known host
+struct BuiltinClient {
+    host: &'static str,
+    provider_type: &'static str,
+    client_id: &'static str,
+    /// Not confidential, a secret shipped in a native app can't be kept
+    client_secret: Option<&'static str>,
+}
+
+/// Clients used with `use_builtin_clients`, the same public apps as in the
+/// Quick Start
+const BUILTIN_CLIENTS: &[BuiltinClient] = &[
+    BuiltinClient {
+        host: "github.com",
+        provider_type: "github",
+        client_id: "Ov23li8uFPnowNKmRc1h",
+        client_secret: Some("5b364d7edf01e60a2c2c5bfaf51dc7b66f6fb162"),
+    },
+    BuiltinClient {
+        host: "gitlab.com",
+        provider_type: "gitlab",
+        client_id: "b154e7459101fcfaf18f57fc5a069bc87c0e16f31482f0531272acefcb143f1b",
+        client_secret: None,
+    },
+    BuiltinClient {
+        host: "codeberg.org",
+        provider_type: "forgejo",
+        client_id: "a52456a6-fb1d-4326-904d-0139f79a3203",
+        client_secret: None,
+    },
+    BuiltinClient {
+        host: "git.gay",
+        provider_type: "forgejo",
+        client_id: "30202081-7a59-4a55-a22c-29fe6e6ad769",
+        client_secret: None,
+    },
+];
+/// Seconds tokens are renewed before they expire unless configured otherwise
+const DEFAULT_CLOCK_SKEW: u64 = 60;
+/// Seconds after which `get` gives up by default
+const DEFAULT_GET_TIMEOUT: u64 = 120;
+/// Scopes requested from "oidc" providers unless configured otherwise
+const OIDC_SCOPES: &[&str] = &["openid", "offline_access"];
+/// Flows `flows` may list
+const FLOWS: &[&str] = &["device", "authcode"];
+/// Parameters of the authorization request warden sets itself, which
+/// `auth_params` can't override
+const RESERVED_AUTH_PARAMS: &[&str] = &[
+    "client_id",
+    "code_challenge",
+    "code_challenge_method",
+    "redirect_uri",
+    "response_type",
+    "scope",
+    "state",
+];
+/// `client_secret` of providers whose secret is kept in the keyring
+pub const CLIENT_SECRET_IN_KEYRING: &str = "keyring";
 
 /// Configuration for a single OAuth provider.
 ///
 /// Fields:
 /// - `type`: Optional, gives defaults for URLs and scopes. Known values:
-///   "github", "gitlab", "forgejo", "gitea". If omitted, `auth_url` and
-///   `token_url` must be provided.
-/// - `client_id`: Required, empty strings are treated as invalid
-/// - `client_secret`: Optional (PKCE auth-code flow often does not need it)
+///   "github", "gitlab", "forgejo", "gitea", "gcloud", "oidc". If omitted,
+///   `auth_url` and `token_url` must be provided.
+/// - `issuer`: Required for "oidc". Endpoints and the flow are discovered from
+///   the issuer's `.well-known/openid-configuration` at login time
+/// - `client_id`: Required, empty strings are treated as invalid. Not needed
+///   for "gcloud", which takes its client from the application default
+///   credentials
+/// - `client_secret`: Optional (PKCE auth-code flow often does not need it).
+///   "keyring" uses the secret stored with `provider secret set`
 /// - `auth_url`, `token_url`: Optional; filled from provider type when omitted.
 ///   If provided, must be absolute URLs or start with "/" (validated)
 /// - `device_auth_url`: Optional device authorization endpoint (validated if
 ///   present)
-/// - `scopes`: Optional list of scopes. `None` => do not send a `scope`
-///   parameter. `Some(empty)` => explicitly send an empty scope set (depends on
-///   OAuth server behavior)
+/// - `revocation_url`: Optional token revocation endpoint, used by `nuke`
+///   (validated if present)
+/// - `scopes`: Optional list of scopes to request. Omitted or "default" uses
+///   the type's defaults, "none" sends no `scope` parameter and `[]` sends an
+///   empty one (depends on OAuth server behavior)
+/// - `auth_params`: Optional, extra parameters of the authorization request in
+///   both flows, e.g. `{ access_type = "offline", prompt = "consent" }` for
+///   providers that only return refresh tokens when asked to
 /// - `preferred_flow`: Optional override ("auto" | "device" | "authcode")
+/// - `flows`: Optional, flows to try in order when `preferred_flow` is "auto"
+///   or unset, e.g. `["device", "authcode"]`. The next flow is only tried if
+///   one is listed
+/// - `service_account`: Optional, "gcloud" only. Service account to impersonate
+///   instead of using the application default credentials
+/// - `clock_skew`: Optional, seconds before their expiry at which tokens are
+///   renewed. Defaults to the top-level `clock_skew`, or 60
+/// - `auth_style`: Optional ("basic" | "body"), how the client credentials are
+///   sent to the token endpoint. Defaults to "basic" for the auth-code flow and
+///   refreshes, "body" for the device flow
+/// - `match_subdomains`: Optional, also use this provider for subdomains of its
+///   host, e.g. `sub.gitlab.example.com` for `gitlab.example.com`
+/// - `refresh`: Optional, `false` never uses refresh tokens. Tokens are served
+///   until they expire and then replaced by logging in again. Defaults to true
+/// - `credential_name_template`: Optional, name of credentials `login` isn't
+///   given a name for, e.g. `"{{host}}-main"`. Defaults to the top-level
+///   `credential_name_template`, or "oauth"
+/// - `tls.pinned_sha256`: Optional, SHA-256 digests in hex or base64 of the
+///   certificates or public keys the provider's servers may present. Any other
+///   is refused, even if a trusted CA signed it
 #[derive(Clone, Debug, Deserialize)]
 pub struct ProviderConfig {
     #[serde(alias = "type")]
     pub provider_type: Option<String>,
+    /// `OpenID` Connect issuer URL for "oidc" providers
+    pub issuer: Option<String>,
+    #[serde(default)]
     pub client_id: String,
-    pub client_secret: Option<String>,
+    pub client_secret: Option<Secret>,
     #[serde(default)]
     pub auth_url: String,
     #[serde(default)]
     pub token_url: String,
     /// Device authorization endpoint, if supported by the provider
     pub device_auth_url: Option<String>,
-    /// Optional scopes to request during authorization
-    pub scopes: Option<Vec<String>>,
+    /// Token revocation endpoint (RFC 7009), if supported by the provider
+    pub revocation_url: Option<String>,
+    /// Scopes to request during authorization
+    #[serde(default)]
+    pub scopes: Scopes,
+    /// Extra parameters of the authorization request
+    #[serde(default)]
+    pub auth_params: BTreeMap<String, String>,
     // Optional override: "auto", "device" or "authcode"
     pub preferred_flow: Option<String>,
+    /// Flows to try in order, each of "device" and "authcode"
+    pub flows: Option<Vec<String>>,
+    /// Service account to impersonate for "gcloud" providers
+    pub service_account: Option<String>,
+    /// Optional override: "basic" or "body"
+    pub auth_style: Option<String>,
+    /// Seconds tokens are renewed before they expire
+    pub clock_skew: Option<u64>,
+    /// Whether requests for subdomains of the provider's host use it too
+    pub match_subdomains: Option<bool>,
+    /// Whether expired tokens are renewed with their refresh token
+    pub refresh: Option<bool>,
+    /// Name of credentials logged in to without one, with `{{host}}` and
+    /// `{{user_api_login}}` filled in
+    pub credential_name_template: Option<String>,
+    /// TLS settings of the connections to the provider
+    #[serde(default)]
+    pub tls: TlsConfig,
+    /// Host the client secret is kept in the keyring for, with
+    /// `client_secret = "keyring"`. Read by [`Self::client_secret`] only
+    /// when a flow needs it.
+    #[serde(skip)]
+    pub secret_in_keyring: Option<String>,
+}
+
+/// TLS settings of a provider's connections
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct TlsConfig {
+    /// SHA-256 digests of the certificates or public keys (SPKI) the servers
+    /// may present
+    #[serde(default)]
+    pub pinned_sha256: Vec<String>,
+}
+
+impl TlsConfig {
+    /// The pinned digests, without the invalid ones validation reported
+    pub fn pins(&self) -> Vec<[u8; 32]> {
+        self.pinned_sha256
+            .iter()
+            .filter_map(|pin| parse_pin(pin))
+            .collect()
+    }
+}
+
+/// The digest of `pin`, in hex with optional colons as `openssl x509
+/// -fingerprint -sha256` prints it, or in base64 with an optional `sha256//`
+/// prefix as curl's `--pinnedpubkey` takes it
+fn parse_pin(pin: &str) -> Option<[u8; 32]> {
+    let pin = pin.trim();
+    let hex = pin.replace(':', "");
+    let bytes = if hex.len() == 64 && hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        (0..32)
+            .map(|i| u8::from_str_radix(hex.get(i * 2..i * 2 + 2)?, 16).ok())
+            .collect::<Option<Vec<u8>>>()?
+    } else {
+        STANDARD
+            .decode(pin.strip_prefix("sha256//").unwrap_or(pin))
+            .ok()?
+    };
+    bytes.try_into().ok()
+}
+
+impl ProviderConfig {
+    /// The client secret, read from the keyring if it is kept there
+    pub fn client_secret(&self) -> Result<Option<Secret>> {
+        let Some(host) = &self.secret_in_keyring else {
+            return Ok(self.client_secret.clone());
+        };
+        let secret =
+            get_keyring_secret(&client_secret_entry(host), STATE_HOST).with_context(|| {
+                format!(
+                    "client_secret of [{host}] is in the keyring, but none could be read, run `{} \
+                     provider secret set {host}` to store it",
+                    env!("CARGO_PKG_NAME")
+                )
+            })?;
+        Ok(Some(Secret::from(secret)))
+    }
+
+    /// True if tokens come from Google Cloud application default credentials
+    /// instead of an OAuth flow
+    pub fn is_gcloud(&self) -> bool {
+        self.provider_type
+            .as_deref()
+            .is_some_and(|t| matches!(t.to_lowercase().as_str(), "gcloud" | "google"))
+    }
+
+    /// True if endpoints are discovered from an `OpenID` Connect issuer
+    pub fn is_oidc(&self) -> bool {
+        self.provider_type
+            .as_deref()
+            .is_some_and(|t| t.eq_ignore_ascii_case("oidc"))
+    }
+
+    /// Whether expired tokens may be renewed with their refresh token
+    pub fn refreshes(&self) -> bool {
+        self.refresh.unwrap_or(true)
+    }
+
+    /// How long before their reported expiry tokens are treated as expired,
+    /// to absorb clock differences and request latency
+    pub fn expiry_margin(&self) -> TimeDelta {
+        let seconds = self.clock_skew.unwrap_or(DEFAULT_CLOCK_SKEW);
+        TimeDelta::try_seconds(i64::try_from(seconds).unwrap_or(i64::MAX)).unwrap_or(TimeDelta::MAX)
+    }
+
+    /// Request the scopes of `preset` for the provider's type instead of the
+    /// configured ones
+    pub fn apply_scope_preset(&mut self, preset: ScopePreset) -> Result<()> {
+        let Some(scopes) = self.preset_scopes(preset) else {
+            bail!(
+                "Scope presets are only available for the github, gitlab, forgejo and gitea \
+                 provider types"
+            );
+        };
+        self.scopes = Scopes::from(scopes);
+        Ok(())
+    }
+
+    /// The scopes of `preset` for the provider's type, `None` for types
+    /// without presets
+    pub fn preset_scopes(&self, preset: ScopePreset) -> Option<&'static [&'static str]> {
+        let presets = self
+            .provider_type
+            .as_deref()
+            .and_then(type_defaults)?
+            .presets
+            .as_ref()?;
+        Some(match preset {
+            ScopePreset::Readonly => presets.readonly,
+            ScopePreset::Standard => presets.standard,
+            ScopePreset::Admin => presets.admin,
+        })
+    }
+
+    /// The flows to try in order: `flows` if configured, otherwise the one
+    /// named by `preferred_flow`, where "auto" picks the device flow if the
+    /// provider supports it
+    pub fn flow_order(&self) -> Vec<&str> {
+        if let Some(flows) = &self.flows {
+            return flows.iter().map(String::as_str).collect();
+        }
+        match self.preferred_flow.as_deref() {
+            Some(flow @ ("device" | "authcode")) => vec![flow],
+            Some(_) | None if self.device_auth_url.is_some() => vec!["device"],
+            Some(_) | None => vec!["authcode"],
+        }
+    }
+
+    /// How client credentials are sent to the token endpoint, `default` if the
+    /// provider doesn't set `auth_style`
+    pub fn auth_type(&self, default: AuthType) -> AuthType {
+        match self.auth_style.as_deref() {
+            Some("basic") => AuthType::BasicAuth,
+            Some("body") => AuthType::RequestBody,
+            _ => default,
+        }
+    }
+}
+
+/// Name of the keyring entry, under [`STATE_HOST`], holding the client secret
+/// of the provider for `host`
+pub fn client_secret_entry(host: &str) -> String {
+    format!("client_secret:{}", normalize_host(host))
 }
 
 /// OAuth configurations for various providers.
@@ -89,39 +413,351 @@ pub struct OAuthConfig {
     pub providers: HashMap<String, ProviderConfig>,
     pub port: Option<u16>,
     pub oauth_only: Option<bool>,
+    /// Default `clock_skew` of all providers
+    pub clock_skew: Option<u64>,
+    /// Record local usage statistics, off by default
+    pub stats: Option<bool>,
+    /// Ask which credential to use when a host has several, off by default
+    pub choose_credential: Option<bool>,
+    /// Offer to configure a provider when Git asks for a host without one,
+    /// off by default
+    pub interactive_onboarding: Option<bool>,
+    /// Fall back to bundled public clients for well-known hosts, off by
+    /// default
+    pub use_builtin_clients: Option<bool>,
+    /// Forge host whose tokens a container registry accepts, by registry host
+    #[serde(default)]
+    pub registries: HashMap<String, String>,
+    /// Fixed credentials kept in the configuration, by host
+    #[serde(default)]
+    pub insecure_static: HashMap<String, StaticCredential>,
+    /// Providers discarded by validation, by key, with what is wrong
+    #[serde(skip)]
+    pub discarded: BTreeMap<String, Vec<String>>,
+    #[serde(default)]
+    pub state: StateConfig,
+    /// When destructive commands ask before going ahead, `tty` by default
+    pub confirm_destructive: Option<ConfirmPolicy>,
+    /// Seconds after which `get` gives up, 0 for never
+    pub get_timeout: Option<u64>,
+    /// Seconds a credential `get` served answers repeated requests of the
+    /// same Git operation, 0 (the default) for never
+    pub coalesce_window: Option<u64>,
+    /// Default `credential_name_template` of all providers
+    pub credential_name_template: Option<String>,
+    /// How tokens are shown by `status` and `audit`, `fingerprint` by default
+    pub token_display: Option<TokenDisplay>,
+    /// Provider fields several sources set to different values
+    #[serde(skip)]
+    pub conflicts: Vec<Conflict>,
 }
 
-impl LoadableConfig for OAuthConfig {
-    const KIND: &'static str = "OAuth";
+/// A username and password `get` serves for a host as they are, for
+/// throwaway internal mirrors where OAuth and the keyring are overkill. The
+/// password is kept in plain text, in the configuration or a file.
+#[derive(Clone, Debug, Deserialize)]
+pub struct StaticCredential {
+    pub username: String,
+    /// The password or token
+    pub password: Option<Secret>,
+    /// A file holding the password or token, read whenever it is served
+    pub password_file: Option<PathBuf>,
+}
 
-    /// Load and merge configuration sources
-    fn load_raw() -> Result<Self> {
-        let config_file = config_dir()?.join("oauth.toml");
+impl StaticCredential {
+    /// The password, from `password_file` if one is set
+    pub fn password(&self) -> Result<Secret> {
+        match (&self.password, &self.password_file) {
+            (Some(password), None) => Ok(password.clone()),
+            (None, Some(path)) => {
+                let password = fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read {}", path.display()))?;
+                Ok(Secret::from(password.trim_end_matches(['\r', '\n'])))
+            },
+            (Some(_), Some(_)) | (None, None) => {
+                bail!("Set either password or password_file")
+            },
+        }
+    }
+}
+
+/// How tokens are shown to the user
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenDisplay {
+    /// A short salted SHA-256 of the token
+    #[default]
+    Fingerprint,
+    /// The first four characters of the token
+    Prefix,
+}
+
+/// The scopes requested from a provider
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "RawScopes")]
+pub enum Scopes {
+    /// The defaults of the provider's type, no `scope` parameter for types
+    /// without any
+    #[default]
+    Default,
+    /// No `scope` parameter, leaving the scopes to the provider
+    None,
+    /// Exactly these scopes, an empty `scope` parameter if there are none
+    Explicit(Vec<String>),
+}
+
+impl Scopes {
+    /// The scopes sent in the `scope` parameter, `None` if none is sent
+    pub fn requested(&self) -> Option<&[String]> {
+        match self {
+            Self::Explicit(scopes) => Some(scopes),
+            Self::Default | Self::None => None,
+        }
+    }
+}
+
+impl From<&[&str]> for Scopes {
+    fn from(scopes: &[&str]) -> Self {
+        Self::Explicit(scopes.iter().map(|s| (*s).to_string()).collect())
+    }
+}
+
+/// `scopes` as written in the configuration, a list or "default" or "none"
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawScopes {
+    List(Vec<String>),
+    Word(String),
+}
+
+impl TryFrom<RawScopes> for Scopes {
+    type Error = String;
+
+    fn try_from(raw: RawScopes) -> Result<Self, Self::Error> {
+        match raw {
+            RawScopes::List(scopes) => Ok(Self::Explicit(scopes)),
+            RawScopes::Word(word) => {
+                match word.to_ascii_lowercase().as_str() {
+                    "default" => Ok(Self::Default),
+                    "none" => Ok(Self::None),
+                    _ => {
+                        Err(format!(
+                            "invalid scopes {word:?}, use a list, \"default\" or \"none\""
+                        ))
+                    },
+                }
+            },
+        }
+    }
+}
+
+/// When destructive commands such as `logout` and `nuke` ask for
+/// confirmation
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConfirmPolicy {
+    /// Always ask, refusing to go ahead without a terminal
+    Always,
+    /// Never ask
+    Never,
+    /// Ask when a terminal is attached
+    #[default]
+    Tty,
+}
+
+/// Where warden keeps its own state
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct StateConfig {
+    /// Keep the hosts state in the keyring instead of `hosts.toml`, off by
+    /// default
+    pub encrypted: Option<bool>,
+}
+
+impl OAuthConfig {
+    /// How long `get` may take, including prompts and OAuth flows, `None` if
+    /// it may take forever
+    pub fn get_deadline(&self) -> Option<Duration> {
+        match self.get_timeout.unwrap_or(DEFAULT_GET_TIMEOUT) {
+            0 => None,
+            seconds => Some(Duration::from_secs(seconds)),
+        }
+    }
+
+    /// How long a served credential answers repeated requests of the same Git
+    /// operation, at most [`coalesce::MAX_WINDOW`], `None` if it doesn't
+    pub fn coalesce_window(&self) -> Option<Duration> {
+        match self.coalesce_window.unwrap_or(0) {
+            0 => None,
+            seconds => Some(Duration::from_secs(seconds).min(coalesce::MAX_WINDOW)),
+        }
+    }
+
+    /// Look up the provider responsible for `host`.
+    ///
+    /// Both the request host and the provider keys are compared in their
+    /// normalized form (see [`normalize_host`]). An exact match, including
+    /// the port, wins. Otherwise a provider configured without a port is
+    /// used for requests carrying one, and last a provider with
+    /// `match_subdomains` whose host is a parent domain of the request's,
+    /// the longest such key first.
+    ///
+    /// Returns the canonical host, which is the key under which hosts state
+    /// and keyring entries are stored, together with the provider. For
+    /// subdomain matches that is the provider's key, so all subdomains share
+    /// its credentials.
+    pub fn find_provider(&self, host: &str) -> Option<(String, &ProviderConfig)> {
+        let host = normalize_host(host);
+        let mut best: Option<((u8, usize), &str, &ProviderConfig)> = None;
+        for (raw, provider) in &self.providers {
+            let key = normalize_host(raw);
+            let Some(rank) = specificity(&key, &host, provider.match_subdomains.unwrap_or(false))
+            else {
+                continue;
+            };
+            // keys normalizing to the same host tie, the first one in order
+            // wins so the same provider is used on every run
+            if best.as_ref().is_none_or(|(best_rank, best_raw, _)| {
+                rank > *best_rank || (rank == *best_rank && raw.as_str() < *best_raw)
+            }) {
+                best = Some((rank, raw, provider));
+            }
+        }
+        best.map(|(_, raw, provider)| (normalize_host(raw), provider))
+    }
+
+    /// The `insecure_static` credential for `host`, with its key
+    pub fn static_credential(&self, host: &str) -> Option<(&str, &StaticCredential)> {
+        let host = normalize_host(host);
+        self.insecure_static
+            .iter()
+            .find(|(key, _)| normalize_host(key) == host)
+            .map(|(key, credential)| (key.as_str(), credential))
+    }
+
+    /// Why the provider for `host` was discarded, if one was: its key and
+    /// the problems validation found
+    pub fn discarded_for(&self, host: &str) -> Option<(&str, &[String])> {
+        let host = normalize_host(host);
+        self.discarded
+            .iter()
+            .filter_map(|(key, errs)| {
+                specificity(&normalize_host(key), &host, false).map(|rank| (rank, key, errs))
+            })
+            .max_by(|(rank, key, _), (other_rank, other_key, _)| {
+                rank.cmp(other_rank).then_with(|| other_key.cmp(key))
+            })
+            .map(|(_, key, errs)| (key.as_str(), errs.as_slice()))
+    }
+
+    /// Note for a host without a provider: why its provider was discarded,
+    /// or that none is configured
+    pub fn missing_provider_message(&self, host: &str) -> String {
+        self.discarded_for(host).map_or_else(
+            || format!("No OAuth provider is configured for [{host}]."),
+            |(key, errs)| {
+                let provider = if normalize_host(key) == normalize_host(host) {
+                    String::new()
+                } else {
+                    format!(" [{key}]")
+                };
+                format!(
+                    "The provider{provider} for [{host}] was discarded because: {}",
+                    errs.join(", ")
+                )
+            },
+        )
+    }
+
+    /// Merge all sources and validate the providers like [`Self::load`],
+    /// but keep going when none is valid so the problems can be reported
+    pub fn load_report() -> Result<Self> {
+        let mut cfg = Self::load_merged().context("Failed to load OAuth configuration")?;
+        discard_invalid(&mut cfg);
+        Ok(cfg)
+    }
 
-        let builder = Config::builder()
-            .add_source(File::from(config_file).required(false))
-            .add_source(GitConfigSource::global())
-            .add_source(GitConfigSource::repo());
+    /// `oauth.toml` and the git config overrides merged as `merge_strategy`
+    /// says, not yet validated
+    fn load_merged() -> Result<Self> {
+        let layers = Layers::read(&config_dir()?.join("oauth.toml"))?;
+        let strategy = layers.strategy()?;
+        let conflicts = layers.conflicts(strategy);
+        if strategy == MergeStrategy::Error && !conflicts.is_empty() {
+            let list: Vec<String> = conflicts.iter().map(ToString::to_string).collect();
+            bail!(
+                "The OAuth configuration sources disagree, with merge_strategy = \"error\":\n  {}",
+                list.join("\n  ")
+            );
+        }
+        for conflict in &conflicts {
+            warn!("{conflict}, using the last");
+        }
 
-        let settings = builder
+        let settings = Config::builder()
+            .add_source(layers.sources(strategy))
             .build()
             .context("Failed to build OAuth provider configurations")?;
 
-        let cfg: Self = settings
+        let mut cfg: Self = settings
             .try_deserialize()
             .context("Malformed OAuth provider configuration")?;
+        cfg.conflicts = conflicts;
+        Hosts::use_state_config(&cfg.state);
+        Ok(cfg)
+    }
+
+    /// Canonical host for `host`: the matching provider's key if one exists,
+    /// otherwise the normalized host itself.
+    pub fn canonical_host(&self, host: &str) -> String {
+        self.find_provider(host)
+            .map_or_else(|| normalize_host(host), |(key, _)| key)
+    }
+}
+
+/// How specifically the normalized provider key `key` matches the normalized
+/// request `host`, higher is more specific: exactly, by name for keys without
+/// a port, or as a parent domain when `match_subdomains` is set. Among parent
+/// domains the longer key is more specific.
+fn specificity(key: &str, host: &str, match_subdomains: bool) -> Option<(u8, usize)> {
+    if key == host {
+        return Some((3, key.len()));
+    }
+    let (name, port) = split_port(host);
+    let (key_name, key_port) = split_port(key);
+    if key_port.is_some() && key_port != port {
+        return None;
+    }
+    if key_name == name {
+        return Some((2, key.len()));
+    }
+    (match_subdomains
+        && name
+            .strip_suffix(key_name)
+            .is_some_and(|sub| sub.len() > 1 && sub.ends_with('.')))
+    .then_some((1, key.len()))
+}
 
-        let cfg = validate_providers(cfg).context("Invalid OAuth provider configuration")?;
+impl LoadableConfig for OAuthConfig {
+    const KIND: &'static str = "OAuth";
+
+    /// Load and merge configuration sources
+    fn load_raw() -> Result<Self> {
+        let cfg = validate_providers(Self::load_merged()?)
+            .context("Invalid OAuth provider configuration")?;
         Ok(cfg)
     }
 }
 
+/// Base URL relative endpoints of the provider keyed `name` are joined to:
+/// its normalized host, keeping a custom port, over `http` only if the key
+/// says so
 fn provider_endpoint_base(name: &str) -> String {
-    if name.starts_with("http://") || name.starts_with("https://") {
-        name.to_string()
+    let scheme = if name.trim().starts_with("http://") {
+        "http"
     } else {
-        format!("https://{name}")
-    }
+        "https"
+    };
+    format!("{scheme}://{}", normalize_host(name))
 }
 
 fn resolve_endpoint(base: &str, v: &str) -> String {
@@ -132,15 +768,22 @@ fn resolve_endpoint(base: &str, v: &str) -> String {
     }
 }
 
-fn apply_type_defaults(provider: &mut ProviderConfig, ptype: &str, errs: &mut Vec<String>) {
-    let defaults = match ptype.to_lowercase().as_str() {
+fn type_defaults(ptype: &str) -> Option<&'static ProviderDefaults> {
+    match ptype.to_lowercase().as_str() {
         "github" => Some(&GITHUB),
         "gitlab" => Some(&GITLAB),
         "forgejo" | "gitea" => Some(&FORGEJO),
+        "gcloud" | "google" => Some(&GCLOUD),
         _ => None,
-    };
+    }
+}
 
-    if let Some(defaults) = defaults {
+fn apply_type_defaults(provider: &mut ProviderConfig, ptype: &str, errs: &mut Vec<String>) {
+    if provider.is_oidc() {
+        apply_oidc_defaults(provider, errs);
+        return;
+    }
+    if let Some(defaults) = type_defaults(ptype) {
         if provider.auth_url.trim().is_empty() {
             provider.auth_url = defaults.auth_path.to_string();
         }
@@ -156,16 +799,13 @@ fn apply_type_defaults(provider: &mut ProviderConfig, ptype: &str, errs: &mut Ve
             },
             _ => {},
         }
-        if provider.scopes.is_none() || provider.scopes.as_ref().unwrap().is_empty() {
-            provider.scopes = Some(
-                defaults
-                    .scopes
-                    .iter()
-                    .map(|scope| (*scope).to_string())
-                    .collect(),
-            );
+        if provider.revocation_url.is_none() {
+            provider.revocation_url = defaults.revocation_path.map(str::to_string);
+        }
+        if provider.scopes == Scopes::Default {
+            provider.scopes = Scopes::from(defaults.scopes);
         }
-        if provider.preferred_flow.is_none() {
+        if provider.preferred_flow.is_none() && provider.flows.is_none() {
             provider.preferred_flow = Some(defaults.preferred_flow.to_string());
         }
     } else {
@@ -173,6 +813,21 @@ fn apply_type_defaults(provider: &mut ProviderConfig, ptype: &str, errs: &mut Ve
     }
 }
 
+/// Requires an absolute issuer URL and defaults the scopes. Endpoints and the
+/// preferred flow are left for discovery.
+fn apply_oidc_defaults(provider: &mut ProviderConfig, errs: &mut Vec<String>) {
+    match provider.issuer.as_mut() {
+        Some(issuer) if Url::parse(issuer.trim()).is_ok() => {
+            *issuer = issuer.trim().trim_end_matches('/').to_string();
+        },
+        Some(_) => errs.push("invalid issuer".into()),
+        None => errs.push("missing issuer".into()),
+    }
+    if provider.scopes == Scopes::Default {
+        provider.scopes = Scopes::from(OIDC_SCOPES);
+    }
+}
+
 fn validate_and_normalize_provider(name: &str, provider: &mut ProviderConfig) -> Vec<String> {
     let mut errs = Vec::new();
     let endpoint_base = provider_endpoint_base(name);
@@ -186,12 +841,28 @@ fn validate_and_normalize_provider(name: &str, provider: &mut ProviderConfig) ->
         apply_type_defaults(provider, &ptype, &mut errs);
     }
 
-    if provider.client_id.trim().is_empty() {
+    if provider.client_id.trim().is_empty() && !provider.is_gcloud() {
         errs.push("missing client_id".into());
     }
 
+    // read when a flow needs it, a locked keyring must not discard the
+    // provider
+    if provider
+        .client_secret
+        .as_ref()
+        .is_some_and(|secret| secret.expose() == CLIENT_SECRET_IN_KEYRING)
+    {
+        provider.client_secret = None;
+        provider.secret_in_keyring = Some(normalize_host(name));
+    }
+
+    // discovery fills in whatever endpoints an oidc provider leaves empty
+    let discovered = provider.is_oidc();
+
     if provider.auth_url.trim().is_empty() {
-        errs.push("missing auth_url".into());
+        if !discovered {
+            errs.push("missing auth_url".into());
+        }
     } else {
         provider.auth_url = resolve_endpoint(&endpoint_base, &provider.auth_url);
         if Url::parse(&provider.auth_url).is_err() {
@@ -200,7 +871,9 @@ fn validate_and_normalize_provider(name: &str, provider: &mut ProviderConfig) ->
     }
 
     if provider.token_url.trim().is_empty() {
-        errs.push("missing token_url".into());
+        if !discovered {
+            errs.push("missing token_url".into());
+        }
     } else {
         provider.token_url = resolve_endpoint(&endpoint_base, &provider.token_url);
         if Url::parse(&provider.token_url).is_err() {
@@ -208,6 +881,53 @@ fn validate_and_normalize_provider(name: &str, provider: &mut ProviderConfig) ->
         }
     }
 
+    if let Some(flow) = provider.preferred_flow.as_mut() {
+        *flow = flow.trim().to_lowercase();
+        if !matches!(flow.as_str(), "auto" | "device" | "authcode") {
+            errs.push(
+                "invalid preferred_flow, expected \"auto\", \"device\" or \"authcode\"".into(),
+            );
+        }
+    }
+
+    if let Some(flows) = provider.flows.as_mut() {
+        for flow in flows.iter_mut() {
+            *flow = flow.trim().to_lowercase();
+        }
+        if flows.is_empty() {
+            errs.push("empty flows".into());
+        }
+        if let Some(flow) = flows.iter().find(|f| !FLOWS.contains(&f.as_str())) {
+            errs.push(format!(
+                "invalid flow \"{flow}\" in flows, expected \"device\" or \"authcode\""
+            ));
+        }
+        if provider
+            .preferred_flow
+            .as_deref()
+            .is_some_and(|flow| flow != "auto")
+        {
+            errs.push("flows requires preferred_flow to be \"auto\" or unset".into());
+        }
+    }
+
+    if let Some(param) = provider
+        .auth_params
+        .keys()
+        .find(|param| RESERVED_AUTH_PARAMS.contains(&param.as_str()))
+    {
+        errs.push(format!(
+            "auth_params can't set \"{param}\", which warden sets itself"
+        ));
+    }
+
+    if let Some(style) = provider.auth_style.as_mut() {
+        *style = style.trim().to_lowercase();
+        if !matches!(style.as_str(), "basic" | "body") {
+            errs.push("invalid auth_style, expected \"basic\" or \"body\"".into());
+        }
+    }
+
     if let Some(url) = provider.device_auth_url.as_mut() {
         let resolved = resolve_endpoint(&endpoint_base, url);
         *url = resolved;
@@ -216,33 +936,122 @@ fn validate_and_normalize_provider(name: &str, provider: &mut ProviderConfig) ->
         }
     }
 
+    if let Some(url) = provider.revocation_url.as_mut() {
+        *url = resolve_endpoint(&endpoint_base, url);
+        if Url::parse(url.as_str()).is_err() {
+            errs.push("invalid revocation_url".into());
+        }
+    }
+
+    if let Some(pin) = provider
+        .tls
+        .pinned_sha256
+        .iter()
+        .find(|pin| parse_pin(pin).is_none())
+    {
+        errs.push(format!(
+            "invalid tls.pinned_sha256 entry \"{pin}\", expected a SHA-256 digest in hex or base64"
+        ));
+    }
+
+    // discovery may still find the device endpoint of an oidc provider
+    if provider.device_auth_url.is_none()
+        && !discovered
+        && provider.flow_order().contains(&"device")
+    {
+        errs.push("the device flow requires device_auth_url".into());
+    }
+
     errs
 }
 
-/// Validate provider entries and discard invalid ones, logging warnings
-fn validate_providers(mut cfg: OAuthConfig) -> Result<OAuthConfig> {
-    let mut invalid: Vec<(String, Vec<String>)> = Vec::new();
+/// Add providers for the hosts of [`BUILTIN_CLIENTS`] that have none and
+/// give configured ones without a `client_id` the built-in client. Anything
+/// set in the configuration wins.
+fn apply_builtin_clients(providers: &mut HashMap<String, ProviderConfig>) {
+    for builtin in BUILTIN_CLIENTS {
+        let configured = providers
+            .iter_mut()
+            .find(|(key, _)| normalize_host(key) == builtin.host);
+        let Some((_, provider)) = configured else {
+            providers.insert(
+                builtin.host.to_string(),
+                ProviderConfig {
+                    provider_type: Some(builtin.provider_type.to_string()),
+                    issuer: None,
+                    client_id: builtin.client_id.to_string(),
+                    client_secret: builtin.client_secret.map(Secret::from),
+                    auth_url: String::new(),
+                    token_url: String::new(),
+                    device_auth_url: None,
+                    revocation_url: None,
+                    scopes: Scopes::Default,
+                    auth_params: BTreeMap::new(),
+                    preferred_flow: None,
+                    flows: None,
+                    service_account: None,
+                    auth_style: None,
+                    clock_skew: None,
+                    match_subdomains: None,
+                    refresh: None,
+                    credential_name_template: None,
+                    tls: TlsConfig::default(),
+                    secret_in_keyring: None,
+                },
+            );
+            continue;
+        };
+        let same_type = provider
+            .provider_type
+            .as_deref()
+            .is_none_or(|t| t.eq_ignore_ascii_case(builtin.provider_type));
+        if provider.client_id.trim().is_empty() && same_type {
+            provider.provider_type = Some(builtin.provider_type.to_string());
+            provider.client_id = builtin.client_id.to_string();
+            provider.client_secret = builtin.client_secret.map(Secret::from);
+        }
+    }
+}
 
+/// Validate provider entries and discard invalid ones, logging warnings
+/// Fill in defaults and move invalid providers from `providers` to
+/// `discarded`
+fn discard_invalid(cfg: &mut OAuthConfig) {
+    if cfg.use_builtin_clients.unwrap_or(false) {
+        apply_builtin_clients(&mut cfg.providers);
+    }
     for (name, provider) in &mut cfg.providers {
+        if provider.clock_skew.is_none() {
+            provider.clock_skew = cfg.clock_skew;
+        }
         let errs = validate_and_normalize_provider(name, provider);
         if !errs.is_empty() {
-            invalid.push((name.clone(), errs));
-        }
-    }
-
-    if !invalid.is_empty() {
-        for (name, errs) in &invalid {
             warn!(
                 "Discarding invalid OAuth provider '{name}': {}",
                 errs.join(", ")
             );
-        }
-        for (name, _) in invalid {
-            cfg.providers.remove(&name);
+            cfg.discarded.insert(name.clone(), errs);
         }
     }
-    if cfg.providers.is_empty() {
-        bail!("No valid OAuth providers configured");
+    cfg.providers
+        .retain(|name, _| !cfg.discarded.contains_key(name));
+    cfg.insecure_static.retain(|host, credential| {
+        let valid = credential.password.is_some() != credential.password_file.is_some();
+        if !valid {
+            warn!("Discarding insecure_static '{host}': set either password or password_file");
+        }
+        valid
+    });
+}
+
+pub fn validate_providers(mut cfg: OAuthConfig) -> Result<OAuthConfig> {
+    discard_invalid(&mut cfg);
+    if cfg.providers.is_empty() && cfg.insecure_static.is_empty() {
+        let mut message = "No valid OAuth providers configured".to_string();
+        for (name, errs) in &cfg.discarded {
+            let _ = write!(message, "; '{name}' discarded: {}", errs.join(", "));
+        }
+        bail!(message);
     }
     Ok(cfg)
 }
@@ -259,36 +1068,85 @@ mod tests {
                     "good.example".into(),
                     ProviderConfig {
                         provider_type: None,
+                        issuer: None,
                         client_id: "some-id".into(),
                         client_secret: None,
                         auth_url: "https://good.example/auth".into(),
                         token_url: "https://good.example/token".into(),
                         device_auth_url: None,
-                        scopes: None,
+                        revocation_url: None,
+                        scopes: Scopes::Default,
+                        auth_params: BTreeMap::new(),
                         preferred_flow: None,
+                        flows: None,
+                        service_account: None,
+                        auth_style: None,
+                        clock_skew: None,
+                        match_subdomains: None,
+                        refresh: None,
+                        credential_name_template: None,
+                        tls: TlsConfig::default(),
+                        secret_in_keyring: None,
                     },
                 ),
                 (
                     "bad.example".into(),
                     ProviderConfig {
                         provider_type: None,
+                        issuer: None,
                         client_id: String::new(),
                         client_secret: None,
                         auth_url: "notaurl".into(),
                         token_url: "https://still.ok/token".into(),
                         device_auth_url: Some("also_bad".into()),
-                        scopes: Some(vec![]),
+                        revocation_url: None,
+                        scopes: Scopes::Explicit(vec![]),
+                        auth_params: BTreeMap::new(),
                         preferred_flow: None,
+                        flows: None,
+                        service_account: None,
+                        auth_style: None,
+                        clock_skew: None,
+                        match_subdomains: None,
+                        refresh: None,
+                        credential_name_template: None,
+                        tls: TlsConfig::default(),
+                        secret_in_keyring: None,
                     },
                 ),
             ]),
             port: None,
             oauth_only: None,
+            clock_skew: None,
+            stats: None,
+            choose_credential: None,
+            interactive_onboarding: None,
+            use_builtin_clients: None,
+            registries: HashMap::new(),
+            insecure_static: HashMap::new(),
+            discarded: BTreeMap::new(),
+            state: StateConfig::default(),
+            confirm_destructive: None,
+            get_timeout: None,
+            coalesce_window: None,
+            credential_name_template: None,
+            token_display: None,
+            conflicts: Vec::new(),
         };
 
         let cfg = validate_providers(cfg).unwrap();
         assert!(cfg.providers.contains_key("good.example"));
         assert!(!cfg.providers.contains_key("bad.example"));
+        assert!(
+            cfg.discarded["bad.example"].contains(&"missing client_id".to_string()),
+            "reasons are kept: {:?}",
+            cfg.discarded
+        );
+        assert!(
+            cfg.missing_provider_message("Bad.Example:443")
+                .contains("discarded because: missing client_id"),
+            "the reasons are reported for the host"
+        );
     }
 
     #[test]
@@ -298,17 +1156,44 @@ mod tests {
                 "example.com".into(),
                 ProviderConfig {
                     provider_type: Some("gitlab".into()),
+                    issuer: None,
                     client_id: "some-id".into(),
                     client_secret: None,
                     auth_url: String::new(),
                     token_url: String::new(),
                     device_auth_url: None,
-                    scopes: None,
+                    revocation_url: None,
+                    scopes: Scopes::Default,
+                    auth_params: BTreeMap::new(),
                     preferred_flow: None,
+                    flows: None,
+                    service_account: None,
+                    auth_style: None,
+                    clock_skew: None,
+                    match_subdomains: None,
+                    refresh: None,
+                    credential_name_template: None,
+                    tls: TlsConfig::default(),
+                    secret_in_keyring: None,
                 },
             )]),
             port: None,
             oauth_only: None,
+            clock_skew: None,
+            stats: None,
+            choose_credential: None,
+            interactive_onboarding: None,
+            use_builtin_clients: None,
+            registries: HashMap::new(),
+            insecure_static: HashMap::new(),
+            discarded: BTreeMap::new(),
+            state: StateConfig::default(),
+            confirm_destructive: None,
+            get_timeout: None,
+            coalesce_window: None,
+            credential_name_template: None,
+            token_display: None,
+            conflicts: Vec::new(),
         };
 
         let cfg = validate_providers(cfg).unwrap();
@@ -322,7 +1207,7 @@ mod tests {
         );
         assert_eq!(p.preferred_flow.as_deref(), Some("authcode"));
         assert_eq!(
-            p.scopes.as_ref().unwrap(),
+            p.scopes.requested().unwrap(),
             &vec![
                 "read_repository".to_string(),
                 "write_repository".to_string(),
@@ -338,17 +1223,44 @@ mod tests {
                 "example".into(),
                 ProviderConfig {
                     provider_type: Some("forgejo".into()),
+                    issuer: None,
                     client_id: "some-id".into(),
                     client_secret: None,
                     auth_url: "https://override.example/custom_auth".into(),
                     token_url: String::new(),
                     device_auth_url: Some("/custom/device".into()),
-                    scopes: None,
+                    revocation_url: None,
+                    scopes: Scopes::Default,
+                    auth_params: BTreeMap::new(),
                     preferred_flow: None,
+                    flows: None,
+                    service_account: None,
+                    auth_style: None,
+                    clock_skew: None,
+                    match_subdomains: None,
+                    refresh: None,
+                    credential_name_template: None,
+                    tls: TlsConfig::default(),
+                    secret_in_keyring: None,
                 },
             )]),
             port: None,
             oauth_only: None,
+            clock_skew: None,
+            stats: None,
+            choose_credential: None,
+            interactive_onboarding: None,
+            use_builtin_clients: None,
+            registries: HashMap::new(),
+            insecure_static: HashMap::new(),
+            discarded: BTreeMap::new(),
+            state: StateConfig::default(),
+            confirm_destructive: None,
+            get_timeout: None,
+            coalesce_window: None,
+            credential_name_template: None,
+            token_display: None,
+            conflicts: Vec::new(),
         };
 
         let cfg = validate_providers(cfg).unwrap();
@@ -362,7 +1274,7 @@ mod tests {
         );
         assert_eq!(p.preferred_flow.as_deref(), Some("authcode"));
         assert_eq!(
-            p.scopes.as_ref().unwrap(),
+            p.scopes.requested().unwrap(),
             &vec![
                 "read:repository".to_string(),
                 "write:repository".to_string(),
@@ -377,17 +1289,44 @@ mod tests {
                 "https://gitlab.example.com".into(),
                 ProviderConfig {
                     provider_type: Some("gitlab".into()),
+                    issuer: None,
                     client_id: "some-id".into(),
                     client_secret: None,
                     auth_url: String::new(),
                     token_url: String::new(),
                     device_auth_url: None,
-                    scopes: None,
+                    revocation_url: None,
+                    scopes: Scopes::Default,
+                    auth_params: BTreeMap::new(),
                     preferred_flow: None,
+                    flows: None,
+                    service_account: None,
+                    auth_style: None,
+                    clock_skew: None,
+                    match_subdomains: None,
+                    refresh: None,
+                    credential_name_template: None,
+                    tls: TlsConfig::default(),
+                    secret_in_keyring: None,
                 },
             )]),
             port: None,
             oauth_only: None,
+            clock_skew: None,
+            stats: None,
+            choose_credential: None,
+            interactive_onboarding: None,
+            use_builtin_clients: None,
+            registries: HashMap::new(),
+            insecure_static: HashMap::new(),
+            discarded: BTreeMap::new(),
+            state: StateConfig::default(),
+            confirm_destructive: None,
+            get_timeout: None,
+            coalesce_window: None,
+            credential_name_template: None,
+            token_display: None,
+            conflicts: Vec::new(),
         };
 
         let cfg = validate_providers(cfg).unwrap();
@@ -401,7 +1340,7 @@ mod tests {
         );
         assert_eq!(p.preferred_flow.as_deref(), Some("authcode"));
         assert_eq!(
-            p.scopes.as_ref().unwrap(),
+            p.scopes.requested().unwrap(),
             &vec![
                 "read_repository".to_string(),
                 "write_repository".to_string(),
@@ -409,13 +1348,722 @@ mod tests {
         );
     }
 
+    #[test]
+    fn provider_type_gcloud_needs_no_client_id() {
+        let cfg = OAuthConfig {
+            providers: HashMap::from_iter([(
+                "source.developers.google.com".into(),
+                ProviderConfig {
+                    provider_type: Some("gcloud".into()),
+                    issuer: None,
+                    client_id: String::new(),
+                    client_secret: None,
+                    auth_url: String::new(),
+                    token_url: String::new(),
+                    device_auth_url: None,
+                    revocation_url: None,
+                    scopes: Scopes::Default,
+                    auth_params: BTreeMap::new(),
+                    preferred_flow: None,
+                    flows: None,
+                    service_account: Some("sa@project.iam.gserviceaccount.com".into()),
+                    auth_style: None,
+                    clock_skew: None,
+                    match_subdomains: None,
+                    refresh: None,
+                    credential_name_template: None,
+                    tls: TlsConfig::default(),
+                    secret_in_keyring: None,
+                },
+            )]),
+            port: None,
+            oauth_only: None,
+            clock_skew: None,
+            stats: None,
+            choose_credential: None,
+            interactive_onboarding: None,
+            use_builtin_clients: None,
+            registries: HashMap::new(),
+            insecure_static: HashMap::new(),
+            discarded: BTreeMap::new(),
+            state: StateConfig::default(),
+            confirm_destructive: None,
+            get_timeout: None,
+            coalesce_window: None,
+            credential_name_template: None,
+            token_display: None,
+            conflicts: Vec::new(),
+        };
+
+        let cfg = validate_providers(cfg).unwrap();
+
+        let p = &cfg.providers["source.developers.google.com"];
+        assert!(p.is_gcloud(), "provider should be recognized as gcloud");
+        assert_eq!(p.token_url, "https://oauth2.googleapis.com/token");
+        assert!(p.device_auth_url.is_none(), "gcloud has no device flow");
+    }
+
+    #[test]
+    fn scope_presets_depend_on_type() {
+        let mut provider = ProviderConfig {
+            provider_type: Some("GitHub".into()),
+            issuer: None,
+            client_id: "id".into(),
+            client_secret: None,
+            auth_url: String::new(),
+            token_url: String::new(),
+            device_auth_url: None,
+            revocation_url: None,
+            scopes: Scopes::Default,
+            auth_params: BTreeMap::new(),
+            preferred_flow: None,
+            flows: None,
+            service_account: None,
+            auth_style: None,
+            clock_skew: None,
+            match_subdomains: None,
+            refresh: None,
+            credential_name_template: None,
+            tls: TlsConfig::default(),
+            secret_in_keyring: None,
+        };
+        provider.apply_scope_preset(ScopePreset::Readonly).unwrap();
+        assert_eq!(provider.scopes, Scopes::Explicit(vec!["repo".to_string()]));
+
+        provider.provider_type = Some("gitlab".into());
+        provider.apply_scope_preset(ScopePreset::Standard).unwrap();
+        assert_eq!(
+            provider.scopes.requested(),
+            Some(
+                &[
+                    "read_repository".to_string(),
+                    "write_repository".to_string()
+                ][..]
+            )
+        );
+
+        provider.provider_type = Some("oidc".into());
+        provider.apply_scope_preset(ScopePreset::Admin).unwrap_err();
+    }
+
+    #[test]
+    fn scopes_distinguish_defaults_none_and_empty() {
+        let cfg: OAuthConfig = toml::from_str(
+            r#"
+            [providers."a.example"]
+            type = "gitlab"
+            client_id = "id"
+            [providers."b.example"]
+            type = "gitlab"
+            client_id = "id"
+            scopes = "none"
+            [providers."c.example"]
+            type = "gitlab"
+            client_id = "id"
+            scopes = []
+            "#,
+        )
+        .unwrap();
+        let cfg = validate_providers(cfg).unwrap();
+        assert_eq!(
+            cfg.providers["a.example"].scopes,
+            Scopes::from(&["read_repository", "write_repository"][..])
+        );
+        assert_eq!(cfg.providers["b.example"].scopes, Scopes::None);
+        assert_eq!(cfg.providers["c.example"].scopes.requested(), Some(&[][..]));
+
+        toml::from_str::<OAuthConfig>("[providers.\"d.example\"]\nscopes = \"all\"").unwrap_err();
+    }
+
+    #[test]
+    fn auth_params_cannot_override_warden_params() {
+        let cfg: OAuthConfig = toml::from_str(
+            r#"
+            [providers."a.example"]
+            type = "gitlab"
+            client_id = "id"
+            auth_params = { access_type = "offline", prompt = "consent" }
+            [providers."b.example"]
+            type = "gitlab"
+            client_id = "id"
+            auth_params = { redirect_uri = "https://elsewhere.example" }
+            "#,
+        )
+        .unwrap();
+        let mut providers = cfg.providers;
+        let mut good = providers.remove("a.example").unwrap();
+        assert_eq!(
+            validate_and_normalize_provider("a.example", &mut good),
+            Vec::<String>::new()
+        );
+        assert_eq!(good.auth_params["access_type"], "offline");
+        let mut bad = providers.remove("b.example").unwrap();
+        assert_eq!(
+            validate_and_normalize_provider("b.example", &mut bad),
+            ["auth_params can't set \"redirect_uri\", which warden sets itself"]
+        );
+    }
+
+    #[test]
+    fn builtin_clients_are_lowest_precedence() {
+        let own = ProviderConfig {
+            provider_type: Some("github".into()),
+            issuer: None,
+            client_id: "own-id".into(),
+            client_secret: None,
+            auth_url: String::new(),
+            token_url: String::new(),
+            device_auth_url: None,
+            revocation_url: None,
+            scopes: Scopes::Default,
+            auth_params: BTreeMap::new(),
+            preferred_flow: None,
+            flows: None,
+            service_account: None,
+            auth_style: None,
+            clock_skew: None,
+            match_subdomains: None,
+            refresh: None,
+            credential_name_template: None,
+            tls: TlsConfig::default(),
+            secret_in_keyring: None,
+        };
+        let mut providers = HashMap::new();
+        apply_builtin_clients(&mut providers);
+        assert_eq!(
+            providers["github.com"].client_id,
+            BUILTIN_CLIENTS[0].client_id
+        );
+        assert_eq!(providers.len(), BUILTIN_CLIENTS.len());
+
+        let mut providers = HashMap::from_iter([("GitHub.com".to_string(), own.clone())]);
+        apply_builtin_clients(&mut providers);
+        assert!(
+            !providers.contains_key("github.com"),
+            "no second github.com provider"
+        );
+        assert_eq!(providers["GitHub.com"].client_id, "own-id");
+
+        let mut providers = HashMap::from_iter([(
+            "github.com".to_string(),
+            ProviderConfig {
+                client_id: String::new(),
+                scopes: Scopes::Explicit(vec!["repo".into()]),
+                ..own
+            },
+        )]);
+        apply_builtin_clients(&mut providers);
+        let p = &providers["github.com"];
+        assert_eq!(p.client_id, BUILTIN_CLIENTS[0].client_id);
+        assert_eq!(p.scopes, Scopes::Explicit(vec!["repo".to_string()]));
+    }
+
+    #[test]
+    fn find_provider_normalizes_case_and_ports() {
+        let cfg = OAuthConfig {
+            providers: HashMap::from_iter([
+                (
+                    "https://GitLab.Example.com".into(),
+                    ProviderConfig {
+                        provider_type: Some("gitlab".into()),
+                        issuer: None,
+                        client_id: "some-id".into(),
+                        client_secret: None,
+                        auth_url: String::new(),
+                        token_url: String::new(),
+                        device_auth_url: None,
+                        revocation_url: None,
+                        scopes: Scopes::Default,
+                        auth_params: BTreeMap::new(),
+                        preferred_flow: None,
+                        flows: None,
+                        service_account: None,
+                        auth_style: None,
+                        clock_skew: None,
+                        match_subdomains: None,
+                        refresh: None,
+                        credential_name_template: None,
+                        tls: TlsConfig::default(),
+                        secret_in_keyring: None,
+                    },
+                ),
+                (
+                    "gitlab.example.com:8443".into(),
+                    ProviderConfig {
+                        provider_type: Some("gitlab".into()),
+                        issuer: None,
+                        client_id: "other-id".into(),
+                        client_secret: None,
+                        auth_url: String::new(),
+                        token_url: String::new(),
+                        device_auth_url: None,
+                        revocation_url: None,
+                        scopes: Scopes::Default,
+                        auth_params: BTreeMap::new(),
+                        preferred_flow: None,
+                        flows: None,
+                        service_account: None,
+                        auth_style: None,
+                        clock_skew: None,
+                        match_subdomains: None,
+                        refresh: None,
+                        credential_name_template: None,
+                        tls: TlsConfig::default(),
+                        secret_in_keyring: None,
+                    },
+                ),
+            ]),
+            port: None,
+            oauth_only: None,
+            clock_skew: None,
+            stats: None,
+            choose_credential: None,
+            interactive_onboarding: None,
+            use_builtin_clients: None,
+            registries: HashMap::new(),
+            insecure_static: HashMap::new(),
+            discarded: BTreeMap::new(),
+            state: StateConfig::default(),
+            confirm_destructive: None,
+            get_timeout: None,
+            coalesce_window: None,
+            credential_name_template: None,
+            token_display: None,
+            conflicts: Vec::new(),
+        };
+
+        let (host, p) = cfg.find_provider("gitlab.example.com:443").unwrap();
+        assert_eq!(host, "gitlab.example.com");
+        assert_eq!(p.client_id, "some-id");
+
+        let (host, p) = cfg.find_provider("GITLAB.example.com:8443").unwrap();
+        assert_eq!(host, "gitlab.example.com:8443");
+        assert_eq!(p.client_id, "other-id");
+
+        // unknown port falls back to the provider without a port
+        let (host, _) = cfg.find_provider("gitlab.example.com:9000").unwrap();
+        assert_eq!(host, "gitlab.example.com");
+
+        assert!(cfg.find_provider("example.com").is_none(), "no match");
+        assert_eq!(cfg.canonical_host("Example.com:443"), "example.com");
+    }
+
+    #[test]
+    fn find_provider_breaks_ties_by_key() {
+        // every parse gets differently seeded maps
+        for _ in 0..8 {
+            let cfg: OAuthConfig = toml::from_str(
+                r#"
+                [providers."github.com:443"]
+                type = "github"
+                client_id = "port"
+
+                [providers."GitHub.com"]
+                type = "github"
+                client_id = "case"
+                "#,
+            )
+            .unwrap();
+            let (host, provider) = cfg.find_provider("github.com").unwrap();
+            assert_eq!(host, "github.com");
+            assert_eq!(provider.client_id, "case", "same provider on every run");
+        }
+    }
+
+    #[test]
+    fn endpoint_base_keeps_custom_ports() {
+        assert_eq!(
+            provider_endpoint_base("GitLab.example.com:8443"),
+            "https://gitlab.example.com:8443"
+        );
+        assert_eq!(
+            provider_endpoint_base("https://gitlab.example.com:443/"),
+            "https://gitlab.example.com"
+        );
+        assert_eq!(
+            provider_endpoint_base("http://localhost:3000"),
+            "http://localhost:3000"
+        );
+    }
+
+    #[test]
+    fn find_provider_matches_subdomains_by_specificity() {
+        let cfg: OAuthConfig = toml::from_str(
+            r#"
+            [providers."example.com"]
+            type = "gitlab"
+            client_id = "parent"
+            match_subdomains = true
+
+            [providers."gitlab.example.com"]
+            type = "gitlab"
+            client_id = "gitlab"
+            match_subdomains = true
+
+            [providers."git.example.org"]
+            type = "gitlab"
+            client_id = "no-subdomains"
+            "#,
+        )
+        .unwrap();
+        let find = |host| {
+            cfg.find_provider(host)
+                .map(|(key, p)| (key, p.client_id.clone()))
+        };
+
+        assert_eq!(
+            find("sub.gitlab.example.com"),
+            Some(("gitlab.example.com".into(), "gitlab".into())),
+            "longest suffix wins"
+        );
+        assert_eq!(
+            find("a.b.example.com:8443"),
+            Some(("example.com".into(), "parent".into())),
+            "keys without a port match any port"
+        );
+        assert_eq!(
+            find("gitlab.example.com"),
+            Some(("gitlab.example.com".into(), "gitlab".into())),
+            "exact match"
+        );
+        assert_eq!(find("badexample.com"), None, "whole labels only");
+        assert_eq!(find("sub.git.example.org"), None, "opt-in");
+    }
+
+    #[test]
+    fn provider_type_oidc_needs_issuer() {
+        let provider = |issuer: Option<&str>| {
+            ProviderConfig {
+                provider_type: Some("oidc".into()),
+                issuer: issuer.map(Into::into),
+                client_id: "some-id".into(),
+                client_secret: None,
+                auth_url: String::new(),
+                token_url: String::new(),
+                device_auth_url: None,
+                revocation_url: None,
+                scopes: Scopes::Default,
+                auth_params: BTreeMap::new(),
+                preferred_flow: None,
+                flows: None,
+                service_account: None,
+                auth_style: None,
+                clock_skew: None,
+                match_subdomains: None,
+                refresh: None,
+                credential_name_template: None,
+                tls: TlsConfig::default(),
+                secret_in_keyring: None,
+            }
+        };
+        let cfg = OAuthConfig {
+            providers: HashMap::from_iter([
+                (
+                    "good.example".into(),
+                    provider(Some("https://sso.example/realms/dev/")),
+                ),
+                ("bad.example".into(), provider(Some("sso.example"))),
+                ("missing.example".into(), provider(None)),
+            ]),
+            port: None,
+            oauth_only: None,
+            clock_skew: None,
+            stats: None,
+            choose_credential: None,
+            interactive_onboarding: None,
+            use_builtin_clients: None,
+            registries: HashMap::new(),
+            insecure_static: HashMap::new(),
+            discarded: BTreeMap::new(),
+            state: StateConfig::default(),
+            confirm_destructive: None,
+            get_timeout: None,
+            coalesce_window: None,
+            credential_name_template: None,
+            token_display: None,
+            conflicts: Vec::new(),
+        };
+
+        let cfg = validate_providers(cfg).unwrap();
+        assert_eq!(cfg.providers.len(), 1, "only the valid issuer remains");
+        let p = &cfg.providers["good.example"];
+        assert_eq!(p.issuer.as_deref(), Some("https://sso.example/realms/dev"));
+        assert!(p.auth_url.is_empty(), "left for discovery");
+        assert!(p.preferred_flow.is_none(), "left for discovery");
+        assert_eq!(
+            p.scopes.requested().unwrap(),
+            &vec!["openid".to_string(), "offline_access".to_string()]
+        );
+    }
+
+    #[test]
+    fn auth_style_is_validated() {
+        let provider = |style: &str| {
+            ProviderConfig {
+                provider_type: Some("gitlab".into()),
+                issuer: None,
+                client_id: "some-id".into(),
+                client_secret: Some("secret".into()),
+                auth_url: String::new(),
+                token_url: String::new(),
+                device_auth_url: None,
+                revocation_url: None,
+                scopes: Scopes::Default,
+                auth_params: BTreeMap::new(),
+                preferred_flow: None,
+                flows: None,
+                service_account: None,
+                auth_style: Some(style.into()),
+                clock_skew: None,
+                match_subdomains: None,
+                refresh: None,
+                credential_name_template: None,
+                tls: TlsConfig::default(),
+                secret_in_keyring: None,
+            }
+        };
+        let cfg = OAuthConfig {
+            providers: HashMap::from_iter([
+                ("basic.example".into(), provider(" Basic")),
+                ("body.example".into(), provider("body")),
+                ("bad.example".into(), provider("header")),
+            ]),
+            port: None,
+            oauth_only: None,
+            clock_skew: None,
+            stats: None,
+            choose_credential: None,
+            interactive_onboarding: None,
+            use_builtin_clients: None,
+            registries: HashMap::new(),
+            insecure_static: HashMap::new(),
+            discarded: BTreeMap::new(),
+            state: StateConfig::default(),
+            confirm_destructive: None,
+            get_timeout: None,
+            coalesce_window: None,
+            credential_name_template: None,
+            token_display: None,
+            conflicts: Vec::new(),
+        };
+
+        let cfg = validate_providers(cfg).unwrap();
+        assert!(!cfg.providers.contains_key("bad.example"), "unknown style");
+        assert!(matches!(
+            cfg.providers["basic.example"].auth_type(AuthType::RequestBody),
+            AuthType::BasicAuth
+        ));
+        assert!(matches!(
+            cfg.providers["body.example"].auth_type(AuthType::BasicAuth),
+            AuthType::RequestBody
+        ));
+    }
+
+    #[test]
+    fn flows_are_ordered_and_validated() {
+        let provider = |ptype: &str, preferred: Option<&str>, flows: Option<&[&str]>| {
+            ProviderConfig {
+                provider_type: Some(ptype.into()),
+                issuer: None,
+                client_id: "some-id".into(),
+                client_secret: None,
+                auth_url: String::new(),
+                token_url: String::new(),
+                device_auth_url: None,
+                revocation_url: None,
+                scopes: Scopes::Default,
+                auth_params: BTreeMap::new(),
+                preferred_flow: preferred.map(Into::into),
+                flows: flows.map(|f| f.iter().map(|s| (*s).to_string()).collect()),
+                service_account: None,
+                auth_style: None,
+                clock_skew: None,
+                match_subdomains: None,
+                refresh: None,
+                credential_name_template: None,
+                tls: TlsConfig::default(),
+                secret_in_keyring: None,
+            }
+        };
+        let cfg = OAuthConfig {
+            providers: HashMap::from_iter([
+                (
+                    "ordered.example".into(),
+                    provider("gitlab", None, Some(&["Device", "authcode"])),
+                ),
+                (
+                    "auto.example".into(),
+                    provider("gitlab", Some("auto"), None),
+                ),
+                ("default.example".into(), provider("gitlab", None, None)),
+                (
+                    "conflict.example".into(),
+                    provider("gitlab", Some("device"), Some(&["authcode"])),
+                ),
+                (
+                    "unknown.example".into(),
+                    provider("gitlab", None, Some(&["implicit"])),
+                ),
+                (
+                    "no-device.example".into(),
+                    provider("forgejo", None, Some(&["device", "authcode"])),
+                ),
+                ("bad.example".into(), provider("gitlab", Some("both"), None)),
+            ]),
+            port: None,
+            oauth_only: None,
+            clock_skew: None,
+            stats: None,
+            choose_credential: None,
+            interactive_onboarding: None,
+            use_builtin_clients: None,
+            registries: HashMap::new(),
+            insecure_static: HashMap::new(),
+            discarded: BTreeMap::new(),
+            state: StateConfig::default(),
+            confirm_destructive: None,
+            get_timeout: None,
+            coalesce_window: None,
+            credential_name_template: None,
+            token_display: None,
+            conflicts: Vec::new(),
+        };
+
+        let cfg = validate_providers(cfg).unwrap();
+        for invalid in [
+            "conflict.example",
+            "unknown.example",
+            "no-device.example",
+            "bad.example",
+        ] {
+            assert!(
+                !cfg.providers.contains_key(invalid),
+                "{invalid} should be discarded"
+            );
+        }
+        assert_eq!(
+            cfg.providers["ordered.example"].flow_order(),
+            ["device", "authcode"],
+            "flows should be kept in order"
+        );
+        assert_eq!(
+            cfg.providers["auto.example"].flow_order(),
+            ["device"],
+            "auto should not fall back"
+        );
+        assert_eq!(
+            cfg.providers["default.example"].flow_order(),
+            ["authcode"],
+            "the type's preferred flow should apply"
+        );
+    }
+
     #[test]
     fn empty_providers_error() {
         let cfg = OAuthConfig {
             providers: HashMap::new(),
             port: None,
             oauth_only: None,
+            clock_skew: None,
+            stats: None,
+            choose_credential: None,
+            interactive_onboarding: None,
+            use_builtin_clients: None,
+            registries: HashMap::new(),
+            insecure_static: HashMap::new(),
+            discarded: BTreeMap::new(),
+            state: StateConfig::default(),
+            confirm_destructive: None,
+            get_timeout: None,
+            coalesce_window: None,
+            credential_name_template: None,
+            token_display: None,
+            conflicts: Vec::new(),
         };
         validate_providers(cfg).unwrap_err();
     }
+
+    #[test]
+    fn get_deadline_defaults_to_two_minutes() {
+        let deadline = |toml: &str| toml::from_str::<OAuthConfig>(toml).unwrap().get_deadline();
+        assert_eq!(deadline(""), Some(Duration::from_secs(120)), "default");
+        assert_eq!(
+            deadline("get_timeout = 30"),
+            Some(Duration::from_secs(30)),
+            "configured"
+        );
+        assert_eq!(deadline("get_timeout = 0"), None, "off");
+    }
+
+    #[test]
+    fn client_secrets_are_kept_per_normalized_host() {
+        assert_eq!(
+            client_secret_entry("GitLab.Example.com:443"),
+            "client_secret:gitlab.example.com"
+        );
+        assert_eq!(
+            client_secret_entry("https://git.example.com:8443"),
+            "client_secret:git.example.com:8443"
+        );
+
+        let cfg: OAuthConfig = toml::from_str(
+            r#"
+            [providers."GitLab.Example.com"]
+            type = "gitlab"
+            client_id = "id"
+            client_secret = "keyring"
+            "#,
+        )
+        .unwrap();
+        let cfg = validate_providers(cfg).unwrap();
+        let provider = &cfg.providers["GitLab.Example.com"];
+        assert!(provider.client_secret.is_none(), "the marker is no secret");
+        assert_eq!(
+            provider.secret_in_keyring.as_deref(),
+            Some("gitlab.example.com"),
+            "read from the keyring once a flow needs it"
+        );
+    }
+
+    #[test]
+    fn insecure_static_credentials_need_one_password() {
+        let path = std::env::temp_dir().join(format!("warden-static-{}", std::process::id()));
+        fs::write(&path, "from-file\n").unwrap();
+        let mut cfg: OAuthConfig = toml::from_str(&format!(
+            r#"
+            [insecure_static."Mirror.Internal"]
+            username = "ci"
+            password_file = {path:?}
+
+            [insecure_static."inline.internal"]
+            username = "ci"
+            password = "inline"
+
+            [insecure_static."both.internal"]
+            username = "ci"
+            password = "inline"
+            password_file = {path:?}
+            "#
+        ))
+        .unwrap();
+        cfg = validate_providers(cfg).unwrap();
+
+        let (key, mirror) = cfg.static_credential("mirror.internal").unwrap();
+        assert_eq!(key, "Mirror.Internal");
+        assert_eq!(mirror.password().unwrap().expose(), "from-file");
+        assert_eq!(
+            cfg.static_credential("inline.internal")
+                .unwrap()
+                .1
+                .password()
+                .unwrap()
+                .expose(),
+            "inline"
+        );
+        assert!(
+            cfg.static_credential("both.internal").is_none(),
+            "ambiguous ones are discarded"
+        );
+        fs::remove_file(&path).unwrap();
+    }
 }
@@ -0,0 +1,132 @@
+use std::collections::BTreeMap;
+use std::fs;
+
+use anyhow::{Context as _, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::config::LoadableConfig;
+use crate::config::persist::write_atomic;
+use crate::utils::state_dir;
+
+/// Usage statistics file in the state directory
+const STATS_FILE: &str = "stats.toml";
+
+/// How often and how recently a credential answered a Git request
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct Usage {
+    pub count: u64,
+    pub last_used: Option<DateTime<Utc>>,
+    /// Protocol of the last request, e.g. "https"
+    pub last_protocol: Option<String>,
+    /// Repository path of the last request, only sent by Git with
+    /// `credential.useHttpPath`
+    pub last_path: Option<String>,
+}
+
+/// Local, opt-in credential usage statistics keyed by host and credential.
+///
+/// Nothing in here ever leaves the machine, it only helps deciding which
+/// credentials and scopes are still needed.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct Stats {
+    #[serde(flatten)]
+    hosts: BTreeMap<String, BTreeMap<String, Usage>>,
+}
+
+impl LoadableConfig for Stats {
+    const KIND: &'static str = "stats";
+
+    fn load_raw() -> Result<Self> {
+        let path = state_dir()?.join(STATS_FILE);
+        match fs::read_to_string(&path) {
+            Ok(s) => toml::from_str(&s).context("Malformed usage statistics"),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err).with_context(|| format!("Failed to read {}", path.display())),
+        }
+    }
+}
+
+impl Stats {
+    /// Count a request answered with `credential` for `host`
+    pub fn record(&mut self, host: &str, credential: &str, protocol: &str, path: Option<&str>) {
+        let usage = self
+            .hosts
+            .entry(host.to_string())
+            .or_default()
+            .entry(credential.to_string())
+            .or_default();
+        usage.count += 1;
+        usage.last_used = Some(Utc::now());
+        usage.last_protocol = Some(protocol.to_string());
+        usage.last_path = path.map(str::to_string);
+    }
+
+    pub fn write(&self) -> Result<()> {
+        let path = state_dir()?.join(STATS_FILE);
+        let toml = toml::to_string(self).context("Failed to serialize usage statistics")?;
+        write_atomic(&path, &toml).context("Failed to write usage statistics")
+    }
+
+    /// Move the usage recorded for `old` to `new`, returning whether there
+    /// was any
+    pub fn rename_host(&mut self, old: &str, new: &str) -> bool {
+        let Some(usage) = self.hosts.remove(old) else {
+            return false;
+        };
+        self.hosts.entry(new.to_string()).or_default().extend(usage);
+        true
+    }
+
+    /// Delete the statistics file, returning whether there was one
+    pub fn remove() -> Result<bool> {
+        let path = state_dir()?.join(STATS_FILE);
+        match fs::remove_file(&path) {
+            Ok(()) => Ok(true),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(err) => Err(err).with_context(|| format!("Failed to remove {}", path.display())),
+        }
+    }
+
+    /// Usage of `credential` on `host`, if it was ever recorded
+    pub fn get(&self, host: &str, credential: &str) -> Option<&Usage> {
+        self.hosts.get(host)?.get(credential)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hosts.is_empty()
+    }
+
+    /// Hosts in alphabetical order, each with its credentials, most used first
+    pub fn iter_sorted(&self) -> impl Iterator<Item = (&str, Vec<(&str, &Usage)>)> {
+        self.hosts.iter().map(|(host, credentials)| {
+            let mut credentials: Vec<(&str, &Usage)> = credentials
+                .iter()
+                .map(|(name, usage)| (name.as_str(), usage))
+                .collect();
+            credentials.sort_by(|a, b| b.1.count.cmp(&a.1.count).then(a.0.cmp(b.0)));
+            (host.as_str(), credentials)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_counts_and_round_trips() {
+        let mut stats = Stats::default();
+        stats.record("github.com", "work", "https", None);
+        stats.record("github.com", "work", "https", Some("me/x.git"));
+        stats.record("github.com", "home", "https", None);
+
+        let parsed: Stats = toml::from_str(&toml::to_string(&stats).unwrap()).unwrap();
+        let (host, credentials) = parsed.iter_sorted().next().unwrap();
+        assert_eq!(host, "github.com");
+        assert_eq!(credentials[0].0, "work", "most used first");
+        assert_eq!(credentials[0].1.count, 2);
+        assert_eq!(credentials[0].1.last_path.as_deref(), Some("me/x.git"));
+        assert_eq!(credentials[1].1.count, 1);
+    }
+}
@@ -0,0 +1,246 @@
+//! Everything a command reads or writes besides its arguments.
+//!
+//! [`AppContext`] is built once in [`crate::cli::Command::run`] and handed to
+//! every command. The configuration files, the token store and the output
+//! sit behind traits, so tests can run commands against in-memory stand-ins.
+
+use std::io::{IsTerminal as _, Write as _, stderr, stdin, stdout};
+
+use anyhow::{Context as _, Result};
+use dialoguer::Confirm;
+
+use crate::coalesce;
+use crate::commands::common::CredentialPair;
+use crate::config::{Hosts, LoadableConfig, OAuthConfig, ProfileConfig};
+use crate::keyring::{
+    Token, erase_keyring_token, get_keyring_token, list_keyring_entries, store_keyring_token,
+};
+use crate::theme::InputTheme;
+
+/// Loads and saves one kind of configuration
+pub trait ConfigStore<T>: Send + Sync {
+    fn load(&self) -> Result<T>;
+    fn save(&self, config: &T) -> Result<()>;
+}
+
+/// Keeps the tokens of credentials
+pub trait TokenStore: Send + Sync {
+    fn get(&self, credential: &str, host: &str) -> Result<Token>;
+    fn store(&self, credential: &str, host: &str, token: &Token) -> Result<()>;
+    fn erase(&self, credential: &str, host: &str) -> Result<()>;
+    /// Every entry in the store, whether the hosts state knows it or not
+    fn list(&self) -> Result<Vec<CredentialPair>>;
+}
+
+/// Where machine-readable output goes and the user is asked
+pub trait Io: Send + Sync {
+    /// Write `text` to stdout as is
+    fn print(&self, text: &str);
+    /// Ask a yes/no question, `None` when there is no terminal to ask on
+    fn confirm(&self, prompt: &str) -> Result<Option<bool>>;
+}
+
+/// The configuration files in the config and state directories
+pub struct Files;
+
+impl<T: LoadableConfig> ConfigStore<T> for Files {
+    fn load(&self) -> Result<T> {
+        T::load()
+    }
+
+    fn save(&self, config: &T) -> Result<()> {
+        config.save()
+    }
+}
+
+/// The token store picked by [`crate::keyring::backend`]
+pub struct Keyring;
+
+impl TokenStore for Keyring {
+    fn get(&self, credential: &str, host: &str) -> Result<Token> {
+        get_keyring_token(credential, host)
+    }
+
+    fn store(&self, credential: &str, host: &str, token: &Token) -> Result<()> {
+        store_keyring_token(credential, host, token)
+    }
+
+    fn erase(&self, credential: &str, host: &str) -> Result<()> {
+        coalesce::forget();
+        erase_keyring_token(credential, host)
+    }
+
+    fn list(&self) -> Result<Vec<CredentialPair>> {
+        Ok(list_keyring_entries()?
+            .into_iter()
+            .map(|(credential, host)| CredentialPair::new(host, credential))
+            .collect())
+    }
+}
+
+/// The process' stdout
+pub struct Stdio;
+
+impl Io for Stdio {
+    fn print(&self, text: &str) {
+        let mut out = stdout().lock();
+        let _ = out.write_all(text.as_bytes());
+        let _ = out.flush();
+    }
+
+    fn confirm(&self, prompt: &str) -> Result<Option<bool>> {
+        if !stdin().is_terminal() || !stderr().is_terminal() {
+            return Ok(None);
+        }
+        let answer = Confirm::with_theme(&InputTheme::default())
+            .with_prompt(prompt)
+            .default(false)
+            .interact_opt()
+            .context("Failed to ask for confirmation")?;
+        Ok(Some(answer.unwrap_or(false)))
+    }
+}
+
+pub struct AppContext {
+    pub oauth: Box<dyn ConfigStore<OAuthConfig>>,
+    pub hosts: Box<dyn ConfigStore<Hosts>>,
+    pub profiles: Box<dyn ConfigStore<ProfileConfig>>,
+    pub token_store: Box<dyn TokenStore>,
+    pub io: Box<dyn Io>,
+}
+
+impl AppContext {
+    /// The real configuration files, token store and stdout
+    pub fn system() -> Self {
+        Self {
+            oauth: Box::new(Files),
+            hosts: Box::new(Files),
+            profiles: Box::new(Files),
+            token_store: Box::new(Keyring),
+            io: Box::new(Stdio),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod mock {
+    //! In-memory stand-ins for tests
+
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    use anyhow::{Context as _, Result, bail};
+    use zeroize::Zeroizing;
+
+    use super::{AppContext, ConfigStore, Io, TokenStore};
+    use crate::commands::common::CredentialPair;
+    use crate::config::{Hosts, OAuthConfig, ProfileConfig};
+    use crate::keyring::Token;
+
+    /// A configuration kept in memory, missing until saved or given
+    pub struct Memory<T>(Mutex<Option<T>>);
+
+    impl<T> Memory<T> {
+        pub const fn new(config: Option<T>) -> Self {
+            Self(Mutex::new(config))
+        }
+    }
+
+    impl<T: Clone + Send> ConfigStore<T> for Memory<T> {
+        fn load(&self) -> Result<T> {
+            self.0
+                .lock()
+                .expect("config lock poisoned")
+                .clone()
+                .context("No configuration")
+        }
+
+        fn save(&self, config: &T) -> Result<()> {
+            *self.0.lock().expect("config lock poisoned") = Some(config.clone());
+            Ok(())
+        }
+    }
+
+    /// Packed tokens by credential and host
+    #[derive(Default)]
+    pub struct Tokens(Mutex<HashMap<(String, String), Zeroizing<String>>>);
+
+    impl TokenStore for Tokens {
+        fn get(&self, credential: &str, host: &str) -> Result<Token> {
+            let packed = self
+                .0
+                .lock()
+                .expect("tokens lock poisoned")
+                .get(&(credential.to_string(), host.to_string()))
+                .cloned();
+            let Some(packed) = packed else {
+                bail!("No token for {credential} on {host}");
+            };
+            Token::from_string(&packed)
+        }
+
+        fn store(&self, credential: &str, host: &str, token: &Token) -> Result<()> {
+            self.0
+                .lock()
+                .expect("tokens lock poisoned")
+                .insert((credential.to_string(), host.to_string()), token.pack());
+            Ok(())
+        }
+
+        fn erase(&self, credential: &str, host: &str) -> Result<()> {
+            let removed = self
+                .0
+                .lock()
+                .expect("tokens lock poisoned")
+                .remove(&(credential.to_string(), host.to_string()));
+            if removed.is_none() {
+                bail!("No token for {credential} on {host}");
+            }
+            Ok(())
+        }
+
+        fn list(&self) -> Result<Vec<CredentialPair>> {
+            Ok(self
+                .0
+                .lock()
+                .expect("tokens lock poisoned")
+                .keys()
+                .map(|(credential, host)| CredentialPair::new(host.as_str(), credential.as_str()))
+                .collect())
+        }
+    }
+
+    /// Output collected in memory, shared between clones
+    #[derive(Clone, Default)]
+    pub struct Captured(Arc<Mutex<String>>);
+
+    impl Captured {
+        pub fn text(&self) -> String {
+            self.0.lock().expect("output lock poisoned").clone()
+        }
+    }
+
+    impl Io for Captured {
+        fn print(&self, text: &str) {
+            self.0.lock().expect("output lock poisoned").push_str(text);
+        }
+
+        fn confirm(&self, _prompt: &str) -> Result<Option<bool>> {
+            Ok(None)
+        }
+    }
+
+    /// A context with the given hosts state, default profiles, no OAuth
+    /// configuration and no tokens, and what it prints
+    pub fn context(hosts: Hosts) -> (AppContext, Captured) {
+        let output = Captured::default();
+        let ctx = AppContext {
+            oauth: Box::new(Memory::<OAuthConfig>::new(None)),
+            hosts: Box::new(Memory::new(Some(hosts))),
+            profiles: Box::new(Memory::new(Some(ProfileConfig::default()))),
+            token_store: Box::new(Tokens::default()),
+            io: Box::new(output.clone()),
+        };
+        (ctx, output)
+    }
+}
@@ -0,0 +1,382 @@
+//! Small clients for the REST APIs of GitHub, GitLab and Forgejo/Gitea,
+//! authenticated with the stored tokens.
+
+use anyhow::{Context as _, Result, bail};
+use reqwest::header::{ACCEPT, AUTHORIZATION, HeaderMap, USER_AGENT};
+use reqwest::{ClientBuilder, Method, StatusCode, redirect};
+use serde_json::{Value, json};
+use tracing::{debug, instrument};
+use url::form_urlencoded::byte_serialize;
+use zeroize::Zeroizing;
+
+use crate::commands::common::{CredentialPair, fresh_token};
+use crate::config::ProviderConfig;
+use crate::context::AppContext;
+use crate::keyring::Token;
+use crate::oauth::pinning::pinned;
+
+/// GitLab access level of Developers, the lowest one that may push
+const GITLAB_DEVELOPER: u64 = 30;
+
+/// The API flavours warden speaks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ForgeKind {
+    GitHub,
+    GitLab,
+    Forgejo,
+}
+
+impl ForgeKind {
+    /// The forge of `host`, from its provider type or for the well-known
+    /// hosts
+    pub fn detect(provider_type: Option<&str>, host: &str) -> Option<Self> {
+        match provider_type.map(str::to_lowercase).as_deref() {
+            Some("github") => Some(Self::GitHub),
+            Some("gitlab") => Some(Self::GitLab),
+            Some("forgejo" | "gitea") => Some(Self::Forgejo),
+            Some(_) | None => {
+                match host {
+                    "github.com" => Some(Self::GitHub),
+                    "gitlab.com" => Some(Self::GitLab),
+                    "codeberg.org" => Some(Self::Forgejo),
+                    _ => None,
+                }
+            },
+        }
+    }
+
+    /// Base URL of the REST API on `host`
+    fn api_base(self, host: &str) -> String {
+        match self {
+            Self::GitHub if host == "github.com" => "https://api.github.com".to_string(),
+            Self::GitHub => format!("https://{host}/api/v3"),
+            Self::GitLab => format!("https://{host}/api/v4"),
+            Self::Forgejo => format!("https://{host}/api/v1"),
+        }
+    }
+}
+
+/// A repository as reported by the forge
+#[derive(Debug, Clone)]
+pub struct Repo {
+    /// `owner/name`, with nested groups on GitLab
+    pub full_name: String,
+    pub private: bool,
+    /// Whether the authenticated user may push, if the forge said
+    pub can_push: Option<bool>,
+    /// HTTPS URL to clone from
+    pub clone_url: Option<String>,
+}
+
+/// Client for one forge host
+pub struct Forge {
+    pub kind: ForgeKind,
+    /// The credential whose token is used
+    pub pair: CredentialPair,
+    api: String,
+    token: Zeroizing<String>,
+    /// Scopes the provider reported granting at login
+    granted: Option<Vec<String>>,
+    http: reqwest::Client,
+}
+
+impl Forge {
+    /// Client for `host` using the token of the active credential, or `name`
+    pub async fn connect(ctx: &AppContext, host: &str, name: Option<&str>) -> Result<Self> {
+        let (pair, token) = fresh_token(ctx, host, name).await?;
+        let oauth_config = ctx.oauth.load().ok();
+        let provider = oauth_config
+            .as_ref()
+            .and_then(|cfg| cfg.find_provider(&pair.host))
+            .map(|(_, provider)| provider);
+        Self::with_token(pair, &token, provider)
+    }
+
+    /// Client for the host of `pair` using `token`, which needn't be stored.
+    /// The type and TLS pins of the host's `provider` apply.
+    pub fn with_token(
+        pair: CredentialPair,
+        token: &Token,
+        provider: Option<&ProviderConfig>,
+    ) -> Result<Self> {
+        let provider_type = provider.and_then(|provider| provider.provider_type.as_deref());
+        let Some(kind) = ForgeKind::detect(provider_type, &pair.host) else {
+            bail!(
+                "Unknown forge at {}, set the provider type to github, gitlab, forgejo or gitea",
+                pair.host
+            );
+        };
+        let builder = ClientBuilder::new().redirect(redirect::Policy::none());
+        let builder = match provider {
+            Some(provider) => pinned(builder, &provider.tls),
+            None => builder,
+        };
+        let http = builder.build().context("Failed to build HTTP client")?;
+        Ok(Self {
+            kind,
+            api: kind.api_base(&pair.host),
+            pair,
+            token: Zeroizing::new(token.access_token().to_string()),
+            granted: token.scopes.clone(),
+            http,
+        })
+    }
+
+    /// Send a request to the API, returning the status, headers and JSON body
+    #[instrument(skip(self, body))]
+    async fn request(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<&Value>,
+    ) -> Result<(StatusCode, HeaderMap, Value)> {
+        let url = format!("{}{path}", self.api);
+        let mut request = self
+            .http
+            .request(method, &url)
+            .header(AUTHORIZATION, format!("Bearer {}", self.token.as_str()))
+            .header(ACCEPT, "application/json")
+            .header(USER_AGENT, concat!("warden/", env!("CARGO_PKG_VERSION")));
+        if let Some(body) = body {
+            request = request.json(body);
+        }
+        let response = request
+            .send()
+            .await
+            .with_context(|| format!("Failed to reach {url}"))?;
+        let status = response.status();
+        let headers = response.headers().clone();
+        let text = response.text().await.unwrap_or_default();
+        debug!("{status} from {url}");
+        let json = serde_json::from_str(&text).unwrap_or(Value::Null);
+        Ok((status, headers, json))
+    }
+
+    /// Fail with the forge's message unless `status` is a success
+    fn check(status: StatusCode, json: &Value, what: &str) -> Result<()> {
+        if status.is_success() {
+            return Ok(());
+        }
+        let message = json
+            .get("message")
+            .or_else(|| json.get("error"))
+            .map(|m| m.as_str().map_or_else(|| m.to_string(), str::to_string))
+            .unwrap_or_default();
+        bail!("{what} failed with {status} {message}");
+    }
+
+    /// Path of a repository in the API
+    fn repo_path(&self, owner: &str, name: &str) -> String {
+        match self.kind {
+            ForgeKind::GitHub | ForgeKind::Forgejo => format!("/repos/{owner}/{name}"),
+            ForgeKind::GitLab => {
+                let id: String = byte_serialize(format!("{owner}/{name}").as_bytes()).collect();
+                format!("/projects/{id}")
+            },
+        }
+    }
+
+    /// The repository `owner/name`, `None` if it doesn't exist or isn't
+    /// visible to the user
+    pub async fn repo(&self, owner: &str, name: &str) -> Result<Option<Repo>> {
+        let (status, _, json) = self
+            .request(Method::GET, &self.repo_path(owner, name), None)
+            .await?;
+        if status == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        Self::check(status, &json, "Looking up the repository")?;
+        Ok(Some(self.parse_repo(&json)))
+    }
+
+    fn parse_repo(&self, json: &Value) -> Repo {
+        let str_at = |pointer: &str| json.pointer(pointer).and_then(Value::as_str);
+        match self.kind {
+            ForgeKind::GitHub | ForgeKind::Forgejo => {
+                Repo {
+                    full_name: str_at("/full_name").unwrap_or_default().to_string(),
+                    private: json
+                        .get("private")
+                        .and_then(Value::as_bool)
+                        .unwrap_or(false),
+                    can_push: json.pointer("/permissions/push").and_then(Value::as_bool),
+                    clone_url: str_at("/clone_url").map(str::to_string),
+                }
+            },
+            ForgeKind::GitLab => {
+                let levels =
+                    ["/permissions/project_access", "/permissions/group_access"].map(|p| {
+                        json.pointer(&format!("{p}/access_level"))
+                            .and_then(Value::as_u64)
+                    });
+                Repo {
+                    full_name: str_at("/path_with_namespace")
+                        .unwrap_or_default()
+                        .to_string(),
+                    private: str_at("/visibility") != Some("public"),
+                    can_push: json
+                        .get("permissions")
+                        .map(|_| levels.iter().flatten().any(|l| *l >= GITLAB_DEVELOPER)),
+                    clone_url: str_at("/http_url_to_repo").map(str::to_string),
+                }
+            },
+        }
+    }
+
+    /// Login of the user the token belongs to
+    pub async fn current_user(&self) -> Result<String> {
+        let (status, _, json) = self.request(Method::GET, "/user", None).await?;
+        Self::check(status, &json, "Looking up the user")?;
+        let key = match self.kind {
+            ForgeKind::GitHub | ForgeKind::Forgejo => "login",
+            ForgeKind::GitLab => "username",
+        };
+        json.get(key)
+            .and_then(Value::as_str)
+            .map(str::to_string)
+            .context("The forge didn't report the user's name")
+    }
+
+    /// Create the repository `owner/name`, under the user's own account when
+    /// `owner` is the user and in the organization or group `owner` otherwise
+    pub async fn create_repo(&self, owner: &str, name: &str, private: bool) -> Result<Repo> {
+        let personal = self.current_user().await?.eq_ignore_ascii_case(owner);
+        let (path, body) = match self.kind {
+            ForgeKind::GitHub | ForgeKind::Forgejo => {
+                let path = if personal {
+                    "/user/repos".to_string()
+                } else {
+                    format!("/orgs/{owner}/repos")
+                };
+                (path, json!({ "name": name, "private": private }))
+            },
+            ForgeKind::GitLab => {
+                let mut body = json!({
+                    "path": name,
+                    "name": name,
+                    "visibility": if private { "private" } else { "public" },
+                });
+                if !personal {
+                    body["namespace_id"] = self.namespace_id(owner).await?.into();
+                }
+                ("/projects".to_string(), body)
+            },
+        };
+        let (status, _, json) = self.request(Method::POST, &path, Some(&body)).await?;
+        Self::check(status, &json, "Creating the repository")?;
+        Ok(self.parse_repo(&json))
+    }
+
+    /// Fork `owner/name` to the user's account, or to the organization or
+    /// group `into`
+    pub async fn fork(&self, owner: &str, name: &str, into: Option<&str>) -> Result<Repo> {
+        let (path, body) = match self.kind {
+            ForgeKind::GitHub | ForgeKind::Forgejo => {
+                (
+                    format!("{}/forks", self.repo_path(owner, name)),
+                    into.map_or_else(|| json!({}), |org| json!({ "organization": org })),
+                )
+            },
+            ForgeKind::GitLab => {
+                (
+                    format!("{}/fork", self.repo_path(owner, name)),
+                    into.map_or_else(|| json!({}), |group| json!({ "namespace_path": group })),
+                )
+            },
+        };
+        let (status, _, json) = self.request(Method::POST, &path, Some(&body)).await?;
+        if status == StatusCode::NOT_FOUND {
+            bail!("{owner}/{name} doesn't exist or isn't visible");
+        }
+        Self::check(status, &json, "Forking the repository")?;
+        Ok(self.parse_repo(&json))
+    }
+
+    /// ID of the GitLab group at `path`
+    async fn namespace_id(&self, path: &str) -> Result<u64> {
+        let id: String = byte_serialize(path.as_bytes()).collect();
+        let (status, _, json) = self
+            .request(Method::GET, &format!("/namespaces/{id}"), None)
+            .await?;
+        if status == StatusCode::NOT_FOUND {
+            bail!("No group named {path}");
+        }
+        Self::check(status, &json, "Looking up the group")?;
+        json.get("id")
+            .and_then(Value::as_u64)
+            .context("The forge didn't report the group's ID")
+    }
+
+    /// Scopes of the token as GitHub reports them, or as granted at login
+    /// for the other forges, `None` when unknown
+    pub async fn token_scopes(&self) -> Result<Option<Vec<String>>> {
+        if self.kind != ForgeKind::GitHub {
+            return Ok(self.granted.clone());
+        }
+        let (status, headers, json) = self.request(Method::GET, "/user", None).await?;
+        Self::check(status, &json, "Looking up the user")?;
+        Ok(headers
+            .get("x-oauth-scopes")
+            .and_then(|v| v.to_str().ok())
+            .map(|v| {
+                v.split(',')
+                    .map(str::trim)
+                    .filter(|s| !s.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn forge(kind: ForgeKind) -> Forge {
+        Forge {
+            kind,
+            pair: CredentialPair::new("example.com", "test"),
+            api: kind.api_base("example.com"),
+            token: Zeroizing::new(String::new()),
+            granted: None,
+            http: reqwest::Client::new(),
+        }
+    }
+
+    #[test]
+    fn repos_parse_per_forge() {
+        let github = forge(ForgeKind::GitHub).parse_repo(&json!({
+            "full_name": "octo/cat",
+            "private": true,
+            "permissions": {"admin": false, "push": false, "pull": true},
+            "clone_url": "https://github.com/octo/cat.git",
+        }));
+        assert_eq!(github.full_name, "octo/cat", "github name");
+        assert!(github.private, "github visibility");
+        assert_eq!(github.can_push, Some(false), "github permissions");
+
+        let gitlab = forge(ForgeKind::GitLab).parse_repo(&json!({
+            "path_with_namespace": "group/sub/project",
+            "visibility": "internal",
+            "permissions": {"project_access": null, "group_access": {"access_level": 30}},
+            "http_url_to_repo": "https://gitlab.com/group/sub/project.git",
+        }));
+        assert_eq!(gitlab.full_name, "group/sub/project", "gitlab name");
+        assert!(gitlab.private, "internal is not public");
+        assert_eq!(gitlab.can_push, Some(true), "developers may push");
+    }
+
+    #[test]
+    fn gitlab_paths_are_encoded() {
+        assert_eq!(
+            forge(ForgeKind::GitLab).repo_path("group/sub", "project"),
+            "/projects/group%2Fsub%2Fproject",
+            "project paths are one segment"
+        );
+        assert_eq!(
+            ForgeKind::GitHub.api_base("ghe.example.com"),
+            "https://ghe.example.com/api/v3",
+            "enterprise API"
+        );
+    }
+}
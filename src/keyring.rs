@@ -1,223 +0,0 @@
-use core::fmt::Display;
-use std::cmp::min;
-use std::collections::HashMap;
-use std::env::consts::OS;
-
-use anyhow::{Context as _, Result};
-use chrono::{DateTime, Utc};
-use keyring_core::Entry;
-use serde::{Deserialize, Serialize};
-use tracing::{info, instrument};
-use zeroize::Zeroize;
-
-use crate::config::ProviderConfig;
-use crate::oauth::refresh_access_token;
-
-#[expect(clippy::struct_field_names, reason = "name is intended")]
-#[derive(Serialize, Deserialize, Clone)]
-pub struct Token {
-    access_token: String,
-    refresh_token: Option<String>,
-    pub expires_at: Option<DateTime<Utc>>,
-}
-
-impl Display for Token {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        if self.access_token.len() <= 4 {
-            write!(f, "{}", "*".repeat(self.access_token.len()))
-        } else {
-            write!(
-                f,
-                "{}{}",
-                &self.access_token[0..4],
-                "*".repeat(min(3, self.access_token.len() - 4))
-            )
-        }
-    }
-}
-
-impl Zeroize for Token {
-    fn zeroize(&mut self) {
-        self.access_token.zeroize();
-        if let Some(rt) = &mut self.refresh_token {
-            rt.zeroize();
-        }
-    }
-}
-
-impl Drop for Token {
-    fn drop(&mut self) {
-        self.zeroize();
-    }
-}
-
-impl Token {
-    pub const fn new(
-        access_token: String,
-        refresh_token: Option<String>,
-        expires_at: Option<DateTime<Utc>>,
-    ) -> Self {
-        Self {
-            access_token,
-            refresh_token,
-            expires_at,
-        }
-    }
-
-    pub fn access_token(&self) -> &str {
-        &self.access_token
-    }
-
-    /// Checks if the access token is expired and refreshes it if necessary.
-    /// Returns the access token if it is valid, or refreshes it and returns the
-    /// new token.
-    ///
-    /// Side effect: if the token is refreshed, the current instance is updated
-    /// with the new token.
-    #[instrument(skip(self, provider))]
-    pub async fn access_token_checked(&mut self, provider: &ProviderConfig) -> Result<&str> {
-        if self.is_expired() {
-            info!("Access token expired, refreshing...");
-            let new_token = refresh_access_token(provider, self)
-                .await
-                .context("Failed to refresh access token")?;
-            *self = new_token;
-        }
-        Ok(&self.access_token)
-    }
-
-    pub fn refresh_token(&self) -> Option<&str> {
-        self.refresh_token.as_deref()
-    }
-
-    pub fn is_expired(&self) -> bool {
-        self.expires_at.is_some_and(|expiry| expiry < Utc::now())
-    }
-
-    pub fn pack(&self) -> String {
-        serde_json::to_string(self)
-            .context("Failed to serialize token")
-            .unwrap()
-    }
-
-    pub fn from_string(s: &str) -> Result<Self> {
-        serde_json::from_str::<Self>(s).context("Failed to deserialize token")
-    }
-}
-
-fn set_keyring_store() -> Result<()> {
-    if keyring_core::get_default_store().is_some() {
-        return Ok(());
-    }
-    #[cfg(any(
-        target_os = "linux",
-        target_os = "freebsd",
-        target_os = "openbsd",
-        target_os = "netbsd",
-        target_os = "dragonfly"
-    ))]
-    keyring_core::set_default_store(
-        zbus_secret_service_keyring_store::Store::new()
-            .context("Failed to create keyring store")?,
-    );
-
-    #[cfg(target_os = "windows")]
-    keyring_core::set_default_store(
-        windows_native_keyring_store::Store::new().context("Failed to create keyring store")?,
-    );
-
-    #[cfg(any(target_os = "macos", target_os = "ios"))]
-    keyring_core::set_default_store(
-        apple_native_keyring_store::keychain::Store::new()
-            .context("Failed to create keyring store")?,
-    );
-
-    #[cfg(not(any(
-        target_os = "linux",
-        target_os = "freebsd",
-        target_os = "openbsd",
-        target_os = "netbsd",
-        target_os = "dragonfly",
-        target_os = "windows",
-        target_os = "macos",
-        target_os = "ios"
-    )))]
-    bail!("No keyring backend is implemented for platform '{OS}'.");
-
-    Ok(())
-}
-
-fn get_entry(credential: &str, host: &str) -> Result<Entry> {
-    set_keyring_store().context("Failed to set keyring store")?;
-    let entry = match OS {
-        "linux" | "freebsd" | "openbsd" | "netbsd" | "dragonfly" => {
-            Entry::new_with_modifiers(
-                format!("{}:{host}", env!("CARGO_PKG_NAME")).as_str(),
-                credential,
-                &HashMap::from([(
-                    "label",
-                    format!("{}:{credential}@{host}", env!("CARGO_PKG_NAME")).as_str(),
-                )]),
-            )?
-        },
-        "windows" => {
-            Entry::new_with_modifiers(
-                format!("{}:{host}", env!("CARGO_PKG_NAME")).as_str(),
-                credential,
-                &HashMap::from([(
-                    "target",
-                    format!("{}:{credential}@{host}", env!("CARGO_PKG_NAME")).as_str(),
-                )]),
-            )?
-        },
-        _ => {
-            Entry::new(
-                format!("{}:{host}", env!("CARGO_PKG_NAME")).as_str(),
-                credential,
-            )?
-        },
-    };
-    Ok(entry)
-}
-
-pub fn store_keyring_token(credential: &str, host: &str, token: &Token) -> Result<()> {
-    let entry = get_entry(credential, host)?;
-    entry
-        .set_password(&token.pack())
-        .context("Failed to set secret in keyring entry")?;
-
-    match OS {
-        "linux" | "freebsd" | "openbsd" | "netbsd" | "dragonfly" => {
-            // Remove label entry, it is only set in the first place to change the name
-            // of the entry in the keyring but unfortunately also shows up in the
-            // attributes
-            entry.update_attributes(&HashMap::from([(
-                "application",
-                format!("{} v{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")).as_str(),
-            )]))?;
-        },
-        "windows" => {
-            entry.update_attributes(&HashMap::from([(
-                "comment",
-                format!("{} v{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")).as_str(),
-            )]))?;
-        },
-        _ => {},
-    }
-
-    Ok(())
-}
-
-pub fn erase_keyring_token(credential: &str, host: &str) -> Result<()> {
-    let entry = get_entry(credential, host)?;
-    entry.delete_credential()?;
-    Ok(())
-}
-
-pub fn get_keyring_token(credential: &str, host: &str) -> Result<Token> {
-    let entry = get_entry(credential, host)?;
-    let secret = entry
-        .get_password()
-        .context("Failed to retrieve token from keyring")?;
-    Token::from_string(&secret)
-}
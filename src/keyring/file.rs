@@ -0,0 +1,144 @@
+//! Token store in a file, used where no keyring is available, e.g. in
+//! minimal containers without a D-Bus session.
+//!
+//! Tokens are kept in `tokens.json` in the state directory, readable only by
+//! the user. The file is not encrypted.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context as _, Result, bail};
+use zeroize::Zeroizing;
+
+use crate::commands::common::styled_note;
+use crate::config::persist::write_atomic_private;
+use crate::utils::state_dir;
+
+/// Packed tokens by `credential@host`
+type Tokens = BTreeMap<String, String>;
+
+pub fn path() -> Result<PathBuf> {
+    Ok(state_dir()?.join("tokens.json"))
+}
+
+pub fn store(credential: &str, host: &str, packed: &str) -> Result<()> {
+    let path = path()?;
+    if !path.exists() {
+        styled_note(format!(
+            "No keyring is available, tokens are stored in {}.",
+            path.display()
+        ));
+    }
+    store_at(&path, credential, host, packed)
+}
+
+pub fn get(credential: &str, host: &str) -> Result<Zeroizing<String>> {
+    get_at(&path()?, credential, host)
+}
+
+pub fn erase(credential: &str, host: &str) -> Result<()> {
+    erase_at(&path()?, credential, host)
+}
+
+/// The `(credential, host)` of every stored token
+pub fn entries() -> Result<Vec<(String, String)>> {
+    Ok(read(&path()?)?
+        .into_keys()
+        .filter_map(|key| split_key(&key))
+        .collect())
+}
+
+fn key(credential: &str, host: &str) -> String {
+    format!("{credential}@{host}")
+}
+
+/// Host names never contain an `@`, credential names might
+fn split_key(key: &str) -> Option<(String, String)> {
+    key.rsplit_once('@')
+        .map(|(credential, host)| (credential.to_string(), host.to_string()))
+}
+
+fn read(path: &Path) -> Result<Tokens> {
+    match fs::read_to_string(path) {
+        Ok(contents) => {
+            let contents = Zeroizing::new(contents);
+            serde_json::from_str(&contents)
+                .with_context(|| format!("Failed to parse {}", path.display()))
+        },
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(Tokens::new()),
+        Err(err) => Err(err).with_context(|| format!("Failed to read {}", path.display())),
+    }
+}
+
+fn write(path: &Path, tokens: &Tokens) -> Result<()> {
+    let contents =
+        Zeroizing::new(serde_json::to_string_pretty(tokens).context("Failed to serialize tokens")?);
+    write_atomic_private(path, &contents)
+}
+
+fn store_at(path: &Path, credential: &str, host: &str, packed: &str) -> Result<()> {
+    let mut tokens = read(path)?;
+    tokens.insert(key(credential, host), packed.to_string());
+    write(path, &tokens)
+}
+
+fn get_at(path: &Path, credential: &str, host: &str) -> Result<Zeroizing<String>> {
+    let mut tokens = read(path)?;
+    let Some(packed) = tokens.remove(&key(credential, host)) else {
+        bail!("No token for {credential} on {host} in {}", path.display());
+    };
+    Ok(Zeroizing::new(packed))
+}
+
+fn erase_at(path: &Path, credential: &str, host: &str) -> Result<()> {
+    let mut tokens = read(path)?;
+    if tokens.remove(&key(credential, host)).is_none() {
+        bail!("No token for {credential} on {host} in {}", path.display());
+    }
+    write(path, &tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_round_trip() {
+        let dir = std::env::temp_dir().join(format!("warden-tokens-{}", std::process::id()));
+        let path = dir.join("tokens.json");
+
+        store_at(&path, "alice", "github.com", "{\"a\":1}").unwrap();
+        store_at(&path, "bob", "github.com", "{\"b\":2}").unwrap();
+        assert_eq!(
+            get_at(&path, "alice", "github.com").unwrap().as_str(),
+            "{\"a\":1}",
+            "stored token"
+        );
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt as _;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600, "only the user may read tokens");
+        }
+
+        erase_at(&path, "alice", "github.com").unwrap();
+        assert!(get_at(&path, "alice", "github.com").is_err(), "erased");
+        assert!(get_at(&path, "bob", "github.com").is_ok(), "others kept");
+        assert!(
+            erase_at(&path, "alice", "github.com").is_err(),
+            "erased twice"
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn keys_split_at_the_last_at() {
+        assert_eq!(
+            split_key(&key("alice@corp", "github.com")),
+            Some(("alice@corp".into(), "github.com".into()))
+        );
+    }
+}
@@ -0,0 +1,188 @@
+//! Index of the entries warden keeps in the keyring.
+//!
+//! Most keyrings can't list their entries, so every store and erase is also
+//! recorded in `keyring-index.json` in the state directory, with a
+//! fingerprint of the secret. A checksum over the entries catches edits and
+//! truncated writes; a damaged index is not trusted for listing and is
+//! started over on the next change.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context as _, Result, bail};
+use ring::digest::{SHA256, digest};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::config::persist::write_atomic_private;
+use crate::utils::state_dir;
+
+/// Hex digits of the SHA-256 of a secret kept as its fingerprint
+const FINGERPRINT_LEN: usize = 16;
+
+/// A keyring entry as recorded in the index
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct IndexEntry {
+    pub credential: String,
+    pub host: String,
+    /// Start of the SHA-256 of the stored secret
+    pub fingerprint: String,
+}
+
+#[derive(Deserialize, Serialize)]
+struct Index {
+    entries: Vec<IndexEntry>,
+    /// SHA-256 of `entries` serialized as JSON
+    checksum: String,
+}
+
+pub fn path() -> Result<PathBuf> {
+    Ok(state_dir()?.join("keyring-index.json"))
+}
+
+/// Record that `secret` was stored for `credential` on `host`
+pub fn record(credential: &str, host: &str, secret: &str) -> Result<()> {
+    record_at(&path()?, credential, host, secret)
+}
+
+/// Record that the entry of `credential` on `host` is gone
+pub fn forget(credential: &str, host: &str) -> Result<()> {
+    forget_at(&path()?, credential, host)
+}
+
+/// The recorded entries, sorted by host and credential
+pub fn entries() -> Result<Vec<IndexEntry>> {
+    read(&path()?)
+}
+
+/// Short hex digest identifying `secret` without revealing it
+pub fn fingerprint(secret: &str) -> String {
+    hex(digest(&SHA256, secret.as_bytes()).as_ref())
+        .chars()
+        .take(FINGERPRINT_LEN)
+        .collect()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut out, byte| {
+        let _ = write!(out, "{byte:02x}");
+        out
+    })
+}
+
+fn checksum(entries: &[IndexEntry]) -> Result<String> {
+    let json = serde_json::to_string(entries).context("Failed to serialize keyring index")?;
+    Ok(hex(digest(&SHA256, json.as_bytes()).as_ref()))
+}
+
+fn read(path: &Path) -> Result<Vec<IndexEntry>> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => {
+            return Err(err).with_context(|| format!("Failed to read {}", path.display()));
+        },
+    };
+    let index: Index = serde_json::from_str(&contents)
+        .with_context(|| format!("Failed to parse {}", path.display()))?;
+    if checksum(&index.entries)? != index.checksum {
+        bail!(
+            "{} was changed outside of {}",
+            path.display(),
+            env!("CARGO_PKG_NAME")
+        );
+    }
+    Ok(index.entries)
+}
+
+/// The entries to update, dropping a damaged index
+fn read_for_update(path: &Path) -> Vec<IndexEntry> {
+    read(path).unwrap_or_else(|err| {
+        warn!("Starting the keyring index over: {err:#}");
+        Vec::new()
+    })
+}
+
+fn write(path: &Path, mut entries: Vec<IndexEntry>) -> Result<()> {
+    entries.sort_by(|a, b| {
+        a.host
+            .cmp(&b.host)
+            .then_with(|| a.credential.cmp(&b.credential))
+    });
+    let index = Index {
+        checksum: checksum(&entries)?,
+        entries,
+    };
+    let contents =
+        serde_json::to_string_pretty(&index).context("Failed to serialize keyring index")?;
+    write_atomic_private(path, &contents)
+}
+
+fn record_at(path: &Path, credential: &str, host: &str, secret: &str) -> Result<()> {
+    let mut entries = read_for_update(path);
+    entries.retain(|e| e.credential != credential || e.host != host);
+    entries.push(IndexEntry {
+        credential: credential.to_string(),
+        host: host.to_string(),
+        fingerprint: fingerprint(secret),
+    });
+    write(path, entries)
+}
+
+fn forget_at(path: &Path, credential: &str, host: &str) -> Result<()> {
+    let mut entries = read_for_update(path);
+    let before = entries.len();
+    entries.retain(|e| e.credential != credential || e.host != host);
+    if entries.len() == before {
+        return Ok(());
+    }
+    write(path, entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn index_follows_stores_and_erases() {
+        let dir = std::env::temp_dir().join(format!("warden-index-{}", std::process::id()));
+        let path = dir.join("keyring-index.json");
+
+        record_at(&path, "bob", "gitlab.com", "one").unwrap();
+        record_at(&path, "alice", "github.com", "two").unwrap();
+        record_at(&path, "bob", "gitlab.com", "three").unwrap();
+        let entries = read(&path).unwrap();
+        assert_eq!(
+            entries
+                .iter()
+                .map(|e| (e.credential.as_str(), e.host.as_str()))
+                .collect::<Vec<_>>(),
+            [("alice", "github.com"), ("bob", "gitlab.com")],
+            "sorted, one entry per credential"
+        );
+        assert_eq!(
+            entries[1].fingerprint,
+            fingerprint("three"),
+            "latest secret"
+        );
+        assert!(
+            !fs::read_to_string(&path).unwrap().contains("three"),
+            "secrets stay out of the index"
+        );
+
+        forget_at(&path, "alice", "github.com").unwrap();
+        assert_eq!(read(&path).unwrap().len(), 1, "forgotten");
+
+        let tampered = fs::read_to_string(&path)
+            .unwrap()
+            .replace("gitlab.com", "evil.example.com");
+        fs::write(&path, tampered).unwrap();
+        assert!(read(&path).is_err(), "edits are detected");
+        record_at(&path, "carol", "codeberg.org", "four").unwrap();
+        assert_eq!(read(&path).unwrap().len(), 1, "damaged index started over");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,534 @@
+use core::fmt::{Debug, Display};
+use std::cmp::min;
+use std::collections::HashMap;
+use std::env::consts::OS;
+use std::sync::OnceLock;
+
+use anyhow::{Context as _, Result, bail};
+use chrono::{DateTime, TimeDelta, Utc};
+use keyring_core::Entry;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tracing::{debug, info, instrument, warn};
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::commands::common::styled_warning;
+use crate::config::{ProviderConfig, TokenDisplay};
+use crate::oauth::refresh_access_token;
+
+mod file;
+pub mod index;
+
+/// Host of the keyring entries holding warden's own state instead of a
+/// token; parentheses never appear in host names
+pub const STATE_HOST: &str = "(state)";
+
+/// Mixed into the fingerprints of tokens so they don't match plain SHA-256
+/// digests of the token published elsewhere
+const FINGERPRINT_SALT: &str = "warden token fingerprint:";
+/// Hex digits of the fingerprint tokens are shown as
+const DISPLAY_FINGERPRINT_LEN: usize = 8;
+
+/// Overrides where tokens are stored: `keyring`, `file` or `auto`
+pub const TOKEN_STORE_ENV: &str = "WARDEN_TOKEN_STORE";
+
+static BACKEND: OnceLock<Backend> = OnceLock::new();
+
+/// Where tokens are stored
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// The platform's keyring: Secret Service, Credential Manager or Keychain
+    Keyring,
+    /// `tokens.json` in the state directory, readable only by the user
+    File,
+}
+
+impl Display for Backend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Keyring => write!(f, "keyring"),
+            Self::File => write!(f, "file"),
+        }
+    }
+}
+
+#[expect(clippy::struct_field_names, reason = "name is intended")]
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Token {
+    access_token: String,
+    refresh_token: Option<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+    /// When the refresh token stops working, if the provider reported it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refresh_expires_at: Option<DateTime<Utc>>,
+    /// Scopes the provider reported granting, if it did
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scopes: Option<Vec<String>>,
+    /// When an ephemeral login ends, the token is erased instead of served
+    /// after it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deadline: Option<DateTime<Utc>>,
+    /// Provider specific fields of the token response, e.g. GitHub's
+    /// `refresh_token_expires_in`
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub extra: Map<String, Value>,
+}
+
+impl Display for Token {
+    /// The fingerprint, which identifies the token without revealing any of
+    /// it
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let salted = Zeroizing::new(format!("{FINGERPRINT_SALT}{}", self.access_token));
+        let digest = index::fingerprint(&salted);
+        write!(f, "sha256:{}", &digest[..DISPLAY_FINGERPRINT_LEN])
+    }
+}
+
+impl Debug for Token {
+    /// The fields besides the secrets, which are shown as the fingerprint or
+    /// left out
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Token")
+            .field("fingerprint", &format_args!("{self}"))
+            .field("has_refresh_token", &self.refresh_token.is_some())
+            .field("expires_at", &self.expires_at)
+            .field("refresh_expires_at", &self.refresh_expires_at)
+            .field("scopes", &self.scopes)
+            .field("deadline", &self.deadline)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Zeroize for Token {
+    fn zeroize(&mut self) {
+        self.access_token.zeroize();
+        if let Some(rt) = &mut self.refresh_token {
+            rt.zeroize();
+        }
+    }
+}
+
+impl Drop for Token {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
+impl Token {
+    pub fn new(
+        access_token: String,
+        refresh_token: Option<String>,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Self {
+        Self {
+            access_token,
+            refresh_token,
+            expires_at,
+            refresh_expires_at: None,
+            scopes: None,
+            deadline: None,
+            extra: Map::new(),
+        }
+    }
+
+    #[must_use]
+    pub const fn with_refresh_expiry(mut self, refresh_expires_at: Option<DateTime<Utc>>) -> Self {
+        self.refresh_expires_at = refresh_expires_at;
+        self
+    }
+
+    #[must_use]
+    pub fn with_scopes(mut self, scopes: Option<Vec<String>>) -> Self {
+        self.scopes = scopes;
+        self
+    }
+
+    #[must_use]
+    pub fn with_extra(mut self, extra: Map<String, Value>) -> Self {
+        self.extra = extra;
+        self
+    }
+
+    #[must_use]
+    pub const fn with_deadline(mut self, deadline: Option<DateTime<Utc>>) -> Self {
+        self.deadline = deadline;
+        self
+    }
+
+    /// Keep what refreshing `previous` didn't renew: its refresh token and
+    /// that one's expiry when the provider returned no new one, its scopes
+    /// when the provider didn't report any, and its deadline
+    pub fn keep_unrenewed(&mut self, previous: &Self) {
+        if self.refresh_token.is_none() {
+            self.refresh_token.clone_from(&previous.refresh_token);
+            self.refresh_expires_at = previous.refresh_expires_at;
+        }
+        if self.scopes.is_none() {
+            self.scopes.clone_from(&previous.scopes);
+        }
+        self.deadline = previous.deadline;
+    }
+
+    /// Keep what Git doesn't hand back when it stores a token it got from
+    /// warden: the deadline, scopes, refresh token expiry and extra fields of
+    /// `previous`, and its refresh token when Git sent none
+    pub fn keep_login(&mut self, previous: &Self) {
+        if self.refresh_token.is_none() {
+            self.refresh_token.clone_from(&previous.refresh_token);
+        }
+        self.refresh_expires_at = previous.refresh_expires_at;
+        self.scopes.clone_from(&previous.scopes);
+        self.deadline = previous.deadline;
+        self.extra.clone_from(&previous.extra);
+    }
+
+    pub fn access_token(&self) -> &str {
+        &self.access_token
+    }
+
+    /// Checks if the access token is expired and refreshes it if necessary.
+    /// Returns the access token if it is valid, or refreshes it and returns the
+    /// new token.
+    ///
+    /// Side effect: if the token is refreshed, the current instance is updated
+    /// with the new token.
+    #[instrument(skip(self, provider))]
+    pub async fn access_token_checked(&mut self, provider: &ProviderConfig) -> Result<&str> {
+        if self.is_expired() {
+            if !provider.refreshes() {
+                bail!("Access token expired and refreshing is disabled for its provider");
+            }
+            info!("Access token expired, refreshing...");
+            let new_token = refresh_access_token(provider, self)
+                .await
+                .context("Failed to refresh access token")?;
+            *self = new_token;
+        }
+        Ok(&self.access_token)
+    }
+
+    pub fn refresh_token(&self) -> Option<&str> {
+        self.refresh_token.as_deref()
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at.is_some_and(|expiry| expiry < Utc::now())
+    }
+
+    /// Whether the token belongs to an ephemeral login that has ended
+    pub fn is_past_deadline(&self) -> bool {
+        self.deadline.is_some_and(|deadline| deadline <= Utc::now())
+    }
+
+    /// When Git should stop caching the token: its expiry or, if earlier, the
+    /// deadline
+    pub fn expiry_for_git(&self) -> Option<DateTime<Utc>> {
+        match (self.expires_at, self.deadline) {
+            (Some(expiry), Some(deadline)) => Some(expiry.min(deadline)),
+            (expiry, deadline) => expiry.or(deadline),
+        }
+    }
+
+    /// Time left until the refresh token expires, negative once it has
+    pub fn refresh_expires_in(&self) -> Option<TimeDelta> {
+        self.refresh_token.as_ref()?;
+        self.refresh_expires_at.map(|expiry| expiry - Utc::now())
+    }
+
+    /// The token as stored, zeroized when dropped as it holds the secrets
+    pub fn pack(&self) -> Zeroizing<String> {
+        Zeroizing::new(serde_json::to_string(self).expect("Token serializes"))
+    }
+
+    /// The token as shown to the user, its fingerprint or with `Prefix` its
+    /// first characters
+    pub fn masked(&self, display: TokenDisplay) -> String {
+        match display {
+            TokenDisplay::Fingerprint => self.to_string(),
+            TokenDisplay::Prefix if self.access_token.len() <= 4 => {
+                "*".repeat(self.access_token.len())
+            },
+            TokenDisplay::Prefix => {
+                format!(
+                    "{}{}",
+                    &self.access_token[0..4],
+                    "*".repeat(min(3, self.access_token.len() - 4))
+                )
+            },
+        }
+    }
+
+    /// Fingerprint of everything stored for the token, to compare copies
+    /// without revealing them
+    pub fn fingerprint(&self) -> String {
+        index::fingerprint(&self.pack())
+    }
+
+    pub fn from_string(s: &str) -> Result<Self> {
+        serde_json::from_str::<Self>(s).context("Failed to deserialize token")
+    }
+}
+
+fn set_keyring_store() -> Result<()> {
+    if keyring_core::get_default_store().is_some() {
+        return Ok(());
+    }
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "freebsd",
+        target_os = "openbsd",
+        target_os = "netbsd",
+        target_os = "dragonfly"
+    ))]
+    keyring_core::set_default_store(
+        zbus_secret_service_keyring_store::Store::new()
+            .context("Failed to create keyring store")?,
+    );
+
+    #[cfg(target_os = "windows")]
+    keyring_core::set_default_store(
+        windows_native_keyring_store::Store::new().context("Failed to create keyring store")?,
+    );
+
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    keyring_core::set_default_store(
+        apple_native_keyring_store::keychain::Store::new()
+            .context("Failed to create keyring store")?,
+    );
+
+    #[cfg(not(any(
+        target_os = "linux",
+        target_os = "freebsd",
+        target_os = "openbsd",
+        target_os = "netbsd",
+        target_os = "dragonfly",
+        target_os = "windows",
+        target_os = "macos",
+        target_os = "ios"
+    )))]
+    bail!("No keyring backend is implemented for platform '{OS}'.");
+
+    Ok(())
+}
+
+/// The token store of this process, chosen once at runtime from
+/// [`TOKEN_STORE_ENV`] or what the system offers
+pub fn backend() -> Backend {
+    *BACKEND.get_or_init(|| {
+        let backend = match std::env::var(TOKEN_STORE_ENV)
+            .unwrap_or_default()
+            .to_ascii_lowercase()
+            .as_str()
+        {
+            "keyring" => Backend::Keyring,
+            "file" => Backend::File,
+            "" | "auto" => {
+                if keyring_available() {
+                    Backend::Keyring
+                } else {
+                    Backend::File
+                }
+            },
+            // a typo must not put tokens into a plain file unnoticed
+            other => {
+                styled_warning(format!(
+                    "Unknown {TOKEN_STORE_ENV} \"{other}\", use keyring, file or auto. Storing \
+                     tokens in the keyring."
+                ));
+                Backend::Keyring
+            },
+        };
+        debug!("Storing tokens in the {backend}");
+        backend
+    })
+}
+
+/// Whether the platform's keyring can be reached. The Secret Service is only
+/// there with a D-Bus session bus, which minimal containers and static
+/// builds on servers usually lack.
+fn keyring_available() -> bool {
+    match OS {
+        "linux" | "freebsd" | "openbsd" | "netbsd" | "dragonfly" => {
+            std::env::var_os("DBUS_SESSION_BUS_ADDRESS").is_some_and(|addr| !addr.is_empty())
+                || std::env::var_os("XDG_RUNTIME_DIR")
+                    .is_some_and(|dir| std::path::Path::new(&dir).join("bus").exists())
+        },
+        "windows" | "macos" | "ios" => true,
+        _ => false,
+    }
+}
+
+fn get_entry(credential: &str, host: &str) -> Result<Entry> {
+    set_keyring_store().context("Failed to set keyring store")?;
+    let entry = match OS {
+        "linux" | "freebsd" | "openbsd" | "netbsd" | "dragonfly" => {
+            Entry::new_with_modifiers(
+                format!("{}:{host}", env!("CARGO_PKG_NAME")).as_str(),
+                credential,
+                &HashMap::from([(
+                    "label",
+                    format!("{}:{credential}@{host}", env!("CARGO_PKG_NAME")).as_str(),
+                )]),
+            )?
+        },
+        "windows" => {
+            Entry::new_with_modifiers(
+                format!("{}:{host}", env!("CARGO_PKG_NAME")).as_str(),
+                credential,
+                &HashMap::from([(
+                    "target",
+                    format!("{}:{credential}@{host}", env!("CARGO_PKG_NAME")).as_str(),
+                )]),
+            )?
+        },
+        _ => {
+            Entry::new(
+                format!("{}:{host}", env!("CARGO_PKG_NAME")).as_str(),
+                credential,
+            )?
+        },
+    };
+    Ok(entry)
+}
+
+pub fn store_keyring_token(credential: &str, host: &str, token: &Token) -> Result<()> {
+    store_keyring_secret(credential, host, &token.pack())
+}
+
+pub fn erase_keyring_token(credential: &str, host: &str) -> Result<()> {
+    erase_keyring_secret(credential, host)
+}
+
+pub fn get_keyring_token(credential: &str, host: &str) -> Result<Token> {
+    Token::from_string(&get_keyring_secret(credential, host)?)
+}
+
+/// Store `secret` in the entry of `credential` on `host`. Besides tokens,
+/// warden keeps its own state under [`STATE_HOST`].
+pub fn store_keyring_secret(credential: &str, host: &str, secret: &str) -> Result<()> {
+    if backend() == Backend::File {
+        return file::store(credential, host, secret);
+    }
+    let entry = get_entry(credential, host)?;
+    entry
+        .set_password(secret)
+        .context("Failed to set secret in keyring entry")?;
+
+    match OS {
+        "linux" | "freebsd" | "openbsd" | "netbsd" | "dragonfly" => {
+            // Remove label entry, it is only set in the first place to change the name
+            // of the entry in the keyring but unfortunately also shows up in the
+            // attributes
+            entry.update_attributes(&HashMap::from([(
+                "application",
+                format!("{} v{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")).as_str(),
+            )]))?;
+        },
+        "windows" => {
+            entry.update_attributes(&HashMap::from([(
+                "comment",
+                format!("{} v{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")).as_str(),
+            )]))?;
+        },
+        _ => {},
+    }
+
+    if let Err(err) = index::record(credential, host, secret) {
+        warn!("Failed to update the keyring index: {err:#}");
+    }
+    Ok(())
+}
+
+pub fn erase_keyring_secret(credential: &str, host: &str) -> Result<()> {
+    if backend() == Backend::File {
+        return file::erase(credential, host);
+    }
+    let entry = get_entry(credential, host)?;
+    let result = entry.delete_credential();
+    if matches!(result, Ok(()) | Err(keyring_core::Error::NoEntry))
+        && let Err(err) = index::forget(credential, host)
+    {
+        warn!("Failed to update the keyring index: {err:#}");
+    }
+    result?;
+    Ok(())
+}
+
+pub fn get_keyring_secret(credential: &str, host: &str) -> Result<Zeroizing<String>> {
+    if backend() == Backend::File {
+        return file::get(credential, host);
+    }
+    let entry = get_entry(credential, host)?;
+    let secret = entry
+        .get_password()
+        .context("Failed to retrieve token from keyring")?;
+    Ok(Zeroizing::new(secret))
+}
+
+/// The `(credential, host)` of every entry warden keeps in the token store,
+/// including its own state under [`STATE_HOST`], sorted. Keyrings that can
+/// list their entries are asked, the others are covered by the [`index`].
+pub fn list_keyring_entries() -> Result<Vec<(String, String)>> {
+    if backend() == Backend::File {
+        return file::entries();
+    }
+    set_keyring_store().context("Failed to set keyring store")?;
+    let mut entries: Vec<(String, String)> = index::entries()
+        .context("Failed to read the keyring index")?
+        .into_iter()
+        .map(|entry| (entry.credential, entry.host))
+        .collect();
+    let prefix = format!("{}:", env!("CARGO_PKG_NAME"));
+    match Entry::search(&HashMap::new()) {
+        Ok(found) => {
+            entries.extend(found.iter().filter_map(Entry::get_specifiers).filter_map(
+                |(service, credential)| {
+                    service
+                        .strip_prefix(&prefix)
+                        .map(|host| (credential, host.to_string()))
+                },
+            ));
+        },
+        Err(keyring_core::Error::NotSupportedByStore(_)) => {
+            debug!("The keyring can't list its entries, using the index only");
+        },
+        Err(err) => return Err(err).context("Failed to list keyring entries"),
+    }
+    entries.sort_by(|a, b| a.1.cmp(&b.1).then_with(|| a.0.cmp(&b.0)));
+    entries.dedup();
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_are_shown_as_fingerprints() {
+        let token = Token::new("gho_secret".into(), None, None);
+        let shown = token.to_string();
+        assert!(shown.starts_with("sha256:"), "{shown}");
+        assert_eq!(shown.len(), "sha256:".len() + DISPLAY_FINGERPRINT_LEN);
+        assert_ne!(
+            shown[7..],
+            index::fingerprint("gho_secret")[..DISPLAY_FINGERPRINT_LEN],
+            "salted"
+        );
+        assert_eq!(
+            shown,
+            Token::new("gho_secret".into(), Some("refresh".into()), None).to_string(),
+            "same access token"
+        );
+        assert_ne!(
+            shown,
+            Token::new("gho_other".into(), None, None).to_string()
+        );
+
+        assert_eq!(token.masked(TokenDisplay::Fingerprint), shown);
+        assert_eq!(token.masked(TokenDisplay::Prefix), "gho_***");
+        assert_eq!(
+            Token::new("abc".into(), None, None).masked(TokenDisplay::Prefix),
+            "***"
+        );
+    }
+}
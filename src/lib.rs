@@ -0,0 +1,66 @@
+#![cfg_attr(doc, doc = include_str!("../README.md"))]
+
+use std::env;
+
+use anyhow::{Context as _, Result};
+use tracing_subscriber::layer::SubscriberExt as _;
+use tracing_subscriber::util::SubscriberInitExt as _;
+use tracing_subscriber::{EnvFilter, fmt, registry};
+
+use crate::cli::Cli;
+use crate::context::AppContext;
+
+pub mod bench;
+mod cli;
+mod coalesce;
+mod commands;
+mod config;
+mod context;
+mod forge;
+mod keyring;
+mod oauth;
+mod output;
+mod profile;
+mod secret;
+mod signal;
+mod table;
+mod theme;
+mod trace_io;
+mod transfer;
+mod utils;
+
+/// Run the command line `warden` was started with
+pub async fn run() -> Result<()> {
+    let cli = Cli::parse_args();
+
+    registry()
+        .with(
+            EnvFilter::builder()
+                .with_default_directive(cli.verbosity.tracing_level_filter().into())
+                .from_env_lossy(),
+        )
+        .with(fmt::layer().with_writer(std::io::stderr))
+        .init();
+
+    signal::install();
+    coalesce::prune_expired();
+    if let Some(path) = &cli.trace_io {
+        trace_io::open(path)?;
+    }
+    if let Some(dir) = &cli.cwd {
+        env::set_current_dir(dir)
+            .with_context(|| format!("Failed to change to {}", dir.display()))?;
+    }
+    let result = match cli.command {
+        Some(command) => command.run(cli.device).await,
+        None => commands::version::version(&AppContext::system(), cli.json),
+    };
+    if let Err(err) = result {
+        trace_io::note(&format!("Error: {err:#}"));
+        if signal::is_interrupted(&err) {
+            std::process::exit(signal::exit_code());
+        }
+        return Err(err);
+    }
+    Ok(())
+}
@@ -3,14 +3,12 @@ use std::fmt::Write as _;
 use std::time::Duration;
 
 use anyhow::{Context as _, Result, anyhow};
-use chrono::Utc;
 use colored::Colorize as _;
-use oauth2::basic::BasicClient;
 use oauth2::{
-    AuthUrl, AuthorizationCode, ClientId, ClientSecret, CsrfToken, PkceCodeChallenge, RedirectUrl,
-    Scope, TokenResponse as _, TokenUrl,
+    AuthType, AuthUrl, AuthorizationCode, ClientId, ClientSecret, CsrfToken, PkceCodeChallenge,
+    RedirectUrl, Scope, TokenUrl,
 };
-use reqwest::{ClientBuilder, Url, redirect};
+use reqwest::Url;
 use tokio::io::{AsyncBufReadExt as _, AsyncWriteExt, BufReader};
 use tokio::net::TcpListener;
 use tokio::time::{Instant, sleep};
@@ -18,6 +16,9 @@ use tracing::{error, instrument};
 
 use crate::config::{OAuthConfig, ProviderConfig};
 use crate::keyring::Token;
+use crate::oauth::clock::DatedClient;
+use crate::oauth::progress::Progress;
+use crate::oauth::{OAuthClient, token_from_response};
 
 /// Performs `OAuth2` Authorization Code flow with PKCE to obtain an access
 /// token.
@@ -28,34 +29,38 @@ pub async fn exchange_auth_code_pkce(
 ) -> Result<Token> {
     let (listener, redirect_addr) = bind_listener(config).await?;
 
-    let mut oauth_client = BasicClient::new(ClientId::new(provider.client_id.clone()))
+    let mut oauth_client = OAuthClient::new(ClientId::new(provider.client_id.clone()))
         .set_auth_uri(AuthUrl::new(provider.auth_url.clone())?)
         .set_token_uri(TokenUrl::new(provider.token_url.clone())?)
-        .set_redirect_uri(RedirectUrl::new(redirect_addr.clone())?);
+        .set_redirect_uri(RedirectUrl::new(redirect_addr.clone())?)
+        .set_auth_type(provider.auth_type(AuthType::BasicAuth));
 
-    if let Some(secret) = &provider.client_secret {
-        oauth_client = oauth_client.set_client_secret(ClientSecret::new(secret.clone()));
+    if let Some(secret) = provider.client_secret()? {
+        oauth_client =
+            oauth_client.set_client_secret(ClientSecret::new(secret.expose().to_string()));
     }
 
-    let http_client = ClientBuilder::new()
-        // following redirects opens the client up to SSRF vulnerabilities
-        .redirect(redirect::Policy::none())
-        .build()
-        .context("Failed to build HTTP client")?;
+    let http_client = DatedClient::new(&provider.tls);
 
     let (pkce_challenge, pkce_verifier) = PkceCodeChallenge::new_random_sha256();
 
-    let mut auth_req = oauth_client.authorize_url(CsrfToken::new_random);
-    if let Some(scopes) = &provider.scopes
-        && !scopes.is_empty()
-    {
-        for s in scopes {
-            auth_req = auth_req.add_scope(Scope::new(s.clone()));
-        }
-    }
+    let auth_req = oauth_client.authorize_url(CsrfToken::new_random);
+    let auth_req = match provider.scopes.requested() {
+        Some([]) => auth_req.add_extra_param("scope", ""),
+        Some(scopes) => auth_req.add_scopes(scopes.iter().cloned().map(Scope::new)),
+        None => auth_req,
+    };
+    let auth_req = provider
+        .auth_params
+        .iter()
+        .fold(auth_req, |req, (key, value)| {
+            req.add_extra_param(key, value)
+        });
     let (authorize_url, csrf_state) = auth_req.set_pkce_challenge(pkce_challenge).url();
 
-    let (code, returned_state) = wait_for_code(&listener, &redirect_addr, &authorize_url).await?;
+    open_browser(&authorize_url);
+    let progress = Progress::start("Waiting for authorization in the browser");
+    let (code, returned_state) = wait_for_code(&listener, &redirect_addr).await?;
 
     if !constant_time_eq::constant_time_eq(
         returned_state.secret().as_bytes(),
@@ -63,6 +68,7 @@ pub async fn exchange_auth_code_pkce(
     ) {
         return Err(anyhow!("CSRF token mismatch")).context("State validation failed");
     }
+    progress.next("Exchanging the code for a token");
 
     let token_res = oauth_client
         .exchange_code(code)
@@ -76,13 +82,9 @@ pub async fn exchange_auth_code_pkce(
             return Err(anyhow!(err)).context("Failed to exchange code");
         },
     };
-    let expires_at = token.expires_in().map(|d| Utc::now() + d);
-    let token = Token::new(
-        token.access_token().secret().clone(),
-        token.refresh_token().map(|rt| rt.secret().clone()),
-        expires_at,
-    );
-    Ok(token)
+    http_client.check_drift(&provider.token_url);
+    progress.finish();
+    Ok(token_from_response(&token, provider.expiry_margin()))
 }
 
 /// Bind a local TCP listener on the configured (or ephemeral) port, retrying
@@ -109,18 +111,8 @@ async fn bind_listener(config: &OAuthConfig) -> Result<(TcpListener, String)> {
     Ok((listener, redirect_addr))
 }
 
-/// Open the user's browser (best-effort) and wait for the redirect, capturing
-/// the authorization code.
-///
-/// Emits a minimal HTTP response so the user can close the browser tab.
-/// Returns the `AuthorizationCode` and the `CsrfToken` returned by the
-/// provider.
-#[instrument(skip(listener))]
-async fn wait_for_code(
-    listener: &TcpListener,
-    redirect_addr: &str,
-    authorize_url: &oauth2::url::Url,
-) -> Result<(AuthorizationCode, CsrfToken)> {
+/// Open the user's browser (best-effort), or ask them to open the URL.
+fn open_browser(authorize_url: &oauth2::url::Url) {
     match open::that_detached(authorize_url.to_string()) {
         Ok(()) => {
             eprintln!("Beep Boop! Check your browser for authorization");
@@ -133,7 +125,18 @@ async fn wait_for_code(
             );
         },
     }
+}
 
+/// Wait for the redirect, capturing the authorization code.
+///
+/// Emits a minimal HTTP response so the user can close the browser tab.
+/// Returns the `AuthorizationCode` and the `CsrfToken` returned by the
+/// provider.
+#[instrument(skip(listener))]
+async fn wait_for_code(
+    listener: &TcpListener,
+    redirect_addr: &str,
+) -> Result<(AuthorizationCode, CsrfToken)> {
     loop {
         if let Ok((mut stream, _)) = listener.accept().await {
             let mut reader = BufReader::new(&mut stream);
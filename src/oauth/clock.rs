@@ -0,0 +1,127 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Mutex;
+
+use chrono::{DateTime, TimeDelta, Utc};
+use colored::Colorize as _;
+use oauth2::http::HeaderMap;
+use oauth2::http::header::DATE;
+use oauth2::{AsyncHttpClient, HttpClientError, HttpRequest, HttpResponse};
+use reqwest::{ClientBuilder, Url, redirect};
+use tracing::debug;
+
+use crate::commands::common::{human_duration, styled_warning};
+use crate::config::provider::TlsConfig;
+use crate::oauth::pinning::pinned;
+
+/// Difference between the local and a server's clock above which a warning
+/// is shown
+const DRIFT_WARNING: TimeDelta = TimeDelta::minutes(5);
+
+/// HTTP client for token requests that remembers the `Date` of the last
+/// response, to tell how far the local clock is off.
+pub struct DatedClient {
+    client: reqwest::Client,
+    date: Mutex<Option<DateTime<Utc>>>,
+}
+
+impl DatedClient {
+    /// A client for the provider with the TLS settings `tls`
+    pub fn new(tls: &TlsConfig) -> Self {
+        let builder = ClientBuilder::new()
+            // following redirects opens the client up to SSRF vulnerabilities
+            .redirect(redirect::Policy::none());
+        let client = pinned(builder, tls).build().expect("Client should build");
+        Self {
+            client,
+            date: Mutex::new(None),
+        }
+    }
+
+    /// The underlying client, for requests made outside of oauth2
+    pub const fn inner(&self) -> &reqwest::Client {
+        &self.client
+    }
+
+    /// Record the `Date` header of a response received outside of oauth2
+    pub fn observe(&self, headers: &HeaderMap) {
+        if let Some(date) = server_date(headers) {
+            *self.date.lock().expect("Date lock poisoned") = Some(date);
+        }
+    }
+
+    /// Warn when the `Date` of the last response from `url` is more than
+    /// [`DRIFT_WARNING`] away from the local clock.
+    pub fn check_drift(&self, url: &str) {
+        let Some(date) = *self.date.lock().expect("Date lock poisoned") else {
+            return;
+        };
+        let host = Url::parse(url)
+            .ok()
+            .and_then(|u| u.host_str().map(str::to_string))
+            .unwrap_or_else(|| url.to_string());
+        let drift = Utc::now() - date;
+        debug!(
+            "Local clock differs from {host} by {}s",
+            drift.num_seconds()
+        );
+        if drift.abs() <= DRIFT_WARNING {
+            return;
+        }
+        let direction = if drift > TimeDelta::zero() {
+            "ahead of"
+        } else {
+            "behind"
+        };
+        styled_warning(format!(
+            "Your system clock is {} {direction} {}, tokens may be treated as expired too early \
+             or too late.",
+            human_duration(drift.abs()),
+            host.bold()
+        ));
+    }
+}
+
+impl<'c> AsyncHttpClient<'c> for DatedClient {
+    type Error = HttpClientError<reqwest::Error>;
+    type Future = Pin<Box<dyn Future<Output = Result<HttpResponse, Self::Error>> + Send + 'c>>;
+
+    fn call(&'c self, request: HttpRequest) -> Self::Future {
+        Box::pin(async move {
+            let response = self.client.call(request).await?;
+            self.observe(response.headers());
+            Ok(response)
+        })
+    }
+}
+
+/// Parse the HTTP `Date` header (RFC 7231 IMF-fixdate, a subset of RFC 2822)
+pub fn server_date(headers: &HeaderMap) -> Option<DateTime<Utc>> {
+    let value = headers.get(DATE)?.to_str().ok()?;
+    DateTime::parse_from_rfc2822(value)
+        .ok()
+        .map(|date| date.to_utc())
+}
+
+#[cfg(test)]
+mod tests {
+    use oauth2::http::HeaderValue;
+
+    use super::*;
+
+    #[test]
+    fn parses_http_date() {
+        let mut headers = HeaderMap::new();
+        assert!(server_date(&headers).is_none(), "no header");
+        headers.insert(
+            DATE,
+            HeaderValue::from_static("Sun, 06 Nov 1994 08:49:37 GMT"),
+        );
+        assert_eq!(
+            server_date(&headers).map(|d| d.timestamp()),
+            Some(784_111_777)
+        );
+        headers.insert(DATE, HeaderValue::from_static("yesterday"));
+        assert!(server_date(&headers).is_none(), "malformed header");
+    }
+}
@@ -3,17 +3,14 @@ use std::string;
 use std::time::Duration;
 
 use anyhow::{Context as _, Result, anyhow};
-use chrono::Utc;
 use colored::Colorize as _;
-use oauth2::basic::BasicClient;
 use oauth2::{
     AuthType, AuthUrl, ClientId, ClientSecret, DeviceAuthorizationResponse, DeviceAuthorizationUrl,
-    ExtraDeviceAuthorizationFields, RequestTokenError, Scope, TokenResponse as _, TokenUrl,
+    ExtraDeviceAuthorizationFields, RequestTokenError, Scope, TokenUrl,
 };
 use qr2term::matrix::Matrix;
 use qr2term::render::Renderer;
 use qrcode::{Color, EcLevel, QrCode};
-use reqwest::{ClientBuilder, redirect};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use tokio::time::sleep;
@@ -21,6 +18,11 @@ use tracing::{info, instrument};
 
 use crate::config::ProviderConfig;
 use crate::keyring::Token;
+use crate::oauth::clock::DatedClient;
+use crate::oauth::progress::Progress;
+use crate::oauth::{
+    OAuthClient, TokenExtraFields, access_expiry, refresh_expiry, token_from_response,
+};
 
 #[derive(Debug, Serialize, Deserialize)]
 struct StoringFields(HashMap<String, Value>);
@@ -45,28 +47,30 @@ pub async fn exchange_device_code(provider: &ProviderConfig) -> Result<Token> {
     )
     .expect("Invalid device authorization endpoint URL");
 
-    let mut device_client = BasicClient::new(ClientId::new(provider.client_id.clone()))
+    let mut device_client = OAuthClient::new(ClientId::new(provider.client_id.clone()))
         .set_auth_uri(auth_url)
         .set_token_uri(token_url.clone())
         .set_device_authorization_url(device_auth_url)
-        .set_auth_type(AuthType::RequestBody);
-    if let Some(secret) = &provider.client_secret {
-        device_client = device_client.set_client_secret(ClientSecret::new(secret.clone()));
+        .set_auth_type(provider.auth_type(AuthType::RequestBody));
+    if let Some(secret) = provider.client_secret()? {
+        device_client =
+            device_client.set_client_secret(ClientSecret::new(secret.expose().to_string()));
     }
 
-    let http_client = ClientBuilder::new()
-        .redirect(redirect::Policy::none())
-        .build()
-        .expect("Client should build");
-
-    let mut device_auth_req = device_client.exchange_device_code();
-    if let Some(scopes) = &provider.scopes
-        && !scopes.is_empty()
-    {
-        for s in scopes {
-            device_auth_req = device_auth_req.add_scope(Scope::new(s.clone()));
-        }
-    }
+    let http_client = DatedClient::new(&provider.tls);
+
+    let device_auth_req = device_client.exchange_device_code();
+    let device_auth_req = match provider.scopes.requested() {
+        Some([]) => device_auth_req.add_extra_param("scope", ""),
+        Some(scopes) => device_auth_req.add_scopes(scopes.iter().cloned().map(Scope::new)),
+        None => device_auth_req,
+    };
+    let device_auth_req = provider
+        .auth_params
+        .iter()
+        .fold(device_auth_req, |req, (key, value)| {
+            req.add_extra_param(key, value)
+        });
     let details: StoringDeviceAuthorizationResponse = device_auth_req
         .request_async(&http_client)
         .await
@@ -109,6 +113,7 @@ pub async fn exchange_device_code(provider: &ProviderConfig) -> Result<Token> {
         );
     }
 
+    let progress = Progress::start("Waiting for authorization in the browser");
     loop {
         let token = device_client
             .exchange_device_access_token(&details)
@@ -120,13 +125,9 @@ pub async fn exchange_device_code(provider: &ProviderConfig) -> Result<Token> {
             .await;
         match token {
             Ok(token) => {
-                let expires_at = token.expires_in().map(|d| Utc::now() + d);
-                let token = Token::new(
-                    token.access_token().secret().clone(),
-                    token.refresh_token().map(|s| s.secret().clone()),
-                    expires_at,
-                );
-                return Ok(token);
+                http_client.check_drift(token_url.as_str());
+                progress.finish();
+                return Ok(token_from_response(&token, provider.expiry_margin()));
             },
             Err(RequestTokenError::Parse(_, serde_error))
                 if String::from_utf8_lossy(&serde_error).contains("authorization_pending") =>
@@ -143,6 +144,7 @@ pub async fn exchange_device_code(provider: &ProviderConfig) -> Result<Token> {
     // weird custom implementation for github
     loop {
         let res = http_client
+            .inner()
             .post(token_url.as_str())
             .header("Accept", "application/json")
             .form(&[
@@ -153,6 +155,7 @@ pub async fn exchange_device_code(provider: &ProviderConfig) -> Result<Token> {
             .send()
             .await
             .context("Failed to request access token via device flow")?;
+        http_client.observe(res.headers());
 
         let json: Value = res.json().await.context("Failed to parse token response")?;
 
@@ -198,8 +201,22 @@ pub async fn exchange_device_code(provider: &ProviderConfig) -> Result<Token> {
             .get("expires_in")
             .and_then(Value::as_u64)
             .map(Duration::from_secs);
-        let expires_at = expires_in.map(|d| Utc::now() + d);
-        let token = Token::new(access_token, refresh_token, expires_at);
+        http_client.check_drift(token_url.as_str());
+        let expires_at = access_expiry(expires_in, provider.expiry_margin());
+        let extra = json
+            .as_object()
+            .map(TokenExtraFields::from_json)
+            .unwrap_or_default();
+        let refresh_expires_at = refresh_expiry(extra.refresh_lifetime());
+        let scopes = json
+            .get("scope")
+            .and_then(Value::as_str)
+            .map(|s| s.split_whitespace().map(str::to_string).collect());
+        let token = Token::new(access_token, refresh_token, expires_at)
+            .with_refresh_expiry(refresh_expires_at)
+            .with_scopes(scopes)
+            .with_extra(extra.to_map());
+        progress.finish();
 
         return Ok(token);
     }
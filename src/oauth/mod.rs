@@ -1,85 +1,369 @@
 pub mod auth_code_pkce;
+pub mod clock;
 pub mod device_code;
+pub mod gcloud;
+pub mod oidc;
+pub mod pinning;
+pub mod progress;
+use std::fmt::Write as _;
+use std::time::Duration;
+
 use anyhow::{Context as _, Result, anyhow, bail};
-use chrono::Utc;
-use oauth2::basic::BasicClient;
-use oauth2::{AuthUrl, ClientId, ClientSecret, RefreshToken, TokenResponse as _, TokenUrl};
-use reqwest::{ClientBuilder, redirect};
+use chrono::{DateTime, TimeDelta, Utc};
+use oauth2::basic::{
+    BasicErrorResponse, BasicRevocationErrorResponse, BasicTokenIntrospectionResponse,
+    BasicTokenType,
+};
+use oauth2::{
+    AccessToken, AuthType, AuthUrl, Client, ClientId, ClientSecret, EndpointNotSet,
+    ExtraTokenFields, RefreshToken, RevocationUrl, StandardRevocableToken, StandardTokenResponse,
+    TokenResponse as _, TokenUrl,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 use tracing::{error, instrument};
 
+use crate::commands::common::styled_warning;
 use crate::config::{OAuthConfig, ProviderConfig};
 use crate::keyring::Token;
+use crate::oauth::clock::DatedClient;
+use crate::signal;
+
+/// Fields of a token response that RFC 6749 doesn't define
+const STANDARD_FIELDS: &[&str] = &[
+    "access_token",
+    "token_type",
+    "expires_in",
+    "refresh_token",
+    "scope",
+];
+
+/// Non-standard token response fields, the refresh token lifetime and
+/// whatever else the provider sends
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct TokenExtraFields {
+    /// Sent by GitHub
+    refresh_token_expires_in: Option<u64>,
+    /// Sent by Keycloak, 0 for refresh tokens that don't expire
+    refresh_expires_in: Option<u64>,
+    #[serde(flatten)]
+    other: Map<String, Value>,
+}
+
+impl ExtraTokenFields for TokenExtraFields {}
+
+impl TokenExtraFields {
+    /// The non-standard fields of a token response body
+    pub fn from_json(json: &Map<String, Value>) -> Self {
+        let extra: Map<String, Value> = json
+            .iter()
+            .filter(|(key, _)| !STANDARD_FIELDS.contains(&key.as_str()))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        serde_json::from_value(Value::Object(extra)).unwrap_or_default()
+    }
+
+    /// Seconds the refresh token stays valid, if the provider said
+    pub fn refresh_lifetime(&self) -> Option<u64> {
+        self.refresh_token_expires_in.or(self.refresh_expires_in)
+    }
+
+    /// All fields as they were sent, to be kept with the token
+    pub fn to_map(&self) -> Map<String, Value> {
+        match serde_json::to_value(self) {
+            Ok(Value::Object(map)) => {
+                map.into_iter()
+                    .filter(|(key, value)| {
+                        !value.is_null() && !STANDARD_FIELDS.contains(&key.as_str())
+                    })
+                    .collect()
+            },
+            Ok(_) | Err(_) => Map::new(),
+        }
+    }
+}
 
-/// Selects and executes the OAuth flow based on provider settings.
+pub type OAuthTokenResponse = StandardTokenResponse<TokenExtraFields, BasicTokenType>;
+
+/// [`oauth2::basic::BasicClient`] that keeps the refresh token lifetime
+pub type OAuthClient<
+    HasAuthUrl = EndpointNotSet,
+    HasDeviceAuthUrl = EndpointNotSet,
+    HasIntrospectionUrl = EndpointNotSet,
+    HasRevocationUrl = EndpointNotSet,
+    HasTokenUrl = EndpointNotSet,
+> = Client<
+    BasicErrorResponse,
+    OAuthTokenResponse,
+    BasicTokenIntrospectionResponse,
+    StandardRevocableToken,
+    BasicRevocationErrorResponse,
+    HasAuthUrl,
+    HasDeviceAuthUrl,
+    HasIntrospectionUrl,
+    HasRevocationUrl,
+    HasTokenUrl,
+>;
+
+/// Expiry of a token valid for `expires_in` from now, moved `margin` earlier
+/// so it is renewed before the server rejects it. At most half the lifetime
+/// is taken off.
+///
+/// `expires_in` is relative, so counting it from the local clock is correct
+/// even if that clock is off. The server's `Date` is only used to warn about
+/// such drift, see [`DatedClient::check_drift`].
+pub fn access_expiry(expires_in: Option<Duration>, margin: TimeDelta) -> Option<DateTime<Utc>> {
+    let lifetime = TimeDelta::from_std(expires_in?).ok()?;
+    Some(Utc::now() + lifetime - margin.min(lifetime / 2))
+}
+
+/// Seconds until the refresh token expires as reported by the provider
+pub fn refresh_expiry(seconds: Option<u64>) -> Option<DateTime<Utc>> {
+    seconds
+        .filter(|s| *s > 0)
+        .and_then(|s| TimeDelta::try_seconds(i64::try_from(s).ok()?))
+        .map(|d| Utc::now() + d)
+}
+
+/// Convert a token endpoint response into a [`Token`], computing the expiry
+/// times from now.
+pub fn token_from_response(res: &OAuthTokenResponse, margin: TimeDelta) -> Token {
+    let extra = res.extra_fields();
+    Token::new(
+        res.access_token().secret().clone(),
+        res.refresh_token().map(|rt| rt.secret().clone()),
+        access_expiry(res.expires_in(), margin),
+    )
+    .with_refresh_expiry(refresh_expiry(extra.refresh_lifetime()))
+    .with_scopes(
+        res.scopes()
+            .map(|scopes| scopes.iter().map(|s| s.to_string()).collect()),
+    )
+    .with_extra(extra.to_map())
+}
+
+/// Selects and executes the OAuth flow based on provider settings, trying the
+/// flows of [`ProviderConfig::flow_order`] in turn. Ctrl-C
+/// cancels the flow, closing its callback listener, with
+/// [`Interrupted`](crate::signal::Interrupted).
 #[instrument(skip(provider, config))]
 pub async fn get_access_token(
     config: &OAuthConfig,
     provider: &String,
     force_device: bool,
 ) -> Result<Token> {
-    let provider = config
-        .providers
-        .get(provider)
+    signal::cancellable(run_flow(config, provider, force_device)).await?
+}
+
+async fn run_flow(config: &OAuthConfig, provider: &String, force_device: bool) -> Result<Token> {
+    let (_, provider) = config
+        .find_provider(provider)
         .ok_or_else(|| anyhow!("No OAuth provider configuration found for {provider}"))?;
+    if provider.is_gcloud() {
+        return gcloud::fetch_gcloud_token(provider).await;
+    }
+    let provider = &*oidc::discover(provider).await?;
     if force_device {
         if provider.device_auth_url.is_none() {
             bail!("Device code flow is not supported for this provider");
         }
         return device_code::exchange_device_code(provider).await;
     }
-    match provider.preferred_flow.as_deref() {
-        Some("device") => device_code::exchange_device_code(provider).await,
-        Some("authcode") => auth_code_pkce::exchange_auth_code_pkce(provider, config).await,
-        _ => {
-            if provider.device_auth_url.is_some() {
-                // Try device flow first, fall back to auth code
-                match device_code::exchange_device_code(provider).await {
-                    Ok(secret) => Ok(secret),
-                    Err(_) => auth_code_pkce::exchange_auth_code_pkce(provider, config).await,
-                }
-            } else {
-                auth_code_pkce::exchange_auth_code_pkce(provider, config).await
-            }
-        },
+    let flows = provider.flow_order();
+    let mut failures = Vec::new();
+    for (i, flow) in flows.iter().enumerate() {
+        let result = match *flow {
+            "device" if provider.device_auth_url.is_none() => {
+                Err(anyhow!("the provider has no device authorization endpoint"))
+            },
+            "device" => device_code::exchange_device_code(provider).await,
+            _ => auth_code_pkce::exchange_auth_code_pkce(provider, config).await,
+        };
+        let err = match result {
+            Ok(token) => return Ok(token),
+            Err(err) => err,
+        };
+        if let Some(next) = flows.get(i + 1) {
+            styled_warning(format!(
+                "The {} failed: {err:#}. Trying the {} next.",
+                flow_name(flow),
+                flow_name(next)
+            ));
+        }
+        failures.push((*flow, err));
+    }
+    if let [(flow, _)] = failures.as_slice() {
+        let context = format!("The {} failed", flow_name(flow));
+        let (_, err) = failures.remove(0);
+        return Err(err.context(context));
+    }
+    let mut msg = String::from("All configured flows failed:");
+    for (flow, err) in &failures {
+        let _ = write!(msg, "\n  {}: {err:#}", flow_name(flow));
+    }
+    bail!(msg)
+}
+
+fn flow_name(flow: &str) -> &'static str {
+    match flow {
+        "device" => "device code flow",
+        _ => "auth code flow",
     }
 }
 
 /// Refreshes the access token using the refresh token.
 #[instrument(skip(provider, token))]
 pub async fn refresh_access_token(provider: &ProviderConfig, token: &Token) -> Result<Token> {
+    if provider.is_gcloud() {
+        // gcloud tokens carry no refresh token, a fresh one is simply fetched again
+        return gcloud::fetch_gcloud_token(provider).await;
+    }
     let refresh_token = token
         .refresh_token()
         .ok_or_else(|| anyhow!("No refresh token available"))?;
+    let provider = &*oidc::discover(provider).await?;
 
-    let mut client = BasicClient::new(ClientId::new(provider.client_id.clone()))
+    let mut client = OAuthClient::new(ClientId::new(provider.client_id.clone()))
         .set_auth_uri(AuthUrl::new(provider.auth_url.clone())?)
-        .set_token_uri(TokenUrl::new(provider.token_url.clone())?);
-    if let Some(secret) = &provider.client_secret {
-        client = client.set_client_secret(ClientSecret::new(secret.clone()));
+        .set_token_uri(TokenUrl::new(provider.token_url.clone())?)
+        .set_auth_type(provider.auth_type(AuthType::BasicAuth));
+    if let Some(secret) = provider.client_secret()? {
+        client = client.set_client_secret(ClientSecret::new(secret.expose().to_string()));
     }
 
-    let http_client = ClientBuilder::new()
-        .redirect(redirect::Policy::none())
-        .build()
-        .expect("Client should build");
+    let http_client = DatedClient::new(&provider.tls);
 
     let token_res = client
         .exchange_refresh_token(&RefreshToken::new((*refresh_token).to_string()))
         .request_async(&http_client)
         .await;
-    let token = match token_res {
-        Ok(token) => token,
+    let response = match token_res {
+        Ok(response) => response,
         Err(err) => {
             error!("Failed to exchange code: {}", err);
             return Err(anyhow!(err)).context("Failed to exchange refresh token");
         },
     };
-    let expires_at = token.expires_in().map(|d| Utc::now() + d);
-    let token = Token::new(
-        token.access_token().secret().clone(),
-        token.refresh_token().map(|rt| rt.secret().clone()),
-        expires_at,
+    http_client.check_drift(&provider.token_url);
+    // many providers omit the refresh token and scopes when they are unchanged
+    let mut refreshed = token_from_response(&response, provider.expiry_margin());
+    refreshed.keep_unrenewed(token);
+    Ok(refreshed)
+}
+
+/// Revoke `token` at the provider's revocation endpoint (RFC 7009). Returns
+/// false if the provider has none.
+#[instrument(skip(provider, token))]
+pub async fn revoke_token(provider: &ProviderConfig, token: &Token) -> Result<bool> {
+    if provider.is_gcloud() {
+        return Ok(false);
+    }
+    let provider = &*oidc::discover(provider).await?;
+    let Some(revocation_url) = &provider.revocation_url else {
+        return Ok(false);
+    };
+    let mut client = OAuthClient::new(ClientId::new(provider.client_id.clone()))
+        .set_revocation_url(RevocationUrl::new(revocation_url.clone())?)
+        .set_auth_type(provider.auth_type(AuthType::BasicAuth));
+    if let Some(secret) = provider.client_secret()? {
+        client = client.set_client_secret(ClientSecret::new(secret.expose().to_string()));
+    }
+    // revoking the refresh token ends the whole grant, including its access
+    // tokens
+    let revocable = token.refresh_token().map_or_else(
+        || StandardRevocableToken::AccessToken(AccessToken::new(token.access_token().to_string())),
+        |refresh_token| {
+            StandardRevocableToken::RefreshToken(RefreshToken::new(refresh_token.to_string()))
+        },
     );
+    client
+        .revoke_token(revocable)?
+        .request_async(&DatedClient::new(&provider.tls))
+        .await
+        .map_err(|err| anyhow!(err))
+        .context("Failed to revoke token")?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn access_expiry_applies_margin() {
+        let margin = TimeDelta::seconds(60);
+        let before = Utc::now();
+        let expiry = access_expiry(Some(Duration::from_hours(1)), margin).unwrap();
+        assert!(
+            (expiry - before - TimeDelta::minutes(59)).abs() < TimeDelta::seconds(1),
+            "one minute taken off"
+        );
+
+        // short-lived tokens keep at least half their lifetime
+        let expiry = access_expiry(Some(Duration::from_secs(60)), margin).unwrap();
+        assert!(
+            expiry - before >= TimeDelta::seconds(30),
+            "half the lifetime"
+        );
+
+        assert!(access_expiry(None, margin).is_none(), "no expiry reported");
+    }
+
+    #[test]
+    fn refreshing_keeps_the_refresh_token() {
+        let previous = Token::new("old".into(), Some("refresh".into()), None)
+            .with_scopes(Some(vec!["repo".into()]))
+            .with_refresh_expiry(Some(Utc::now() + TimeDelta::days(30)));
+        let res: OAuthTokenResponse =
+            serde_json::from_str(r#"{"access_token": "new", "token_type": "bearer"}"#).unwrap();
+        let mut refreshed = token_from_response(&res, TimeDelta::zero());
+        refreshed.keep_unrenewed(&previous);
+        assert_eq!(refreshed.access_token(), "new");
+        assert_eq!(refreshed.refresh_token(), Some("refresh"));
+        assert_eq!(refreshed.refresh_expires_at, previous.refresh_expires_at);
+        assert_eq!(refreshed.scopes, previous.scopes);
+
+        let res: OAuthTokenResponse = serde_json::from_str(
+            r#"{"access_token": "newer", "token_type": "bearer", "refresh_token": "rotated"}"#,
+        )
+        .unwrap();
+        let mut rotated = token_from_response(&res, TimeDelta::zero());
+        rotated.keep_unrenewed(&refreshed);
+        assert_eq!(rotated.refresh_token(), Some("rotated"));
+        assert_eq!(
+            rotated.refresh_expires_at, None,
+            "expiry of the old one dropped"
+        );
+    }
+
+    #[test]
+    fn extra_fields_are_kept() {
+        let res: OAuthTokenResponse = serde_json::from_str(
+            r#"{
+                "access_token": "ghu_x",
+                "token_type": "bearer",
+                "expires_in": 28800,
+                "refresh_token": "ghr_x",
+                "refresh_token_expires_in": 15897600,
+                "scope": "",
+                "installation_id": 42
+            }"#,
+        )
+        .unwrap();
+        let token = token_from_response(&res, TimeDelta::zero());
+        assert!(token.refresh_expires_at.is_some(), "refresh token lifetime");
+        assert_eq!(
+            token.extra.keys().collect::<Vec<_>>(),
+            ["installation_id", "refresh_token_expires_in"],
+            "only non-standard fields"
+        );
 
-    Ok(token)
+        let json = serde_json::json!({"access_token": "x", "refresh_expires_in": 1800});
+        let extra = TokenExtraFields::from_json(json.as_object().unwrap());
+        assert_eq!(extra.refresh_lifetime(), Some(1800), "Keycloak lifetime");
+        assert!(
+            !extra.to_map().contains_key("access_token"),
+            "standard fields dropped"
+        );
+    }
 }
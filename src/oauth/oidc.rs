@@ -0,0 +1,200 @@
+use std::borrow::Cow;
+
+use anyhow::{Context as _, Result, bail};
+use reqwest::{ClientBuilder, redirect};
+use serde::Deserialize;
+use tracing::{info, instrument};
+
+use crate::config::ProviderConfig;
+use crate::oauth::pinning::pinned;
+
+/// Location of the discovery document relative to the issuer
+const DISCOVERY_PATH: &str = "/.well-known/openid-configuration";
+const AUTHORIZATION_CODE_GRANT: &str = "authorization_code";
+const DEVICE_CODE_GRANT: &str = "urn:ietf:params:oauth:grant-type:device_code";
+
+/// The subset of an `OpenID` Connect discovery document we care about.
+#[derive(Debug, Deserialize)]
+struct Discovery {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    device_authorization_endpoint: Option<String>,
+    revocation_endpoint: Option<String>,
+    grant_types_supported: Option<Vec<String>>,
+}
+
+impl Discovery {
+    fn supports(&self, grant: &str) -> bool {
+        // the spec defaults to authorization_code and implicit when omitted
+        self.grant_types_supported.as_ref().map_or_else(
+            || grant == AUTHORIZATION_CODE_GRANT,
+            |grants| grants.iter().any(|g| g == grant),
+        )
+    }
+
+    /// Auth-code if the issuer allows it, like the other provider types,
+    /// otherwise the device flow.
+    fn preferred_flow(&self) -> Result<&'static str> {
+        let device =
+            self.device_authorization_endpoint.is_some() && self.supports(DEVICE_CODE_GRANT);
+        if self.supports(AUTHORIZATION_CODE_GRANT) {
+            Ok("authcode")
+        } else if device {
+            Ok("device")
+        } else {
+            bail!(
+                "Issuer {} supports neither the auth-code nor the device flow",
+                self.issuer
+            )
+        }
+    }
+}
+
+/// Fill in the endpoints and preferred flow of an "oidc" provider from its
+/// issuer's discovery document. Values set in the configuration win, other
+/// provider types are returned as they are.
+#[instrument(skip(provider))]
+pub async fn discover(provider: &ProviderConfig) -> Result<Cow<'_, ProviderConfig>> {
+    if !provider.is_oidc() {
+        return Ok(Cow::Borrowed(provider));
+    }
+    let issuer = provider
+        .issuer
+        .as_deref()
+        .context("Missing issuer for oidc provider")?;
+    let url = format!("{issuer}{DISCOVERY_PATH}");
+    info!("Discovering OpenID Connect configuration at {url}");
+
+    let builder = ClientBuilder::new().redirect(redirect::Policy::none());
+    let http_client = pinned(builder, &provider.tls)
+        .build()
+        .context("Failed to build HTTP client")?;
+    let discovery: Discovery = http_client
+        .get(&url)
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .with_context(|| format!("Failed to fetch {url}"))?
+        .json()
+        .await
+        .with_context(|| format!("Malformed discovery document at {url}"))?;
+
+    apply(provider.clone(), &discovery, issuer).map(Cow::Owned)
+}
+
+fn apply(
+    mut provider: ProviderConfig,
+    discovery: &Discovery,
+    issuer: &str,
+) -> Result<ProviderConfig> {
+    if discovery.issuer.trim_end_matches('/') != issuer {
+        bail!(
+            "Discovery document is for issuer {}, expected {issuer}",
+            discovery.issuer
+        );
+    }
+    if provider.auth_url.is_empty() {
+        provider
+            .auth_url
+            .clone_from(&discovery.authorization_endpoint);
+    }
+    if provider.token_url.is_empty() {
+        provider.token_url.clone_from(&discovery.token_endpoint);
+    }
+    if provider.device_auth_url.is_none() && discovery.supports(DEVICE_CODE_GRANT) {
+        provider
+            .device_auth_url
+            .clone_from(&discovery.device_authorization_endpoint);
+    }
+    if provider.revocation_url.is_none() {
+        provider
+            .revocation_url
+            .clone_from(&discovery.revocation_endpoint);
+    }
+    if provider.preferred_flow.is_none() && provider.flows.is_none() {
+        provider.preferred_flow = Some(discovery.preferred_flow()?.to_string());
+    }
+    Ok(provider)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::config::provider::Scopes;
+
+    const ISSUER: &str = "https://sso.example.com/realms/dev";
+
+    fn provider() -> ProviderConfig {
+        ProviderConfig {
+            provider_type: Some("oidc".into()),
+            issuer: Some(ISSUER.into()),
+            client_id: "warden".into(),
+            client_secret: None,
+            auth_url: String::new(),
+            token_url: String::new(),
+            device_auth_url: None,
+            revocation_url: None,
+            scopes: Scopes::Default,
+            auth_params: BTreeMap::new(),
+            preferred_flow: None,
+            flows: None,
+            service_account: None,
+            auth_style: None,
+            clock_skew: None,
+            match_subdomains: None,
+            refresh: None,
+            credential_name_template: None,
+            tls: crate::config::provider::TlsConfig::default(),
+            secret_in_keyring: None,
+        }
+    }
+
+    fn discovery(grants: Option<&[&str]>) -> Discovery {
+        serde_json::from_value(serde_json::json!({
+            "issuer": format!("{ISSUER}/"),
+            "authorization_endpoint": format!("{ISSUER}/protocol/openid-connect/auth"),
+            "token_endpoint": format!("{ISSUER}/protocol/openid-connect/token"),
+            "device_authorization_endpoint": format!("{ISSUER}/protocol/openid-connect/auth/device"),
+            "grant_types_supported": grants,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn discovery_fills_endpoints_and_flow() {
+        let grants = [AUTHORIZATION_CODE_GRANT, DEVICE_CODE_GRANT];
+        let p = apply(provider(), &discovery(Some(&grants)), ISSUER).unwrap();
+        assert_eq!(p.auth_url, format!("{ISSUER}/protocol/openid-connect/auth"));
+        assert_eq!(
+            p.token_url,
+            format!("{ISSUER}/protocol/openid-connect/token")
+        );
+        assert!(p.device_auth_url.is_some(), "device grant is advertised");
+        assert_eq!(p.preferred_flow.as_deref(), Some("authcode"));
+
+        let p = apply(provider(), &discovery(Some(&[DEVICE_CODE_GRANT])), ISSUER).unwrap();
+        assert_eq!(p.preferred_flow.as_deref(), Some("device"));
+
+        // without grant_types_supported only auth-code may be assumed
+        let p = apply(provider(), &discovery(None), ISSUER).unwrap();
+        assert!(p.device_auth_url.is_none(), "device grant not advertised");
+        assert_eq!(p.preferred_flow.as_deref(), Some("authcode"));
+
+        apply(provider(), &discovery(Some(&["implicit"])), ISSUER).unwrap_err();
+    }
+
+    #[test]
+    fn discovery_keeps_overrides_and_checks_issuer() {
+        let mut configured = provider();
+        configured.token_url = "https://proxy.example.com/token".into();
+        configured.preferred_flow = Some("device".into());
+        let p = apply(configured, &discovery(None), ISSUER).unwrap();
+        assert_eq!(p.token_url, "https://proxy.example.com/token");
+        assert_eq!(p.preferred_flow.as_deref(), Some("device"));
+
+        apply(provider(), &discovery(None), "https://other.example.com").unwrap_err();
+    }
+}
@@ -0,0 +1,216 @@
+//! Pinning of the TLS certificates of a provider's servers.
+//!
+//! With `tls.pinned_sha256` set for a provider, the connections of its OAuth
+//! HTTP clients are checked right after the TLS handshake: the SHA-256 digest
+//! of the server's certificate or of its public key (SPKI) has to be one of
+//! the pins. A CA the system trusts, such as a corporate root intercepting
+//! TLS, can't stand in for the server then. Nothing is sent over a connection
+//! that fails the check.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use base64::Engine as _;
+use base64::engine::general_purpose::STANDARD;
+use hyper_util::client::legacy::connect::Connection;
+use oauth2::http::Extensions;
+use reqwest::ClientBuilder;
+use reqwest::tls::TlsInfo;
+use ring::digest::{SHA256, digest};
+use tower_layer::Layer;
+use tower_service::Service;
+
+use crate::config::provider::TlsConfig;
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+type Digest = [u8; 32];
+
+/// Context-specific tag of the optional version of a certificate
+const VERSION_TAG: u8 = 0xA0;
+/// Fields of a certificate before its public key: serial number, signature
+/// algorithm, issuer, validity and subject
+const FIELDS_BEFORE_SPKI: usize = 5;
+
+/// `builder` refusing servers that don't match the pins of `tls`, only
+/// talking https then. Without pins it is returned as it is.
+pub fn pinned(builder: ClientBuilder, tls: &TlsConfig) -> ClientBuilder {
+    if tls.pinned_sha256.is_empty() {
+        return builder;
+    }
+    builder
+        .https_only(true)
+        .tls_info(true)
+        .connector_layer(PinLayer {
+            pins: tls.pins().into(),
+        })
+}
+
+/// Checks the connections of the wrapped connector against the pins
+#[derive(Clone)]
+struct PinLayer {
+    pins: Arc<[Digest]>,
+}
+
+impl<S> Layer<S> for PinLayer {
+    type Service = PinService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        PinService {
+            inner,
+            pins: Arc::clone(&self.pins),
+        }
+    }
+}
+
+#[derive(Clone)]
+struct PinService<S> {
+    inner: S,
+    pins: Arc<[Digest]>,
+}
+
+impl<S, R> Service<R> for PinService<S>
+where
+    S: Service<R>,
+    S::Response: Connection + Send + 'static,
+    S::Error: Into<BoxError>,
+    S::Future: Send + 'static,
+{
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+    type Response = S::Response;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: R) -> Self::Future {
+        let connecting = self.inner.call(req);
+        let pins = Arc::clone(&self.pins);
+        Box::pin(async move {
+            let conn = connecting.await.map_err(Into::into)?;
+            let mut extensions = Extensions::new();
+            conn.connected().get_extras(&mut extensions);
+            let cert = extensions
+                .get::<TlsInfo>()
+                .and_then(TlsInfo::peer_certificate)
+                .ok_or("The server presented no certificate to check tls.pinned_sha256 against")?;
+            check(cert, &pins)?;
+            Ok(conn)
+        })
+    }
+}
+
+/// Whether the DER certificate `cert` or its public key matches one of `pins`
+fn check(cert: &[u8], pins: &[Digest]) -> Result<(), String> {
+    let key = spki(cert);
+    let matches = [Some(cert), key]
+        .into_iter()
+        .flatten()
+        .any(|der| pins.contains(&sha256(der)));
+    if matches {
+        return Ok(());
+    }
+    Err(format!(
+        "The server's certificate matches none of tls.pinned_sha256, its public key is sha256//{}",
+        key.map_or_else(|| "unknown".into(), |key| STANDARD.encode(sha256(key)))
+    ))
+}
+
+fn sha256(der: &[u8]) -> Digest {
+    digest(&SHA256, der)
+        .as_ref()
+        .try_into()
+        .expect("SHA-256 digests are 32 bytes")
+}
+
+/// The public key (SPKI) of the DER certificate `cert`, as encoded in it
+fn spki(cert: &[u8]) -> Option<&[u8]> {
+    let (certificate, _) = split(cert)?;
+    let (tbs, _) = split(certificate.content)?;
+    let mut fields = tbs.content;
+    let (first, rest) = split(fields)?;
+    if first.tag == VERSION_TAG {
+        fields = rest;
+    }
+    for _ in 0..FIELDS_BEFORE_SPKI {
+        fields = split(fields)?.1;
+    }
+    split(fields).map(|(key, _)| key.whole)
+}
+
+/// A DER element
+struct Element<'a> {
+    tag: u8,
+    /// Tag, length and content
+    whole: &'a [u8],
+    content: &'a [u8],
+}
+
+/// The first DER element of `der` and what follows it
+fn split(der: &[u8]) -> Option<(Element<'_>, &[u8])> {
+    let (&tag, rest) = der.split_first()?;
+    let (&first, rest) = rest.split_first()?;
+    let (len, rest) = if first < 0x80 {
+        (usize::from(first), rest)
+    } else {
+        let count = usize::from(first & 0x7F);
+        if count == 0 || count > size_of::<usize>() {
+            return None;
+        }
+        let (bytes, rest) = rest.split_at_checked(count)?;
+        let len = bytes
+            .iter()
+            .fold(0, |len, byte| (len << 8) | usize::from(*byte));
+        (len, rest)
+    };
+    let header = der.len() - rest.len();
+    let (whole, after) = der.split_at_checked(header.checked_add(len)?)?;
+    let content = whole.get(header..)?;
+    Some((
+        Element {
+            tag,
+            whole,
+            content,
+        },
+        after,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Self-signed P-256 certificate for git.example.com
+    const CERT: &str = "MIIBjDCCATGgAwIBAgIUFcDYTdCUtU9LQv2vHDRYHwz6mCMwCgYIKoZIzj0EAwIwGjEYMBYGA1UEAwwPZ2l0LmV4YW1wbGUuY29tMCAXDTI2MTAxNjE0MjY0MloYDzIxMjYwOTIyMTQyNjQyWjAaMRgwFgYDVQQDDA9naXQuZXhhbXBsZS5jb20wWTATBgcqhkjOPQIBBggqhkjOPQMBBwNCAAS1jOWpz8kUv0RyTiTlJqeokSqpg2lY9Dcod3YdKJD6LnGz1W00gowzrnU44GoqmFdgfdfcHVb6fgJ6o6Xz/hsSo1MwUTAdBgNVHQ4EFgQUd527oPDtgW6AoVY8m8ab7EvakSYwHwYDVR0jBBgwFoAUd527oPDtgW6AoVY8m8ab7EvakSYwDwYDVR0TAQH/BAUwAwEB/zAKBggqhkjOPQQDAgNJADBGAiEA7fddrNWDJo0KvhgbEZdaBpWQexKNO9A3m+vO5EZIZF0CIQDJ2UMOpqgnc3rbKI+QL9u8G9eIC5IhhUgRjL5pIRxK9g==";
+    /// `openssl x509 -pubkey | openssl pkey -pubin -outform der | openssl
+    /// dgst -sha256 -binary | base64`
+    const SPKI_PIN: &str = "sha256//yWhynn3eMZTIBkAwK2AZXJbyiBJP18NYQROYn562d4s=";
+    /// `openssl x509 -fingerprint -sha256`
+    const CERT_PIN: &str = "97:3B:E0:F8:3D:4E:A7:FA:D3:33:E5:8A:FD:84:6E:9B:9C:8E:62:CB:D3:0C:98:\
+                            81:3A:67:70:BC:61:E8:81:9D";
+
+    fn pins(pins: &[&str]) -> Vec<Digest> {
+        TlsConfig {
+            pinned_sha256: pins.iter().map(ToString::to_string).collect(),
+        }
+        .pins()
+    }
+
+    #[test]
+    fn certificates_are_checked_against_the_pins() {
+        let cert = STANDARD.decode(CERT).unwrap();
+        assert_eq!(pins(&[SPKI_PIN, CERT_PIN, "not a pin"]).len(), 2);
+        check(&cert, &pins(&[SPKI_PIN])).expect("public key pin");
+        check(&cert, &pins(&[CERT_PIN])).expect("certificate pin");
+
+        let other = "sha256//AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=";
+        let err = check(&cert, &pins(&[other])).unwrap_err();
+        assert!(
+            err.ends_with(SPKI_PIN),
+            "the actual public key is named: {err}"
+        );
+        assert!(spki(&cert[..40]).is_none(), "truncated certificate");
+    }
+}
@@ -0,0 +1,112 @@
+use std::io::{IsTerminal as _, Write as _, stderr};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use colored::Colorize as _;
+use crossterm::cursor::MoveToColumn;
+use crossterm::execute;
+use crossterm::terminal::{Clear, ClearType};
+use tokio::task::JoinHandle;
+use tokio::time::{Instant, interval};
+
+const FRAMES: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+const TICK: Duration = Duration::from_millis(100);
+
+struct Step {
+    label: String,
+    started: Instant,
+    /// Set once reported for good, the spinner must not draw over it
+    done: bool,
+}
+
+/// Step-by-step progress of a login on stderr, with a spinner and the elapsed
+/// time of the running step when stderr is a terminal.
+///
+/// Finished steps are listed with their duration. A `Progress` dropped
+/// without [`Progress::finish`] marks its current step as the one that failed.
+pub struct Progress {
+    step: Arc<Mutex<Step>>,
+    spinner: Option<JoinHandle<()>>,
+    finished: bool,
+}
+
+impl Progress {
+    pub fn start<S: Into<String>>(label: S) -> Self {
+        let step = Arc::new(Mutex::new(Step {
+            label: label.into(),
+            started: Instant::now(),
+            done: false,
+        }));
+        let spinner = stderr().is_terminal().then(|| {
+            let step = Arc::clone(&step);
+            tokio::spawn(async move {
+                let mut ticks = interval(TICK);
+                for frame in FRAMES.iter().cycle() {
+                    ticks.tick().await;
+                    let step = step.lock().expect("Progress lock poisoned");
+                    if step.done {
+                        break;
+                    }
+                    clear_line();
+                    eprint!("{frame} {} {}", step.label, elapsed(step.started).dimmed());
+                    let _ = stderr().flush();
+                    drop(step);
+                }
+            })
+        });
+        Self {
+            step,
+            spinner,
+            finished: false,
+        }
+    }
+
+    /// Mark the current step as done and start `label`
+    pub fn next<S: Into<String>>(&self, label: S) {
+        let mut step = self.step.lock().expect("Progress lock poisoned");
+        self.report(&step, true);
+        *step = Step {
+            label: label.into(),
+            started: Instant::now(),
+            done: false,
+        };
+    }
+
+    /// Mark the current step, and with it the whole login, as done
+    pub fn finish(mut self) {
+        self.finished = true;
+    }
+
+    fn report(&self, step: &Step, success: bool) {
+        if self.spinner.is_some() {
+            clear_line();
+        }
+        let mark = if success { "✓".green() } else { "✗".red() };
+        let outcome = if success { "" } else { " failed" };
+        eprintln!(
+            "{mark} {}{outcome} {}",
+            step.label,
+            elapsed(step.started).dimmed()
+        );
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        if let Some(spinner) = self.spinner.take() {
+            spinner.abort();
+        }
+        let mut step = self.step.lock().expect("Progress lock poisoned");
+        step.done = true;
+        self.report(&step, self.finished);
+        drop(step);
+    }
+}
+
+fn clear_line() {
+    let _ = execute!(stderr(), MoveToColumn(0), Clear(ClearType::CurrentLine));
+}
+
+fn elapsed(since: Instant) -> String {
+    format!("({:.1}s)", since.elapsed().as_secs_f64())
+}
@@ -0,0 +1,175 @@
+//! Structured output for commands that support `--output`.
+
+use std::fmt::Write as _;
+
+use anyhow::{Context as _, Result};
+use clap::ValueEnum;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::context::Io;
+
+/// How a command prints its result
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Human readable, on stderr
+    #[default]
+    Text,
+    /// JSON on stdout
+    Json,
+    /// Nushell object notation on stdout
+    Nuon,
+}
+
+impl OutputFormat {
+    pub const fn is_text(self) -> bool {
+        matches!(self, Self::Text)
+    }
+
+    /// Print `value` to `io` in this format, nothing for [`Self::Text`]
+    pub fn print<T: Serialize>(self, io: &dyn Io, value: &T) -> Result<()> {
+        let value = serde_json::to_value(value).context("Failed to serialize output")?;
+        match self {
+            Self::Text => {},
+            Self::Json => {
+                io.print(&format!(
+                    "{}\n",
+                    serde_json::to_string_pretty(&value).context("Failed to serialize output")?
+                ));
+            },
+            Self::Nuon => io.print(&format!("{}\n", to_nuon(&value))),
+        }
+        Ok(())
+    }
+}
+
+/// Render `value` as NUON, writing lists of records with the same columns as
+/// tables.
+fn to_nuon(value: &Value) -> String {
+    let mut out = String::new();
+    write_nuon(&mut out, value);
+    out
+}
+
+fn write_nuon(out: &mut String, value: &Value) {
+    match value {
+        Value::Null => out.push_str("null"),
+        Value::Bool(b) => {
+            let _ = write!(out, "{b}");
+        },
+        Value::Number(n) => {
+            let _ = write!(out, "{n}");
+        },
+        Value::String(s) => write_string(out, s),
+        Value::Array(items) => {
+            out.push('[');
+            if let Some(columns) = table_columns(items) {
+                out.push('[');
+                for (i, column) in columns.iter().enumerate() {
+                    if i > 0 {
+                        out.push_str(", ");
+                    }
+                    write_key(out, column);
+                }
+                out.push(']');
+                for item in items {
+                    out.push_str("; [");
+                    for (i, column) in columns.iter().enumerate() {
+                        if i > 0 {
+                            out.push_str(", ");
+                        }
+                        write_nuon(out, &item[column.as_str()]);
+                    }
+                    out.push(']');
+                }
+            } else {
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        out.push_str(", ");
+                    }
+                    write_nuon(out, item);
+                }
+            }
+            out.push(']');
+        },
+        Value::Object(map) => {
+            out.push('{');
+            for (i, (key, value)) in map.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                write_key(out, key);
+                out.push_str(": ");
+                write_nuon(out, value);
+            }
+            out.push('}');
+        },
+    }
+}
+
+/// The shared columns if `items` is a non-empty list of records that all have
+/// the same keys
+fn table_columns(items: &[Value]) -> Option<Vec<&String>> {
+    let columns: Vec<&String> = items.first()?.as_object()?.keys().collect();
+    if columns.is_empty() {
+        return None;
+    }
+    items
+        .iter()
+        .all(|item| {
+            item.as_object()
+                .is_some_and(|map| map.keys().eq(columns.iter().copied()))
+        })
+        .then_some(columns)
+}
+
+fn write_key(out: &mut String, key: &str) {
+    let bare = key
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        && key.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_');
+    if bare {
+        out.push_str(key);
+    } else {
+        write_string(out, key);
+    }
+}
+
+fn write_string(out: &mut String, s: &str) {
+    // NUON strings use the same escapes as JSON
+    out.push_str(&Value::String(s.to_string()).to_string());
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn nuon_tables_records_and_escapes() {
+        let value = json!([
+            {"host": "github.com", "active": true, "expires_at": null},
+            {"host": "git \"x\"", "active": false, "expires_at": 1},
+        ]);
+        assert_eq!(
+            to_nuon(&value),
+            r#"[[active, expires_at, host]; [true, null, "github.com"]; [false, 1, "git \"x\""]]"#,
+            "uniform records should form a table"
+        );
+
+        let value = json!({"user.name": "A", "list": [1, {"a": []}], "empty": {}});
+        assert_eq!(
+            to_nuon(&value),
+            r#"{empty: {}, list: [1, {a: []}], "user.name": "A"}"#,
+            "keys that are not bare words should be quoted"
+        );
+
+        let value = json!([{"a": 1}, {"b": 2}]);
+        assert_eq!(
+            to_nuon(&value),
+            "[{a: 1}, {b: 2}]",
+            "records with differing keys should stay a list"
+        );
+    }
+}
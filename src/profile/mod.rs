@@ -5,18 +5,22 @@
 // Local modifications:
 // Copyright (c) 2025 Adolar0042
 
-use std::collections::HashMap;
 use std::collections::hash_map::Iter;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt::Formatter;
+use std::fs;
 use std::ops::Deref;
+use std::path::{Path, PathBuf};
 
 use anyhow::{Context as _, Result, bail};
-use git2::Repository;
+use clap::ValueEnum;
+use git2::{ConfigLevel, ErrorCode};
 use serde::de::{MapAccess, Visitor};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use toml::{Table, Value};
 
 use crate::profile::rule::ProfileRef;
+use crate::utils::{RepoContext, RepoKind, config_dir, repo_context, state_dir};
 
 pub mod rule;
 pub mod url;
@@ -24,8 +28,13 @@ pub mod url;
 #[derive(Clone, Debug, Default)]
 pub struct Configs {
     map: HashMap<String, String>,
+    /// Multi-valued keys, written with `set_multivar`
+    multi: HashMap<String, Vec<String>>,
 }
 
+/// A regex that never matches an existing value, so `set_multivar` appends.
+const APPEND_MULTIVAR: &str = "a^";
+
 impl Configs {
     /// Convert this flattened map to a nested TOML table structure.
     /// Returns an error if conflicting keys are encountered, e.g. when
@@ -33,7 +42,15 @@ impl Configs {
     fn to_toml(&self) -> Result<Table> {
         let mut root = Table::new();
 
-        for (full_key, value) in &self.map {
+        let scalars = self.map.iter().map(|(k, v)| (k, Value::String(v.clone())));
+        let arrays = self.multi.iter().map(|(k, values)| {
+            (
+                k,
+                Value::Array(values.iter().cloned().map(Value::String).collect()),
+            )
+        });
+
+        for (full_key, value) in scalars.chain(arrays) {
             let segments: Vec<&str> = full_key.split('.').collect();
             if segments.is_empty() {
                 continue;
@@ -81,7 +98,7 @@ impl Configs {
                     bail!("Conflicting key '{full_key}': cannot overwrite a table with a value");
                 },
                 _ => {
-                    current.insert(last.to_string(), Value::String(value.clone()));
+                    current.insert(last.to_string(), value);
                 },
             }
         }
@@ -92,7 +109,7 @@ impl Configs {
     /// Extend the flattened map by reading the provided TOML value recursively.
     /// - Tables are traversed and keys are joined with '.'
     /// - Scalar values are stringified and inserted
-    /// - Arrays are rejected (git config expects scalar values)
+    /// - Arrays of scalars become multi-valued keys
     fn extend_from_toml(&mut self, input: &Value, current_key: &str) -> Result<()> {
         match input {
             Value::Table(table) => {
@@ -109,10 +126,20 @@ impl Configs {
                     };
                     self.extend_from_toml(value, &next_key)?;
                 }
-                Ok(())
             },
-            Value::Array(_) => {
-                bail!("Arrays are not supported in profile configs at key '{current_key}'")
+            Value::Array(items) => {
+                let values = items
+                    .iter()
+                    .map(|item| {
+                        coerce_scalar(item).with_context(|| {
+                            format!(
+                                "Arrays in profile configs may only contain scalars at key \
+                                 '{current_key}'"
+                            )
+                        })
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                self.multi.insert(current_key.to_string(), values);
             },
             // All scalars: coerce to string (git config values are strings)
             other @ (Value::String(_)
@@ -120,18 +147,47 @@ impl Configs {
             | Value::Float(_)
             | Value::Boolean(_)
             | Value::Datetime(_)) => {
-                let coerced = if let Value::String(s) = other {
-                    s.clone()
-                } else {
-                    other.to_string()
-                };
+                let coerced = coerce_scalar(other)?;
                 self.map.insert(current_key.to_string(), coerced);
-                Ok(())
             },
         }
+        Ok(())
+    }
+
+    /// Iterate the multi-valued keys.
+    pub fn multi_values(&self) -> impl Iterator<Item = (&String, &Vec<String>)> {
+        self.multi.iter()
+    }
+}
+
+/// Stringify a TOML scalar, git config values are strings.
+fn coerce_scalar(value: &Value) -> Result<String> {
+    match value {
+        Value::String(s) => Ok(s.clone()),
+        Value::Integer(_) | Value::Float(_) | Value::Boolean(_) | Value::Datetime(_) => {
+            Ok(value.to_string())
+        },
+        Value::Array(_) | Value::Table(_) => bail!("Expected a scalar value"),
     }
 }
 
+/// The `env` table of a profile, scalars stringified like git config values
+fn deserialize_env<'de, D>(
+    deserializer: D,
+) -> std::result::Result<BTreeMap<String, String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Table::deserialize(deserializer)?
+        .iter()
+        .map(|(name, value)| {
+            coerce_scalar(value)
+                .map(|value| (name.clone(), value))
+                .map_err(|err| serde::de::Error::custom(format!("env.{name}: {err}")))
+        })
+        .collect()
+}
+
 impl Deref for Configs {
     type Target = HashMap<String, String>;
     fn deref(&self) -> &Self::Target {
@@ -191,24 +247,317 @@ impl<'de> Visitor<'de> for ConfigsVisitor {
 /// A profile wraps a set of configuration entries.
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub struct Profile {
+    /// Keys removed from the repository config when the profile is applied
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unset: Vec<String>,
+    /// Extra config files written by warden and pulled in via `include.path`
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub includes: HashMap<String, Configs>,
+    /// Environment variables `env --profile` and `exec --profile` set, for
+    /// tools other than git
+    #[serde(
+        default,
+        deserialize_with = "deserialize_env",
+        skip_serializing_if = "BTreeMap::is_empty"
+    )]
+    pub env: BTreeMap<String, String>,
+    /// Files copied next to the git config on apply and pointed to from it
+    #[serde(default, skip_serializing_if = "ManagedFiles::is_empty")]
+    pub files: ManagedFiles,
     #[serde(default, flatten)]
     pub configs: Configs,
 }
 
+/// Files a profile brings along, copied into `.git/warden/` of the
+/// repository (or the state directory for the global scope) on apply. Paths
+/// are relative to the config directory, `~/` is the home directory.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ManagedFiles {
+    /// Commit message template, set as `commit.template`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit_template: Option<PathBuf>,
+    /// Directory of hooks, set as `core.hooksPath`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hooks: Option<PathBuf>,
+}
+
+impl ManagedFiles {
+    pub const fn is_empty(&self) -> bool {
+        self.commit_template.is_none() && self.hooks.is_none()
+    }
+
+    /// Copy the files into `dir`, replacing what an earlier apply left there,
+    /// and point `cfg` at them
+    fn write(&self, dir: &Path, cfg: &mut git2::Config) -> Result<()> {
+        remove_managed(dir, cfg)?;
+        if self.is_empty() {
+            return Ok(());
+        }
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+        if let Some(source) = &self.commit_template {
+            let source = resolve_path(source)?;
+            let target = dir.join(COMMIT_TEMPLATE);
+            fs::copy(&source, &target)
+                .with_context(|| format!("Failed to copy {}", source.display()))?;
+            cfg.set_str("commit.template", &target.to_string_lossy())
+                .context("Failed to set commit.template")?;
+        }
+        if let Some(source) = &self.hooks {
+            let source = resolve_path(source)?;
+            let target = dir.join(HOOKS);
+            copy_dir(&source, &target)?;
+            cfg.set_str("core.hooksPath", &target.to_string_lossy())
+                .context("Failed to set core.hooksPath")?;
+        }
+        Ok(())
+    }
+}
+
+/// Name of the commit template in a managed files directory
+const COMMIT_TEMPLATE: &str = "commit-template";
+/// Name of the hooks directory in a managed files directory
+const HOOKS: &str = "hooks";
+/// Keys pointing to managed files
+const MANAGED_KEYS: [&str; 2] = ["commit.template", "core.hooksPath"];
+
+/// `path` of a profile, relative to the config directory or `~/`
+fn resolve_path(path: &Path) -> Result<PathBuf> {
+    if let Ok(rest) = path.strip_prefix("~") {
+        return Ok(dirs::home_dir()
+            .context("Failed to get home directory")?
+            .join(rest));
+    }
+    Ok(config_dir()?.join(path))
+}
+
+/// Copy the files of the directory `source` into `target`, keeping their
+/// permissions so hooks stay executable
+fn copy_dir(source: &Path, target: &Path) -> Result<()> {
+    fs::create_dir_all(target).with_context(|| format!("Failed to create {}", target.display()))?;
+    for entry in
+        fs::read_dir(source).with_context(|| format!("Failed to read {}", source.display()))?
+    {
+        let path = entry?.path();
+        if path.is_file()
+            && let Some(name) = path.file_name()
+        {
+            fs::copy(&path, target.join(name))
+                .with_context(|| format!("Failed to copy {}", path.display()))?;
+        }
+    }
+    Ok(())
+}
+
+/// Remove the managed files directory `dir` and the keys of `cfg` pointing
+/// into it
+fn remove_managed(dir: &Path, cfg: &mut git2::Config) -> Result<()> {
+    for key in MANAGED_KEYS {
+        let managed = cfg.get_path(key).is_ok_and(|path| path.starts_with(dir));
+        if managed {
+            remove_all(cfg, key)?;
+        }
+    }
+    if dir.exists() {
+        fs::remove_dir_all(dir).with_context(|| format!("Failed to remove {}", dir.display()))?;
+    }
+    Ok(())
+}
+
+/// Which git config file a profile is written to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Scope {
+    /// The user's global config (`~/.gitconfig`)
+    Global,
+    /// The repository's `.git/config`
+    #[default]
+    Local,
+    /// The current worktree's `config.worktree`
+    Worktree,
+}
+
+impl Scope {
+    /// Open the config file for this scope in the repository of `repo`, or of
+    /// the current directory. Writes go only to that file.
+    fn open(self, repo: Option<&Path>) -> Result<git2::Config> {
+        if self == Self::Global {
+            return git2::Config::open_default()
+                .and_then(|mut cfg| cfg.open_global())
+                .context("Failed to open global git config");
+        }
+
+        let RepoContext { repo, kind } = repo_context(repo)?;
+        if kind == RepoKind::Bare && self == Self::Worktree {
+            bail!("A bare repository has no worktree to configure");
+        }
+        // a submodule's and a linked worktree's `repo.path()` is its own git
+        // dir, the local config of a linked worktree is shared with the main
+        // repository
+        let mut local = repo
+            .config()
+            .and_then(|cfg| cfg.open_level(ConfigLevel::Local))
+            .context("Failed to open git config")?;
+        if self == Self::Local {
+            return Ok(local);
+        }
+
+        // git ignores config.worktree unless the extension is enabled
+        if !local.get_bool("extensions.worktreeConfig").unwrap_or(false) {
+            local
+                .set_bool("extensions.worktreeConfig", true)
+                .context("Failed to enable extensions.worktreeConfig")?;
+        }
+        // the per-worktree git dir, `.git/worktrees/<name>` for linked worktrees
+        git2::Config::open(&repo.path().join("config.worktree"))
+            .context("Failed to open worktree git config")
+    }
+
+    /// Where the managed files of a profile applied to this scope go, in the
+    /// repository of `repo` or of the current directory
+    fn files_dir(self, repo: Option<&Path>) -> Result<PathBuf> {
+        let repo = match self {
+            Self::Global => return Ok(state_dir()?.join("files")),
+            Self::Local | Self::Worktree => repo_context(repo)?.repo,
+        };
+        // the local config is shared by all worktrees, like its files
+        let git_dir = if self == Self::Local {
+            repo.commondir()
+        } else {
+            repo.path()
+        };
+        Ok(git_dir.join("warden"))
+    }
+}
+
 impl Profile {
-    /// Apply this profile's configurations to the current git repository
-    /// config.
-    pub fn apply(&self) -> Result<()> {
-        let repo = Repository::open_from_env().context("Failed to open git repository")?;
-        let mut cfg = repo.config().context("Failed to open git config")?;
-
-        for (key, value) in &self.configs.map {
-            cfg.set_str(key, value)
-                .with_context(|| format!("Failed to set git config '{key}'"))?;
+    /// Apply this profile's configurations to the git config of `scope`, in
+    /// the repository of `repo` or of the current directory.
+    ///
+    /// Include files of the profile are (re)generated and referenced from
+    /// `scope`, includes of previously applied profiles are dropped.
+    pub fn apply(&self, name: &str, scope: Scope, repo: Option<&Path>) -> Result<()> {
+        let mut cfg = scope.open(repo)?;
+
+        for key in &self.unset {
+            remove_all(&mut cfg, key)?;
+        }
+        write_configs(&mut cfg, &self.configs)?;
+        self.files.write(&scope.files_dir(repo)?, &mut cfg)?;
+
+        remove_includes(&mut cfg)?;
+        for path in self.write_includes(&includes_dir()?.join(name))? {
+            cfg.set_multivar("include.path", APPEND_MULTIVAR, &path.to_string_lossy())
+                .context("Failed to add include.path")?;
         }
 
         Ok(())
     }
+
+    /// Write every include to `<dir>/<include>.gitconfig`, removing files of
+    /// includes the profile no longer declares. Returns the written paths.
+    fn write_includes(&self, dir: &Path) -> Result<Vec<PathBuf>> {
+        if dir.exists() {
+            for entry in
+                fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))?
+            {
+                let path = entry?.path();
+                let declared = path
+                    .file_stem()
+                    .is_some_and(|stem| self.includes.contains_key(&*stem.to_string_lossy()));
+                if !declared {
+                    fs::remove_file(&path)
+                        .with_context(|| format!("Failed to remove {}", path.display()))?;
+                }
+            }
+        }
+        if self.includes.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+        let mut names: Vec<_> = self.includes.keys().collect();
+        names.sort_unstable();
+        names
+            .into_iter()
+            .map(|name| {
+                if name.is_empty() || name.contains(['/', '\\', '.']) {
+                    bail!("Invalid include name '{name}', use a plain file name");
+                }
+                let path = dir.join(format!("{name}.gitconfig"));
+                // regenerate from scratch so removed keys disappear
+                if path.exists() {
+                    fs::remove_file(&path)
+                        .with_context(|| format!("Failed to remove {}", path.display()))?;
+                }
+                let mut cfg = git2::Config::open(&path)
+                    .with_context(|| format!("Failed to open {}", path.display()))?;
+                write_configs(&mut cfg, &self.includes[name])?;
+                Ok(path)
+            })
+            .collect()
+    }
+}
+
+/// Undo what applying a profile to `scope` set up beyond plain config keys:
+/// remove the managed files and the includes of warden, in the repository of
+/// `repo` or of the current directory
+pub fn unapply(scope: Scope, repo: Option<&Path>) -> Result<()> {
+    let mut cfg = scope.open(repo)?;
+    remove_managed(&scope.files_dir(repo)?, &mut cfg)?;
+    remove_includes(&mut cfg)
+}
+
+/// Remove the `include.path` entries of `cfg` pointing to include files
+/// generated by warden
+fn remove_includes(cfg: &mut git2::Config) -> Result<()> {
+    for managed in [includes_dir()?, legacy_includes_dir()?] {
+        let pattern = format!("^{}", regex::escape(&managed.to_string_lossy()));
+        match cfg.remove_multivar("include.path", &pattern) {
+            Err(e) if e.code() != ErrorCode::NotFound => {
+                return Err(e).context("Failed to remove previous warden includes");
+            },
+            _ => {},
+        }
+    }
+    Ok(())
+}
+
+/// Directory holding the include files generated for profiles.
+fn includes_dir() -> Result<PathBuf> {
+    Ok(state_dir()?.join("includes"))
+}
+
+/// Where include files were generated before the state directory existed,
+/// `include.path` entries pointing there are still cleaned up.
+fn legacy_includes_dir() -> Result<PathBuf> {
+    Ok(config_dir()?.join("includes"))
+}
+
+/// Set every scalar and multi-valued key of `configs` in `cfg`.
+fn write_configs(cfg: &mut git2::Config, configs: &Configs) -> Result<()> {
+    for (key, value) in &configs.map {
+        cfg.set_str(key, value)
+            .with_context(|| format!("Failed to set git config '{key}'"))?;
+    }
+
+    for (key, values) in &configs.multi {
+        // replace all existing values so re-applying is idempotent
+        remove_all(cfg, key)?;
+        for value in values {
+            cfg.set_multivar(key, APPEND_MULTIVAR, value)
+                .with_context(|| format!("Failed to add git config '{key}'"))?;
+        }
+    }
+    Ok(())
+}
+
+/// Remove every value of `key`, a key that isn't set is not an error.
+fn remove_all(cfg: &mut git2::Config, key: &str) -> Result<()> {
+    match cfg.remove_multivar(key, ".*") {
+        Err(e) if e.code() != ErrorCode::NotFound => {
+            Err(e).with_context(|| format!("Failed to unset git config '{key}'"))
+        },
+        _ => Ok(()),
+    }
 }
 
 /// A collection of named profiles.
@@ -284,13 +633,89 @@ mod tests {
     }
 
     #[test]
-    fn reject_arrays_in_configs() {
+    fn arrays_become_multi_valued_keys() {
+        let toml = r#"
+        credential.helper = ["", "warden"]
+        "#;
+
+        let profile = toml::from_str::<Profile>(toml).unwrap();
+        let (key, values) = profile.configs.multi_values().next().unwrap();
+        assert_eq!(key, "credential.helper");
+        assert_eq!(values, &["", "warden"]);
+
+        let table = profile.configs.to_toml().unwrap();
+        let Value::Table(credential) = table.get("credential").unwrap() else {
+            panic!("expected credential to be a table")
+        };
+        assert_eq!(
+            credential.get("helper").unwrap(),
+            &Value::Array(vec![
+                Value::String(String::new()),
+                Value::String("warden".to_string())
+            ])
+        );
+    }
+
+    #[test]
+    fn unset_is_not_a_config_key() {
+        let toml = r#"
+        unset = ["user.signingkey", "commit.gpgsign"]
+        user.name = "User"
+        "#;
+
+        let profile = toml::from_str::<Profile>(toml).unwrap();
+        assert_eq!(profile.unset, ["user.signingkey", "commit.gpgsign"]);
+        assert_eq!(profile.configs.len(), 1, "only user.name is set");
+    }
+
+    #[test]
+    fn includes_are_not_config_keys() {
         let toml = r#"
-        core.excludesfile = ["a", "b"]
+        user.name = "User"
+
+        [includes.aliases]
+        alias.co = "checkout"
+        alias.st = "status"
+        "#;
+
+        let profile = toml::from_str::<Profile>(toml).unwrap();
+        assert_eq!(profile.configs.len(), 1, "only user.name is set");
+        assert_eq!(profile.includes["aliases"].len(), 2);
+    }
+
+    #[test]
+    fn write_includes_regenerates_and_prunes_files() {
+        let dir = std::env::temp_dir().join(format!("warden-includes-{}", std::process::id()));
+        let mut profile = toml::from_str::<Profile>(
+            r#"
+            [includes.aliases]
+            alias.co = "checkout"
+            "#,
+        )
+        .unwrap();
+
+        let written = profile.write_includes(&dir).unwrap();
+        assert_eq!(written, [dir.join("aliases.gitconfig")]);
+        let cfg = git2::Config::open(&written[0]).unwrap();
+        assert_eq!(cfg.get_string("alias.co").unwrap(), "checkout");
+
+        profile.includes.clear();
+        assert!(
+            profile.write_includes(&dir).unwrap().is_empty(),
+            "no includes left"
+        );
+        assert!(!written[0].exists(), "stale include must be removed");
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn reject_nested_arrays_in_configs() {
+        let toml = r#"
+        core.excludesfile = [["a"], "b"]
         "#;
 
         let res = toml::from_str::<Profile>(toml);
-        assert!(res.is_err(), "arrays must be rejected");
+        assert!(res.is_err(), "nested arrays must be rejected");
     }
 
     #[test]
@@ -332,7 +757,7 @@ mod tests {
         // We can't guarantee a repo is available in tests, so just ensure method exists
         // and returns Result.
         let p = Profile::default();
-        let res = p.apply();
+        let res = p.apply("empty", Scope::Local, None);
         // Either ok (if tests are run inside a git repo) or an error about not being in
         // a repo.
         if let Err(e) = res {
@@ -345,4 +770,90 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn profiles_apply_to_the_nearest_repository() {
+        let dir = crate::utils::tests::checkouts("profile-apply");
+        let config = |path: &str| {
+            git2::Config::open(&dir.join(path))
+                .unwrap()
+                .get_string("user.email")
+                .ok()
+        };
+        let profile: Profile = toml::from_str("user.email = \"sub@example.com\"").unwrap();
+
+        profile
+            .apply("sub", Scope::Local, Some(&dir.join("main/sub")))
+            .unwrap();
+        assert_eq!(
+            config("main/.git/modules/sub/config").as_deref(),
+            Some("sub@example.com"),
+            "submodule"
+        );
+        assert_eq!(config("main/.git/config"), None, "superproject untouched");
+
+        profile
+            .apply("wt", Scope::Worktree, Some(&dir.join("wt")))
+            .unwrap();
+        assert_eq!(
+            config("main/.git/worktrees/wt/config.worktree").as_deref(),
+            Some("sub@example.com"),
+            "linked worktree"
+        );
+        assert!(
+            profile
+                .apply("bare", Scope::Worktree, Some(&dir.join("bare.git")))
+                .is_err(),
+            "bare repositories have no worktree"
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn managed_files_are_materialized_and_removed() {
+        let dir = std::env::temp_dir().join(format!("warden-managed-{}", std::process::id()));
+        let repo = git2::Repository::init(dir.join("repo")).unwrap();
+        fs::create_dir_all(dir.join("hooks")).unwrap();
+        fs::write(dir.join("hooks/commit-msg"), "#!/bin/sh\n").unwrap();
+        fs::write(dir.join("template.txt"), "feat: \n").unwrap();
+        let profile: Profile = toml::from_str(&format!(
+            "files.commit_template = {:?}\nfiles.hooks = {:?}",
+            dir.join("template.txt"),
+            dir.join("hooks")
+        ))
+        .unwrap();
+        let config = |key: &str| {
+            repo.config()
+                .unwrap()
+                .open_level(ConfigLevel::Local)
+                .unwrap()
+                .get_path(key)
+                .ok()
+        };
+        let managed = repo.path().join("warden");
+
+        profile
+            .apply("work", Scope::Local, Some(repo.path()))
+            .unwrap();
+        let template = config("commit.template").unwrap();
+        assert_eq!(template, managed.join(COMMIT_TEMPLATE));
+        assert_eq!(fs::read_to_string(template).unwrap(), "feat: \n");
+        let hooks = config("core.hooksPath").unwrap();
+        assert!(hooks.join("commit-msg").is_file(), "hooks copied");
+
+        Profile::default()
+            .apply("plain", Scope::Local, Some(repo.path()))
+            .unwrap();
+        assert_eq!(config("commit.template"), None, "dropped with the profile");
+        assert!(!managed.exists(), "files removed");
+
+        profile
+            .apply("work", Scope::Local, Some(repo.path()))
+            .unwrap();
+        unapply(Scope::Local, Some(repo.path())).unwrap();
+        assert_eq!(config("core.hooksPath"), None, "unapplied");
+        assert!(!managed.exists(), "files removed");
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
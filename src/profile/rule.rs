@@ -5,31 +5,207 @@
 // Local modifications:
 // Copyright (c) 2025 Adolar0042
 
+use std::sync::LazyLock;
+
+use regex::{Captures, Regex};
 use serde::Deserialize;
 
 use crate::profile::url::Url;
 
+/// Parts of the matched URL a rule's profile name can refer to, as
+/// `{{host}}`, `{{owner}}`, `{{repo}}` or `{{user}}`
+const PLACEHOLDERS: [&str; 4] = ["host", "owner", "repo", "user"];
+
+static PLACEHOLDER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\{\{(\w*)\}\}").expect("valid placeholder regex"));
+
 #[derive(Clone, Debug, Deserialize)]
 pub struct ProfileRef {
     pub name: String,
 }
 
+impl ProfileRef {
+    /// This reference with the placeholders in its name filled in from `url`,
+    /// e.g. `client-{{owner}}` becomes `client-acme` for `github.com/acme/app`
+    pub fn render(&self, url: &Url) -> Self {
+        let name = PLACEHOLDER.replace_all(&self.name, |caps: &Captures<'_>| {
+            match &caps[1] {
+                "host" => url.host.to_string(),
+                "owner" => url.owner.clone(),
+                "repo" => url.repo.clone(),
+                "user" => url.user.clone().unwrap_or_default(),
+                _ => caps[0].to_string(),
+            }
+        });
+        Self {
+            name: name.into_owned(),
+        }
+    }
+
+    /// Whether the name has placeholders to fill in
+    pub fn is_template(&self) -> bool {
+        PLACEHOLDER.is_match(&self.name)
+    }
+
+    /// Placeholders in the name that [`Self::render`] doesn't know
+    pub fn unknown_placeholders(&self) -> Vec<&str> {
+        PLACEHOLDER
+            .captures_iter(&self.name)
+            .filter_map(|caps| caps.get(1))
+            .map(|m| m.as_str())
+            .filter(|name| !PLACEHOLDERS.contains(name))
+            .collect()
+    }
+
+    /// Whether `name` is a name this reference can render to
+    pub fn could_render_to(&self, name: &str) -> bool {
+        let mut pattern = String::from("^");
+        let mut last = 0;
+        for m in PLACEHOLDER.find_iter(&self.name) {
+            pattern.push_str(&regex::escape(&self.name[last..m.start()]));
+            pattern.push_str(".+");
+            last = m.end();
+        }
+        pattern.push_str(&regex::escape(&self.name[last..]));
+        pattern.push('$');
+        Regex::new(&pattern).is_ok_and(|re| re.is_match(name))
+    }
+}
+
 #[derive(Clone, Debug, Deserialize)]
 pub struct Rule {
     pub profile: ProfileRef,
     pub host: Option<String>,
     pub owner: Option<String>,
     pub repo: Option<String>,
+    /// Hosts this rule never matches.
+    #[serde(default)]
+    pub not_host: Vec<String>,
+    /// Owners this rule never matches, `group/*` excludes a whole group.
+    #[serde(default)]
+    pub not_owner: Vec<String>,
+    /// Repositories this rule never matches.
+    #[serde(default)]
+    pub not_repo: Vec<String>,
+    /// Rules with a higher priority are evaluated first, ties keep file
+    /// order.
+    #[serde(default)]
+    pub priority: i64,
+}
+
+/// Compares a rule's expected value with the URL's actual one.
+type Matcher = fn(&str, &str) -> bool;
+
+/// Outcome of comparing one constrained rule field against a URL.
+///
+/// For exclusions (`negated`), `matched` means the URL is *not* the excluded
+/// value.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FieldCheck<'a> {
+    pub field: &'static str,
+    pub expected: &'a str,
+    pub actual: String,
+    pub negated: bool,
+    pub matched: bool,
 }
 
 impl Rule {
     pub fn matches(&self, url: &Url) -> bool {
-        let url_host = format!("{}", url.host);
-        let host_match = self.host.as_deref().is_none_or(|h| h == url_host);
-        let owner_match = self.owner.as_deref().is_none_or(|o| o == url.owner);
-        let repo_match = self.repo.as_deref().is_none_or(|r| r == url.repo);
-        host_match && owner_match && repo_match
+        self.check(url).iter().all(|check| check.matched)
     }
+
+    /// Compare every field this rule constrains against `url`. Fields the
+    /// rule leaves unset match anything and are omitted, each exclusion is
+    /// its own check.
+    pub fn check(&self, url: &Url) -> Vec<FieldCheck<'_>> {
+        let url_host = url.host.to_string();
+        let exact: Matcher = |a, b| a == b;
+        let fields = [
+            (
+                "host",
+                self.host.as_deref(),
+                &self.not_host,
+                url_host.as_str(),
+                exact,
+            ),
+            (
+                "owner",
+                self.owner.as_deref(),
+                &self.not_owner,
+                &url.owner,
+                owner_matches,
+            ),
+            (
+                "repo",
+                self.repo.as_deref(),
+                &self.not_repo,
+                &url.repo,
+                exact,
+            ),
+        ];
+
+        let mut checks = Vec::new();
+        for (field, expected, excluded, actual, eq) in fields {
+            if let Some(expected) = expected {
+                checks.push(FieldCheck {
+                    field,
+                    expected,
+                    actual: actual.to_string(),
+                    negated: false,
+                    matched: eq(expected, actual),
+                });
+            }
+            for expected in excluded {
+                checks.push(FieldCheck {
+                    field,
+                    expected,
+                    actual: actual.to_string(),
+                    negated: true,
+                    matched: !eq(expected, actual),
+                });
+            }
+        }
+        checks
+    }
+
+    /// Whether every URL matched by `later` is already matched by this rule,
+    /// making `later` unreachable when this rule is evaluated first.
+    pub fn shadows(&self, later: &Self) -> bool {
+        let no_exclusions =
+            self.not_host.is_empty() && self.not_owner.is_empty() && self.not_repo.is_empty();
+        let covers =
+            |mine: Option<&str>, theirs: Option<&str>| mine.is_none_or(|m| theirs == Some(m));
+        let owner_covered = self.owner.as_deref().is_none_or(|mine| {
+            later.owner.as_deref().is_some_and(|theirs| {
+                match (mine.ends_with("/*"), theirs.strip_suffix("/*")) {
+                    // an exact owner can't cover a whole group
+                    (false, Some(_)) => false,
+                    (_, Some(group)) => owner_matches(mine, group),
+                    (_, None) => owner_matches(mine, theirs),
+                }
+            })
+        });
+        no_exclusions
+            && covers(self.host.as_deref(), later.host.as_deref())
+            && owner_covered
+            && covers(self.repo.as_deref(), later.repo.as_deref())
+    }
+}
+
+/// Matches an owner against a rule's `owner`.
+///
+/// A trailing `/*` matches the group itself and any subgroup below it, so
+/// `group/*` matches `group`, `group/sub` and `group/sub/deeper`. Everything
+/// else must match exactly.
+fn owner_matches(pattern: &str, owner: &str) -> bool {
+    pattern.strip_suffix("/*").map_or_else(
+        || pattern == owner,
+        |group| {
+            owner
+                .strip_prefix(group)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+        },
+    )
 }
 
 #[derive(Clone, Debug, Default, Deserialize)]
@@ -37,6 +213,182 @@ pub struct Rules(Vec<Rule>);
 
 impl Rules {
     pub fn resolve(&self, url: &Url) -> Option<&Rule> {
-        self.0.iter().find(|rule| rule.matches(url))
+        self.iter().find(|rule| rule.matches(url))
+    }
+
+    /// Iterate the rules in evaluation order: highest priority first, rules
+    /// sharing a priority in file order.
+    pub fn iter(&self) -> impl Iterator<Item = &Rule> {
+        let mut ordered: Vec<&Rule> = self.0.iter().collect();
+        // stable, so ties keep their position in the file
+        ordered.sort_by_key(|rule| std::cmp::Reverse(rule.priority));
+        ordered.into_iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profile::url::Patterns;
+
+    fn rule(host: Option<&str>, owner: Option<&str>, repo: Option<&str>) -> Rule {
+        Rule {
+            profile: ProfileRef {
+                name: "work".to_string(),
+            },
+            host: host.map(str::to_string),
+            owner: owner.map(str::to_string),
+            repo: repo.map(str::to_string),
+            not_host: Vec::new(),
+            not_owner: Vec::new(),
+            not_repo: Vec::new(),
+            priority: 0,
+        }
+    }
+
+    #[test]
+    fn check_reports_each_constrained_field() {
+        let url = Url::from_str("git@github.com:org/repo.git", &Patterns::default(), None).unwrap();
+        let r = rule(Some("github.com"), Some("other"), None);
+        let checks = r.check(&url);
+        assert_eq!(checks.len(), 2, "unset fields are omitted");
+        assert!(checks[0].matched, "host should match");
+        assert_eq!(checks[1].field, "owner");
+        assert_eq!(checks[1].actual, "org");
+        assert!(!checks[1].matched, "owner should not match");
+        assert!(
+            rule(None, None, None).matches(&url),
+            "empty rule matches anything"
+        );
+    }
+
+    #[test]
+    fn profile_names_are_filled_from_the_url() {
+        let url = Url::from_str("git@github.com:acme/app.git", &Patterns::default(), None).unwrap();
+        let profile = ProfileRef {
+            name: "client-{{owner}}-{{repo}}@{{host}}".into(),
+        };
+        assert_eq!(profile.render(&url).name, "client-acme-app@github.com");
+        assert!(profile.is_template(), "template");
+        assert!(
+            profile.could_render_to("client-acme-app@github.com"),
+            "rendered"
+        );
+        assert!(
+            !profile.could_render_to("client--app@github.com"),
+            "empty owner"
+        );
+        assert!(
+            profile.unknown_placeholders().is_empty(),
+            "known placeholders"
+        );
+
+        let typo = ProfileRef {
+            name: "client-{{org}}".into(),
+        };
+        assert_eq!(typo.render(&url).name, "client-{{org}}", "unknown kept");
+        assert_eq!(typo.unknown_placeholders(), ["org"]);
+        assert!(
+            !ProfileRef {
+                name: "work".into()
+            }
+            .is_template(),
+            "plain name"
+        );
+    }
+
+    #[test]
+    fn owner_exact_match() {
+        assert!(owner_matches("group", "group"), "exact owner should match");
+        assert!(
+            !owner_matches("group", "group/sub"),
+            "subgroup needs a wildcard"
+        );
+    }
+
+    #[test]
+    fn owner_subgroup_wildcard() {
+        assert!(owner_matches("group/*", "group"), "group itself matches");
+        assert!(owner_matches("group/*", "group/sub"), "subgroup matches");
+        assert!(
+            owner_matches("group/*", "group/sub/deeper"),
+            "nested subgroup matches"
+        );
+        assert!(
+            !owner_matches("group/*", "groupie"),
+            "prefix must end at a segment boundary"
+        );
+        assert!(
+            owner_matches("group/sub/*", "group/sub/deeper"),
+            "wildcard below a subgroup"
+        );
+        assert!(
+            !owner_matches("group/sub/*", "group"),
+            "parent group does not match"
+        );
+    }
+
+    #[test]
+    fn higher_priority_wins_and_ties_keep_file_order() {
+        let url = Url::from_str("git@github.com:org/repo.git", &Patterns::default(), None).unwrap();
+        let named = |name: &str, priority: i64| {
+            Rule {
+                profile: ProfileRef {
+                    name: name.to_string(),
+                },
+                priority,
+                ..rule(Some("github.com"), None, None)
+            }
+        };
+        let rules = Rules(vec![named("first", 0), named("second", 0)]);
+        assert_eq!(rules.resolve(&url).unwrap().profile.name, "first");
+
+        let rules = Rules(vec![named("low", 0), named("high", 10), named("neg", -1)]);
+        let order: Vec<_> = rules.iter().map(|r| r.profile.name.as_str()).collect();
+        assert_eq!(order, ["high", "low", "neg"]);
+        assert_eq!(rules.resolve(&url).unwrap().profile.name, "high");
+    }
+
+    #[test]
+    fn exclusions_veto_a_match() {
+        let rule = Rule {
+            not_owner: vec!["oss".to_string(), "friends/*".to_string()],
+            ..rule(Some("github.com"), None, None)
+        };
+        let parse = |s: &str| Url::from_str(s, &Patterns::default(), None).unwrap();
+        assert!(
+            rule.matches(&parse("git@github.com:company/repo.git")),
+            "other owners still match"
+        );
+        assert!(
+            !rule.matches(&parse("git@github.com:oss/repo.git")),
+            "excluded owner must not match"
+        );
+        assert!(
+            !rule.matches(&parse("https://github.com/friends/sub/repo")),
+            "excluded group must not match"
+        );
+    }
+
+    #[test]
+    fn broader_rules_shadow_narrower_ones() {
+        let host = rule(Some("github.com"), None, None);
+        let owner = rule(Some("github.com"), Some("group/sub"), None);
+        let group = rule(Some("github.com"), Some("group/*"), None);
+        assert!(host.shadows(&owner), "host-only rule covers any owner");
+        assert!(group.shadows(&owner), "group wildcard covers its subgroup");
+        assert!(
+            !owner.shadows(&host),
+            "narrow rule does not cover a broad one"
+        );
+        assert!(
+            !rule(Some("github.com"), Some("group"), None).shadows(&group),
+            "exact owner does not cover the group wildcard"
+        );
+        let excluding = Rule {
+            not_owner: vec!["x".to_string()],
+            ..host
+        };
+        assert!(!excluding.shadows(&owner), "exclusions are not analyzed");
     }
 }
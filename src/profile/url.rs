@@ -8,21 +8,29 @@
 use std::convert::Infallible;
 use std::fmt::{Display, Formatter};
 use std::str::FromStr;
-use std::sync::LazyLock;
+use std::sync::{LazyLock, OnceLock};
 
-use anyhow::{Error, Result, anyhow, bail};
+use anyhow::{Context as _, Error, Result, anyhow, bail};
 use regex::Regex;
 use serde::Deserialize;
 use serde_with::DeserializeFromStr;
 
+use crate::utils::split_port;
+
 const GIT_EXTENSION: &str = ".git";
 const EXTENSIONS: &[&str] = &[GIT_EXTENSION];
 
+/// Hostname, underscores are tolerated for internal hosts, or a bracketed IPv6
+/// literal
+const HOST: &str = r"(?:\[[0-9A-Fa-f:\.]+\]|[0-9A-Za-z_\.\-]+)";
+/// Owner path, possibly nested (GitLab subgroups: `group/subgroup`)
+const OWNER: &str = r"[0-9A-Za-z_\.\-]+(?:/[0-9A-Za-z_\.\-]+)*";
+
 static SSH: LazyLock<Pattern> = LazyLock::new(|| {
     Pattern::from(
-        Regex::new(
-            r"^(?P<user>[0-9A-Za-z\-]+)@(?P<host>[0-9A-Za-z\.\-]+):(?P<owner>[0-9A-Za-z_\.\-]+)/(?P<repo>[0-9A-Za-z_\.\-]+)$",
-        )
+        Regex::new(&format!(
+            r"^(?P<user>[0-9A-Za-z_\.\-]+)@(?P<host>{HOST}):(?P<owner>{OWNER})/(?P<repo>[0-9A-Za-z_\.\-]+)$",
+        ))
         .unwrap(),
     )
     .with_scheme(Scheme::Ssh)
@@ -31,9 +39,9 @@ static SSH: LazyLock<Pattern> = LazyLock::new(|| {
 
 static HOST_ORG_REPO: LazyLock<Pattern> = LazyLock::new(|| {
     Pattern::from(
-        Regex::new(
-            r"^(?P<host>[0-9A-Za-z\.\-]+)[:/](?P<owner>[0-9A-Za-z_\.\-]+)/(?P<repo>[0-9A-Za-z_\.\-]+)$",
-        )
+        Regex::new(&format!(
+            r"^(?P<host>{HOST}(?::[0-9]+)?)[:/](?P<owner>{OWNER})/(?P<repo>[0-9A-Za-z_\.\-]+)$",
+        ))
         .unwrap(),
     )
     .with_infer()
@@ -41,7 +49,10 @@ static HOST_ORG_REPO: LazyLock<Pattern> = LazyLock::new(|| {
 
 static ORG_REPO: LazyLock<Pattern> = LazyLock::new(|| {
     Pattern::from(
-        Regex::new(r"^(?P<owner>[0-9A-Za-z_\.\-]+)/(?P<repo>[0-9A-Za-z_\.\-]+)$").unwrap(),
+        Regex::new(&format!(
+            r"^(?P<owner>{OWNER})/(?P<repo>[0-9A-Za-z_\.\-]+)$"
+        ))
+        .unwrap(),
     )
     .with_infer()
 });
@@ -114,8 +125,7 @@ pub struct Pattern {
     /// Regular expression used to match the input. Must include at least
     /// `(?P<repo>...)`. May optionally include `vcs`, `scheme`, `user`,
     /// `host`, and `owner` named groups.
-    #[serde(with = "serde_regex")]
-    regex: Regex,
+    regex: LazyRegex,
     /// Default VCS when not captured by the regex (currently only "git").
     vcs: Option<Vcs>,
     /// Default URL scheme when not captured by the regex ("https" or "ssh").
@@ -136,7 +146,45 @@ pub struct Pattern {
     infer: Option<bool>,
 }
 
+/// A regular expression compiled when it's first matched against, so
+/// patterns after the one that matches are never compiled
+#[derive(Debug, Clone, Deserialize)]
+#[serde(from = "String")]
+struct LazyRegex {
+    source: String,
+    compiled: OnceLock<Result<Regex, regex::Error>>,
+}
+
+impl From<String> for LazyRegex {
+    fn from(source: String) -> Self {
+        Self {
+            source,
+            compiled: OnceLock::new(),
+        }
+    }
+}
+
 impl Pattern {
+    /// The regular expression this pattern matches with, as written
+    #[inline]
+    pub fn source(&self) -> &str {
+        &self.regex.source
+    }
+
+    /// The compiled regular expression, compiling it on first use
+    pub fn regex(&self) -> Result<&Regex> {
+        self.regex
+            .compiled
+            .get_or_init(|| Regex::new(&self.regex.source))
+            .as_ref()
+            .map_err(|err| anyhow!("Invalid regex {:?}: {err}", self.regex.source))
+    }
+
+    /// Whether the regular expression was compiled already
+    pub fn is_compiled(&self) -> bool {
+        self.regex.compiled.get().is_some()
+    }
+
     #[inline]
     pub const fn with_scheme(mut self, s: Scheme) -> Self {
         self.scheme = Some(s);
@@ -150,7 +198,7 @@ impl Pattern {
     }
 
     pub fn matches(&self, s: &str) -> Option<Match> {
-        let c = self.regex.captures(s)?;
+        let c = self.regex().ok()?.captures(s)?;
         let repo_cap = c.name("repo")?;
         let repo = repo_cap.as_str().to_string();
 
@@ -210,7 +258,10 @@ impl Pattern {
 impl From<Regex> for Pattern {
     fn from(value: Regex) -> Self {
         Self {
-            regex: value,
+            regex: LazyRegex {
+                source: value.as_str().to_string(),
+                compiled: OnceLock::from(Ok(value)),
+            },
             vcs: None,
             scheme: None,
             user: None,
@@ -251,6 +302,22 @@ impl Patterns {
     pub fn matches(&self, s: &str) -> Option<Match> {
         self.0.iter().find_map(|p| p.matches(s))
     }
+
+    /// Compile every pattern not compiled yet, failing on the first invalid
+    /// one
+    pub fn compile(&self) -> Result<()> {
+        for (i, pattern) in self.0.iter().enumerate() {
+            pattern
+                .regex()
+                .with_context(|| format!("Invalid pattern #{}", i + 1))?;
+        }
+        Ok(())
+    }
+
+    /// Iterate over the patterns in the order they are tried
+    pub fn iter(&self) -> impl Iterator<Item = &Pattern> {
+        self.0.iter()
+    }
 }
 
 impl Default for Patterns {
@@ -351,6 +418,16 @@ impl FromStr for Host {
     }
 }
 
+impl Host {
+    /// Explicit port carried by the host, if any
+    pub fn port(&self) -> Option<u16> {
+        match self {
+            Self::Unknown(s) => split_port(s).1.and_then(|p| p.parse().ok()),
+            Self::GitHub | Self::GitLab | Self::Codeberg => None,
+        }
+    }
+}
+
 impl Display for Host {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -385,11 +462,27 @@ impl Url {
     }
 
     pub fn from_url(url: &url::Url) -> Result<Self> {
-        let mut segments = url
+        let mut segments: Vec<&str> = url
             .path_segments()
-            .ok_or_else(|| anyhow!("Could not parse path segments from the URL: {url}"))?;
+            .ok_or_else(|| anyhow!("Could not parse path segments from the URL: {url}"))?
+            .filter(|s| !s.is_empty())
+            .collect();
+        // everything before the last segment is the (possibly nested) owner
+        let repo = segments
+            .pop()
+            .ok_or_else(|| anyhow!("Could not find repository name from the URL: {url}"))?;
+        if segments.is_empty() {
+            bail!("Could not find owner from the URL: {url}");
+        }
 
         let scheme = Scheme::from_str(url.scheme())?;
+        let host = url
+            .host_str()
+            .ok_or_else(|| anyhow!("Could not find hostname from the URL: {url}"))?;
+        // `url` already drops ports that are the scheme's default
+        let host = url
+            .port()
+            .map_or_else(|| host.to_string(), |port| format!("{host}:{port}"));
 
         Ok(Self {
             vcs: Vcs::from_url(url),
@@ -399,19 +492,9 @@ impl Url {
             } else {
                 Some(url.username().to_string())
             },
-            host: Host::from_str(
-                url.host_str()
-                    .ok_or_else(|| anyhow!("Could not find hostname from the URL: {url}"))?,
-            )?,
-            owner: segments
-                .next()
-                .ok_or_else(|| anyhow!("Could not find owner from the URL: {url}"))?
-                .to_string(),
-            repo: Self::remove_extensions(
-                segments
-                    .next()
-                    .ok_or_else(|| anyhow!("Could not find repository name from the URL: {url}"))?,
-            ),
+            host: Host::from_str(&host)?,
+            owner: segments.join("/"),
+            repo: Self::remove_extensions(repo),
             raw: match scheme {
                 // HTTPS URLs can be used directly on cloning, so we prefer it than inferred one.
                 // SSH URLs are not; Git only accepts 'git@github.com:org/repo.git' style.
@@ -421,7 +504,7 @@ impl Url {
         })
     }
 
-    fn from_match(m: Match, default_owner: Option<&str>) -> Option<Self> {
+    pub fn from_match(m: Match, default_owner: Option<&str>) -> Option<Self> {
         Some(Self {
             vcs: m.vcs.unwrap_or_default(),
             scheme: m.scheme.unwrap_or_default(),
@@ -474,6 +557,17 @@ impl Display for Url {
                     self.vcs.extension()
                 )
             },
+            // scp-like syntax cannot carry a port, fall back to an ssh:// URL
+            Scheme::Ssh if self.host.port().is_some() => {
+                write!(
+                    f,
+                    "ssh://{}/{}/{}{}",
+                    authority,
+                    self.owner,
+                    self.repo,
+                    self.vcs.extension()
+                )
+            },
             Scheme::Ssh => {
                 write!(
                     f,
@@ -637,6 +731,127 @@ mod tests {
         );
     }
 
+    #[test]
+    fn parse_from_url_ssh_ipv6_with_port() {
+        let url = url::Url::parse("ssh://git@[::1]:2222/username/repo.git").unwrap();
+
+        let parsed = Url::from_url(&url).unwrap();
+        assert_eq!(parsed.host, Host::Unknown("[::1]:2222".to_string()));
+        assert_eq!(parsed.host.port(), Some(2222));
+        assert_eq!(parsed.owner, "username");
+        assert_eq!(parsed.repo, "repo");
+        assert_eq!(parsed.to_string(), "ssh://git@[::1]:2222/username/repo.git");
+    }
+
+    #[test]
+    fn parse_from_url_https_custom_port() {
+        let url = url::Url::parse("https://git_internal.corp:8443/username/repo.git").unwrap();
+
+        let parsed = Url::from_url(&url).unwrap();
+        assert_eq!(
+            parsed.host,
+            Host::Unknown("git_internal.corp:8443".to_string())
+        );
+        assert_eq!(parsed.owner, "username");
+    }
+
+    #[test]
+    fn parse_from_url_drops_default_port() {
+        let url = url::Url::parse("https://github.com:443/username/repo.git").unwrap();
+
+        assert_eq!(Url::from_url(&url).unwrap().host, Host::GitHub);
+    }
+
+    #[test]
+    fn parse_from_pattern_ssh_ipv6() {
+        assert_eq!(
+            Url {
+                vcs: Vcs::Git,
+                scheme: Scheme::Ssh,
+                user: Some("git".to_string()),
+                host: Host::Unknown("[::1]".to_string()),
+                owner: "username".to_string(),
+                repo: "repo".to_string(),
+                ..Default::default()
+            },
+            Url::from_pattern("git@[::1]:username/repo.git", &Patterns::default(), None).unwrap(),
+        );
+    }
+
+    #[test]
+    fn parse_from_pattern_underscored_host_and_user() {
+        let parsed = Url::from_pattern(
+            "deploy_bot@git_mirror.lan:username/repo.git",
+            &Patterns::default(),
+            None,
+        )
+        .unwrap();
+        assert_eq!(parsed.user.as_deref(), Some("deploy_bot"));
+        assert_eq!(parsed.host, Host::Unknown("git_mirror.lan".to_string()));
+    }
+
+    #[test]
+    fn parse_from_pattern_host_with_port() {
+        let parsed = Url::from_pattern(
+            "gitlab.example.com:8443/username/repo",
+            &Patterns::default(),
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            parsed.host,
+            Host::Unknown("gitlab.example.com:8443".to_string())
+        );
+        assert_eq!(parsed.owner, "username");
+
+        // without a port the colon still separates host and owner
+        let parsed =
+            Url::from_pattern("gitlab.com:username/repo", &Patterns::default(), None).unwrap();
+        assert_eq!(parsed.host, Host::GitLab);
+    }
+
+    #[test]
+    fn parse_from_url_subgroups() {
+        let url = url::Url::parse("https://gitlab.com/group/subgroup/project.git").unwrap();
+
+        let parsed = Url::from_url(&url).unwrap();
+        assert_eq!(parsed.host, Host::GitLab);
+        assert_eq!(parsed.owner, "group/subgroup");
+        assert_eq!(parsed.repo, "project");
+    }
+
+    #[test]
+    fn parse_from_url_requires_owner() {
+        let url = url::Url::parse("https://gitlab.com/project.git").unwrap();
+
+        Url::from_url(&url).unwrap_err();
+    }
+
+    #[test]
+    fn parse_from_pattern_subgroups() {
+        let parsed = Url::from_pattern(
+            "git@gitlab.com:group/subgroup/deeper/project.git",
+            &Patterns::default(),
+            None,
+        )
+        .unwrap();
+        assert_eq!(parsed.owner, "group/subgroup/deeper");
+        assert_eq!(parsed.repo, "project");
+        assert_eq!(
+            parsed.to_string(),
+            "git@gitlab.com:group/subgroup/deeper/project.git"
+        );
+
+        let parsed = Url::from_pattern(
+            "gitlab.com/group/subgroup/project",
+            &Patterns::default(),
+            None,
+        )
+        .unwrap();
+        assert_eq!(parsed.host, Host::GitLab);
+        assert_eq!(parsed.owner, "group/subgroup");
+    }
+
     #[test]
     fn to_string_https() {
         assert_eq!(
@@ -0,0 +1,94 @@
+//! Secrets held in memory besides tokens, such as OAuth client secrets.
+//!
+//! A [`Secret`] is wiped from memory when dropped and shows up as `***` in
+//! `Debug` output, so structs holding one can derive `Debug` and be recorded
+//! by `#[instrument]` without leaking it.
+
+use std::fmt::{Debug, Formatter};
+
+use serde::Deserialize;
+use zeroize::Zeroizing;
+
+#[derive(Clone, PartialEq, Eq, Deserialize)]
+#[serde(from = "String")]
+pub struct Secret(Zeroizing<String>);
+
+impl Secret {
+    /// The secret itself, for handing it to the provider
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Debug for Secret {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Secret(***)")
+    }
+}
+
+impl From<String> for Secret {
+    fn from(secret: String) -> Self {
+        Self(Zeroizing::new(secret))
+    }
+}
+
+impl From<Zeroizing<String>> for Secret {
+    fn from(secret: Zeroizing<String>) -> Self {
+        Self(secret)
+    }
+}
+
+impl From<&str> for Secret {
+    fn from(secret: &str) -> Self {
+        Self::from(secret.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::OAuthConfig;
+    use crate::keyring::Token;
+    use crate::utils::parse_credential_input;
+
+    /// Debug output of everything holding secrets, which must not show them
+    #[test]
+    fn debug_output_never_shows_secrets() {
+        let config: OAuthConfig = toml::from_str(
+            r#"
+            [providers."github.com"]
+            client_id = "id"
+            client_secret = "client-s3cret"
+            "#,
+        )
+        .unwrap();
+        let token = Token::new("access-s3cret".into(), Some("refresh-s3cret".into()), None);
+        let request = parse_credential_input(
+            b"protocol=https\nhost=github.com\nusername=oauth\npassword=access-s3cret\n\
+              oauth_refresh_token=refresh-s3cret\n\n"
+                .as_slice(),
+        )
+        .unwrap();
+        let printed = [
+            format!("{config:?}"),
+            format!("{:#?}", config.providers["github.com"]),
+            format!("{token:?}"),
+            format!("{token}"),
+            format!("{request:#?}"),
+        ];
+        for text in printed {
+            assert!(!text.contains("s3cret"), "secret shown: {text}");
+        }
+        assert_eq!(
+            config.providers["github.com"]
+                .client_secret
+                .as_ref()
+                .map(Secret::expose),
+            Some("client-s3cret")
+        );
+        assert_eq!(
+            request.oauth_refresh_token.as_ref().map(Secret::expose),
+            Some("refresh-s3cret")
+        );
+    }
+}
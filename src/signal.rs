@@ -0,0 +1,127 @@
+//! Ctrl-C handling and the deadline of `get` for the whole process.
+//!
+//! A single handler, installed at startup, restores the terminal first. If an
+//! OAuth flow is running, it is cancelled so its callback listener and
+//! progress output are torn down before warden exits, otherwise (e.g. in a
+//! prompt) the process exits right away. Either way the exit code is
+//! [`EXIT_CODE`], and a second Ctrl-C always exits immediately.
+//!
+//! The [`watchdog`] does the same once its deadline passes, so a `get` left
+//! waiting by a forgotten background `git fetch` doesn't hold a callback
+//! port forever. It exits with [`TIMEOUT_EXIT_CODE`].
+
+use std::io::stderr;
+use std::process::exit;
+use std::sync::LazyLock;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Duration;
+use std::{fmt, thread};
+
+use crossterm::cursor::Show;
+use crossterm::execute;
+use crossterm::terminal::disable_raw_mode;
+use tokio::sync::Notify;
+use tracing::warn;
+
+/// Exit code after an interrupt, 128 + SIGINT like shells use
+pub const EXIT_CODE: i32 = 130;
+/// Exit code after the watchdog's deadline, like timeout(1) uses
+pub const TIMEOUT_EXIT_CODE: i32 = 124;
+/// Time a cancelled OAuth flow gets to wind down before the watchdog exits
+const TIMEOUT_GRACE: Duration = Duration::from_secs(5);
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+static TIMED_OUT: AtomicBool = AtomicBool::new(false);
+/// Number of running [`cancellable`] futures
+static CANCELLABLE: AtomicUsize = AtomicUsize::new(0);
+static CANCEL: LazyLock<Notify> = LazyLock::new(Notify::new);
+
+/// Error of an operation cancelled by Ctrl-C
+#[derive(Debug)]
+pub struct Interrupted;
+
+impl fmt::Display for Interrupted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Interrupted")
+    }
+}
+
+impl std::error::Error for Interrupted {}
+
+/// Install the process-wide Ctrl-C handler.
+pub fn install() {
+    let result = ctrlc::set_handler(|| {
+        restore_terminal();
+        let repeated = INTERRUPTED.swap(true, Ordering::SeqCst);
+        if repeated || CANCELLABLE.load(Ordering::SeqCst) == 0 {
+            exit(EXIT_CODE);
+        }
+        CANCEL.notify_waiters();
+    });
+    if let Err(err) = result {
+        warn!("Failed to install the Ctrl-C handler: {err}");
+    }
+}
+
+/// Cancel what is running after `deadline` like Ctrl-C does, and exit if it
+/// doesn't wind down shortly after.
+pub fn watchdog(deadline: Duration) {
+    thread::spawn(move || {
+        thread::sleep(deadline);
+        TIMED_OUT.store(true, Ordering::SeqCst);
+        restore_terminal();
+        warn!("Giving up after {}s", deadline.as_secs());
+        let repeated = INTERRUPTED.swap(true, Ordering::SeqCst);
+        if repeated || CANCELLABLE.load(Ordering::SeqCst) == 0 {
+            exit(TIMEOUT_EXIT_CODE);
+        }
+        CANCEL.notify_waiters();
+        thread::sleep(TIMEOUT_GRACE);
+        exit(TIMEOUT_EXIT_CODE);
+    });
+}
+
+/// The exit code for an error that [`is_interrupted`]
+pub fn exit_code() -> i32 {
+    if TIMED_OUT.load(Ordering::SeqCst) {
+        TIMEOUT_EXIT_CODE
+    } else {
+        EXIT_CODE
+    }
+}
+
+/// Show the cursor again and leave raw mode, in case a prompt or spinner was
+/// interrupted.
+pub fn restore_terminal() {
+    let _ = disable_raw_mode();
+    let _ = execute!(stderr(), Show);
+}
+
+/// Run `fut` until it completes or Ctrl-C is pressed, in which case it is
+/// dropped and [`Interrupted`] returned.
+pub async fn cancellable<F: Future>(fut: F) -> Result<F::Output, Interrupted> {
+    struct Registered;
+    impl Drop for Registered {
+        fn drop(&mut self) {
+            CANCELLABLE.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    let _registered = {
+        CANCELLABLE.fetch_add(1, Ordering::SeqCst);
+        Registered
+    };
+    let cancelled = CANCEL.notified();
+    if INTERRUPTED.load(Ordering::SeqCst) {
+        return Err(Interrupted);
+    }
+    tokio::select! {
+        output = fut => Ok(output),
+        () = cancelled => Err(Interrupted),
+    }
+}
+
+/// Whether `err` was caused by Ctrl-C
+pub fn is_interrupted(err: &anyhow::Error) -> bool {
+    err.chain().any(<dyn std::error::Error>::is::<Interrupted>)
+}
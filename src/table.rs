@@ -0,0 +1,224 @@
+//! Aligned tables for the human readable output of `list` and `status`.
+//!
+//! Columns are as wide as their widest cell. On a terminal narrower than the
+//! table the widest columns give way first, and cells that no longer fit are
+//! cut with an ellipsis. Styles are applied after aligning, so their escape
+//! codes don't count towards the width.
+
+use std::io::IsTerminal;
+
+use colored::{ColoredString, Colorize as _};
+use crossterm::terminal;
+
+/// Narrowest a column is shrunk to
+const MIN_WIDTH: usize = 8;
+/// Spaces between columns
+const GAP: &str = "  ";
+
+/// How a cell is highlighted
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Style {
+    #[default]
+    Plain,
+    Bold,
+    Dimmed,
+    Red,
+    Yellow,
+}
+
+impl Style {
+    fn apply(self, text: &str) -> ColoredString {
+        match self {
+            Self::Plain => text.normal(),
+            Self::Bold => text.bold(),
+            Self::Dimmed => text.dimmed(),
+            Self::Red => text.red(),
+            Self::Yellow => text.yellow(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Cell {
+    text: String,
+    style: Style,
+}
+
+impl Cell {
+    pub fn new<S: Into<String>>(text: S) -> Self {
+        Self::styled(text, Style::Plain)
+    }
+
+    pub fn styled<S: Into<String>>(text: S, style: Style) -> Self {
+        Self {
+            text: text.into(),
+            style,
+        }
+    }
+}
+
+pub struct Table {
+    headers: Vec<&'static str>,
+    rows: Vec<Vec<Cell>>,
+}
+
+impl Table {
+    pub fn new(headers: &[&'static str]) -> Self {
+        Self {
+            headers: headers.to_vec(),
+            rows: Vec::new(),
+        }
+    }
+
+    /// Add a row, missing cells stay empty
+    pub fn push(&mut self, row: Vec<Cell>) {
+        self.rows.push(row);
+    }
+
+    /// Whether the table has no rows
+    pub const fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    /// The table for `stream`, fitted to its width if it is a terminal
+    pub fn render<T: IsTerminal>(&self, stream: &T) -> String {
+        let width = stream
+            .is_terminal()
+            .then(terminal::size)
+            .and_then(Result::ok)
+            .map(|(columns, _)| usize::from(columns));
+        let mut out = String::new();
+        for (i, line) in self.lines(width).iter().enumerate() {
+            for (column, text) in line.iter().enumerate() {
+                if column > 0 {
+                    out.push_str(GAP);
+                }
+                let style = if i == 0 {
+                    Style::Bold
+                } else {
+                    self.rows[i - 1]
+                        .get(column)
+                        .map_or(Style::Plain, |cell| cell.style)
+                };
+                out.push_str(&style.apply(text).to_string());
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    /// The header and the rows as padded and cut cells, at most `width`
+    /// characters per line when given
+    fn lines(&self, width: Option<usize>) -> Vec<Vec<String>> {
+        let text = |row: &[Cell], column: usize| {
+            row.get(column)
+                .map_or_else(String::new, |cell| cell.text.clone())
+        };
+        let mut widths: Vec<usize> = self
+            .headers
+            .iter()
+            .enumerate()
+            .map(|(column, header)| {
+                self.rows
+                    .iter()
+                    .map(|row| text(row, column).chars().count())
+                    .chain([header.chars().count()])
+                    .max()
+                    .unwrap_or_default()
+            })
+            .collect();
+        if let Some(width) = width {
+            let gaps = GAP.len() * widths.len().saturating_sub(1);
+            while widths.iter().sum::<usize>() + gaps > width {
+                let Some((widest, _)) = widths
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, w)| **w > MIN_WIDTH)
+                    .max_by_key(|(column, w)| (**w, usize::MAX - column))
+                else {
+                    break;
+                };
+                widths[widest] -= 1;
+            }
+        }
+
+        let last = widths.len().saturating_sub(1);
+        let line = |cells: Vec<String>| {
+            cells
+                .into_iter()
+                .zip(&widths)
+                .enumerate()
+                .map(|(column, (cell, width))| {
+                    let cell = cut(&cell, *width);
+                    if column == last {
+                        cell
+                    } else {
+                        format!("{cell:<width$}")
+                    }
+                })
+                .collect()
+        };
+        let header = self.headers.iter().map(ToString::to_string).collect();
+        let mut lines = vec![line(header)];
+        for row in &self.rows {
+            lines.push(line((0..widths.len()).map(|c| text(row, c)).collect()));
+        }
+        lines
+    }
+}
+
+/// `text` cut to `width` characters, ending in an ellipsis if it was longer
+fn cut(text: &str, width: usize) -> String {
+    if text.chars().count() <= width {
+        return text.to_string();
+    }
+    let mut cut: String = text.chars().take(width.saturating_sub(1)).collect();
+    cut.push('…');
+    cut
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table() -> Table {
+        let mut table = Table::new(&["HOST", "CREDENTIAL", "TOKEN"]);
+        table.push(vec![
+            Cell::new("gitlab.example.com"),
+            Cell::styled("alice (active)", Style::Bold),
+            Cell::new("glpa***"),
+        ]);
+        table.push(vec![Cell::new("github.com"), Cell::new("bob")]);
+        table
+    }
+
+    #[test]
+    fn columns_are_aligned() {
+        let lines: Vec<String> = table()
+            .lines(None)
+            .into_iter()
+            .map(|line| line.join(GAP))
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                "HOST                CREDENTIAL      TOKEN",
+                "gitlab.example.com  alice (active)  glpa***",
+                "github.com          bob             ",
+            ]
+        );
+    }
+
+    #[test]
+    fn narrow_terminals_cut_the_widest_column() {
+        let lines = table().lines(Some(39));
+        assert_eq!(lines[1][0], "gitlab.exampl…", "widest column gave way");
+        assert_eq!(lines[1][1], "alice (active)", "others kept");
+        assert!(
+            lines
+                .iter()
+                .all(|line| line.join(GAP).chars().count() <= 39),
+            "fits"
+        );
+    }
+}
@@ -0,0 +1,114 @@
+//! Optional trace of the credential helper protocol, see `--trace-io`.
+//!
+//! Every line read from Git and written back is appended to the trace file
+//! with a timestamp, secrets are redacted.
+
+use std::fs::{File, OpenOptions};
+use std::io::Write as _;
+use std::path::Path;
+use std::sync::Mutex;
+
+use anyhow::{Context as _, Result};
+use chrono::{SecondsFormat, Utc};
+
+/// Keys whose values are never written to the trace
+const SECRET_KEYS: &[&str] = &["password", "oauth_refresh_token", "credential"];
+
+static SINK: Mutex<Option<File>> = Mutex::new(None);
+
+/// Which way a line of the protocol went
+#[derive(Debug, Clone, Copy)]
+pub enum Direction {
+    /// Read from Git
+    In,
+    /// Sent to Git
+    Out,
+}
+
+/// Start tracing to `path`, appending to it
+pub fn open(path: &Path) -> Result<()> {
+    let mut options = OpenOptions::new();
+    options.create(true).append(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt as _;
+        options.mode(0o600);
+    }
+    let file = options
+        .open(path)
+        .with_context(|| format!("Failed to open trace file {}", path.display()))?;
+    *SINK.lock().expect("Trace lock poisoned") = Some(file);
+    note(&format!(
+        "{} {} (pid {}): {}",
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION"),
+        std::process::id(),
+        std::env::args().skip(1).collect::<Vec<_>>().join(" ")
+    ));
+    Ok(())
+}
+
+/// Trace the protocol lines in `text`
+pub fn record(direction: Direction, text: &str) {
+    let marker = match direction {
+        Direction::In => '<',
+        Direction::Out => '>',
+    };
+    write_lines(
+        text.lines()
+            .map(|line| format!("{marker} {}", redact(line))),
+    );
+}
+
+/// Trace a message that isn't part of the protocol
+pub fn note(message: &str) {
+    write_lines(message.lines().map(|line| format!("# {line}")));
+}
+
+fn write_lines<I: Iterator<Item = String>>(lines: I) {
+    let mut sink = SINK.lock().expect("Trace lock poisoned");
+    let Some(file) = sink.as_mut() else {
+        return;
+    };
+    let timestamp = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+    for line in lines {
+        // tracing must never break the helper
+        let _ = writeln!(file, "{timestamp} {line}");
+    }
+    let _ = file.flush();
+    drop(sink);
+}
+
+/// `line` with the value of a secret key replaced
+fn redact(line: &str) -> String {
+    match line.split_once('=') {
+        Some((key, value))
+            if SECRET_KEYS.contains(&key.trim_end_matches("[]")) && !value.is_empty() =>
+        {
+            format!("{key}=<redacted {} bytes>", value.len())
+        },
+        Some(_) | None => line.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn secrets_are_redacted() {
+        assert_eq!(
+            redact("password=gho_secret"),
+            "password=<redacted 10 bytes>",
+            "password"
+        );
+        assert_eq!(
+            redact("oauth_refresh_token=ghr_x"),
+            "oauth_refresh_token=<redacted 5 bytes>",
+            "refresh token"
+        );
+        assert_eq!(redact("host=github.com"), "host=github.com", "other keys");
+        assert_eq!(redact("password="), "password=", "empty values");
+        assert_eq!(redact(""), "", "the terminating empty line");
+    }
+}
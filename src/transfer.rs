@@ -0,0 +1,179 @@
+//! Passphrase-encrypted tokens for moving a login to another machine, see
+//! `login --print-only` and `import-token`.
+//!
+//! A blob is [`PREFIX`] followed by the base64 of the PBKDF2 iteration count
+//! (big-endian `u32`) and salt, the nonce and the ChaCha20-Poly1305 sealed
+//! JSON of a [`TokenTransfer`].
+
+use std::env;
+use std::num::NonZeroU32;
+use std::path::PathBuf;
+
+use anyhow::{Context as _, Result, anyhow, bail};
+use base64::Engine as _;
+use base64::engine::general_purpose::STANDARD;
+use dialoguer::Password;
+use ring::aead::{Aad, CHACHA20_POLY1305, LessSafeKey, NONCE_LEN, Nonce, UnboundKey};
+use ring::pbkdf2;
+use ring::rand::{SecureRandom as _, SystemRandom};
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+use crate::keyring::Token;
+use crate::theme::InputTheme;
+
+const PREFIX: &str = "warden-token-v1:";
+const SALT_LEN: usize = 16;
+const ITERATIONS: u32 = 600_000;
+/// Blobs asking for more PBKDF2 iterations are rejected instead of stalling
+const MAX_ITERATIONS: u32 = 10_000_000;
+/// Environment variable read for the passphrase instead of prompting
+pub const PASSPHRASE_ENV: &str = "WARDEN_TOKEN_PASSPHRASE";
+
+/// Where `login --print-only` puts the encrypted token
+#[derive(Debug, Clone)]
+pub enum ExportTarget {
+    Stdout,
+    File(PathBuf),
+}
+
+/// A token together with the credential it belongs to
+#[derive(Serialize, Deserialize)]
+pub struct TokenTransfer {
+    pub host: String,
+    pub credential: String,
+    pub token: Token,
+}
+
+/// Encrypt `transfer` with a key derived from `passphrase`
+pub fn seal(transfer: &TokenTransfer, passphrase: &str) -> Result<String> {
+    seal_with(transfer, passphrase, ITERATIONS)
+}
+
+fn seal_with(transfer: &TokenTransfer, passphrase: &str, iterations: u32) -> Result<String> {
+    let rng = SystemRandom::new();
+    let mut salt = [0_u8; SALT_LEN];
+    let mut nonce = [0_u8; NONCE_LEN];
+    rng.fill(&mut salt)
+        .and_then(|()| rng.fill(&mut nonce))
+        .map_err(|_unspecified| anyhow!("Failed to generate random bytes"))?;
+
+    let mut sealed =
+        Zeroizing::new(serde_json::to_vec(transfer).context("Failed to serialize token")?);
+    key(passphrase, iterations, &salt)?
+        .seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::from(PREFIX),
+            &mut *sealed,
+        )
+        .map_err(|_unspecified| anyhow!("Failed to encrypt token"))?;
+
+    let mut blob = Vec::with_capacity(4 + SALT_LEN + NONCE_LEN + sealed.len());
+    blob.extend_from_slice(&iterations.to_be_bytes());
+    blob.extend_from_slice(&salt);
+    blob.extend_from_slice(&nonce);
+    blob.extend_from_slice(&sealed);
+    Ok(format!("{PREFIX}{}", STANDARD.encode(blob)))
+}
+
+/// Decrypt a blob created by [`seal`]
+pub fn open(blob: &str, passphrase: &str) -> Result<TokenTransfer> {
+    let Some(encoded) = blob.trim().strip_prefix(PREFIX) else {
+        bail!("Not a token exported by warden");
+    };
+    let data = STANDARD
+        .decode(encoded)
+        .context("The exported token is corrupted")?;
+    let Some((iterations, rest)) = data.split_first_chunk::<4>() else {
+        bail!("The exported token is truncated");
+    };
+    let iterations = u32::from_be_bytes(*iterations);
+    if iterations > MAX_ITERATIONS {
+        bail!("The exported token is corrupted");
+    }
+    if rest.len() < SALT_LEN + NONCE_LEN {
+        bail!("The exported token is truncated");
+    }
+    let (salt, rest) = rest.split_at(SALT_LEN);
+    let (nonce, sealed) = rest.split_at(NONCE_LEN);
+    let nonce = Nonce::try_assume_unique_for_key(nonce)
+        .map_err(|_unspecified| anyhow!("The exported token is corrupted"))?;
+
+    let mut sealed = Zeroizing::new(sealed.to_vec());
+    let plain = key(passphrase, iterations, salt)?
+        .open_in_place(nonce, Aad::from(PREFIX), &mut sealed)
+        .map_err(|_unspecified| anyhow!("Wrong passphrase or corrupted token"))?;
+    serde_json::from_slice(plain).context("Failed to deserialize token")
+}
+
+/// The passphrase from [`PASSPHRASE_ENV`], or prompted for, twice if
+/// `confirm` is set
+pub fn read_passphrase(confirm: bool) -> Result<Zeroizing<String>> {
+    let passphrase = if let Ok(passphrase) = env::var(PASSPHRASE_ENV) {
+        passphrase
+    } else {
+        let theme = InputTheme::default();
+        let mut prompt = Password::with_theme(&theme).with_prompt("Passphrase");
+        if confirm {
+            prompt = prompt.with_confirmation("Repeat passphrase", "The passphrases don't match");
+        }
+        prompt.interact().context("Failed to read passphrase")?
+    };
+    if passphrase.is_empty() {
+        bail!("The passphrase cannot be empty");
+    }
+    Ok(Zeroizing::new(passphrase))
+}
+
+fn key(passphrase: &str, iterations: u32, salt: &[u8]) -> Result<LessSafeKey> {
+    let iterations = NonZeroU32::new(iterations).context("The exported token is corrupted")?;
+    let mut key = Zeroizing::new([0_u8; 32]);
+    pbkdf2::derive(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        iterations,
+        salt,
+        passphrase.as_bytes(),
+        &mut *key,
+    );
+    let key = UnboundKey::new(&CHACHA20_POLY1305, &*key)
+        .map_err(|_unspecified| anyhow!("Failed to derive encryption key"))?;
+    Ok(LessSafeKey::new(key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sealed_token_opens_only_with_passphrase() {
+        let transfer = TokenTransfer {
+            host: "github.com".into(),
+            credential: "work".into(),
+            token: Token::new("gho_secret".into(), Some("ghr_refresh".into()), None),
+        };
+        // few iterations, the count is part of the blob
+        let blob = seal_with(&transfer, "correct horse", 1000).unwrap();
+        assert!(blob.starts_with(PREFIX), "blob should be recognizable");
+        assert!(!blob.contains("gho_secret"), "token must not be readable");
+
+        let opened = open(&format!("{blob}\n"), "correct horse").unwrap();
+        assert_eq!(opened.host, "github.com", "host should survive");
+        assert_eq!(opened.credential, "work", "credential should survive");
+        assert_eq!(
+            opened.token.access_token(),
+            "gho_secret",
+            "token should survive"
+        );
+        assert_eq!(
+            opened.token.refresh_token(),
+            Some("ghr_refresh"),
+            "refresh token should survive"
+        );
+
+        assert!(
+            open(&blob, "wrong horse").is_err(),
+            "wrong passphrase must fail"
+        );
+        assert!(open("gho_secret", "correct horse").is_err(), "no prefix");
+    }
+}
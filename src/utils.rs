@@ -1,27 +1,26 @@
 use std::collections::HashMap;
-use std::env::consts::FAMILY;
 use std::fmt::Display;
-use std::io::{self, BufRead as _, stderr};
-use std::path::PathBuf;
+use std::fs;
+use std::io::{self, BufRead, IsTerminal as _};
+use std::path::{Path, PathBuf};
 use std::process::exit;
+use std::sync::OnceLock;
 
-use anyhow::{Context as _, Result, anyhow};
-use chrono::{DateTime, Utc};
-use crossterm::cursor::Show;
-use crossterm::execute;
-use dialoguer::FuzzySelect;
-use tracing::{error, info, instrument};
+use anyhow::{Context as _, Result, anyhow, bail};
+use chrono::{DateTime, TimeDelta, Utc};
+use dialoguer::{Confirm, FuzzySelect};
+use git2::Repository;
+use tracing::{debug, error, info, instrument};
 
+use crate::secret::Secret;
+use crate::signal;
 use crate::theme::InputTheme;
+use crate::trace_io::{self, Direction};
 
 pub fn select_index<S: Into<String>, T: AsRef<str> + Display>(
     items: &[T],
     prompt: S,
 ) -> Result<usize> {
-    let _ = ctrlc::set_handler(|| {
-        let _ = execute!(stderr(), Show);
-        exit(130);
-    });
     let sel = FuzzySelect::with_theme(&InputTheme::default())
         .items(items)
         .with_prompt(prompt)
@@ -32,41 +31,117 @@ pub fn select_index<S: Into<String>, T: AsRef<str> + Display>(
     match sel {
         Some(index) => Ok(index),
         None => {
-            exit(130);
+            exit(signal::EXIT_CODE);
         },
     }
 }
 
 /// Represents the fields Git sends to a credential helper.
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct CredentialRequest {
-    pub _protocol: String,
+    pub protocol: String,
     pub host: String,
-    pub _path: Option<String>,
+    pub path: Option<String>,
     pub username: Option<String>,
-    pub password: Option<String>,
+    pub password: Option<Secret>,
     pub password_expiry_utc: Option<DateTime<Utc>>,
-    pub oauth_refresh_token: Option<String>,
+    pub oauth_refresh_token: Option<Secret>,
+    /// `WWW-Authenticate` headers of the response that made Git ask, one per
+    /// `wwwauth[]` line
+    pub wwwauth: Vec<String>,
+}
+
+impl CredentialRequest {
+    /// The parsed `wwwauth[]` challenges
+    pub fn challenges(&self) -> Vec<Challenge> {
+        self.wwwauth.iter().map(|h| Challenge::parse(h)).collect()
+    }
+
+    /// The `error` of a Bearer challenge, set by servers that rejected the
+    /// token sent with the previous attempt (RFC 6750), e.g. `invalid_token`
+    /// or `insufficient_scope`
+    pub fn bearer_error(&self) -> Option<String> {
+        self.challenges()
+            .into_iter()
+            .filter(|c| c.scheme.eq_ignore_ascii_case("bearer"))
+            .find_map(|c| c.params.get("error").cloned())
+    }
+}
+
+/// A single `WWW-Authenticate` challenge: its scheme and `key=value`
+/// parameters (keys lowercased, quotes removed).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Challenge {
+    pub scheme: String,
+    pub params: HashMap<String, String>,
+}
+
+impl Challenge {
+    pub fn parse(header: &str) -> Self {
+        let header = header.trim();
+        let (scheme, rest) = header.split_once(' ').unwrap_or((header, ""));
+        let mut params = HashMap::new();
+        let mut rest = rest.trim_start();
+        while let Some((key, after)) = rest.split_once('=') {
+            let key = key.trim().trim_start_matches(',').trim().to_lowercase();
+            let after = after.trim_start();
+            let (value, remainder) = after.strip_prefix('"').map_or_else(
+                || after.split_once(',').unwrap_or((after, "")),
+                |quoted| quoted.split_once('"').unwrap_or((quoted, "")),
+            );
+            params.insert(key, value.trim().to_string());
+            rest = remainder.trim_start().trim_start_matches(',').trim_start();
+        }
+        Self {
+            scheme: scheme.to_string(),
+            params,
+        }
+    }
 }
 
 /// Parses Git's credential helper input from stdin (key=value pairs).
 #[instrument]
 pub fn parse_credential_request() -> Result<CredentialRequest> {
-    let stdin = io::stdin();
-    let lines = stdin.lock().lines();
+    let mut raw = String::new();
+    for line in io::stdin().lock().lines() {
+        let line = line?;
+        raw.push_str(&line);
+        raw.push('\n');
+        if line.is_empty() {
+            break;
+        }
+    }
+    trace_io::record(Direction::In, &raw);
+    parse_credential_input(raw.as_bytes())
+}
+
+/// Parses credential helper input. Keys ending in `[]` may repeat and are
+/// collected in order, an empty value clears what was collected so far.
+pub fn parse_credential_input<R: BufRead>(input: R) -> Result<CredentialRequest> {
     let mut map = HashMap::new();
+    let mut arrays: HashMap<String, Vec<String>> = HashMap::new();
 
-    for line_res in lines {
+    for line_res in input.lines() {
         let line = line_res?;
         if line.is_empty() {
             break;
         }
-        if let Some((key, value)) = line.split_once('=') {
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        if let Some(key) = key.strip_suffix("[]") {
+            let values = arrays.entry(key.to_string()).or_default();
+            if value.is_empty() {
+                values.clear();
+            } else {
+                values.push(value.to_string());
+            }
+        } else {
             map.insert(key.to_string(), value.to_string());
         }
     }
     info!(
-        "{:#?}",
+        "{:#?} {:#?}",
         &map.clone()
             .iter()
             .map(|(k, v)| {
@@ -76,7 +151,8 @@ pub fn parse_credential_request() -> Result<CredentialRequest> {
                     (k.clone(), v.clone())
                 }
             })
-            .collect::<HashMap<_, _>>()
+            .collect::<HashMap<_, _>>(),
+        &arrays
     );
 
     let password_expiry_utc = map
@@ -99,31 +175,791 @@ pub fn parse_credential_request() -> Result<CredentialRequest> {
         .transpose()?;
 
     Ok(CredentialRequest {
-        _protocol: map
+        protocol: map
             .get("protocol")
             .cloned()
             .context("Missing 'protocol' field")?,
         host: map.get("host").cloned().context("Missing 'host' field")?,
-        _path: map.get("path").cloned(),
+        path: map.get("path").cloned(),
         username: map.get("username").cloned(),
-        password: map.get("password").cloned(),
+        password: map.get("password").cloned().map(Secret::from),
         password_expiry_utc,
-        oauth_refresh_token: map.get("oauth_refresh_token").cloned(),
+        oauth_refresh_token: map.get("oauth_refresh_token").cloned().map(Secret::from),
+        wwwauth: arrays.remove("wwwauth").unwrap_or_default(),
     })
 }
 
+/// Canonical form of a host as used for provider lookup, hosts state and
+/// keyring entry naming.
+///
+/// Lowercases the host, strips a leading scheme, trailing slashes and dots, and
+/// drops the default port of the scheme, HTTPS without one. Any other port is
+/// kept, so `GitLab.Example.com:443` becomes `gitlab.example.com` while
+/// `gitlab.example.com:8443` and `http://gitlab.example.com:443` keep theirs.
+pub fn normalize_host(host: &str) -> String {
+    let host = host.trim();
+    let (host, default_port) = host.strip_prefix("http://").map_or_else(
+        || (host.strip_prefix("https://").unwrap_or(host), "443"),
+        |host| (host, "80"),
+    );
+    let host = host.trim_end_matches('/').to_ascii_lowercase();
+
+    let (name, port) = split_port(&host);
+    let name = name.trim_end_matches('.');
+    match port {
+        Some(port) if port != default_port => format!("{name}:{port}"),
+        _ => name.to_string(),
+    }
+}
+
+/// Splits `host[:port]` into its name and optional port, leaving bracketed
+/// IPv6 literals intact.
+pub fn split_port(host: &str) -> (&str, Option<&str>) {
+    match host.rsplit_once(':') {
+        Some((name, port))
+            if !port.is_empty()
+                && port.bytes().all(|b| b.is_ascii_digit())
+                && (!name.contains(':') || name.ends_with(']')) =>
+        {
+            (name, Some(port))
+        },
+        _ => (host, None),
+    }
+}
+
+/// Check that a normalized host (see [`normalize_host`]) is a plausible host
+/// name: dot separated labels of letters, digits, `-` and `_`, or a bracketed
+/// IPv6 literal, with an optional port.
+pub fn validate_host(host: &str) -> Result<()> {
+    if host.contains('/') || host.contains('@') {
+        bail!("'{host}' contains a path or user, only the host name is expected");
+    }
+    let (name, port) = split_port(host);
+    if let Some(port) = port
+        && !port.parse::<u16>().is_ok_and(|p| p != 0)
+    {
+        bail!("Invalid port '{port}' in '{host}'");
+    }
+    if let Some(ip) = name.strip_prefix('[').and_then(|n| n.strip_suffix(']')) {
+        return ip
+            .parse::<std::net::Ipv6Addr>()
+            .map(|_| ())
+            .with_context(|| format!("Invalid IPv6 address in '{host}'"));
+    }
+    if name.is_empty() || name.len() > 253 {
+        bail!("'{host}' is not a valid host name");
+    }
+    for label in name.split('.') {
+        let valid = !label.is_empty()
+            && label.len() <= 63
+            && !label.starts_with('-')
+            && !label.ends_with('-')
+            && label
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_');
+        if !valid {
+            bail!("'{host}' is not a valid host name, label '{label}' is malformed");
+        }
+    }
+    Ok(())
+}
+
+/// Parse a duration like `90m`, `2h` or `1d12h`: numbers each followed by
+/// `s`, `m`, `h`, `d` or `w`.
+pub fn parse_duration(input: &str) -> Result<TimeDelta> {
+    let mut total = TimeDelta::zero();
+    let mut rest = input.trim();
+    if rest.is_empty() {
+        bail!("The duration is empty");
+    }
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let (number, tail) = rest.split_at(digits);
+        let mut chars = tail.chars();
+        let (Ok(number), Some(unit)) = (number.parse::<i64>(), chars.next()) else {
+            bail!("Invalid duration '{input}', expected e.g. 30m, 2h or 1d12h");
+        };
+        let part = match unit {
+            's' => TimeDelta::try_seconds(number),
+            'm' => TimeDelta::try_minutes(number),
+            'h' => TimeDelta::try_hours(number),
+            'd' => TimeDelta::try_days(number),
+            'w' => TimeDelta::try_weeks(number),
+            _ => bail!("Unknown unit '{unit}' in '{input}', use s, m, h, d or w"),
+        };
+        total = part
+            .and_then(|part| total.checked_add(&part))
+            .with_context(|| format!("The duration '{input}' is too long"))?;
+        rest = chars.as_str();
+    }
+    if total <= TimeDelta::zero() {
+        bail!("The duration must be longer than zero");
+    }
+    Ok(total)
+}
+
+/// How the repository a command works on is checked out
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RepoKind {
+    /// A repository with its work tree next to its `.git` directory
+    Main,
+    /// A worktree added with `git worktree add`, its git dir lives in the
+    /// main repository's `.git/worktrees/<name>`
+    LinkedWorktree,
+    /// A submodule, its git dir lives in the superproject's
+    /// `.git/modules/<name>`
+    Submodule,
+    /// A repository without a work tree
+    Bare,
+}
+
+impl Display for RepoKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Main => "repository",
+            Self::LinkedWorktree => "linked worktree",
+            Self::Submodule => "submodule",
+            Self::Bare => "bare repository",
+        })
+    }
+}
+
+/// The repository a command works on and how it is checked out
+pub struct RepoContext {
+    pub repo: Repository,
+    pub kind: RepoKind,
+}
+
+/// Open the repository containing `path`, or the one Git would use in the
+/// current directory.
+///
+/// The nearest repository wins: inside a submodule or a linked worktree that
+/// is the submodule or the worktree, never the repository around it. Without
+/// `path`, the environment Git sets for helpers and hooks comes first: a
+/// `GIT_DIR` (also set by `git --git-dir`) is opened as is, without searching
+/// from the current directory, and `GIT_WORK_TREE` replaces its work tree.
+#[instrument]
+pub fn repo_context(path: Option<&Path>) -> Result<RepoContext> {
+    let repo = match path {
+        Some(path) => {
+            Repository::discover(path)
+                .with_context(|| format!("Failed to open git repository at {}", path.display()))?
+        },
+        None => {
+            open_env_repo(
+                std::env::var_os("GIT_DIR").map(PathBuf::from),
+                std::env::var_os("GIT_WORK_TREE").map(PathBuf::from),
+            )?
+        },
+    };
+    let kind = repo_kind(&repo);
+    debug!("Using the {kind} at {}", repo.path().display());
+    Ok(RepoContext { repo, kind })
+}
+
+/// The repository of `GIT_DIR` with the work tree of `GIT_WORK_TREE`, both
+/// relative to the current directory, or the one found from there
+fn open_env_repo(git_dir: Option<PathBuf>, work_tree: Option<PathBuf>) -> Result<Repository> {
+    let repo = match git_dir {
+        Some(git_dir) => {
+            debug!("Opening GIT_DIR {}", git_dir.display());
+            Repository::open(&git_dir).with_context(|| {
+                format!("Failed to open git repository at {}", git_dir.display())
+            })?
+        },
+        // still honors GIT_CEILING_DIRECTORIES and friends
+        None => Repository::open_from_env().context("Failed to open git repository")?,
+    };
+    if let Some(work_tree) = work_tree {
+        let work_tree = std::path::absolute(&work_tree)
+            .with_context(|| format!("Invalid GIT_WORK_TREE {}", work_tree.display()))?;
+        debug!("Using GIT_WORK_TREE {}", work_tree.display());
+        repo.set_workdir(&work_tree, false)
+            .context("Failed to set the work tree")?;
+    }
+    Ok(repo)
+}
+
+fn repo_kind(repo: &Repository) -> RepoKind {
+    if repo.is_bare() {
+        return RepoKind::Bare;
+    }
+    if repo.is_worktree() {
+        return RepoKind::LinkedWorktree;
+    }
+    let Some(workdir) = repo.workdir() else {
+        return RepoKind::Bare;
+    };
+    // a submodule's `.git` is a file pointing into the superproject, which
+    // lists the submodule at the work tree's path
+    if !workdir.join(".git").is_file() {
+        return RepoKind::Main;
+    }
+    let superproject = workdir
+        .parent()
+        .and_then(|parent| Repository::discover(parent).ok());
+    let is_submodule = superproject.is_some_and(|superproject| {
+        let Some(super_workdir) = superproject.workdir() else {
+            return false;
+        };
+        let (Ok(workdir), Ok(super_workdir)) =
+            (workdir.canonicalize(), super_workdir.canonicalize())
+        else {
+            return false;
+        };
+        workdir.strip_prefix(&super_workdir).is_ok_and(|relative| {
+            superproject
+                .submodules()
+                .is_ok_and(|subs| subs.iter().any(|sub| sub.path() == relative))
+        })
+    });
+    if is_submodule {
+        RepoKind::Submodule
+    } else {
+        RepoKind::Main
+    }
+}
+
+/// A snapshot of the git config Git sees in the repository of `repo` or the
+/// current directory, or the global one outside a repository
+pub fn git_config(repo: Option<&Path>) -> Result<git2::Config> {
+    repo_context(repo)
+        .ok()
+        .map_or_else(git2::Config::open_default, |ctx| ctx.repo.config())
+        .and_then(|mut cfg| cfg.snapshot())
+        .context("Failed to open git config")
+}
+
+/// The `credential.<url>.username` set for `req`, from the most specific
+/// matching context: the exact host over a `*` wildcard over none at all,
+/// then the longest path. Git passes it itself for the contexts it matches,
+/// but not for other callers, and path contexts only match as a whole there.
+pub fn configured_username(cfg: &git2::Config, req: &CredentialRequest) -> Option<String> {
+    let mut best: Option<((u8, usize), String)> = None;
+    let mut entries = cfg.entries(Some(r"^credential\..*")).ok()?;
+    while let Some(Ok(entry)) = entries.next() {
+        let (Some(name), Some(value)) = (entry.name(), entry.value()) else {
+            continue;
+        };
+        let Some(rest) = name.strip_prefix("credential.") else {
+            continue;
+        };
+        // the URL may contain dots, the key never does
+        let (context, key) = rest
+            .rsplit_once('.')
+            .map_or((None, rest), |(context, key)| (Some(context), key));
+        if !key.eq_ignore_ascii_case("username") {
+            continue;
+        }
+        let rank = context.map_or(Some((0, 0)), |context| context_rank(context, req));
+        // later settings override earlier ones of the same rank
+        if let Some(rank) = rank
+            && best.as_ref().is_none_or(|(best, _)| rank >= *best)
+        {
+            best = Some((rank, value.to_string()));
+        }
+    }
+    best.map(|(_, username)| username)
+        .filter(|username| !username.is_empty())
+}
+
+/// How specifically the `credential.<context>` URL matches `req`, `None` if
+/// it doesn't. Contexts with a user only match requests already carrying it.
+fn context_rank(context: &str, req: &CredentialRequest) -> Option<(u8, usize)> {
+    let (scheme, rest) = context
+        .split_once("://")
+        .map_or((None, context), |(scheme, rest)| (Some(scheme), rest));
+    if scheme.is_some_and(|scheme| !scheme.eq_ignore_ascii_case(&req.protocol)) {
+        return None;
+    }
+    let (authority, path) = rest.split_once('/').unwrap_or((rest, ""));
+    if authority.contains('@') {
+        return None;
+    }
+    let host = if authority.eq_ignore_ascii_case(&req.host) {
+        2
+    } else if wildcard_host_matches(authority, &req.host) {
+        1
+    } else {
+        return None;
+    };
+    let path = path.trim_end_matches('/');
+    if !path.is_empty() {
+        let requested = req.path.as_deref()?.trim_start_matches('/');
+        let within = requested
+            .strip_prefix(path)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'));
+        if !within {
+            return None;
+        }
+    }
+    Some((host, path.len()))
+}
+
+/// Whether `pattern` matches `host` with each `*` standing for one name
+/// component, e.g. `*.example.com` for `git.example.com`
+fn wildcard_host_matches(pattern: &str, host: &str) -> bool {
+    let (pattern, pattern_port) = split_port(pattern);
+    let (host, port) = split_port(host);
+    let pattern: Vec<&str> = pattern.split('.').collect();
+    let host: Vec<&str> = host.split('.').collect();
+    pattern_port == port
+        && pattern.len() == host.len()
+        && pattern
+            .iter()
+            .zip(&host)
+            .all(|(p, h)| *p == "*" || p.eq_ignore_ascii_case(h))
+}
+
+/// Whether Git fetches from or pushes to a URL, which decides how
+/// `url.<base>.insteadOf` and `url.<base>.pushInsteadOf` rewrite it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UrlUse {
+    Fetch,
+    Push,
+}
+
+/// `url` as Git uses it: for pushes rewritten by the longest matching
+/// `pushInsteadOf`, otherwise by the longest matching `insteadOf`
+pub fn rewrite_url(cfg: &git2::Config, url: &str, usage: UrlUse) -> String {
+    // (key suffix, base, prefix) of every rewrite
+    let mut rewrites = Vec::new();
+    if let Ok(mut entries) = cfg.entries(Some(r"^url\..*")) {
+        while let Some(Ok(entry)) = entries.next() {
+            let (Some(name), Some(prefix)) = (entry.name(), entry.value()) else {
+                continue;
+            };
+            // the base may contain dots, the key never does
+            let Some((base, key)) = name.strip_prefix("url.").and_then(|r| r.rsplit_once('.'))
+            else {
+                continue;
+            };
+            rewrites.push((
+                key.to_ascii_lowercase(),
+                base.to_string(),
+                prefix.to_string(),
+            ));
+        }
+    }
+    let longest = |key: &str| {
+        rewrites
+            .iter()
+            .filter(|(k, _, prefix)| k == key && url.starts_with(prefix.as_str()))
+            .max_by_key(|(_, _, prefix)| prefix.len())
+            .map(|(_, base, prefix)| format!("{base}{}", &url[prefix.len()..]))
+    };
+    let pushed = match usage {
+        UrlUse::Push => longest("pushinsteadof"),
+        UrlUse::Fetch => None,
+    };
+    pushed
+        .or_else(|| longest("insteadof"))
+        .unwrap_or_else(|| url.to_string())
+}
+
+/// The URLs Git fetches from or pushes to for the remote `name`, as
+/// rewritten by the `url.<base>.*insteadOf` settings. A remote may have
+/// several: Git fetches from the first `url` and pushes to every `pushurl`,
+/// or every `url` without one. Empty for an unknown remote.
+pub fn remote_urls(repo: &Repository, name: &str, usage: UrlUse) -> Result<Vec<String>> {
+    let cfg = repo
+        .config()
+        .and_then(|mut cfg| cfg.snapshot())
+        .context("Failed to open git config")?;
+    let values = |key: &str| -> Result<Vec<String>> {
+        let mut values = Vec::new();
+        let mut entries = cfg
+            .multivar(&format!("remote.{name}.{key}"), None)
+            .context("Failed to read git config")?;
+        while let Some(entry) = entries.next() {
+            let entry = entry.context("Failed to read git config entry")?;
+            match entry.value() {
+                // an empty value resets the list, like Git does
+                Some("") => values.clear(),
+                Some(value) => values.push(value.to_string()),
+                None => {},
+            }
+        }
+        Ok(values)
+    };
+    let urls = values("url")?;
+    let urls = match usage {
+        UrlUse::Fetch => {
+            urls.into_iter()
+                .take(1)
+                .map(|url| rewrite_url(&cfg, &url, UrlUse::Fetch))
+                .collect()
+        },
+        UrlUse::Push => {
+            let push_urls = values("pushurl")?;
+            // an explicit push URL is only rewritten by `insteadOf`
+            if push_urls.is_empty() {
+                urls.iter()
+                    .map(|url| rewrite_url(&cfg, url, UrlUse::Push))
+                    .collect()
+            } else {
+                push_urls
+                    .iter()
+                    .map(|url| rewrite_url(&cfg, url, UrlUse::Fetch))
+                    .collect()
+            }
+        },
+    };
+    Ok(urls)
+}
+
+/// Directory for state warden writes itself (host state, generated files,
+/// backups), kept apart from the user's config files so these can be synced
+/// between machines.
+///
+/// `WARDEN_STATE_DIR` overrides the platform default, which is
+/// `$XDG_STATE_HOME/warden` (`~/.local/state/warden`) on Linux and the local
+/// data directory elsewhere.
+#[instrument]
+pub fn state_dir() -> Result<PathBuf> {
+    if let Some(dir) = std::env::var_os("WARDEN_STATE_DIR") {
+        return Ok(PathBuf::from(dir));
+    }
+    dirs::state_dir()
+        .or_else(dirs::data_local_dir)
+        .context("Failed to get state directory")
+        .map(|dir| dir.join(env!("CARGO_PKG_NAME")))
+}
+
+/// Directory of the user's config files (`oauth.toml`, `profiles.toml`).
+///
+/// `WARDEN_CONFIG_DIR` overrides the platform default:
+/// `$XDG_CONFIG_HOME/warden` on Linux, `~/Library/Application Support/warden`
+/// on macOS and `%APPDATA%\warden` on Windows. A config directory at the old
+/// `~/.config/warden` location is offered to be moved once, and keeps being
+/// used until it is.
 #[instrument]
 pub fn config_dir() -> Result<PathBuf> {
-    match FAMILY {
-        "unix" => {
-            dirs::config_dir()
-                .context("Failed to get config directory")
-                .map(|dir| dir.join(env!("CARGO_PKG_NAME")))
+    if let Some(dir) = std::env::var_os("WARDEN_CONFIG_DIR") {
+        return Ok(PathBuf::from(dir));
+    }
+    let native = dirs::config_dir()
+        .context("Failed to get config directory")?
+        .join(env!("CARGO_PKG_NAME"));
+    let legacy = dirs::home_dir().map(|dir| dir.join(".config").join(env!("CARGO_PKG_NAME")));
+    match legacy {
+        Some(legacy) if legacy != native && legacy.is_dir() && !native.exists() => {
+            static RESOLVED: OnceLock<PathBuf> = OnceLock::new();
+            Ok(RESOLVED
+                .get_or_init(|| migrate_config_dir(&legacy, &native))
+                .clone())
         },
-        _ => {
-            dirs::home_dir()
-                .context("Failed to get home directory")
-                .map(|dir| dir.join(".config").join(env!("CARGO_PKG_NAME")))
+        _ => Ok(native),
+    }
+}
+
+/// Offer to move the config directory from `legacy` to `native`, returning
+/// the directory to use. Without a terminal to ask on (e.g. when run by git)
+/// the legacy directory is kept.
+fn migrate_config_dir(legacy: &Path, native: &Path) -> PathBuf {
+    let interactive = io::stdin().is_terminal() && io::stderr().is_terminal();
+    let confirmed = interactive
+        && Confirm::with_theme(&InputTheme::default())
+            .with_prompt(format!(
+                "Move the config directory from {} to {}?",
+                legacy.display(),
+                native.display()
+            ))
+            .default(true)
+            .interact_opt()
+            .ok()
+            .flatten()
+            .unwrap_or(false);
+    if !confirmed {
+        info!(
+            "Using config directory {}, the platform default is {}",
+            legacy.display(),
+            native.display()
+        );
+        return legacy.to_path_buf();
+    }
+
+    let moved = native
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|()| fs::rename(legacy, native));
+    match moved {
+        Ok(()) => {
+            eprintln!("Moved config directory to {}.", native.display());
+            native.to_path_buf()
         },
+        Err(err) => {
+            error!(
+                "Failed to move {} to {}: {err}",
+                legacy.display(),
+                native.display()
+            );
+            legacy.to_path_buf()
+        },
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn credential_input_collects_wwwauth() {
+        let input = "protocol=https\nhost=github.com\nwwwauth[]=Basic \
+                     realm=\"old\"\nwwwauth[]=\nwwwauth[]=Basic \
+                     realm=\"GitHub\"\nwwwauth[]=Bearer realm=\"api\", \
+                     error=\"insufficient_scope\", scope=\"repo workflow\"\n\nignored=1\n";
+        let req = parse_credential_input(input.as_bytes()).unwrap();
+        assert_eq!(req.host, "github.com");
+        assert_eq!(req.wwwauth.len(), 2, "empty value clears the list");
+
+        let challenges = req.challenges();
+        assert_eq!(challenges[0].scheme, "Basic");
+        assert_eq!(challenges[0].params["realm"], "GitHub");
+        assert_eq!(challenges[1].params["scope"], "repo workflow");
+        assert_eq!(req.bearer_error().as_deref(), Some("insufficient_scope"));
+    }
+
+    #[test]
+    fn normalize_host_lowercases_and_strips_default_port() {
+        assert_eq!(normalize_host("GitLab.Example.com"), "gitlab.example.com");
+        assert_eq!(
+            normalize_host("gitlab.example.com:443"),
+            "gitlab.example.com"
+        );
+        assert_eq!(normalize_host("gitlab.example.com."), "gitlab.example.com");
+        assert_eq!(
+            normalize_host("https://gitlab.example.com/"),
+            "gitlab.example.com"
+        );
+    }
+
+    #[test]
+    fn normalize_host_keeps_custom_port() {
+        assert_eq!(
+            normalize_host("GITLAB.example.com:8443"),
+            "gitlab.example.com:8443"
+        );
+        assert_eq!(normalize_host("[::1]:2222"), "[::1]:2222");
+        assert_eq!(normalize_host("[::1]"), "[::1]");
+        assert_eq!(
+            normalize_host("http://git.example.com:443"),
+            "git.example.com:443",
+            "only the default port of the scheme is dropped"
+        );
+        assert_eq!(
+            normalize_host("http://git.example.com:80"),
+            "git.example.com"
+        );
+    }
+
+    #[test]
+    fn validate_host_accepts_names_ips_and_ports() {
+        for host in [
+            "github.com",
+            "git_lab.example.com:8443",
+            "localhost",
+            "[::1]:2222",
+        ] {
+            assert!(validate_host(host).is_ok(), "{host} should be valid");
+        }
+        for host in [
+            "",
+            "github.com/org",
+            "user@github.com",
+            "-bad.example.com",
+            "a..b",
+            "host:99999",
+            "[not-ipv6]",
+            "white space.com",
+        ] {
+            assert!(validate_host(host).is_err(), "{host} should be invalid");
+        }
+    }
+
+    #[test]
+    fn split_port_ignores_bare_ipv6() {
+        assert_eq!(split_port("::1"), ("::1", None));
+        assert_eq!(split_port("[::1]:80"), ("[::1]", Some("80")));
+        assert_eq!(split_port("example.com"), ("example.com", None));
+    }
+
+    #[test]
+    fn durations_parse() {
+        assert_eq!(parse_duration("2h").unwrap(), TimeDelta::hours(2), "hours");
+        assert_eq!(
+            parse_duration("1d12h30m").unwrap(),
+            TimeDelta::minutes(36 * 60 + 30),
+            "combined units"
+        );
+        for input in ["", "2", "h", "2x", "0m", "-1h"] {
+            assert!(parse_duration(input).is_err(), "{input} should be invalid");
+        }
+    }
+
+    /// Repositories in a fresh temporary directory: `main` with a commit, its
+    /// linked worktree `wt`, its submodule `main/sub` and `bare.git`
+    pub fn checkouts(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("warden-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let commit = |repo: &Repository| {
+            let sig = git2::Signature::now("Alice", "alice@example.com").unwrap();
+            let tree = repo.index().unwrap().write_tree().unwrap();
+            let tree = repo.find_tree(tree).unwrap();
+            repo.commit(Some("HEAD"), &sig, &sig, "init", &tree, &[])
+                .unwrap();
+        };
+        let main = Repository::init(dir.join("main")).unwrap();
+        commit(&main);
+        let upstream = Repository::init(dir.join("upstream")).unwrap();
+        commit(&upstream);
+        main.worktree("wt", &dir.join("wt"), None).unwrap();
+        let mut sub = main
+            .submodule(
+                &dir.join("upstream").to_string_lossy(),
+                Path::new("sub"),
+                true,
+            )
+            .unwrap();
+        sub.clone(None).unwrap();
+        sub.add_finalize().unwrap();
+        Repository::init_bare(dir.join("bare.git")).unwrap();
+        dir
+    }
+
+    #[test]
+    fn repo_context_finds_the_nearest_repository() {
+        let dir = checkouts("repo-context");
+        let context = |path: &str| repo_context(Some(&dir.join(path))).unwrap();
+
+        assert_eq!(context("main").kind, RepoKind::Main, "main");
+        assert_eq!(context("wt").kind, RepoKind::LinkedWorktree, "worktree");
+        assert_eq!(context("bare.git").kind, RepoKind::Bare, "bare");
+        let sub = context("main/sub");
+        assert_eq!(sub.kind, RepoKind::Submodule, "submodule");
+        assert!(
+            sub.repo.path().ends_with("modules/sub/"),
+            "the submodule's git dir, not the superproject's: {}",
+            sub.repo.path().display()
+        );
+        assert!(repo_context(Some(&dir)).is_err(), "outside of repositories");
+
+        let repo = open_env_repo(Some(dir.join("main/.git")), Some(dir.join("wt"))).unwrap();
+        assert_eq!(
+            repo.path().canonicalize().unwrap(),
+            dir.join("main/.git").canonicalize().unwrap(),
+            "GIT_DIR is opened as is"
+        );
+        assert_eq!(
+            repo.workdir().unwrap().canonicalize().unwrap(),
+            dir.join("wt").canonicalize().unwrap(),
+            "GIT_WORK_TREE replaces the work tree"
+        );
+        fs::create_dir_all(dir.join("main/docs")).unwrap();
+        assert!(
+            open_env_repo(Some(dir.join("main/docs")), None).is_err(),
+            "GIT_DIR is not searched from"
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn remote_urls_follow_insteadof_rewrites() {
+        let dir = std::env::temp_dir().join(format!("warden-insteadof-{}", std::process::id()));
+        let repo = Repository::init(&dir).unwrap();
+        let mut cfg = repo
+            .config()
+            .unwrap()
+            .open_level(git2::ConfigLevel::Local)
+            .unwrap();
+        cfg.set_str("url.git@github.com:.insteadOf", "gh:").unwrap();
+        cfg.set_str("url.git@github.com:company/.insteadOf", "gh:company/")
+            .unwrap();
+        cfg.set_str("url.ssh://push.example.com/.pushInsteadOf", "gh:")
+            .unwrap();
+        cfg.set_str("remote.origin.url", "gh:company/app.git")
+            .unwrap();
+        cfg.set_multivar("remote.origin.url", "^$", "gh:mirror/app.git")
+            .unwrap();
+        let urls = |usage| remote_urls(&repo, "origin", usage).unwrap();
+
+        assert_eq!(
+            urls(UrlUse::Fetch),
+            ["git@github.com:company/app.git"],
+            "longest prefix, first url"
+        );
+        assert_eq!(
+            urls(UrlUse::Push),
+            [
+                "ssh://push.example.com/company/app.git",
+                "ssh://push.example.com/mirror/app.git"
+            ],
+            "pushInsteadOf, every url"
+        );
+        cfg.set_str("remote.origin.pushurl", "gh:fork/app.git")
+            .unwrap();
+        assert_eq!(
+            urls(UrlUse::Push),
+            ["git@github.com:fork/app.git"],
+            "explicit push url only by insteadOf"
+        );
+        assert_eq!(
+            remote_urls(&repo, "missing", UrlUse::Fetch).unwrap(),
+            Vec::<String>::new()
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn configured_username_prefers_the_most_specific_context() {
+        let dir = std::env::temp_dir().join(format!("warden-username-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config");
+        fs::write(
+            &path,
+            "[credential]\n\tusername = fallback\n\
+             [credential \"https://*.example.com\"]\n\tusername = wildcard\n\
+             [credential \"https://git.example.com\"]\n\tusername = host\n\
+             [credential \"https://git.example.com/org\"]\n\tusername = org\n\
+             [credential \"https://git.example.com/org/repo.git\"]\n\tusername = repo\n\
+             [credential \"https://alice@git.example.com\"]\n\tusername = alice\n",
+        )
+        .unwrap();
+        let cfg = git2::Config::open(&path).unwrap();
+        let username = |host: &str, path: Option<&str>| {
+            configured_username(
+                &cfg,
+                &CredentialRequest {
+                    protocol: "https".into(),
+                    host: host.into(),
+                    path: path.map(Into::into),
+                    ..CredentialRequest::default()
+                },
+            )
+        };
+
+        assert_eq!(username("git.example.com", None).as_deref(), Some("host"));
+        assert_eq!(
+            username("git.example.com", Some("org/other.git")).as_deref(),
+            Some("org")
+        );
+        assert_eq!(
+            username("git.example.com", Some("org/repo.git")).as_deref(),
+            Some("repo")
+        );
+        assert_eq!(
+            username("git.example.com", Some("organization/x.git")).as_deref(),
+            Some("host"),
+            "whole path components"
+        );
+        assert_eq!(
+            username("ci.example.com", None).as_deref(),
+            Some("wildcard")
+        );
+        assert_eq!(username("github.com", None).as_deref(), Some("fallback"));
+        fs::remove_dir_all(&dir).unwrap();
     }
 }